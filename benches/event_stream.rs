@@ -0,0 +1,158 @@
+//! How much reading codex's output costs: `EventStream`, which decodes
+//! each line from one reused buffer, against `BufRead::lines`, which
+//! allocates a `String` per line, and against parsing every line as JSON,
+//! which the decoder avoids for event types it doesn't read, and against
+//! `decode_parallel` using every core.
+//!
+//! Run with `cargo bench --bench event_stream [LINES]`. Each reader gets
+//! the same synthetic stream, and the time and heap allocations of the
+//! fastest of a few rounds are printed.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{BufRead, Cursor};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use codex_clean::events::{decode_parallel, extract_event, EventDecoder, EventStream};
+
+/// Counts allocations, so the difference isn't only seen as time.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ROUNDS: usize = 5;
+
+/// A long agentic run: mostly command output and streamed deltas, with
+/// the odd message.
+fn stream(lines: usize) -> Vec<u8> {
+    let mut out = String::from("{\"type\":\"thread.started\",\"thread_id\":\"0199a213-81c0-7800-8aa1-bbab2a035a53\"}\n");
+    for n in 0..lines {
+        let line = match n % 10 {
+            0 => "{\"type\":\"turn.started\"}".to_string(),
+            1 => format!(
+                "{{\"type\":\"item.completed\",\"item\":{{\"id\":\"item_{}\",\"type\":\"agent_message\",\"text\":\"Looking at step {} next.\"}}}}",
+                n, n
+            ),
+            2..=5 => format!(
+                "{{\"type\":\"item.updated\",\"item\":{{\"id\":\"item_{}\",\"type\":\"command_execution\",\"command\":\"cargo test\",\"aggregated_output\":\"test parser::case_{} ... ok\\n\",\"status\":\"in_progress\"}}}}",
+                n - n % 10,
+                n
+            ),
+            _ => format!(
+                "{{\"type\":\"item.completed\",\"item\":{{\"id\":\"item_{}\",\"type\":\"command_execution\",\"command\":\"cargo test -p crate_{}\",\"aggregated_output\":\"running 12 tests\\ntest result: ok\",\"exit_code\":0,\"status\":\"completed\"}}}}",
+                n,
+                n % 7
+            ),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.into_bytes()
+}
+
+fn with_lines(data: &[u8]) -> usize {
+    let mut decoder = EventDecoder::new();
+    let mut events = 0;
+    for line in Cursor::new(data).lines() {
+        let line = line.expect("in-memory read");
+        if !line.trim().is_empty() && matches!(decoder.decode(&line), Ok(Some(_))) {
+            events += 1;
+        }
+    }
+    events
+}
+
+/// `extract_event` parses each line in full, whatever its type.
+fn parse_every_line(data: &[u8]) -> usize {
+    Cursor::new(data)
+        .lines()
+        .filter(|line| extract_event(line.as_ref().expect("in-memory read")).is_some())
+        .count()
+}
+
+fn with_event_stream(data: &[u8]) -> usize {
+    EventStream::new(Cursor::new(data)).filter(Result::is_ok).count()
+}
+
+fn in_parallel(data: &[u8]) -> usize {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    decode_parallel(data, workers).items.iter().filter(|i| i.is_ok()).count()
+}
+
+/// The fastest round's time and allocations.
+fn measure(data: &[u8], read: fn(&[u8]) -> usize) -> (Duration, usize, usize) {
+    let mut best: Option<(Duration, usize, usize)> = None;
+    for _ in 0..ROUNDS {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let started = Instant::now();
+        let events = std::hint::black_box(read(std::hint::black_box(data)));
+        let took = started.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        if best.is_none_or(|(fastest, _, _)| took < fastest) {
+            best = Some((took, allocations, events));
+        }
+    }
+    best.expect("at least one round")
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; the first number is the line count.
+    let lines = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(200_000);
+    let data = stream(lines);
+    println!("{} lines, {:.1} MB", lines + 1, data.len() as f64 / 1e6);
+    let (parse_took, parse_allocs, parse_events) = measure(&data, parse_every_line);
+    let (lines_took, lines_allocs, lines_events) = measure(&data, with_lines);
+    let (stream_took, stream_allocs, stream_events) = measure(&data, with_event_stream);
+    let (parallel_took, parallel_allocs, parallel_events) = measure(&data, in_parallel);
+    assert_eq!(parallel_events, stream_events, "every reader should see the same events");
+    assert_eq!(lines_events, stream_events, "every reader should see the same events");
+    assert_eq!(parse_events, stream_events, "every reader should see the same events");
+    for (name, took, allocs) in [
+        ("parse all", parse_took, parse_allocs),
+        ("BufRead::lines", lines_took, lines_allocs),
+        ("EventStream", stream_took, stream_allocs),
+        ("decode_parallel", parallel_took, parallel_allocs),
+    ] {
+        println!(
+            "{:<16} {:>8.1} ms  {:>8.0} ns/line  {:>9} allocations",
+            name,
+            took.as_secs_f64() * 1e3,
+            took.as_secs_f64() * 1e9 / (lines + 1) as f64,
+            allocs
+        );
+    }
+    println!(
+        "EventStream: {} fewer allocations ({:.2} per line), {:.0}% of the time",
+        lines_allocs.saturating_sub(stream_allocs),
+        lines_allocs.saturating_sub(stream_allocs) as f64 / (lines + 1) as f64,
+        stream_took.as_secs_f64() / lines_took.as_secs_f64() * 100.0
+    );
+    println!(
+        "Skipping unread event types: {:.0}% of the time of parsing every line",
+        lines_took.as_secs_f64() / parse_took.as_secs_f64() * 100.0
+    );
+    println!(
+        "decode_parallel on {} threads: {:.1}x EventStream",
+        std::thread::available_parallelism().map_or(1, |n| n.get()),
+        stream_took.as_secs_f64() / parallel_took.as_secs_f64()
+    );
+}
@@ -0,0 +1,391 @@
+//! `codex-clean archive` and `unarchive`: a session and what it left
+//! behind, in one file to attach to a review or an incident report.
+//!
+//! An archive is a tar file, zstd-compressed when its name ends in `.zst`,
+//! holding one directory, `session-<id>/`:
+//!
+//! - `manifest.json`: what the archive holds and where each part came from
+//! - `transcript.md` and `transcript.jsonl`: the dialogue, readable and as
+//!   codex logged it (the rollout file)
+//! - `reports/`: the run report of every run of the session
+//! - `record.jsonl` (or `.zst`): the `--record` stream, if a run kept one
+//! - `files/`: the files `--write-files` wrote, as they are now
+//! - `changes.diff`: what codex changed in the working tree, from
+//!   `--git-snapshot` (`changes/N-<repo>.diff`, one per repository, for a
+//!   run over several)
+//!
+//! The last three come from the [`Artifacts`] each run notes in its report.
+//! Compressing and unpacking go through the `tar` and `zstd` commands, as
+//! `--record` does.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::export::render_dialogue_markdown;
+use crate::record;
+use crate::sessions;
+use crate::stats::{self, RunReport};
+
+/// What a run left besides its answer, kept in its report so the session
+/// can be archived later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Artifacts {
+    /// The `--record` file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<PathBuf>,
+    /// Files written by `--write-files`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<WrittenFile>,
+    /// The working tree before and after, for `--git-snapshot` runs that
+    /// changed it: one entry per repository changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git: Vec<GitChanges>,
+}
+
+impl Artifacts {
+    pub fn is_empty(&self) -> bool {
+        self.record.is_none() && self.files.is_empty() && self.git.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WrittenFile {
+    /// As the answer named it.
+    pub path: PathBuf,
+    /// Where it was written.
+    pub target: PathBuf,
+}
+
+/// Two snapshot commits (see [`crate::git::Snapshot`]) of the repository
+/// at `root`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitChanges {
+    pub root: PathBuf,
+    pub before: String,
+    pub after: String,
+}
+
+/// `manifest.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    pub session_id: String,
+    /// The tool that answered, for an imported session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub version: String,
+    pub contents: Vec<Entry>,
+}
+
+/// One part of an archive.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    /// Inside `session-<id>/`.
+    pub path: String,
+    pub what: String,
+    /// Where it was copied from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+}
+
+/// A directory that is removed with everything in it when dropped.
+struct Staging(PathBuf);
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Lay out the archive of the session logged at `rollout` in `dir`, from
+/// its `reports`. Parts that can't be found any more are left out with a
+/// warning.
+fn stage(dir: &Path, session_id: &str, rollout: &Path, reports: &[RunReport]) -> Result<Manifest> {
+    let mut contents = Vec::new();
+    let mut add = |path: &str, what: &str, source: Option<&Path>| {
+        contents.push(Entry { path: path.to_string(), what: what.to_string(), source: source.map(Path::to_path_buf) });
+    };
+
+    let mut transcript = sessions::read_transcript(rollout)?;
+    transcript.session_id.get_or_insert_with(|| session_id.to_string());
+    write(&dir.join("transcript.md"), render_dialogue_markdown(&transcript).as_bytes())?;
+    add("transcript.md", "the dialogue", None);
+    copy(rollout, &dir.join("transcript.jsonl"))?;
+    add("transcript.jsonl", "the session log", Some(rollout));
+
+    for (n, report) in reports.iter().enumerate() {
+        let name = format!("reports/run-{}.json", n + 1);
+        let raw = serde_json::to_string_pretty(report).context("serialising run report")?;
+        write(&dir.join(&name), raw.as_bytes())?;
+        add(&name, &format!("run report, {}", report.started_at.format("%Y-%m-%d %H:%M UTC")), None);
+    }
+
+    let artifacts: Vec<&Artifacts> = reports.iter().map(|r| &r.artifacts).collect();
+    // A session resumed with the same --record file shares it; take it once.
+    let mut records: Vec<&PathBuf> = artifacts.iter().filter_map(|a| a.record.as_ref()).collect();
+    records.sort();
+    records.dedup();
+    for (n, source) in records.iter().enumerate() {
+        let ext = if record::compresses(source) { "jsonl.zst" } else { "jsonl" };
+        let name = if n == 0 { format!("record.{}", ext) } else { format!("record-{}.{}", n + 1, ext) };
+        match copy(source, &dir.join(&name)) {
+            Ok(()) => add(&name, "raw event stream", Some(source)),
+            Err(e) => eprintln!("Warning: leaving out the recording: {:#}", e),
+        }
+    }
+
+    for file in artifacts.iter().flat_map(|a| &a.files) {
+        let name = format!("files/{}", file.path.display());
+        if file.path.is_absolute() || file.path.components().any(|c| c == std::path::Component::ParentDir) {
+            continue;
+        }
+        match copy(&file.target, &dir.join(&name)) {
+            Ok(()) => add(&name, "written from the answer (as it is now)", Some(&file.target)),
+            Err(e) => eprintln!("Warning: leaving out {}: {:#}", file.path.display(), e),
+        }
+    }
+
+    // One diff per repository, in the order the runs first changed them.
+    let mut diffs: Vec<(PathBuf, String)> = Vec::new();
+    for changes in artifacts.iter().flat_map(|a| &a.git) {
+        let range = format!("{}..{}", changes.before, changes.after);
+        match crate::git::git(&changes.root, &["diff", "--no-renames", &changes.before, &changes.after]) {
+            Ok(patch) => match diffs.iter_mut().find(|(root, _)| *root == changes.root) {
+                Some((_, diff)) => diff.push_str(&patch),
+                None => diffs.push((changes.root.clone(), patch)),
+            },
+            Err(e) => eprintln!(
+                "Warning: leaving out the changes {} in {}: {:#} (git may have pruned the snapshots)",
+                range,
+                changes.root.display(),
+                e
+            ),
+        }
+    }
+    for (i, (root, diff)) in diffs.iter().enumerate() {
+        // A run over several repositories gets a diff for each, named
+        // after the repository.
+        let name = match (diffs.len(), root.file_name()) {
+            (1, _) => "changes.diff".to_string(),
+            (_, Some(repo)) => format!("changes/{}-{}.diff", i + 1, repo.to_string_lossy()),
+            (_, None) => format!("changes/{}.diff", i + 1),
+        };
+        write(&dir.join(&name), diff.as_bytes())?;
+        add(&name, "what codex changed in the working tree", Some(root));
+    }
+
+    let manifest = Manifest {
+        session_id: session_id.to_string(),
+        agent: transcript.agent.clone(),
+        created_at: Utc::now(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        contents,
+    };
+    let raw = serde_json::to_string_pretty(&manifest).context("serialising the manifest")?;
+    write(&dir.join("manifest.json"), raw.as_bytes())?;
+    Ok(manifest)
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    fs::write(path, data).with_context(|| format!("writing {}", path.display()))
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    fs::copy(from, to).with_context(|| format!("copying {}", from.display()))?;
+    Ok(())
+}
+
+fn spawn_error(program: &str, e: io::Error) -> anyhow::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        anyhow!("archives need the `{}` command; install it", program)
+    } else {
+        anyhow::Error::new(e).context(format!("running {}", program))
+    }
+}
+
+fn check(program: &str, status: io::Result<std::process::ExitStatus>) -> Result<()> {
+    let status = status.map_err(|e| spawn_error(program, e))?;
+    if !status.success() {
+        bail!("{} failed ({})", program, status);
+    }
+    Ok(())
+}
+
+/// Tar `name` in `dir` into `out`, through zstd when `out` ends in `.zst`.
+fn pack(dir: &Path, name: &str, out: &Path) -> Result<()> {
+    let file = File::create(out).with_context(|| format!("creating {}", out.display()))?;
+    let mut tar = Command::new("tar");
+    tar.arg("-C").arg(dir).args(["-cf", "-", name]);
+    if !record::compresses(out) {
+        return check("tar", tar.stdout(file).status());
+    }
+    let mut zstd = Command::new("zstd")
+        .args(["-q", "-c"])
+        .stdin(Stdio::piped())
+        .stdout(file)
+        .spawn()
+        .map_err(|e| spawn_error("zstd", e))?;
+    let stdin = zstd.stdin.take().expect("stdin is piped");
+    let child = tar.stdout(stdin).spawn();
+    // The command holds the write end of zstd's stdin until it is dropped,
+    // and zstd only finishes once every copy is closed.
+    drop(tar);
+    let packed = check("tar", child.and_then(|mut tar| tar.wait()));
+    check("zstd", zstd.wait())?;
+    packed
+}
+
+/// `codex-clean archive <session> [-o PATH]`.
+pub fn archive(session: &str, out: Option<&Path>) -> Result<()> {
+    let file = sessions::lookup_session(session)?;
+    let reports: Vec<RunReport> = stats::load_recent_in(&stats::reports_dir()?, usize::MAX)?
+        .into_iter()
+        .filter(|r| r.session_id.as_deref() == Some(file.id.as_str()))
+        .collect();
+    let name = format!("session-{}", file.id);
+    let out = out.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from(format!("{}.tar.zst", name)));
+    let staging = Staging(std::env::temp_dir().join(format!("codex-clean-archive-{}-{}", std::process::id(), file.id)));
+    let _ = fs::remove_dir_all(&staging.0);
+    let manifest = stage(&staging.0.join(&name), &file.id, &file.path, &reports)?;
+    if let Err(e) = pack(&staging.0, &name, &out) {
+        let _ = fs::remove_file(&out);
+        return Err(e);
+    }
+    eprintln!("Archived session {} ({} parts) into {}", file.id, manifest.contents.len(), out.display());
+    Ok(())
+}
+
+/// `codex-clean unarchive <archive> [-C DIR]`: unpack an archive into `dir`
+/// and list what it holds.
+pub fn unarchive(archive: &Path, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let mut input = record::open(archive)?;
+    let mut tar = Command::new("tar")
+        .arg("-C")
+        .arg(dir)
+        .args(["-xf", "-"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error("tar", e))?;
+    let mut stdin = tar.stdin.take().expect("stdin is piped");
+    let copied = io::copy(&mut input, &mut stdin).with_context(|| format!("reading {}", archive.display()));
+    drop(stdin);
+    check("tar", tar.wait())?;
+    copied?;
+
+    let top = fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.join("manifest.json").is_file())
+        .max_by_key(|p| fs::metadata(p.join("manifest.json")).and_then(|m| m.modified()).ok())
+        .ok_or_else(|| anyhow!("{} isn't a codex-clean archive (no manifest.json)", archive.display()))?;
+    let raw = fs::read_to_string(top.join("manifest.json")).context("reading manifest.json")?;
+    let manifest: Manifest = serde_json::from_str(&raw).context("reading manifest.json")?;
+    print!("{}", describe(&manifest, &top));
+    Ok(())
+}
+
+/// What `unarchive` prints: the session, then each part's path and what
+/// it is.
+fn describe(manifest: &Manifest, top: &Path) -> String {
+    let mut out = format!(
+        "Session {}{}, archived {}\n",
+        manifest.session_id,
+        manifest.agent.as_ref().map(|a| format!(" ({})", a)).unwrap_or_default(),
+        manifest.created_at.format("%Y-%m-%d %H:%M UTC")
+    );
+    let width = manifest.contents.iter().map(|e| e.path.len()).max().unwrap_or(0);
+    for entry in &manifest.contents {
+        out.push_str(&format!("  {:<width$}  {}\n", entry.path, entry.what, width = width));
+    }
+    out.push_str(&format!("Unpacked into {}\n", top.display()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_session_is_staged_with_its_reports_and_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let rollout = dir.path().join("rollout.jsonl");
+        fs::write(
+            &rollout,
+            concat!(
+                r#"{"type":"session_meta","payload":{"id":"s-1"}}"#, "\n",
+                r#"{"type":"event_msg","payload":{"type":"user_message","message":"Add notes"}}"#, "\n",
+                r#"{"type":"event_msg","payload":{"type":"agent_message","message":"Added."}}"#, "\n",
+            ),
+        )
+        .unwrap();
+        let recording = dir.path().join("run.jsonl");
+        fs::write(&recording, "{}\n").unwrap();
+        let written = dir.path().join("NOTES.md");
+        fs::write(&written, "notes\n").unwrap();
+        let artifacts = Artifacts {
+            record: Some(recording.clone()),
+            files: vec![
+                WrittenFile { path: "NOTES.md".into(), target: written },
+                WrittenFile { path: "gone.txt".into(), target: dir.path().join("gone.txt") },
+            ],
+            git: Vec::new(),
+        };
+        let reports = [
+            RunReport { session_id: Some("s-1".into()), artifacts, ..Default::default() },
+            RunReport { session_id: Some("s-1".into()), ..Default::default() },
+        ];
+
+        let staged = dir.path().join("session-s-1");
+        let manifest = stage(&staged, "s-1", &rollout, &reports).unwrap();
+        let paths: Vec<&str> = manifest.contents.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "transcript.md",
+                "transcript.jsonl",
+                "reports/run-1.json",
+                "reports/run-2.json",
+                "record.jsonl",
+                "files/NOTES.md"
+            ]
+        );
+        assert!(fs::read_to_string(staged.join("transcript.md")).unwrap().contains("> Add notes"));
+        assert_eq!(fs::read_to_string(staged.join("files/NOTES.md")).unwrap(), "notes\n");
+        let described = describe(&manifest, &staged);
+        assert!(described.starts_with("Session s-1, archived "), "{}", described);
+        assert!(described.contains("  record.jsonl        raw event stream\n"), "{}", described);
+    }
+
+    #[test]
+    fn a_compressed_archive_unpacks_to_what_was_staged() {
+        let dir = tempfile::tempdir().unwrap();
+        let rollout = dir.path().join("rollout.jsonl");
+        fs::write(&rollout, concat!(r#"{"type":"session_meta","payload":{"id":"s-1"}}"#, "\n")).unwrap();
+        let staging = dir.path().join("staging");
+        stage(&staging.join("session-s-1"), "s-1", &rollout, &[]).unwrap();
+
+        let out = dir.path().join("out.tar.zst");
+        pack(&staging, "session-s-1", &out).unwrap();
+        assert!(fs::metadata(&out).unwrap().len() > 0);
+        let unpacked = dir.path().join("unpacked");
+        unarchive(&out, &unpacked).unwrap();
+        assert_eq!(
+            fs::read(unpacked.join("session-s-1/transcript.jsonl")).unwrap(),
+            fs::read(staging.join("session-s-1/transcript.jsonl")).unwrap()
+        );
+    }
+}
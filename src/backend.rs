@@ -0,0 +1,526 @@
+//! Where the codex process runs.
+//!
+//! The runner builds codex's argv (`exec --json ...`) the same way for
+//! every backend and parses whatever comes back on stdout. A
+//! [`CodexBackend`] only decides how to turn that argv into a running
+//! [`CodexProcess`]: a local child, `ssh`, a container, a recorded stream
+//! played back from disk, or canned output for tests.
+
+use std::fmt::Debug;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+
+use crate::compat::{self, Compat};
+use crate::error::Error;
+
+/// Where the codex home is mounted inside a container.
+const CONTAINER_CODEX_HOME: &str = "/codex-home";
+
+/// How the runner wants the process set up.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpawnOptions {
+    /// The prompt will be written to stdin (otherwise stdin is closed).
+    pub stdin_prompt: bool,
+    /// Strip these variables from the process environment.
+    pub scrub_env: &'static [&'static str],
+}
+
+/// Something that can start codex.
+pub trait CodexBackend: Debug + Send + Sync {
+    /// Start `codex <argv...>`.
+    fn spawn(&self, argv: &[String], opts: SpawnOptions) -> Result<Box<dyn CodexProcess>>;
+
+    /// Whether codex reads the local `~/.codex/auth.json`. Seat rotation
+    /// only makes sense when it does.
+    fn uses_local_auth(&self) -> bool {
+        true
+    }
+
+    /// How to build argv for the codex this backend starts. Backends that
+    /// can't cheaply ask assume the current interface.
+    fn compat(&self) -> Compat {
+        Compat::default()
+    }
+}
+
+/// How a process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessExit {
+    /// `None` when killed by a signal.
+    pub code: Option<i32>,
+    pub success: bool,
+}
+
+impl From<std::process::ExitStatus> for ProcessExit {
+    fn from(status: std::process::ExitStatus) -> Self {
+        Self { code: status.code(), success: status.success() }
+    }
+}
+
+/// A started codex. Each stream is taken once; `kill` may be called from
+/// the timeout watchdog while the runner is still reading stdout.
+pub trait CodexProcess: Send {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>>;
+    fn take_stdout(&mut self) -> Box<dyn Read + Send>;
+    fn take_stderr(&mut self) -> Box<dyn Read + Send>;
+    fn kill(&mut self) -> io::Result<()>;
+    fn wait(&mut self) -> io::Result<ProcessExit>;
+    /// How the process exited, or `None` while it is still running.
+    fn try_wait(&mut self) -> io::Result<Option<ProcessExit>>;
+    /// Tear down anything a `kill` left behind.
+    fn cleanup(&mut self) {}
+}
+
+/// An OS child process: codex itself, `ssh`, or a container engine CLI.
+pub struct ChildProcess {
+    child: Child,
+    /// (engine, container name) to force-remove after a kill.
+    container: Option<(String, String)>,
+}
+
+impl ChildProcess {
+    pub fn spawn(mut cmd: Command, opts: SpawnOptions) -> Result<Self> {
+        for var in opts.scrub_env {
+            cmd.env_remove(var);
+        }
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.stdin(if opts.stdin_prompt { Stdio::piped() } else { Stdio::null() });
+        let child = cmd.spawn().map_err(Error::SpawnFailed)?;
+        Ok(Self { child, container: None })
+    }
+}
+
+impl CodexProcess for ChildProcess {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.child.stdin.take().map(|s| Box::new(s) as Box<dyn Write + Send>)
+    }
+
+    fn take_stdout(&mut self) -> Box<dyn Read + Send> {
+        Box::new(self.child.stdout.take().expect("stdout was piped"))
+    }
+
+    fn take_stderr(&mut self) -> Box<dyn Read + Send> {
+        Box::new(self.child.stderr.take().expect("stderr was piped"))
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+
+    fn wait(&mut self) -> io::Result<ProcessExit> {
+        self.child.wait().map(ProcessExit::from)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ProcessExit>> {
+        Ok(self.child.try_wait()?.map(ProcessExit::from))
+    }
+
+    /// Remove a container left behind when its engine CLI was killed (the
+    /// CLI's `--rm` only fires when the CLI itself sees the exit).
+    fn cleanup(&mut self) {
+        if let Some((engine, name)) = &self.container {
+            let _ = Command::new(engine)
+                .args(["rm", "-f", name])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+/// `codex` from the local PATH.
+#[derive(Debug, Clone, Default)]
+pub struct LocalBackend;
+
+impl CodexBackend for LocalBackend {
+    fn spawn(&self, argv: &[String], opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+        let mut cmd = Command::new("codex");
+        cmd.args(argv);
+        match ChildProcess::spawn(cmd, opts).map_err(Error::from) {
+            Ok(child) => Ok(Box::new(child)),
+            Err(Error::SpawnFailed(e)) if e.kind() == io::ErrorKind::NotFound => Err(Error::CodexNotFound.into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn compat(&self) -> Compat {
+        compat::local()
+    }
+}
+
+/// `codex` on another machine, via `ssh`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshBackend {
+    /// Anything `ssh` accepts as a destination (`user@host`, a config alias).
+    pub host: String,
+    /// Directory to `cd` into on the remote side before starting codex.
+    pub cwd: Option<String>,
+    /// Variables set for the remote codex.
+    pub env: Vec<(String, String)>,
+}
+
+impl SshBackend {
+    pub fn command(&self, argv: &[String]) -> Command {
+        let mut cmd = Command::new("ssh");
+        // No tty: a pty would merge codex's stderr into stdout.
+        cmd.arg("-T").arg(&self.host).arg("--");
+        cmd.arg(self.remote_command(argv));
+        cmd
+    }
+
+    /// The single shell string ssh hands to the remote login shell.
+    pub fn remote_command(&self, argv: &[String]) -> String {
+        let mut parts = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            // Leave a leading ~/ outside the quotes so the remote shell expands it.
+            let dir = match cwd.strip_prefix("~/") {
+                Some(rest) => format!("~/{}", shell_quote(rest)),
+                None => shell_quote(cwd),
+            };
+            parts.push(format!("cd {} &&", dir));
+        }
+        parts.push("exec".to_string());
+        if !self.env.is_empty() {
+            parts.push("env".to_string());
+            for (k, v) in &self.env {
+                parts.push(shell_quote(&format!("{}={}", k, v)));
+            }
+        }
+        parts.push("codex".to_string());
+        parts.extend(argv.iter().map(|a| shell_quote(a)));
+        parts.join(" ")
+    }
+}
+
+impl CodexBackend for SshBackend {
+    fn spawn(&self, argv: &[String], opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+        Ok(Box::new(ChildProcess::spawn(self.command(argv), opts)?))
+    }
+
+    /// A remote codex uses its own machine's login.
+    fn uses_local_auth(&self) -> bool {
+        false
+    }
+}
+
+/// `codex` inside a throwaway container. The codex home is mounted, so
+/// seat rotation still applies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerBackend {
+    /// `docker` or `podman`.
+    pub engine: String,
+    pub image: String,
+    /// Host directory mounted at the same path and used as the working
+    /// directory, so paths mean the same inside and out.
+    pub project_dir: PathBuf,
+    /// Host codex home (auth, config, sessions), mounted read-write.
+    pub codex_home: PathBuf,
+}
+
+impl ContainerBackend {
+    /// A container whose project directory is the current directory.
+    pub fn for_current_dir(engine: &str, image: &str, codex_home: &Path) -> Result<Self> {
+        Ok(Self {
+            engine: engine.to_string(),
+            image: image.to_string(),
+            project_dir: std::env::current_dir()?,
+            codex_home: codex_home.to_path_buf(),
+        })
+    }
+
+    pub fn command(&self, name: &str, argv: &[String]) -> Command {
+        let mut cmd = Command::new(&self.engine);
+        cmd.args(self.run_args(name));
+        cmd.arg("codex").args(argv);
+        cmd
+    }
+
+    /// `run ...` arguments up to (not including) the image's command.
+    pub fn run_args(&self, name: &str) -> Vec<String> {
+        let project = self.project_dir.display().to_string();
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            // Keep stdin open for prompts delivered that way; no tty so
+            // stdout stays pure JSON.
+            "-i".to_string(),
+            "--name".to_string(),
+            name.to_string(),
+            "-v".to_string(),
+            format!("{}:{}", project, project),
+            "-w".to_string(),
+            project,
+            "-v".to_string(),
+            format!("{}:{}", self.codex_home.display(), CONTAINER_CODEX_HOME),
+            "-e".to_string(),
+            format!("CODEX_HOME={}", CONTAINER_CODEX_HOME),
+        ];
+        if let Some(user) = host_user() {
+            // Files codex writes into the mounts stay owned by the caller.
+            args.push("--user".to_string());
+            args.push(user);
+        }
+        args.push(self.image.clone());
+        args
+    }
+}
+
+impl CodexBackend for ContainerBackend {
+    fn spawn(&self, argv: &[String], opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+        let name = next_container_name();
+        let mut process = ChildProcess::spawn(self.command(&name, argv), opts)?;
+        process.container = Some((self.engine.clone(), name));
+        Ok(Box::new(process))
+    }
+}
+
+/// Play back a saved `codex exec --json` stream instead of running codex,
+/// e.g. to re-render an old run or reproduce a parsing bug.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayBackend {
+    pub path: PathBuf,
+    /// Play only the last turn, using the recording's index to seek to it.
+    pub last_turn: bool,
+}
+
+impl CodexBackend for ReplayBackend {
+    fn spawn(&self, _argv: &[String], _opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+        let stream = if self.last_turn {
+            crate::record::open_last_turn(&self.path)?
+        } else {
+            crate::record::open(&self.path)?
+        };
+        Ok(Box::new(CannedProcess::new(stream, Vec::new(), 0)))
+    }
+
+    /// Nothing is sent anywhere, so there is no seat to pick.
+    fn uses_local_auth(&self) -> bool {
+        false
+    }
+}
+
+/// Fixed output and exit code, for exercising parsing and presentation
+/// without codex installed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockBackend {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+impl CodexBackend for MockBackend {
+    fn spawn(&self, _argv: &[String], _opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+        Ok(Box::new(CannedProcess::new(
+            Box::new(Cursor::new(self.stdout.clone().into_bytes())),
+            self.stderr.clone().into_bytes(),
+            self.exit_code,
+        )))
+    }
+
+    fn uses_local_auth(&self) -> bool {
+        false
+    }
+}
+
+/// A "process" whose output is already known.
+struct CannedProcess {
+    stdout: Option<Box<dyn Read + Send>>,
+    stderr: Vec<u8>,
+    exit_code: i32,
+}
+
+impl CannedProcess {
+    fn new(stdout: Box<dyn Read + Send>, stderr: Vec<u8>, exit_code: i32) -> Self {
+        Self { stdout: Some(stdout), stderr, exit_code }
+    }
+}
+
+impl CodexProcess for CannedProcess {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        Some(Box::new(io::sink()))
+    }
+
+    fn take_stdout(&mut self) -> Box<dyn Read + Send> {
+        self.stdout.take().expect("stdout taken once")
+    }
+
+    fn take_stderr(&mut self) -> Box<dyn Read + Send> {
+        Box::new(Cursor::new(std::mem::take(&mut self.stderr)))
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn wait(&mut self) -> io::Result<ProcessExit> {
+        Ok(ProcessExit { code: Some(self.exit_code), success: self.exit_code == 0 })
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ProcessExit>> {
+        self.wait().map(Some)
+    }
+}
+
+fn next_container_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "codex-clean-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(unix)]
+fn host_user() -> Option<String> {
+    // SAFETY: getuid/getgid have no preconditions and cannot fail.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    Some(format!("{}:{}", uid, gid))
+}
+
+#[cfg(not(unix))]
+fn host_user() -> Option<String> {
+    None
+}
+
+/// Quote `s` for a POSIX shell. Plain words are left alone so the remote
+/// command stays readable in `ps`.
+pub fn shell_quote(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+    if plain {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// Parse `KEY=VALUE` (for `--ssh-env`).
+pub fn parse_env_assignment(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() && !k.contains(char::is_whitespace) => {
+            Ok((k.to_string(), v.to_string()))
+        }
+        _ => bail!("expected KEY=VALUE, got '{}'", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args_of(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    fn read_all(mut r: Box<dyn Read + Send>) -> String {
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn shell_quote_leaves_words_and_quotes_the_rest() {
+        assert_eq!(shell_quote("--json"), "--json");
+        assert_eq!(shell_quote("fix the bug"), "'fix the bug'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn remote_command_sets_cwd_and_env() {
+        let target = SshBackend {
+            host: "dev@box".into(),
+            cwd: Some("~/src/my app".into()),
+            env: vec![("RUST_LOG".into(), "info".into())],
+        };
+        let argv: Vec<String> = ["exec", "--json", "say hi"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            target.remote_command(&argv),
+            "cd ~/'src/my app' && exec env RUST_LOG=info codex exec --json 'say hi'"
+        );
+
+        let bare = SshBackend { host: "box".into(), cwd: None, env: vec![] };
+        assert_eq!(bare.remote_command(&argv), "exec codex exec --json 'say hi'");
+    }
+
+    #[test]
+    fn ssh_command_disables_tty() {
+        let target = SshBackend { host: "box".into(), cwd: None, env: vec![] };
+        let cmd = target.command(&["exec".to_string()]);
+        assert_eq!(cmd.get_program(), "ssh");
+        assert_eq!(args_of(&cmd), vec!["-T", "box", "--", "exec codex exec"]);
+        assert!(!target.uses_local_auth());
+    }
+
+    #[test]
+    fn container_command_mounts_project_and_codex_home() {
+        let target = ContainerBackend {
+            engine: "podman".into(),
+            image: "ghcr.io/acme/codex:1".into(),
+            project_dir: PathBuf::from("/work/app"),
+            codex_home: PathBuf::from("/home/me/.codex"),
+        };
+        let cmd = target.command("c1", &["exec".to_string(), "--json".to_string()]);
+        assert_eq!(cmd.get_program(), "podman");
+        let joined = args_of(&cmd).join(" ");
+        assert!(joined.starts_with("run --rm -i --name c1 "), "{}", joined);
+        assert!(joined.contains("-v /work/app:/work/app -w /work/app"), "{}", joined);
+        assert!(joined.contains("-v /home/me/.codex:/codex-home -e CODEX_HOME=/codex-home"), "{}", joined);
+        assert!(joined.ends_with("ghcr.io/acme/codex:1 codex exec --json"), "{}", joined);
+        assert!(target.uses_local_auth());
+    }
+
+    #[test]
+    fn container_names_are_unique_per_launch() {
+        assert_ne!(next_container_name(), next_container_name());
+    }
+
+    #[test]
+    fn mock_backend_yields_canned_output() {
+        let mock = MockBackend { stdout: "line\n".into(), stderr: "warn".into(), exit_code: 3 };
+        let mut p = mock.spawn(&[], SpawnOptions::default()).unwrap();
+        assert_eq!(read_all(p.take_stdout()), "line\n");
+        assert_eq!(read_all(p.take_stderr()), "warn");
+        assert_eq!(p.wait().unwrap(), ProcessExit { code: Some(3), success: false });
+    }
+
+    #[test]
+    fn replay_backend_streams_recording() {
+        let replay = ReplayBackend { path: PathBuf::from("tests/fixtures/sample_session.jsonl"), last_turn: false };
+        let mut p = replay.spawn(&[], SpawnOptions::default()).unwrap();
+        assert!(read_all(p.take_stdout()).contains("thread.started"));
+        assert!(p.wait().unwrap().success);
+
+        let missing = ReplayBackend { path: PathBuf::from("tests/fixtures/nope.jsonl"), last_turn: false };
+        assert!(missing.spawn(&[], SpawnOptions::default()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_process_scrubs_env_and_closes_stdin() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("echo \"[$CODEX_CLEAN_BACKEND_TEST]\"; cat");
+        cmd.env("CODEX_CLEAN_BACKEND_TEST", "leak");
+        let opts = SpawnOptions { stdin_prompt: false, scrub_env: &["CODEX_CLEAN_BACKEND_TEST"] };
+        let mut p = ChildProcess::spawn(cmd, opts).unwrap();
+        assert!(p.take_stdin().is_none());
+        assert_eq!(read_all(p.take_stdout()), "[]\n");
+        assert!(p.wait().unwrap().success);
+    }
+
+    #[test]
+    fn parse_env_assignment_requires_key() {
+        assert_eq!(parse_env_assignment("A=b=c").unwrap(), ("A".into(), "b=c".into()));
+        assert_eq!(parse_env_assignment("A=").unwrap(), ("A".into(), "".into()));
+        assert!(parse_env_assignment("=x").is_err());
+        assert!(parse_env_assignment("novalue").is_err());
+    }
+}
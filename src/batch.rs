@@ -0,0 +1,327 @@
+//! `codex-clean batch`: run every prompt in a file, one after another.
+//!
+//! Each non-empty line of the file is one prompt (lines starting with `#`
+//! are comments), sent to a fresh session with the same codex arguments and
+//! options. Answers are printed in turn, or with `--out-dir` kept apart
+//! instead: each prompt gets a subdirectory holding
+//!
+//! - `prompt.txt`, the prompt as given;
+//! - `output.txt`, the answer as it would have been printed;
+//! - `report.json`, the run report (see [`crate::stats`]);
+//! - `events.jsonl`, codex's raw event stream, which `--replay` reads back;
+//! - `files/`, the files the answer marks out (see [`crate::write_files`]).
+//!
+//! Subdirectories are named `001`, `002`, ... after the prompt's place in
+//! the file or, with `--name-by slug`, after its first words. `batch.json`
+//! beside them records how the prompts were chosen and how each run ended.
+//!
+//! `--shuffle` runs the prompts in a random order and `--sample K` runs K
+//! of them picked at random. Both are driven by a seed, printed and kept in
+//! `batch.json`, that `--seed` takes to repeat the same order and subset.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::runner::{self, Mode, RunOptions, RunOutcome};
+use crate::write_files;
+
+/// How each prompt's subdirectory of `--out-dir` is named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameBy {
+    /// `001`, `002`, ...
+    #[default]
+    Index,
+    /// `fix-the-failing-parser-tests`, with `-2` added to repeats.
+    Slug,
+}
+
+/// The prompts in a batch file: its non-empty lines, less `#` comments.
+pub fn read_prompts(raw: &str) -> Vec<String> {
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// The first words of `prompt`, lowercased and joined by `-`.
+pub fn slug(prompt: &str) -> String {
+    let mut slug = String::new();
+    for c in prompt.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 40 {
+            break;
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// One directory name per chosen prompt (by its index in `prompts`), all
+/// different.
+pub fn dir_names(prompts: &[String], chosen: &[usize], name_by: NameBy) -> Vec<String> {
+    let width = prompts.len().to_string().len().max(3);
+    let mut names: Vec<String> = Vec::with_capacity(chosen.len());
+    for &i in chosen {
+        let prompt = &prompts[i];
+        let index = format!("{:0width$}", i + 1, width = width);
+        let base = match name_by {
+            NameBy::Index => index,
+            NameBy::Slug => match slug(prompt) {
+                slug if slug.is_empty() => index,
+                slug => slug,
+            },
+        };
+        let mut name = base.clone();
+        let mut n = 2;
+        while names.contains(&name) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Which prompts run, and in what order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// Run them in a random order (`--shuffle`).
+    pub shuffle: bool,
+    /// Run only this many, picked at random (`--sample`).
+    pub sample: Option<usize>,
+    /// Seed the choice (`--seed`); a fresh one is drawn when unset.
+    pub seed: Option<u64>,
+}
+
+impl Selection {
+    fn is_random(&self) -> bool {
+        self.shuffle || self.sample.is_some()
+    }
+
+    /// The indices of the prompts to run out of `count`, in order, and
+    /// the seed used to choose them (None when nothing was random).
+    pub fn choose(&self, count: usize) -> (Vec<usize>, Option<u64>) {
+        let mut chosen: Vec<usize> = (0..count).collect();
+        if !self.is_random() {
+            return (chosen, None);
+        }
+        let seed = self.seed.unwrap_or_else(fresh_seed);
+        let mut rng = SplitMix64(seed);
+        // Fisher-Yates; a sample is the head of the shuffled list.
+        for i in (1..count).rev() {
+            chosen.swap(i, rng.below(i as u64 + 1) as usize);
+        }
+        if let Some(k) = self.sample {
+            chosen.truncate(k);
+            if !self.shuffle {
+                chosen.sort_unstable();
+            }
+        }
+        (chosen, Some(seed))
+    }
+}
+
+fn fresh_seed() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    SplitMix64(nanos ^ u64::from(std::process::id())).next()
+}
+
+/// A small, well-mixed generator; the same seed gives the same order on
+/// every platform and release.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        // Multiply-shift rather than `%`, which favours small values.
+        ((u128::from(self.next()) * u128::from(n)) >> 64) as u64
+    }
+}
+
+/// `batch.json` in `--out-dir`: how the prompts were chosen and how each
+/// run ended.
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub started_at: DateTime<Utc>,
+    /// The prompts in the file.
+    pub total: usize,
+    pub shuffle: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// In the order they ran.
+    pub runs: Vec<BatchRun>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchRun {
+    /// The prompt's place in the file, from 1.
+    pub index: usize,
+    pub dir: String,
+    pub exit_code: i32,
+}
+
+/// Run the `selection` of `prompts`. Returns 0 when every run succeeded
+/// and 1 otherwise; one failing prompt doesn't stop the rest.
+pub fn run(
+    prompts: &[String],
+    selection: &Selection,
+    codex_args: &[String],
+    opts: &RunOptions,
+    out_dir: Option<&Path>,
+    name_by: NameBy,
+) -> Result<i32> {
+    let started_at = Utc::now();
+    let (chosen, seed) = selection.choose(prompts.len());
+    if let Some(seed) = seed {
+        eprintln!(
+            "Running {} of {} prompt(s){} with seed {} (--seed {} repeats this)",
+            chosen.len(),
+            prompts.len(),
+            if selection.shuffle { ", shuffled," } else { "" },
+            seed,
+            seed
+        );
+    }
+    let names = dir_names(prompts, &chosen, name_by);
+    let mut runs = Vec::with_capacity(chosen.len());
+    for (n, (&i, name)) in chosen.iter().zip(&names).enumerate() {
+        let prompt = &prompts[i];
+        eprintln!("[{}/{}] {}", n + 1, chosen.len(), prompt);
+        let code = match out_dir {
+            Some(dir) => run_into(&dir.join(name), prompt, codex_args, opts),
+            None => runner::run_codex_with_options(codex_args, prompt, Mode::Exec, opts),
+        };
+        let exit_code = code.unwrap_or_else(|e| {
+            eprintln!("Error: {:#}", e);
+            1
+        });
+        runs.push(BatchRun { index: i + 1, dir: name.clone(), exit_code });
+    }
+    let failed = runs.iter().filter(|r| r.exit_code != 0).count();
+    match out_dir {
+        Some(dir) => {
+            let report = BatchReport {
+                started_at,
+                total: prompts.len(),
+                shuffle: selection.shuffle,
+                sample: selection.sample,
+                seed,
+                runs,
+            };
+            let path = dir.join("batch.json");
+            let raw = serde_json::to_string_pretty(&report).context("serialising batch.json")?;
+            fs::write(&path, raw).with_context(|| format!("writing {}", path.display()))?;
+            eprintln!(
+                "{} of {} prompt(s) succeeded; results are in {}",
+                chosen.len() - failed,
+                chosen.len(),
+                dir.display()
+            );
+        }
+        None => eprintln!("{} of {} prompt(s) succeeded", chosen.len() - failed, chosen.len()),
+    }
+    Ok(if failed == 0 { 0 } else { 1 })
+}
+
+/// Run one prompt, keeping everything it produced in `dir`.
+fn run_into(dir: &Path, prompt: &str, codex_args: &[String], opts: &RunOptions) -> Result<i32> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    fs::write(dir.join("prompt.txt"), format!("{}\n", prompt)).context("writing prompt.txt")?;
+    let opts = RunOptions {
+        record: Some(dir.join("events.jsonl")),
+        report_path: Some(dir.join("report.json")),
+        ..opts.clone()
+    };
+    let mut saved = Ok(());
+    let code = runner::run_codex_presenting(codex_args, prompt, Mode::Exec, &opts, |o: &RunOutcome| {
+        saved = save_outcome(dir, o, &opts);
+    })?;
+    saved?;
+    Ok(code)
+}
+
+/// `output.txt` and `files/` for a finished run.
+fn save_outcome(dir: &Path, outcome: &RunOutcome, opts: &RunOptions) -> Result<()> {
+    let Some(attempt) = &outcome.attempt else {
+        return Ok(());
+    };
+    let rendered = attempt.output.render_with(&opts.render);
+    if !rendered.stderr.is_empty() {
+        eprint!("{}", rendered.stderr);
+    }
+    fs::write(dir.join("output.txt"), &rendered.stdout).context("writing output.txt")?;
+    let blocks = write_files::parse(&attempt.output.aggregated_message());
+    if !blocks.is_empty() {
+        write_files::write(&dir.join("files"), &blocks, true).context("writing the answer's files")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_are_lines_less_comments() {
+        assert_eq!(
+            read_prompts("# sweep\nSummarise main.rs\n\n  Explain lib.rs  \n#Not this\n"),
+            ["Summarise main.rs", "Explain lib.rs"]
+        );
+    }
+
+    #[test]
+    fn directories_are_named_by_index_or_slug() {
+        let prompts: Vec<String> =
+            ["Fix the parser!", "fix the parser", "???"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(dir_names(&prompts, &[0, 1, 2], NameBy::Index), ["001", "002", "003"]);
+        assert_eq!(dir_names(&prompts, &[0, 1, 2], NameBy::Slug), ["fix-the-parser", "fix-the-parser-2", "003"]);
+        // Named for their place in the file, whatever order they run in.
+        assert_eq!(dir_names(&prompts, &[2, 0], NameBy::Index), ["003", "001"]);
+        assert_eq!(slug(&"word ".repeat(20)).len(), 39);
+    }
+
+    #[test]
+    fn a_seed_repeats_the_order_and_the_sample() {
+        assert_eq!(Selection::default().choose(4), (vec![0, 1, 2, 3], None));
+
+        let shuffled = Selection { shuffle: true, seed: Some(7), ..Default::default() };
+        let (order, seed) = shuffled.choose(10);
+        assert_eq!(seed, Some(7));
+        assert_eq!(shuffled.choose(10).0, order);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+        assert_ne!(order, sorted);
+
+        let sample = Selection { sample: Some(3), seed: Some(7), ..Default::default() };
+        let (picked, _) = sample.choose(10);
+        assert_eq!(picked.len(), 3);
+        assert!(picked.windows(2).all(|w| w[0] < w[1]));
+        let mut head = order[..3].to_vec();
+        head.sort_unstable();
+        assert_eq!(picked, head);
+        assert_eq!(Selection { sample: Some(30), ..sample }.choose(10).0.len(), 10);
+
+        assert!(Selection { shuffle: true, ..Default::default() }.choose(3).1.is_some());
+    }
+}
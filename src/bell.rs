@@ -0,0 +1,107 @@
+//! `--bell`: get noticed when a run needs you.
+//!
+//! Long agentic runs are left in a background tab, and it is easy to miss
+//! both the end of the run and a question codex-clean is waiting on (the
+//! dangerous-mode prompt, `--confirm`). With `--bell` codex-clean rings the
+//! terminal bell at those moments, or runs `bell_command` from the config
+//! instead (via `sh -c`, without waiting for it) with `CODEX_CLEAN_BELL` set
+//! to `done`, `failed` or `input`, and `CODEX_CLEAN_EXIT_CODE` once the run
+//! is over.
+
+use std::io::{self, Write};
+use std::process::Child;
+use std::sync::Mutex;
+
+use crate::runner::hook_command;
+
+/// Set by [`arm`]: `Some(command)` rings (with `command` if it is set).
+static ARMED: Mutex<Option<Option<String>>> = Mutex::new(None);
+
+/// Why the bell rang.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Done,
+    Failed,
+    /// codex-clean is asking a question.
+    Input,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Done => "done",
+            Self::Failed => "failed",
+            Self::Input => "input",
+        }
+    }
+}
+
+/// Ring from now on, with `command` instead of the bell if given.
+pub fn arm(command: Option<String>) {
+    *ARMED.lock().unwrap_or_else(|e| e.into_inner()) = Some(command);
+}
+
+/// Ring because a question is about to be asked, if armed.
+pub fn ask() {
+    let armed = ARMED.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(command) = armed {
+        ring(command.as_deref(), Reason::Input, None);
+    }
+}
+
+/// Ring for the end of the run, once, if armed.
+pub fn finish(exit_code: i32) {
+    let armed = ARMED.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(command) = armed {
+        let reason = if exit_code == 0 { Reason::Done } else { Reason::Failed };
+        ring(command.as_deref(), reason, Some(exit_code));
+    }
+}
+
+fn ring(command: Option<&str>, reason: Reason, exit_code: Option<i32>) {
+    let Some(script) = command else {
+        write_bell();
+        return;
+    };
+    if let Err(e) = spawn_command(script, reason, exit_code) {
+        eprintln!("Warning: failed to run bell_command: {}", e);
+        write_bell();
+    }
+}
+
+fn spawn_command(script: &str, reason: Reason, exit_code: Option<i32>) -> io::Result<Child> {
+    let mut cmd = hook_command(script);
+    cmd.env("CODEX_CLEAN_BELL", reason.as_str());
+    if let Some(code) = exit_code {
+        cmd.env("CODEX_CLEAN_EXIT_CODE", code.to_string());
+    }
+    cmd.spawn()
+}
+
+/// BEL on the controlling terminal, so it rings with stderr redirected.
+fn write_bell() {
+    #[cfg(unix)]
+    if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(b"\x07");
+        let _ = tty.flush();
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = std::io::stderr().write_all(b"\x07");
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bell_command_learns_why_it_rang() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("rang");
+        let script = format!("echo \"$CODEX_CLEAN_BELL $CODEX_CLEAN_EXIT_CODE\" >> '{}'", out.display());
+        spawn_command(&script, Reason::Input, None).unwrap().wait().unwrap();
+        spawn_command(&script, Reason::Failed, Some(3)).unwrap().wait().unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "input \nfailed 3\n");
+    }
+}
@@ -0,0 +1,119 @@
+//! `--command-log <dir>`: the full output of every shell command codex ran.
+//!
+//! codex-clean's summary only keeps each command's exit code, which is
+//! rarely enough to tell why a test run inside an agent session failed.
+//! With a command log, each command's output (stdout and stderr together,
+//! as codex passes it on) is written to its own file in `dir`, and
+//! `dir/summary.txt` lists them in order with their exit codes.
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::events::CommandRun;
+
+/// The index written next to the logs.
+pub const SUMMARY_FILE: &str = "summary.txt";
+
+/// Write one log per command and the summary into `dir`, creating it if
+/// needed. Returns the summary's path.
+pub fn write(dir: &Path, commands: &[CommandRun]) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let mut summary = String::new();
+    for (i, run) in commands.iter().enumerate() {
+        let name = log_name(i + 1, &run.command);
+        let path = dir.join(&name);
+        fs::write(&path, render_log(run)).with_context(|| format!("writing {}", path.display()))?;
+        let exit = run.exit_code.map_or_else(|| "-".to_string(), |c| c.to_string());
+        let _ = writeln!(summary, "{:>4}  {}  {}", exit, name, run.command);
+    }
+    if commands.is_empty() {
+        summary.push_str("codex ran no commands.\n");
+    }
+    let path = dir.join(SUMMARY_FILE);
+    fs::write(&path, summary).with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+fn render_log(run: &CommandRun) -> String {
+    let mut log = format!("$ {}\n", run.command);
+    match run.exit_code {
+        Some(code) => {
+            let _ = writeln!(log, "(exit code {})", code);
+        }
+        None => log.push_str("(no exit code; the command may have been interrupted)\n"),
+    }
+    log.push('\n');
+    match run.output.as_deref() {
+        Some(output) => {
+            log.push_str(output);
+            if !output.is_empty() && !output.ends_with('\n') {
+                log.push('\n');
+            }
+        }
+        None => log.push_str("(codex didn't report this command's output)\n"),
+    }
+    log
+}
+
+/// `003-cargo-test.log`: numbered so the files sort in the order codex ran
+/// them, and named after the command so they can be told apart at a glance.
+fn log_name(n: usize, command: &str) -> String {
+    // codex wraps most commands as `bash -lc '<script>'`; name the script.
+    let command = command
+        .strip_prefix("bash -lc ")
+        .map(|script| script.trim_matches(|c| c == '\'' || c == '"'))
+        .unwrap_or(command);
+    let mut slug = String::new();
+    for c in command.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 40 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        format!("{:03}.log", n)
+    } else {
+        format!("{:03}-{}.log", n, slug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_each_command_and_lists_them_in_the_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let commands = vec![
+            CommandRun { command: "bash -lc 'cargo test'".into(), exit_code: Some(101), output: Some("FAILED".into()) },
+            CommandRun { command: "ls".into(), exit_code: None, output: None },
+        ];
+        let summary = write(dir.path(), &commands).unwrap();
+        assert_eq!(
+            fs::read_to_string(&summary).unwrap(),
+            " 101  001-cargo-test.log  bash -lc 'cargo test'\n   -  002-ls.log  ls\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("001-cargo-test.log")).unwrap(),
+            "$ bash -lc 'cargo test'\n(exit code 101)\n\nFAILED\n"
+        );
+        let ls = fs::read_to_string(dir.path().join("002-ls.log")).unwrap();
+        assert!(ls.contains("may have been interrupted") && ls.contains("didn't report"));
+    }
+
+    #[test]
+    fn log_names_are_short_and_safe() {
+        assert_eq!(log_name(7, "bash -lc \"rg -n 'fn main' src/\""), "007-rg-n-fn-main-src.log");
+        assert_eq!(log_name(1, "../../etc"), "001-etc.log");
+        assert_eq!(log_name(2, "!!"), "002.log");
+        assert!(log_name(3, &"x".repeat(200)).len() <= "003-.log".len() + 40);
+    }
+}
@@ -0,0 +1,282 @@
+//! Which codex release is installed, and how to talk to it.
+//!
+//! codex's `exec` interface has changed between releases: the JSON flag was
+//! `--experimental-json` before it became `--json`, and `exec resume` is
+//! newer than `exec` itself. A mismatch doesn't fail loudly, it just
+//! produces a stream codex-clean can't read. So the first run checks
+//! `codex --version` against [`COMPAT_TABLE`], adapts the argv where it
+//! can, and says clearly when the installed codex is outside the range
+//! codex-clean supports.
+//!
+//! The answer is cached in `~/.config/codex-clean/codex-version.json`,
+//! keyed by the codex binary's path and modification time, so it is only
+//! asked again after codex is upgraded.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::seat::{atomic_write, config_dir};
+
+/// A `major.minor.patch` codex release.
+pub type Version = (u64, u64, u64);
+
+/// The oldest codex release codex-clean supports: the first with `exec
+/// resume`. Older ones are driven by the table's first row, as well as
+/// they can be.
+pub const MIN_SUPPORTED: Version = (0, 46, 0);
+
+/// Releases from here on may have changed `exec` in ways codex-clean
+/// doesn't know about yet.
+pub const FIRST_UNTESTED: Version = (1, 0, 0);
+
+/// How long `codex --version` may take before we stop waiting.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One row of the compatibility table: how to drive releases from `since`
+/// up to the next row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Row {
+    since: Version,
+    json_flag: &'static str,
+    exec_resume: bool,
+    output_last_message: bool,
+}
+
+/// Oldest first.
+const COMPAT_TABLE: &[Row] = &[
+    Row { since: (0, 0, 0), json_flag: "--experimental-json", exec_resume: false, output_last_message: false },
+    Row { since: (0, 46, 0), json_flag: "--experimental-json", exec_resume: true, output_last_message: false },
+    Row { since: (0, 50, 0), json_flag: "--json", exec_resume: true, output_last_message: false },
+    Row { since: (0, 124, 0), json_flag: "--json", exec_resume: true, output_last_message: true },
+];
+
+/// How to build argv for the installed codex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compat {
+    /// `None` when the version couldn't be determined; the current
+    /// interface is assumed.
+    pub version: Option<Version>,
+    /// The flag that turns on JSONL output.
+    pub json_flag: &'static str,
+    /// Whether `codex exec resume` exists.
+    pub exec_resume: bool,
+    /// Whether `codex exec` takes `--output-last-message`.
+    pub output_last_message: bool,
+}
+
+impl Default for Compat {
+    fn default() -> Self {
+        let latest = COMPAT_TABLE[COMPAT_TABLE.len() - 1];
+        Self {
+            version: None,
+            json_flag: latest.json_flag,
+            exec_resume: latest.exec_resume,
+            output_last_message: latest.output_last_message,
+        }
+    }
+}
+
+impl Compat {
+    pub fn for_version(version: Version) -> Self {
+        let row = COMPAT_TABLE
+            .iter()
+            .rev()
+            .find(|row| row.since <= version)
+            .expect("the table starts at 0.0.0");
+        Self {
+            version: Some(version),
+            json_flag: row.json_flag,
+            exec_resume: row.exec_resume,
+            output_last_message: row.output_last_message,
+        }
+    }
+
+    /// What to tell the user about this version, if anything.
+    pub fn warning(&self) -> Option<String> {
+        let version = self.version?;
+        if version < MIN_SUPPORTED {
+            Some(format!(
+                "Warning: codex {} is older than the oldest supported release ({}); upgrade codex if output is missing or runs fail.",
+                display(version),
+                display(MIN_SUPPORTED)
+            ))
+        } else if version >= FIRST_UNTESTED {
+            Some(format!(
+                "Warning: codex {} is newer than codex-clean has been tested with; if output comes back empty, run `codex-clean self-update`.",
+                display(version)
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn display((major, minor, patch): Version) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+/// The version in `codex --version` output (`codex-cli 0.125.0`).
+pub fn parse_version_output(output: &str) -> Option<Version> {
+    let line = output.lines().find(|l| !l.trim().is_empty())?;
+    line.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches('v');
+        let core = word.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let version = (parts.next()??, parts.next()??, parts.next().unwrap_or(Some(0))?);
+        parts.next().is_none().then_some(version)
+    })
+}
+
+/// The local codex's [`Compat`], detected once per process. Any warning is
+/// printed the first time.
+pub fn local() -> Compat {
+    static LOCAL: OnceLock<Compat> = OnceLock::new();
+    LOCAL
+        .get_or_init(|| {
+            let compat = detect_local().map(Compat::for_version).unwrap_or_default();
+            if let Some(warning) = compat.warning() {
+                eprintln!("{}", warning);
+            }
+            compat
+        })
+        .clone()
+}
+
+/// `~/.config/codex-clean/codex-version.json`.
+pub fn cache_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("codex-version.json"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CacheEntry {
+    path: PathBuf,
+    modified: u64,
+    version: Option<String>,
+}
+
+fn detect_local() -> Option<Version> {
+    let codex = find_on_path("codex")?;
+    let modified = fs::metadata(&codex)
+        .and_then(|m| m.modified())
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let cache = cache_path().ok();
+    if let Some(entry) = cache.as_deref().and_then(load_cache) {
+        if entry.path == codex && entry.modified == modified {
+            return entry.version.as_deref().and_then(parse_version_output);
+        }
+    }
+    let version = ask_version(&codex);
+    if let Some(cache) = cache {
+        let entry = CacheEntry { path: codex, modified, version: version.map(display) };
+        if let Ok(raw) = serde_json::to_string_pretty(&entry) {
+            let _ = atomic_write(&cache, raw.as_bytes());
+        }
+    }
+    version
+}
+
+fn load_cache(path: &Path) -> Option<CacheEntry> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// The first `program` on `PATH`, as `Command::new` would find it.
+fn find_on_path(program: &str) -> Option<PathBuf> {
+    let name = format!("{}{}", program, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Run `<codex> --version`, giving up after [`VERSION_TIMEOUT`].
+fn ask_version(codex: &Path) -> Option<Version> {
+    run_with_timeout(Command::new(codex).arg("--version"), VERSION_TIMEOUT)
+        .ok()
+        .flatten()
+        .as_deref()
+        .and_then(parse_version_output)
+}
+
+/// stdout of `cmd` if it exits successfully within `limit`.
+fn run_with_timeout(cmd: &mut Command, limit: Duration) -> Result<Option<String>> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("spawning codex --version")?;
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= limit {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let mut out = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut out);
+    }
+    Ok(status.success().then_some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_output_is_parsed() {
+        assert_eq!(parse_version_output("codex-cli 0.125.0\n"), Some((0, 125, 0)));
+        assert_eq!(parse_version_output("codex-cli v0.46.0-alpha.3"), Some((0, 46, 0)));
+        assert_eq!(parse_version_output("codex 1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_version_output("{\"type\":\"thread.started\"}"), None);
+        assert_eq!(parse_version_output(""), None);
+    }
+
+    #[test]
+    fn table_picks_flags_by_version() {
+        let old = Compat::for_version((0, 40, 0));
+        assert_eq!((old.json_flag, old.exec_resume), ("--experimental-json", false));
+        let mid = Compat::for_version((0, 46, 2));
+        assert_eq!((mid.json_flag, mid.exec_resume), ("--experimental-json", true));
+        let json = Compat::for_version((0, 100, 0));
+        assert_eq!((json.json_flag, json.exec_resume, json.output_last_message), ("--json", true, false));
+        let current = Compat::for_version((0, 125, 0));
+        assert_eq!((current.json_flag, current.exec_resume, current.output_last_message), ("--json", true, true));
+        assert_eq!(
+            (Compat::default().json_flag, Compat::default().exec_resume),
+            (current.json_flag, current.exec_resume)
+        );
+    }
+
+    #[test]
+    fn warns_outside_supported_range() {
+        assert!(Compat::for_version((0, 125, 0)).warning().is_none());
+        assert!(Compat::default().warning().is_none());
+        assert!(Compat::for_version((0, 46, 0)).warning().is_none());
+        assert!(Compat::for_version((0, 40, 0)).warning().unwrap().contains("older"));
+        assert!(Compat::for_version((1, 0, 0)).warning().unwrap().contains("newer"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_version_command_times_out() {
+        let out = run_with_timeout(Command::new("sleep").arg("5"), Duration::from_millis(100)).unwrap();
+        assert_eq!(out, None);
+        let out = run_with_timeout(Command::new("echo").arg("codex-cli 0.125.0"), VERSION_TIMEOUT).unwrap();
+        assert_eq!(out.as_deref(), Some("codex-cli 0.125.0\n"));
+    }
+}
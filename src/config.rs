@@ -0,0 +1,1157 @@
+//! User configuration (`~/.config/codex-clean/config.toml`).
+//!
+//! Separate from `seats.toml`: seats are machine state managed by
+//! `codex-clean seat ...`, while this file is hand-written preferences.
+//! Its absence is normal and means "all defaults".
+//!
+//! Settings resolve in layers, lowest first: `CODEX_CLEAN_*` environment
+//! variables, the project's `.codex-clean.toml`, top-level keys in this
+//! file, the selected `[profile.<name>]`, then command-line flags. The
+//! environment sits at the bottom so a container image can ship defaults
+//! that a mounted config or an explicit flag still overrides.
+//!
+//! The project file is the nearest `.codex-clean.toml` in the working
+//! directory or above it, usually checked in so a team shares defaults. It
+//! has the same keys as this file; where both set one, this file wins.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::lang;
+use crate::safety::ConfirmPolicy;
+use crate::seat::config_dir;
+use crate::trust::{self, Trust};
+
+/// Shape of `config.toml`, for flagging keys serde would silently ignore
+/// (typos like `timeout = 60` instead of `timeout_seconds`). Keep in step
+/// with the structs below.
+enum Schema {
+    Leaf,
+    Table(&'static [(&'static str, Schema)]),
+    /// A table whose keys are user-chosen names (e.g. profile names).
+    Map(&'static Schema),
+}
+
+const HOOKS_SCHEMA: Schema = Schema::Table(&[("pre", Schema::Leaf), ("post", Schema::Leaf)]);
+
+const PROFILE_SCHEMA: Schema = Schema::Table(&[
+    ("args", Schema::Leaf),
+    ("timeout_seconds", Schema::Leaf),
+    ("prompt_prefix", Schema::Leaf),
+    ("prompt_suffix", Schema::Leaf),
+    ("lang", Schema::Leaf),
+    ("preprocessors", Schema::Leaf),
+    ("token_budget", Schema::Leaf),
+    ("hooks", HOOKS_SCHEMA),
+]);
+
+const PRICING_SCHEMA: Schema =
+    Schema::Table(&[("input", Schema::Leaf), ("cached_input", Schema::Leaf), ("output", Schema::Leaf)]);
+
+const QUOTA_SCHEMA: Schema = Schema::Table(&[
+    ("daily_tokens", Schema::Leaf),
+    ("weekly_tokens", Schema::Leaf),
+    ("daily_cost", Schema::Leaf),
+    ("weekly_cost", Schema::Leaf),
+    ("action", Schema::Leaf),
+]);
+
+const COUNTER_SCHEMA: Schema =
+    Schema::Table(&[("command", Schema::Leaf), ("message", Schema::Leaf), ("file", Schema::Leaf)]);
+
+const ROOT_SCHEMA: Schema = Schema::Table(&[
+    ("model", Schema::Leaf),
+    ("timeout_seconds", Schema::Leaf),
+    ("prompt_prefix", Schema::Leaf),
+    ("prompt_suffix", Schema::Leaf),
+    ("lang", Schema::Leaf),
+    ("preprocessors", Schema::Leaf),
+    ("token_budget", Schema::Leaf),
+    ("sandbox", Schema::Leaf),
+    ("context", Schema::Leaf),
+    ("confirm_dangerous", Schema::Leaf),
+    ("preamble_patterns", Schema::Leaf),
+    ("signoff_patterns", Schema::Leaf),
+    ("bell_command", Schema::Leaf),
+    ("session_max_age_days", Schema::Leaf),
+    ("profile", Schema::Map(&PROFILE_SCHEMA)),
+    ("pricing", Schema::Map(&PRICING_SCHEMA)),
+    ("context_windows", Schema::Map(&Schema::Leaf)),
+    ("quota", QUOTA_SCHEMA),
+    ("counters", Schema::Map(&COUNTER_SCHEMA)),
+]);
+
+/// Select a profile, as `--profile` does.
+pub const ENV_PROFILE: &str = "CODEX_CLEAN_PROFILE";
+/// Default model, passed to codex as `-m`.
+pub const ENV_MODEL: &str = "CODEX_CLEAN_MODEL";
+/// Default timeout in seconds, as `--timeout` does.
+pub const ENV_TIMEOUT: &str = "CODEX_CLEAN_TIMEOUT";
+/// Save the event stream to this file, as `--record` does.
+pub const ENV_RECORD: &str = "CODEX_CLEAN_RECORD";
+/// Print the answer as plain text, as `--plain` does.
+pub const ENV_PLAIN: &str = "CODEX_CLEAN_PLAIN";
+
+/// File name of the per-project config.
+pub const PROJECT_CONFIG_FILE: &str = ".codex-clean.toml";
+
+/// Files bigger than this named by `context` are skipped, not sent.
+const CONTEXT_FILE_LIMIT: u64 = 256 * 1024;
+
+/// `~/.config/codex-clean/config.toml`.
+pub fn config_toml_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// The nearest `.codex-clean.toml` in `start` or one of its parents.
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Config {
+    /// Model passed to codex as `-m` unless the profile or command line
+    /// already picks one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Default for `--timeout`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+    /// Text placed before every prompt (e.g. house coding standards).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_prefix: Option<String>,
+    /// Text placed after every prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_suffix: Option<String>,
+    /// Default for `--lang` (see [`crate::lang`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// The prompt preprocessors to run, in order (see
+    /// [`crate::preprocess`]); empty for the default ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preprocessors: Vec<String>,
+    /// Refuse prompts estimated at more tokens than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_budget: Option<usize>,
+    /// Passed to codex as `--sandbox` unless the profile or command line
+    /// already picks a sandbox.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    /// Globs of files whose contents are sent ahead of each prompt. In
+    /// `.codex-clean.toml` they are relative to that file's directory,
+    /// here to the working directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+    /// What to do before running codex in one of its dangerous modes
+    /// (see [`crate::safety`]): `ask` (the default), `allow`, or `refuse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_dangerous: Option<ConfirmPolicy>,
+    /// Extra regexes for the openers `--strip-preamble` drops (see
+    /// [`crate::preamble`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preamble_patterns: Vec<String>,
+    /// Extra regexes for the sign-offs `--strip-preamble` drops.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signoff_patterns: Vec<String>,
+    /// Run instead of ringing the terminal bell for `--bell` (see
+    /// [`crate::bell`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bell_command: Option<String>,
+    /// Warn before resuming a session idle for longer than this many days
+    /// (see [`crate::expiry`]); `0` turns the warning off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_max_age_days: Option<u64>,
+    /// Named presets, selected with `--profile <name>`.
+    #[serde(default, rename = "profile", skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Per-model token prices for `codex-clean usage`'s cost estimates.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: BTreeMap<String, Pricing>,
+    /// Context window sizes in tokens by model name or prefix, for models
+    /// codex doesn't report one for (see [`crate::window`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub context_windows: BTreeMap<String, u64>,
+    /// Limits on how much may be used per day or week.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+    /// Named counts kept in each run's report (see [`crate::metrics`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub counters: BTreeMap<String, CounterRule>,
+}
+
+/// What a model costs, in US dollars per million tokens.
+///
+/// ```toml
+/// [pricing."gpt-5.5"]
+/// input = 1.25
+/// cached_input = 0.125
+/// output = 10.0
+/// ```
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    /// Cached input tokens; charged at `input` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    pub output: f64,
+}
+
+/// What a counter in the run report counts: the shell commands, agent
+/// messages or changed files' paths this regex matches. Exactly one is set.
+///
+/// ```toml
+/// [counters.cargo_commands]
+/// command = "^cargo "
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CounterRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+/// Usage limits, checked against the usage ledger before each run.
+///
+/// ```toml
+/// [quota]
+/// daily_tokens = 2_000_000
+/// weekly_cost = 25.0
+/// action = "warn"
+/// ```
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct Quota {
+    /// Input plus output tokens per local day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_tokens: Option<u64>,
+    /// Input plus output tokens per week, Monday to Sunday.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_tokens: Option<u64>,
+    /// Estimated US dollars per day (models with `[pricing]` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_cost: Option<f64>,
+    #[serde(default)]
+    pub action: QuotaAction,
+}
+
+/// What to do once a quota is used up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaAction {
+    /// Don't start codex until the window resets.
+    #[default]
+    Refuse,
+    /// Print a warning and run anyway.
+    Warn,
+}
+
+/// A named preset of codex arguments and codex-clean options.
+///
+/// ```toml
+/// [profile.review]
+/// args = ["-m", "gpt-5.5", "--sandbox", "read-only"]
+/// timeout_seconds = 900
+///
+/// [profile.review.hooks]
+/// pre = "git stash list | head -1"
+/// post = "notify-send \"codex done: $CODEX_CLEAN_EXIT_CODE\""
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Profile {
+    /// Arguments passed to codex ahead of any given on the command line.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Kill codex after this many seconds (overridden by `--timeout`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+    /// Replaces the top-level `prompt_prefix` while this profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_prefix: Option<String>,
+    /// Replaces the top-level `prompt_suffix` while this profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_suffix: Option<String>,
+    /// Replaces the top-level `lang` while this profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Replaces the top-level `preprocessors` while this profile is active.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preprocessors: Vec<String>,
+    /// Replaces the top-level `token_budget` while this profile is active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_budget: Option<usize>,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+}
+
+/// Shell commands run around codex (via `sh -c`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Hooks {
+    /// Runs before codex starts; a non-zero exit aborts the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre: Option<String>,
+    /// Runs after codex exits, with `CODEX_CLEAN_EXIT_CODE` and (when known)
+    /// `CODEX_CLEAN_SESSION_ID` set. Failures are reported but don't change
+    /// the exit code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_none() && self.post.is_none()
+    }
+}
+
+/// Boilerplate wrapped around each prompt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptWrap {
+    /// The `context` files, already read and framed.
+    pub context: Option<String>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    /// Language code from `--lang`; its instruction goes last.
+    pub lang: Option<String>,
+}
+
+impl PromptWrap {
+    /// `context`, `prefix`, `prompt`, `suffix` and the `lang`
+    /// instruction, separated by blank lines. Empty prompts (review, or
+    /// resume without a new message) are left empty.
+    pub fn apply(&self, prompt: &str) -> String {
+        if prompt.is_empty() {
+            return String::new();
+        }
+        let lang = self.lang.as_deref().map(lang::instruction);
+        let parts: Vec<&str> = [
+            self.context.as_deref(),
+            self.prefix.as_deref(),
+            Some(prompt),
+            self.suffix.as_deref(),
+            lang.as_deref(),
+        ]
+            .into_iter()
+            .flatten()
+            .map(str::trim_end)
+            .filter(|p| !p.is_empty())
+            .collect();
+        parts.join("\n\n")
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Option<Self>> {
+        let path = config_toml_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let cfg: Config = toml::from_str(&raw)
+            .with_context(|| format!("parsing {}", path.display()))?;
+        Ok(Some(cfg))
+    }
+
+    /// Parse a `.codex-clean.toml` read from `path`, anchoring its
+    /// `context` globs to the directory it lives in.
+    pub fn parse_project(raw: &str, path: &Path) -> Result<Self> {
+        let mut cfg: Config =
+            toml::from_str(raw).with_context(|| format!("parsing {}", path.display()))?;
+        let root = path.parent().unwrap_or(Path::new("."));
+        let root = glob::Pattern::escape(&root.to_string_lossy());
+        for pattern in &mut cfg.context {
+            if !Path::new(pattern.as_str()).is_absolute() {
+                *pattern = format!("{}/{}", root, pattern);
+            }
+        }
+        Ok(cfg)
+    }
+
+    /// The user config layered over the project config for the working
+    /// directory, if there is one and it is trusted (see [`crate::trust`]).
+    pub fn load_layered() -> Result<Self> {
+        let user = Self::load()?.unwrap_or_default();
+        let cwd = std::env::current_dir().context("finding the working directory")?;
+        let Some(path) = find_project_config(&cwd) else {
+            return Ok(user);
+        };
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if trust::check_project_config(&path, &raw)? != Trust::Trusted {
+            return Ok(user);
+        }
+        Ok(user.layered_over(Self::parse_project(&raw, &path)?))
+    }
+
+    /// `self`'s settings, falling back to `base`'s for anything `self`
+    /// leaves unset. Profiles are taken whole: a profile defined in both
+    /// comes from `self`.
+    pub fn layered_over(self, base: Config) -> Config {
+        let mut profiles = base.profiles;
+        profiles.extend(self.profiles);
+        let mut pricing = base.pricing;
+        pricing.extend(self.pricing);
+        let mut counters = base.counters;
+        counters.extend(self.counters);
+        let mut context_windows = base.context_windows;
+        context_windows.extend(self.context_windows);
+        Config {
+            model: self.model.or(base.model),
+            timeout_seconds: self.timeout_seconds.or(base.timeout_seconds),
+            prompt_prefix: self.prompt_prefix.or(base.prompt_prefix),
+            prompt_suffix: self.prompt_suffix.or(base.prompt_suffix),
+            lang: self.lang.or(base.lang),
+            preprocessors: if self.preprocessors.is_empty() { base.preprocessors } else { self.preprocessors },
+            token_budget: self.token_budget.or(base.token_budget),
+            sandbox: self.sandbox.or(base.sandbox),
+            context: if self.context.is_empty() { base.context } else { self.context },
+            confirm_dangerous: self.confirm_dangerous.or(base.confirm_dangerous),
+            preamble_patterns: if self.preamble_patterns.is_empty() {
+                base.preamble_patterns
+            } else {
+                self.preamble_patterns
+            },
+            signoff_patterns: if self.signoff_patterns.is_empty() { base.signoff_patterns } else { self.signoff_patterns },
+            bell_command: self.bell_command.or(base.bell_command),
+            session_max_age_days: self.session_max_age_days.or(base.session_max_age_days),
+            profiles,
+            pricing,
+            context_windows,
+            quota: self.quota.or(base.quota),
+            counters,
+        }
+    }
+
+    /// Look up a profile by name, listing the defined ones when it's missing.
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            if self.profiles.is_empty() {
+                anyhow!("profile '{}' not found: no [profile.<name>] sections are defined", name)
+            } else {
+                let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                anyhow!("profile '{}' not found (available: {})", name, names.join(", "))
+            }
+        })
+    }
+}
+
+/// The config file as a raw TOML document (an empty table if it doesn't
+/// exist), for `config get|set|doctor`.
+pub fn load_document() -> Result<toml::Value> {
+    let path = config_toml_path()?;
+    if !path.exists() {
+        return Ok(toml::Value::Table(toml::map::Map::new()));
+    }
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    parse_document(&raw).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn parse_document(raw: &str) -> Result<toml::Value> {
+    Ok(toml::Value::Table(toml::from_str(raw)?))
+}
+
+/// Everything wrong with `doc`: keys codex-clean doesn't know about, then
+/// the first type error (serde stops at one). Empty means valid.
+pub fn validate_document(doc: &toml::Value) -> Vec<String> {
+    let mut problems: Vec<String> = unknown_keys(doc)
+        .into_iter()
+        .map(|k| format!("unknown key '{}'", k))
+        .collect();
+    match doc.clone().try_into::<Config>() {
+        Ok(cfg) => {
+            if let Err(e) = crate::metrics::Counters::compile(&cfg.counters) {
+                problems.push(format!("{:#}", e));
+            }
+        }
+        Err(e) => {
+            // toml puts the key path on a second line; keep it to one.
+            let msg = e.to_string();
+            let lines: Vec<&str> = msg.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+            problems.push(lines.join(" "));
+        }
+    }
+    problems
+}
+
+/// Dotted paths of keys not in the config schema.
+pub fn unknown_keys(doc: &toml::Value) -> Vec<String> {
+    let mut out = Vec::new();
+    collect_unknown(doc, &ROOT_SCHEMA, "", &mut out);
+    out
+}
+
+fn collect_unknown(value: &toml::Value, schema: &Schema, prefix: &str, out: &mut Vec<String>) {
+    let Some(table) = value.as_table() else {
+        return;
+    };
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match schema {
+        Schema::Leaf => {}
+        Schema::Map(inner) => {
+            for (key, v) in table {
+                collect_unknown(v, inner, &join(key), out);
+            }
+        }
+        Schema::Table(fields) => {
+            for (key, v) in table {
+                match fields.iter().find(|(name, _)| name == key) {
+                    Some((_, inner)) => collect_unknown(v, inner, &join(key), out),
+                    None => out.push(join(key)),
+                }
+            }
+        }
+    }
+}
+
+/// Look up a dotted key (`profile.review.args`) in a document.
+pub fn get_path<'a>(doc: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.').try_fold(doc, |v, part| v.as_table()?.get(part))
+}
+
+/// Set a dotted key, creating intermediate tables as needed.
+pub fn set_path(doc: &mut toml::Value, key: &str, value: toml::Value) -> Result<()> {
+    let parts: Vec<&str> = key.split('.').collect();
+    if parts.iter().any(|p| p.is_empty()) {
+        return Err(anyhow!("invalid key '{}'", key));
+    }
+    let (last, parents) = parts.split_last().expect("split always yields one part");
+    let mut cur = doc;
+    for (i, part) in parents.iter().enumerate() {
+        let table = cur
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("'{}' is not a table", parts[..i].join(".")))?;
+        cur = table
+            .entry(part.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::map::Map::new()));
+    }
+    let table = cur
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("'{}' is not a table", parents.join(".")))?;
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// Interpret a command-line value as a TOML literal (`900`, `true`,
+/// `["-m", "o3"]`), falling back to a plain string.
+pub fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Settings read from `CODEX_CLEAN_*` environment variables.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvSettings {
+    pub profile: Option<String>,
+    pub model: Option<String>,
+    pub timeout_seconds: Option<u64>,
+    pub record: Option<PathBuf>,
+    pub plain: bool,
+}
+
+impl EnvSettings {
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Empty values count as unset, so `CODEX_CLEAN_MODEL=` disables an
+    /// inherited default.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let get = |name: &str| lookup(name).filter(|v| !v.trim().is_empty());
+        let timeout_seconds = get(ENV_TIMEOUT)
+            .map(|v| {
+                v.trim().parse::<u64>().map_err(|_| {
+                    anyhow!("{}={:?} is not a whole number of seconds", ENV_TIMEOUT, v)
+                })
+            })
+            .transpose()?;
+        let plain = match get(ENV_PLAIN).map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("0" | "false" | "no" | "off") => false,
+            Some("1" | "true" | "yes" | "on") => true,
+            Some(v) => bail!("{}={:?} is not a yes or no (1/0, true/false, yes/no, on/off)", ENV_PLAIN, v),
+        };
+        Ok(Self {
+            profile: get(ENV_PROFILE),
+            model: get(ENV_MODEL),
+            timeout_seconds,
+            record: get(ENV_RECORD).map(PathBuf::from),
+            plain,
+        })
+    }
+}
+
+/// Command-line values that take part in layering.
+#[derive(Debug, Clone, Default)]
+pub struct FlagSettings<'a> {
+    pub profile: Option<&'a str>,
+    pub timeout_seconds: Option<u64>,
+    /// `--no-wrap`: skip the configured prompt prefix and suffix.
+    pub no_wrap: bool,
+    /// `--lang`, already normalized.
+    pub lang: Option<&'a str>,
+    /// Codex arguments given on the command line.
+    pub args: &'a [String],
+    /// `--workspace` roots, in order.
+    pub workspaces: &'a [PathBuf],
+}
+
+/// The effective settings for one run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resolved {
+    /// Full codex argument list: injected model, profile args, then
+    /// command-line args.
+    pub args: Vec<String>,
+    pub timeout_seconds: Option<u64>,
+    pub prompt_wrap: PromptWrap,
+    /// The configured prompt preprocessors; empty for the default ones.
+    pub preprocessors: Vec<String>,
+    pub token_budget: Option<usize>,
+    pub hooks: Hooks,
+    /// Name of the selected profile, if any.
+    pub profile: Option<String>,
+    pub confirm_dangerous: ConfirmPolicy,
+    pub preamble_patterns: Vec<String>,
+    pub signoff_patterns: Vec<String>,
+}
+
+/// Combine every layer into the settings for one run.
+pub fn resolve(cfg: &Config, env: &EnvSettings, flags: &FlagSettings) -> Result<Resolved> {
+    let profile_name = flags.profile.or(env.profile.as_deref());
+    let profile = match profile_name {
+        Some(name) => cfg.profile(name)?.clone(),
+        None => Profile::default(),
+    };
+
+    let mut args = Vec::new();
+    // codex rejects a repeated -m, so only inject a default model when
+    // nothing more specific already chose one.
+    if let Some(model) = cfg.model.as_ref().or(env.model.as_ref()) {
+        if !has_model_flag(&profile.args) && !has_model_flag(flags.args) {
+            args.push("-m".to_string());
+            args.push(model.clone());
+        }
+    }
+    if let Some(sandbox) = &cfg.sandbox {
+        if !has_sandbox_flag(&profile.args) && !has_sandbox_flag(flags.args) {
+            args.push("--sandbox".to_string());
+            args.push(sandbox.clone());
+        }
+    }
+    args.extend(profile.args);
+    args.extend_from_slice(flags.args);
+
+    // The first workspace is where codex works unless the args already say;
+    // the rest are added to its writable directories.
+    let workspaces = flags.workspaces.iter().map(|dir| workspace_root(dir)).collect::<Result<Vec<_>>>()?;
+    let cd = cd_flag_dir(&args).map(|dir| fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir)));
+    for (i, root) in workspaces.iter().enumerate() {
+        if cd.as_ref() == Some(root) {
+            continue;
+        }
+        let flag = if i == 0 && cd.is_none() { "-C" } else { "--add-dir" };
+        args.extend([flag.to_string(), root.display().to_string()]);
+    }
+
+    // An explicit --lang still applies under --no-wrap; configured ones don't.
+    let prompt_wrap = if flags.no_wrap {
+        PromptWrap { lang: flags.lang.map(String::from), ..PromptWrap::default() }
+    } else {
+        let lang = match flags.lang {
+            Some(code) => Some(code.to_string()),
+            None => profile.lang.as_deref().or(cfg.lang.as_deref()).map(lang::normalize).transpose()?,
+        };
+        PromptWrap {
+            context: read_context(&cfg.context, &workspaces)?,
+            prefix: profile.prompt_prefix.or_else(|| cfg.prompt_prefix.clone()),
+            suffix: profile.prompt_suffix.or_else(|| cfg.prompt_suffix.clone()),
+            lang,
+        }
+    };
+
+    Ok(Resolved {
+        args,
+        timeout_seconds: flags
+            .timeout_seconds
+            .or(profile.timeout_seconds)
+            .or(cfg.timeout_seconds)
+            .or(env.timeout_seconds),
+        prompt_wrap,
+        preprocessors: if profile.preprocessors.is_empty() { cfg.preprocessors.clone() } else { profile.preprocessors },
+        token_budget: profile.token_budget.or(cfg.token_budget),
+        hooks: profile.hooks,
+        profile: profile_name.map(String::from),
+        confirm_dangerous: cfg.confirm_dangerous.unwrap_or_default(),
+        preamble_patterns: cfg.preamble_patterns.clone(),
+        signoff_patterns: cfg.signoff_patterns.clone(),
+    })
+}
+
+/// `-m X`, `-mX`, `--model X`, or `--model=X`.
+pub fn has_model_flag(args: &[String]) -> bool {
+    args.iter().any(|a| a.starts_with("-m") || a == "--model" || a.starts_with("--model="))
+}
+
+/// `args` with every model flag [`has_model_flag`] finds taken out.
+pub fn without_model_flag(args: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-m" || arg == "--model" {
+            iter.next();
+        } else if !arg.starts_with("-m") && !arg.starts_with("--model=") {
+            out.push(arg.clone());
+        }
+    }
+    out
+}
+
+/// The directory `-C DIR`, `-CDIR`, `--cd DIR` or `--cd=DIR` names.
+fn cd_flag_dir(args: &[String]) -> Option<&str> {
+    let mut dir = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-C" || arg == "--cd" {
+            dir = iter.next().map(String::as_str);
+        } else if let Some(d) = arg.strip_prefix("--cd=").or_else(|| arg.strip_prefix("-C")) {
+            dir = Some(d);
+        }
+    }
+    dir
+}
+
+/// A `--workspace` directory as an absolute path.
+fn workspace_root(dir: &Path) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        bail!("--workspace {} is not a directory", dir.display());
+    }
+    fs::canonicalize(dir).with_context(|| format!("resolving --workspace {}", dir.display()))
+}
+
+/// `-s`, `--sandbox`, or one of codex's flags that implies a sandbox.
+fn has_sandbox_flag(args: &[String]) -> bool {
+    args.iter().any(|a| {
+        a.starts_with("-s")
+            || a == "--sandbox"
+            || a.starts_with("--sandbox=")
+            || a == "--full-auto"
+            || a == "--dangerously-bypass-approvals-and-sandbox"
+    })
+}
+
+/// Read the files matched by `patterns` and frame them for the prompt.
+/// Unmatched patterns, binary files and oversized files are skipped with
+/// a warning rather than failing the run. With `--workspace` roots, a
+/// relative pattern is matched in each root instead of the current
+/// directory.
+fn read_context(patterns: &[String], roots: &[PathBuf]) -> Result<Option<String>> {
+    let mut files = BTreeSet::new();
+    for pattern in patterns {
+        let anchored: Vec<String> = if roots.is_empty() || Path::new(pattern.as_str()).is_absolute() {
+            vec![pattern.clone()]
+        } else {
+            roots
+                .iter()
+                .map(|root| format!("{}/{}", glob::Pattern::escape(&root.to_string_lossy()), pattern))
+                .collect()
+        };
+        let before = files.len();
+        for glob_pattern in &anchored {
+            let paths = glob::glob(glob_pattern).map_err(|e| anyhow!("invalid context glob '{}': {}", pattern, e))?;
+            files.extend(paths.filter_map(|p| p.ok()).filter(|p| p.is_file()));
+        }
+        if files.len() == before {
+            eprintln!("Warning: context glob '{}' matched no files.", pattern);
+        }
+    }
+    if files.is_empty() {
+        return Ok(None);
+    }
+    // Paths are shown from the current directory, or else from the
+    // directory holding their workspace, so "frontend/src/app.ts" says
+    // which root a file is in.
+    let bases: Vec<PathBuf> = std::env::current_dir()
+        .ok()
+        .into_iter()
+        .chain(roots.iter().filter_map(|root| root.parent().map(Path::to_path_buf)))
+        .collect();
+    let mut out = String::from("Project files included for context:\n");
+    for path in files {
+        let shown = bases
+            .iter()
+            .find_map(|base| path.strip_prefix(base).ok())
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > CONTEXT_FILE_LIMIT {
+            eprintln!("Warning: skipping context file {} ({} KiB is over the 256 KiB limit).", shown, size / 1024);
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(&path) else {
+            eprintln!("Warning: skipping context file {} (unreadable or not UTF-8).", shown);
+            continue;
+        };
+        out.push_str(&format!("\n<file path=\"{}\">\n{}\n</file>\n", shown, text.trim_end()));
+    }
+    Ok(Some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles() {
+        let raw = r#"
+[profile.review]
+args = ["-m", "gpt-5.5", "--sandbox", "read-only"]
+timeout_seconds = 900
+
+[profile.review.hooks]
+post = "echo done"
+
+[profile.fast]
+args = ["-c", "model_reasoning_effort=low"]
+"#;
+        let cfg: Config = toml::from_str(raw).unwrap();
+        assert_eq!(cfg.profiles.len(), 2);
+        let review = cfg.profile("review").unwrap();
+        assert_eq!(review.args, vec!["-m", "gpt-5.5", "--sandbox", "read-only"]);
+        assert_eq!(review.timeout_seconds, Some(900));
+        assert_eq!(review.hooks.post.as_deref(), Some("echo done"));
+        assert_eq!(review.hooks.pre, None);
+        assert_eq!(cfg.profile("fast").unwrap().timeout_seconds, None);
+    }
+
+    #[test]
+    fn missing_profile_lists_available() {
+        let raw = "[profile.a]\n[profile.b]\n";
+        let cfg: Config = toml::from_str(raw).unwrap();
+        let err = cfg.profile("c").unwrap_err().to_string();
+        assert!(err.contains("available: a, b"), "{}", err);
+    }
+
+    #[test]
+    fn empty_file_is_default() {
+        let cfg: Config = toml::from_str("").unwrap();
+        assert_eq!(cfg, Config::default());
+        assert!(cfg.profile("x").unwrap_err().to_string().contains("no [profile.<name>]"));
+    }
+
+    #[test]
+    fn unknown_keys_are_flagged_with_paths() {
+        let doc = parse_document(
+            "colour = true\n[profile.a]\ntimeout = 5\n[profile.a.hooks]\npre = 'x'\nafter = 'y'\n",
+        )
+        .unwrap();
+        assert_eq!(unknown_keys(&doc), vec!["colour", "profile.a.hooks.after", "profile.a.timeout"]);
+    }
+
+    #[test]
+    fn validate_document_reports_type_errors() {
+        let doc = parse_document("[profile.a]\ntimeout_seconds = 'soon'\n").unwrap();
+        let problems = validate_document(&doc);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("timeout_seconds"), "{:?}", problems);
+        assert!(validate_document(&parse_document("[profile.a]\nargs = []\n").unwrap()).is_empty());
+        let counters = parse_document("[counters.ok]\ncommand = '^cargo'\n[counters.bad]\nfile = '('\n").unwrap();
+        let problems = validate_document(&counters);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("counter 'bad'"), "{:?}", problems);
+    }
+
+    #[test]
+    fn set_and_get_dotted_paths() {
+        let mut doc = parse_document("").unwrap();
+        set_path(&mut doc, "profile.fast.timeout_seconds", parse_value("60")).unwrap();
+        set_path(&mut doc, "profile.fast.args", parse_value(r#"["-m", "o3"]"#)).unwrap();
+        set_path(&mut doc, "profile.fast.hooks.post", parse_value("echo done")).unwrap();
+        assert_eq!(get_path(&doc, "profile.fast.timeout_seconds"), Some(&toml::Value::Integer(60)));
+        assert_eq!(
+            get_path(&doc, "profile.fast.hooks.post"),
+            Some(&toml::Value::String("echo done".into()))
+        );
+        assert!(get_path(&doc, "profile.slow").is_none());
+        assert!(validate_document(&doc).is_empty());
+
+        assert!(set_path(&mut doc, "profile.fast.args.x", parse_value("1")).is_err());
+        assert!(set_path(&mut doc, "profile..x", parse_value("1")).is_err());
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn env_settings_parse_and_ignore_empty() {
+        let env = EnvSettings::from_lookup(|name| match name {
+            ENV_MODEL => Some("o3".into()),
+            ENV_TIMEOUT => Some(" 90 ".into()),
+            ENV_PROFILE => Some("".into()),
+            ENV_RECORD => Some("run.jsonl".into()),
+            ENV_PLAIN => Some("Yes".into()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(
+            env,
+            EnvSettings {
+                profile: None,
+                model: Some("o3".into()),
+                timeout_seconds: Some(90),
+                record: Some(PathBuf::from("run.jsonl")),
+                plain: true,
+            }
+        );
+        assert!(!EnvSettings::from_lookup(|name| (name == ENV_PLAIN).then(|| "0".into())).unwrap().plain);
+
+        let err = EnvSettings::from_lookup(|name| (name == ENV_TIMEOUT).then(|| "soon".into()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("CODEX_CLEAN_TIMEOUT"), "{}", err);
+        let err = EnvSettings::from_lookup(|name| (name == ENV_PLAIN).then(|| "maybe".into())).unwrap_err();
+        assert!(err.to_string().contains("CODEX_CLEAN_PLAIN=\"maybe\""), "{}", err);
+    }
+
+    #[test]
+    fn resolve_layers_env_file_profile_flags() {
+        let cfg: Config = toml::from_str(
+            "timeout_seconds = 300\n[profile.p]\nargs = ['--sandbox', 'read-only']\ntimeout_seconds = 60\n",
+        )
+        .unwrap();
+        let env = EnvSettings { profile: Some("p".into()), model: Some("o3".into()), timeout_seconds: Some(5), ..Default::default() };
+        let cli = strings(&["-C", "/tmp"]);
+
+        // Env selects the profile and supplies the model; the profile beats the file.
+        let r = resolve(&cfg, &env, &FlagSettings { args: &cli, ..Default::default() }).unwrap();
+        assert_eq!(r.args, strings(&["-m", "o3", "--sandbox", "read-only", "-C", "/tmp"]));
+        assert_eq!(r.timeout_seconds, Some(60));
+        assert_eq!(r.profile.as_deref(), Some("p"));
+
+        // Flags beat everything.
+        let flags = FlagSettings { timeout_seconds: Some(1), args: &cli, ..Default::default() };
+        let no_env_profile = EnvSettings { profile: None, ..env.clone() };
+        let r = resolve(&cfg, &no_env_profile, &flags).unwrap();
+        assert_eq!(r.timeout_seconds, Some(1));
+        assert_eq!(r.args, strings(&["-m", "o3", "-C", "/tmp"]));
+
+        // The file's timeout beats the environment's.
+        let r = resolve(&cfg, &no_env_profile, &FlagSettings::default()).unwrap();
+        assert_eq!(r.timeout_seconds, Some(300));
+        let r = resolve(&Config::default(), &no_env_profile, &FlagSettings::default()).unwrap();
+        assert_eq!(r.timeout_seconds, Some(5));
+    }
+
+    #[test]
+    fn resolve_skips_default_model_when_one_is_given() {
+        let cfg = Config { model: Some("file-model".into()), ..Default::default() };
+        let env = EnvSettings { model: Some("env-model".into()), ..Default::default() };
+        for given in [&["-m", "x"][..], &["--model", "x"], &["--model=x"], &["-mx"]] {
+            let cli = strings(given);
+            let r = resolve(&cfg, &env, &FlagSettings { args: &cli, ..Default::default() }).unwrap();
+            assert_eq!(r.args, cli);
+        }
+        let r = resolve(&cfg, &env, &FlagSettings::default()).unwrap();
+        assert_eq!(r.args, strings(&["-m", "file-model"]));
+    }
+
+    #[test]
+    fn prompt_wrap_surrounds_prompt_and_skips_empty() {
+        let wrap = PromptWrap {
+            prefix: Some("Follow house style.\n".into()),
+            suffix: Some("Answer in English.".into()),
+            ..Default::default()
+        };
+        assert_eq!(wrap.apply("fix it"), "Follow house style.\n\nfix it\n\nAnswer in English.");
+        assert_eq!(wrap.apply(""), "");
+        let prefix_only = PromptWrap { prefix: Some("P".into()), ..Default::default() };
+        assert_eq!(prefix_only.apply("x"), "P\n\nx");
+        let with_context = PromptWrap { context: Some("C\n".into()), ..prefix_only };
+        assert_eq!(with_context.apply("x"), "C\n\nP\n\nx");
+        assert_eq!(PromptWrap::default().apply("x"), "x");
+    }
+
+    #[test]
+    fn resolve_prompt_wrap_profile_overrides_file_and_no_wrap_clears() {
+        let cfg: Config = toml::from_str(
+            "prompt_prefix = 'file prefix'\nprompt_suffix = 'file suffix'\n[profile.p]\nprompt_prefix = 'profile prefix'\n",
+        )
+        .unwrap();
+        let env = EnvSettings::default();
+        let r = resolve(&cfg, &env, &FlagSettings { profile: Some("p"), ..Default::default() }).unwrap();
+        assert_eq!(r.prompt_wrap.prefix.as_deref(), Some("profile prefix"));
+        assert_eq!(r.prompt_wrap.suffix.as_deref(), Some("file suffix"));
+
+        let r = resolve(&cfg, &env, &FlagSettings { no_wrap: true, ..Default::default() }).unwrap();
+        assert_eq!(r.prompt_wrap, PromptWrap::default());
+    }
+
+    #[test]
+    fn profile_preprocessors_replace_the_top_level_ones() {
+        let cfg: Config = toml::from_str(
+            "preprocessors = ['redact', 'wrap']\ntoken_budget = 1000\n[profile.raw]\npreprocessors = ['template']\n[profile.big]\ntoken_budget = 50000\n",
+        )
+        .unwrap();
+        let env = EnvSettings::default();
+        let r = resolve(&cfg, &env, &FlagSettings::default()).unwrap();
+        assert_eq!((r.preprocessors, r.token_budget), (strings(&["redact", "wrap"]), Some(1000)));
+        let r = resolve(&cfg, &env, &FlagSettings { profile: Some("raw"), ..Default::default() }).unwrap();
+        assert_eq!((r.preprocessors, r.token_budget), (strings(&["template"]), Some(1000)));
+        let r = resolve(&cfg, &env, &FlagSettings { profile: Some("big"), ..Default::default() }).unwrap();
+        assert_eq!((r.preprocessors, r.token_budget), (strings(&["redact", "wrap"]), Some(50000)));
+    }
+
+    #[test]
+    fn lang_comes_from_flag_profile_or_file() {
+        let cfg: Config = toml::from_str("lang = 'DE'\n[profile.docs]\nlang = 'ja'\n[profile.bad]\nlang = 'german'\n").unwrap();
+        let env = EnvSettings::default();
+        let lang = |flags: FlagSettings| resolve(&cfg, &env, &flags).map(|r| r.prompt_wrap.lang);
+        assert_eq!(lang(FlagSettings::default()).unwrap().as_deref(), Some("de"));
+        assert_eq!(lang(FlagSettings { profile: Some("docs"), ..Default::default() }).unwrap().as_deref(), Some("ja"));
+        assert_eq!(lang(FlagSettings { lang: Some("fr"), ..Default::default() }).unwrap().as_deref(), Some("fr"));
+        assert_eq!(lang(FlagSettings { no_wrap: true, ..Default::default() }).unwrap(), None);
+        assert_eq!(
+            lang(FlagSettings { no_wrap: true, lang: Some("fr"), ..Default::default() }).unwrap().as_deref(),
+            Some("fr")
+        );
+        assert!(lang(FlagSettings { profile: Some("bad"), ..Default::default() }).is_err());
+
+        let wrap = PromptWrap { suffix: Some("S".into()), lang: Some("de".into()), ..Default::default() };
+        assert!(wrap.apply("x").starts_with("x\n\nS\n\nRespond in German (de),"));
+    }
+
+    #[test]
+    fn project_config_is_found_above_cwd_and_anchors_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("crates/core/src");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&path, "sandbox = 'read-only'\ncontext = ['docs/*.md', '/etc/hostname']\n").unwrap();
+        assert_eq!(find_project_config(&nested), Some(path.clone()));
+
+        let cfg = Config::parse_project(&fs::read_to_string(&path).unwrap(), &path).unwrap();
+        let root = glob::Pattern::escape(&dir.path().to_string_lossy());
+        assert_eq!(cfg.context, vec![format!("{}/docs/*.md", root), "/etc/hostname".to_string()]);
+    }
+
+    #[test]
+    fn user_config_layers_over_project() {
+        let project: Config = toml::from_str(
+            "model = 'team'\nsandbox = 'read-only'\nprompt_prefix = 'team rules'\ncontext = ['a']\n\
+             [profile.ci]\ntimeout_seconds = 60\n[profile.shared]\ntimeout_seconds = 1\n\
+             [quota]\ndaily_tokens = 100\naction = 'warn'\n",
+        )
+        .unwrap();
+        let user: Config =
+            toml::from_str("model = 'mine'\n[profile.shared]\nargs = ['-m', 'o3']\n").unwrap();
+        let cfg = user.layered_over(project);
+        assert_eq!(cfg.model.as_deref(), Some("mine"));
+        assert_eq!(cfg.sandbox.as_deref(), Some("read-only"));
+        assert_eq!(cfg.prompt_prefix.as_deref(), Some("team rules"));
+        assert_eq!(cfg.context, vec!["a"]);
+        assert_eq!(cfg.profile("ci").unwrap().timeout_seconds, Some(60));
+        assert_eq!(cfg.profile("shared").unwrap().timeout_seconds, None);
+        let quota = cfg.quota.unwrap();
+        assert_eq!((quota.daily_tokens, quota.action), (Some(100), QuotaAction::Warn));
+    }
+
+    #[test]
+    fn resolve_injects_sandbox_unless_one_is_given() {
+        let cfg = Config { sandbox: Some("read-only".into()), ..Default::default() };
+        let env = EnvSettings::default();
+        let r = resolve(&cfg, &env, &FlagSettings::default()).unwrap();
+        assert_eq!(r.args, strings(&["--sandbox", "read-only"]));
+        for given in [&["-s", "danger-full-access"][..], &["--sandbox=workspace-write"], &["--full-auto"]] {
+            let cli = strings(given);
+            let r = resolve(&cfg, &env, &FlagSettings { args: &cli, ..Default::default() }).unwrap();
+            assert_eq!(r.args, cli);
+        }
+    }
+
+    #[test]
+    fn context_files_are_framed_ahead_of_the_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.md"), "Alpha\n").unwrap();
+        fs::write(dir.path().join("b.md"), "Beta").unwrap();
+        fs::write(dir.path().join("blob.md"), [0xff, 0xfe, 0x00]).unwrap();
+        let root = glob::Pattern::escape(&dir.path().to_string_lossy());
+        let cfg = Config { context: vec![format!("{}/*.md", root), format!("{}/none/*", root)], ..Default::default() };
+
+        let r = resolve(&cfg, &EnvSettings::default(), &FlagSettings::default()).unwrap();
+        let context = r.prompt_wrap.context.unwrap();
+        let a = context.find(">\nAlpha\n</file>").unwrap();
+        let b = context.find(">\nBeta\n</file>").unwrap();
+        assert!(a < b, "{}", context);
+        assert!(!context.contains("blob.md"), "{}", context);
+
+        let r = resolve(&cfg, &EnvSettings::default(), &FlagSettings { no_wrap: true, ..Default::default() }).unwrap();
+        assert_eq!(r.prompt_wrap.context, None);
+        assert!(read_context(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn workspaces_become_codex_dirs_and_context_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let (web, api) = (dir.path().join("web"), dir.path().join("api"));
+        for (root, file) in [(&web, "notes.md"), (&api, "api.md")] {
+            fs::create_dir_all(root).unwrap();
+            fs::write(root.join(file), "notes").unwrap();
+        }
+        let (web, api) = (fs::canonicalize(web).unwrap(), fs::canonicalize(api).unwrap());
+        let cfg = Config { context: vec!["*.md".into()], ..Default::default() };
+        let roots = [web.clone(), api.clone()];
+        let flags = FlagSettings { workspaces: &roots, ..Default::default() };
+        let r = resolve(&cfg, &EnvSettings::default(), &flags).unwrap();
+        let (web_arg, api_arg) = (web.display().to_string(), api.display().to_string());
+        assert_eq!(r.args, strings(&["-C", &web_arg, "--add-dir", &api_arg]));
+        let context = r.prompt_wrap.context.unwrap();
+        assert!(context.contains("<file path=\"web/notes.md\">") && context.contains("<file path=\"api/api.md\">"), "{}", context);
+
+        // codex's own -C wins; every workspace but that one is added.
+        let cli = strings(&["-C", &api_arg]);
+        let r = resolve(&Config::default(), &EnvSettings::default(), &FlagSettings { args: &cli, ..flags.clone() }).unwrap();
+        assert_eq!(r.args, strings(&["-C", &api_arg, "--add-dir", &web_arg]));
+
+        let missing = [dir.path().join("nope")];
+        let flags = FlagSettings { workspaces: &missing, ..Default::default() };
+        assert!(resolve(&Config::default(), &EnvSettings::default(), &flags).is_err());
+    }
+
+    #[test]
+    fn config_round_trips_via_toml() {
+        let mut cfg = Config { model: Some("o3".into()), timeout_seconds: Some(30), ..Default::default() };
+        cfg.profiles.insert(
+            "review".into(),
+            Profile {
+                args: vec!["-m".into(), "o3".into()],
+                timeout_seconds: Some(60),
+                prompt_suffix: Some("Be brief.".into()),
+                hooks: Hooks { pre: Some("true".into()), post: None },
+                ..Default::default()
+            },
+        );
+        let raw = toml::to_string_pretty(&cfg).unwrap();
+        let back: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(cfg, back);
+    }
+}
@@ -0,0 +1,202 @@
+//! Implementations of the `codex-clean config ...` subcommands.
+//!
+//! Thin CLI over `config.rs`. Every write is validated against the config
+//! schema first, so a typo is reported now rather than at the next run.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{self, config_toml_path};
+use crate::seat::{atomic_write, secure_create_dir_all};
+use crate::trust::{Trust, TrustStore};
+
+pub fn path() -> Result<()> {
+    println!("{}", config_toml_path()?.display());
+    Ok(())
+}
+
+/// Print one value, or the whole file when `key` is `None`.
+pub fn get(key: Option<&str>) -> Result<()> {
+    let doc = config::load_document()?;
+    let Some(key) = key else {
+        print!("{}", toml::to_string_pretty(&doc).context("serialising config")?);
+        return Ok(());
+    };
+    let value =
+        config::get_path(&doc, key).ok_or_else(|| anyhow::anyhow!("'{}' is not set", key))?;
+    println!("{}", format_value(value)?);
+    Ok(())
+}
+
+/// Set `key` to `value` (parsed as a TOML literal, else a string).
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let mut doc = config::load_document()?;
+    config::set_path(&mut doc, key, config::parse_value(value))?;
+    let problems = config::validate_document(&doc);
+    if !problems.is_empty() {
+        bail!("refusing to write config: {}", problems.join("; "));
+    }
+    write_document(&doc)?;
+    eprintln!("Set {}.", key);
+    Ok(())
+}
+
+/// Open the config file in `$VISUAL`/`$EDITOR`. The edit happens on a copy
+/// that only replaces the real file once it validates.
+pub fn edit() -> Result<()> {
+    let path = config_toml_path()?;
+    if let Some(parent) = path.parent() {
+        secure_create_dir_all(parent)?;
+    }
+    let scratch = path.with_extension(format!("toml.edit-{}", std::process::id()));
+    let original = if path.exists() {
+        fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?
+    } else {
+        String::new()
+    };
+    fs::write(&scratch, &original).with_context(|| format!("writing {}", scratch.display()))?;
+    let outcome = edit_until_valid(&scratch).and_then(|edited| match edited {
+        Some(raw) if raw == original => {
+            eprintln!("No changes.");
+            Ok(())
+        }
+        Some(raw) => {
+            atomic_write(&path, raw.as_bytes())?;
+            eprintln!("Saved {}.", path.display());
+            Ok(())
+        }
+        None => {
+            eprintln!("Discarded changes; {} left as it was.", path.display());
+            Ok(())
+        }
+    });
+    let _ = fs::remove_file(&scratch);
+    outcome
+}
+
+/// Run the editor on `scratch` until it holds a valid config or the user
+/// gives up (`None`).
+fn edit_until_valid(scratch: &Path) -> Result<Option<String>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    loop {
+        // Through the shell so EDITOR="code --wait" works.
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", editor))
+            .arg("sh")
+            .arg(scratch)
+            .status()
+            .with_context(|| format!("launching editor `{}`", editor))?;
+        if !status.success() {
+            bail!("editor `{}` exited with {}", editor, status);
+        }
+        let raw = fs::read_to_string(scratch)
+            .with_context(|| format!("reading {}", scratch.display()))?;
+        let problems = match config::parse_document(&raw) {
+            Ok(doc) => config::validate_document(&doc),
+            Err(e) => vec![e.to_string().trim_end().to_string()],
+        };
+        if problems.is_empty() {
+            return Ok(Some(raw));
+        }
+        for p in &problems {
+            eprintln!("  {}", p);
+        }
+        eprint!("Config is invalid. Re-open the editor? [Y/n] ");
+        io::stderr().flush().ok();
+        let mut answer = String::new();
+        // EOF (no terminal to answer) counts as "no".
+        if io::stdin().read_line(&mut answer)? == 0 || matches!(answer.trim(), "n" | "N" | "no") {
+            return Ok(None);
+        }
+    }
+}
+
+/// Report parse errors, unknown keys, and type errors. Fails if any are
+/// found so scripts can gate on it.
+pub fn doctor() -> Result<()> {
+    let path = config_toml_path()?;
+    let mut problems = 0;
+    if path.exists() {
+        problems += check_file(&path, config::load_document()?);
+    } else {
+        println!("No config file at {}; defaults in use.", path.display());
+    }
+    let cwd = env::current_dir().context("finding the working directory")?;
+    if let Some(project) = config::find_project_config(&cwd) {
+        let raw = fs::read_to_string(&project)
+            .with_context(|| format!("reading {}", project.display()))?;
+        let doc = config::parse_document(&raw)
+            .with_context(|| format!("parsing {}", project.display()))?;
+        problems += check_file(&project, doc);
+        let canonical = fs::canonicalize(&project)
+            .with_context(|| format!("resolving {}", project.display()))?;
+        match TrustStore::load()?.check(&canonical, &raw) {
+            Trust::Trusted => {}
+            Trust::Untrusted => println!("{}: not trusted, so not applied (see `codex-clean trust`)", project.display()),
+            Trust::Changed => println!("{}: changed since it was trusted, so not applied (see `codex-clean trust`)", project.display()),
+        }
+    }
+    if problems > 0 {
+        bail!("{} problem(s) found", problems);
+    }
+    Ok(())
+}
+
+/// Print what's wrong with one config file; returns the problem count.
+fn check_file(path: &Path, doc: toml::Value) -> usize {
+    let problems = config::validate_document(&doc);
+    if problems.is_empty() {
+        println!("{}: OK", path.display());
+    }
+    for p in &problems {
+        println!("{}: {}", path.display(), p);
+    }
+    problems.len()
+}
+
+fn write_document(doc: &toml::Value) -> Result<()> {
+    let path = config_toml_path()?;
+    if let Some(parent) = path.parent() {
+        secure_create_dir_all(parent)?;
+    }
+    let raw = toml::to_string_pretty(doc).context("serialising config")?;
+    atomic_write(&path, raw.as_bytes())
+}
+
+/// Strings print bare so `config get` composes in shell scripts; everything
+/// else prints as TOML.
+fn format_value(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Table(_) => toml::to_string_pretty(value)
+            .context("serialising value")?
+            .trim_end()
+            .to_string(),
+        other => other.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_value_prints_strings_bare() {
+        assert_eq!(format_value(&config::parse_value("echo hi")).unwrap(), "echo hi");
+        assert_eq!(format_value(&config::parse_value("60")).unwrap(), "60");
+        assert_eq!(
+            format_value(&config::parse_value(r#"["-m", "o3"]"#)).unwrap(),
+            r#"["-m", "o3"]"#
+        );
+    }
+}
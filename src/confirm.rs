@@ -0,0 +1,140 @@
+//! `--confirm`: preview what codex-clean is about to write, and ask first.
+//!
+//! Features that write the answer to disk (so far `--write-files`) describe
+//! each write as a [`PendingWrite`] and hand the list to [`review`] before
+//! touching anything. It prints the paths with a diffstat against what is
+//! there now, then asks about each file in turn: `y` writes it, `n` skips
+//! it, `a` writes it and the rest, `q` skips it and the rest.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use similar::{ChangeTag, TextDiff};
+
+/// A file about to be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingWrite {
+    /// As shown to the user.
+    pub path: PathBuf,
+    /// Where it will be written.
+    pub target: PathBuf,
+    /// What is there now, if anything.
+    pub old: Option<String>,
+    pub new: String,
+}
+
+impl PendingWrite {
+    /// Lines (added, removed) relative to what is there now.
+    pub fn diffstat(&self) -> (usize, usize) {
+        let old = self.old.as_deref().unwrap_or("");
+        let diff = TextDiff::from_lines(old, &self.new);
+        diff.iter_all_changes().fold((0, 0), |(added, removed), change| match change.tag() {
+            ChangeTag::Insert => (added + 1, removed),
+            ChangeTag::Delete => (added, removed + 1),
+            ChangeTag::Equal => (added, removed),
+        })
+    }
+}
+
+/// The paths and diffstats of `writes`.
+pub fn preview(writes: &[PendingWrite]) -> String {
+    let mut out = format!("About to write {} file(s):\n", writes.len());
+    let width = writes.iter().map(|w| w.path.display().to_string().len()).max().unwrap_or(0);
+    for w in writes {
+        let (added, removed) = w.diffstat();
+        let state = match &w.old {
+            None => "new",
+            Some(old) if *old == w.new => "unchanged",
+            Some(_) => "replace",
+        };
+        out.push_str(&format!(
+            "  {:<9}  {:<width$}  +{} -{}\n",
+            state,
+            w.path.display().to_string(),
+            added,
+            removed,
+            width = width
+        ));
+    }
+    out
+}
+
+/// Print the preview and ask about each write with `ask` (a question in,
+/// the answer line out; None when there is no one to ask). Returns the
+/// writes that were accepted, in order.
+pub fn review<F>(writes: Vec<PendingWrite>, mut ask: F) -> Result<Vec<PendingWrite>>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    if writes.is_empty() {
+        return Ok(writes);
+    }
+    eprint!("{}", preview(&writes));
+    let mut accepted = Vec::with_capacity(writes.len());
+    let mut all = false;
+    for write in writes {
+        if all {
+            accepted.push(write);
+            continue;
+        }
+        loop {
+            let question = format!("Write {}? [y]es/[n]o/[a]ll/[q]uit ", write.path.display());
+            let Some(answer) = ask(&question) else {
+                bail!("--confirm needs a terminal to ask on; nothing was written");
+            };
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => accepted.push(write),
+                "n" | "no" | "" => {}
+                "a" | "all" => {
+                    all = true;
+                    accepted.push(write);
+                }
+                "q" | "quit" => return Ok(accepted),
+                _ => continue,
+            }
+            break;
+        }
+    }
+    Ok(accepted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &str, old: Option<&str>, new: &str) -> PendingWrite {
+        PendingWrite {
+            path: path.into(),
+            target: PathBuf::from("/tmp").join(path),
+            old: old.map(String::from),
+            new: new.into(),
+        }
+    }
+
+    #[test]
+    fn preview_shows_paths_and_diffstats() {
+        let writes = vec![
+            write("src/main.rs", None, "fn main() {}\n"),
+            write("Cargo.toml", Some("[package]\nname = \"old\"\n"), "[package]\nname = \"new\"\nedition = \"2021\"\n"),
+        ];
+        assert_eq!(
+            preview(&writes),
+            "About to write 2 file(s):\n  new        src/main.rs  +1 -0\n  replace    Cargo.toml   +2 -1\n"
+        );
+    }
+
+    #[test]
+    fn review_asks_per_file_until_all_or_quit() {
+        let writes = || vec![write("a", None, "a\n"), write("b", None, "b\n"), write("c", None, "c\n")];
+        let answers = |list: &'static [&'static str]| {
+            let mut list = list.iter();
+            move |_: &str| list.next().map(|a| a.to_string())
+        };
+        let names = |w: Vec<PendingWrite>| w.into_iter().map(|w| w.path.display().to_string()).collect::<Vec<_>>();
+
+        assert_eq!(names(review(writes(), answers(&["n", "what?", "y", "n"])).unwrap()), vec!["b"]);
+        assert_eq!(names(review(writes(), answers(&["y", "a"])).unwrap()), vec!["a", "b", "c"]);
+        assert_eq!(names(review(writes(), answers(&["y", "q"])).unwrap()), vec!["a"]);
+        assert!(review(writes(), answers(&[])).unwrap_err().to_string().contains("needs a terminal"));
+    }
+}
@@ -0,0 +1,122 @@
+//! What an answer is made of: the languages of its code and how much of
+//! it is code, kept in the run report so `codex-clean stats --code LANG`
+//! can find past answers by language.
+//!
+//! A fenced block's info string names its language (with the usual
+//! aliases folded together: `rs` is `rust`, `sh` is `shell`, ...). Blocks
+//! without one are guessed at from a few telltale lines, and left out when
+//! nothing matches.
+
+use serde::{Deserialize, Serialize};
+
+use crate::write_files;
+
+/// The languages in an answer and its share of code.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Content {
+    /// Each language with code in the answer, in order of first use.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    /// The fraction of the answer's characters inside code blocks, to two
+    /// places.
+    pub code_ratio: f64,
+}
+
+impl Content {
+    pub fn has_language(&self, lang: &str) -> bool {
+        let lang = normalise(lang).unwrap_or_else(|| lang.to_ascii_lowercase());
+        self.languages.contains(&lang)
+    }
+}
+
+/// The language named by a code block's info string; `None` for blocks
+/// with none, or that aren't code (`text`, `output`, ...).
+pub fn normalise(info: &str) -> Option<String> {
+    // `rust,no_run`, `python title="x.py"`, `{.rust}` all name a language first.
+    let word = info.trim_start_matches(['{', '.']).split([' ', ',', '}']).next()?.to_ascii_lowercase();
+    let lang = match word.as_str() {
+        "" | "text" | "txt" | "plain" | "plaintext" | "output" | "console" | "log" => return None,
+        "rs" => "rust",
+        "py" | "python3" => "python",
+        "sh" | "bash" | "zsh" | "shell" | "shellscript" | "fish" => "shell",
+        "js" | "jsx" | "mjs" | "node" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "yml" => "yaml",
+        "md" => "markdown",
+        "c++" | "cc" | "hpp" => "cpp",
+        "golang" => "go",
+        "rb" => "ruby",
+        "kt" => "kotlin",
+        "ps" | "ps1" | "pwsh" => "powershell",
+        "dockerfile" => "docker",
+        "patch" => "diff",
+        other => other,
+    };
+    Some(lang.to_string())
+}
+
+/// A guess at the language of an untagged block.
+fn guess(code: &str) -> Option<&'static str> {
+    let first = code.lines().find(|l| !l.trim().is_empty())?.trim();
+    if first.starts_with("#!") {
+        return Some(if first.contains("python") { "python" } else { "shell" });
+    }
+    if first.starts_with("diff --git") || first.starts_with("--- a/") {
+        return Some("diff");
+    }
+    if first.starts_with('$') {
+        return Some("shell");
+    }
+    let has = |needles: &[&str]| code.lines().any(|l| needles.iter().any(|n| l.trim_start().starts_with(n)));
+    if has(&["fn ", "pub fn ", "impl ", "use std::", "#[derive(", "let mut "]) {
+        Some("rust")
+    } else if has(&["def ", "import ", "from "]) && code.contains(':') {
+        Some("python")
+    } else if has(&["package ", "func "]) {
+        Some("go")
+    } else if has(&["const ", "function ", "export "]) {
+        Some("javascript")
+    } else if (first.starts_with('{') || first.starts_with('[')) && code.contains('"') {
+        Some("json")
+    } else {
+        None
+    }
+}
+
+/// What `answer` is made of; `None` for an empty answer.
+pub fn analyse(answer: &str) -> Option<Content> {
+    let total = answer.chars().filter(|c| !c.is_whitespace()).count();
+    if total == 0 {
+        return None;
+    }
+    let mut content = Content::default();
+    let mut code = 0;
+    for (info, body) in write_files::fenced_blocks(answer) {
+        code += body.chars().filter(|c| !c.is_whitespace()).count();
+        let lang = normalise(&info).or_else(|| guess(&body).map(String::from));
+        if let Some(lang) = lang.filter(|l| !content.languages.contains(l)) {
+            content.languages.push(lang);
+        }
+    }
+    content.code_ratio = (code as f64 / total as f64 * 100.0).round() / 100.0;
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_and_code_share_are_found() {
+        let answer = "Add a macro:\n\n```rs\nmacro_rules! hi { () => {} }\n```\n\nThen run:\n\n```\n$ cargo test\n```\n\n```rust,ignore\nhi!();\n```\n\n```text\nok\n```";
+        let content = analyse(answer).unwrap();
+        assert_eq!(content.languages, ["rust", "shell"]);
+        assert_eq!(content.code_ratio, 0.4);
+        assert!(content.has_language("RS") && content.has_language("sh") && !content.has_language("python"));
+
+        assert_eq!(analyse("Just prose."), Some(Content { languages: vec![], code_ratio: 0.0 }));
+        assert_eq!(analyse("  \n"), None);
+        assert_eq!(guess("import os\nprint(os.getcwd())\nif x:\n  pass"), Some("python"));
+        assert_eq!(guess("just some words"), None);
+    }
+}
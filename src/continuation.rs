@@ -0,0 +1,129 @@
+//! `--auto-continue`: finish an answer that was cut off.
+//!
+//! codex doesn't report a finish reason for a complete turn, but when the
+//! model runs out of output tokens the turn fails with codex's
+//! `Incomplete response returned, reason: max_output_tokens` (other
+//! backends say `finish_reason: length` or `max_tokens`). An answer that
+//! ends inside a code fence was cut off too, whatever codex said. Either
+//! way [`detect`] says why, and the runner resumes the session with
+//! [`DEFAULT_CONTINUE_PROMPT`]; [`stitch`] joins the pieces so they read as
+//! one answer.
+
+use std::fmt;
+
+use crate::output::CodexOutput;
+use crate::write_files;
+
+/// What `--auto-continue` sends unless `--continue-prompt` says otherwise.
+pub const DEFAULT_CONTINUE_PROMPT: &str =
+    "continue exactly where you left off, without repeating anything or adding any introduction";
+
+/// The most lines of overlap [`stitch`] looks for between the end of one
+/// piece and the start of the next.
+const MAX_OVERLAP_LINES: usize = 20;
+
+/// Why an answer looks unfinished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CutOff {
+    /// The model reached its output token limit.
+    OutputLimit,
+    /// The answer ends inside a fenced code block.
+    OpenCodeFence,
+}
+
+impl fmt::Display for CutOff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CutOff::OutputLimit => "the model hit its output limit",
+            CutOff::OpenCodeFence => "the answer ends inside a code block",
+        })
+    }
+}
+
+/// Whether an error codex reported is the model running out of output
+/// tokens.
+pub fn is_output_limit(error: &str) -> bool {
+    let lower = error.to_lowercase();
+    lower.contains("max_output_tokens")
+        || lower.contains("max_tokens")
+        || lower.contains("finish_reason: length")
+        || lower.contains("finish_reason\":\"length")
+}
+
+/// Why `output`'s answer was cut off, if it was. An answer needs some
+/// text to be continued.
+pub fn detect(output: &CodexOutput) -> Option<CutOff> {
+    if output.messages.is_empty() {
+        return None;
+    }
+    if output.errors.iter().any(|e| is_output_limit(e)) {
+        return Some(CutOff::OutputLimit);
+    }
+    open_fence(&output.aggregated_message()).then_some(CutOff::OpenCodeFence)
+}
+
+/// Whether `text` ends inside a fenced code block.
+fn open_fence(text: &str) -> bool {
+    let mut open: Option<String> = None;
+    for line in text.lines() {
+        match (&open, write_files::fence_open(line)) {
+            (None, Some((fence, _))) => open = Some(fence.to_string()),
+            (Some(fence), Some((closing, info))) if closing.starts_with(fence.as_str()) && info.is_empty() => {
+                open = None
+            }
+            _ => {}
+        }
+    }
+    open.is_some()
+}
+
+/// `first` followed by its continuation `rest`, as one answer. When the
+/// continuation reopens the code block `first` ended in, the reopening
+/// fence is dropped, and lines it repeats from the end of `first` are
+/// only kept once.
+pub fn stitch(first: &str, rest: &str) -> String {
+    let first = first.trim_end_matches('\n');
+    let mut rest: Vec<&str> = rest.trim_start_matches('\n').lines().collect();
+    if open_fence(first) && rest.first().and_then(|l| write_files::fence_open(l)).is_some() {
+        rest.remove(0);
+    }
+    let tail: Vec<&str> = first.lines().collect();
+    let overlap = (1..=MAX_OVERLAP_LINES.min(tail.len()).min(rest.len()))
+        .rev()
+        .find(|&n| tail[tail.len() - n..] == rest[..n] && rest[..n].iter().any(|l| !l.trim().is_empty()))
+        .unwrap_or(0);
+    let rest = rest[overlap..].join("\n");
+    match (first.is_empty(), rest.is_empty()) {
+        (_, true) => first.to_string(),
+        (true, false) => rest,
+        (false, false) => format!("{}\n{}", first, rest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_off_answers_are_detected() {
+        let mut output = CodexOutput::new();
+        output.add_message("Here you go:\n\n```rust\nfn main() {\n    let x = 1;".into());
+        assert_eq!(detect(&output), Some(CutOff::OpenCodeFence));
+        output.add_error("Incomplete response returned, reason: max_output_tokens".into());
+        assert_eq!(detect(&output), Some(CutOff::OutputLimit));
+
+        let mut done = CodexOutput::new();
+        done.add_message("```rust\nfn main() {}\n```\n\n````md\n```\nnested\n```\n````".into());
+        assert_eq!(detect(&done), None);
+        assert_eq!(detect(&CodexOutput::new()), None);
+    }
+
+    #[test]
+    fn stitching_drops_the_reopened_fence_and_repeated_lines() {
+        let first = "Here you go:\n\n```rust\nfn main() {\n    let x = 1;";
+        let rest = "```rust\n    let x = 1;\n    println!(\"{}\", x);\n}\n```";
+        assert_eq!(stitch(first, rest), "Here you go:\n\n```rust\nfn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n```");
+        assert_eq!(stitch("one\ntwo", "three"), "one\ntwo\nthree");
+        assert_eq!(stitch("one", ""), "one");
+    }
+}
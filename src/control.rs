@@ -0,0 +1,461 @@
+//! Stopping a run from another terminal: `codex-clean cancel <run-id>`.
+//!
+//! Every run listens on a Unix socket at
+//! `~/.config/codex-clean/runs/<run-id>.sock` for as long as it is running.
+//! Writing `cancel` to it kills codex; the run then prints whatever codex
+//! had produced so far and exits 130. Writing `info` returns a JSON line
+//! describing the run, which is how `codex-clean cancel` lists them.
+//!
+//! The socket file is removed when the run ends. One left behind by a crash
+//! refuses connections and is cleaned up by the next caller that finds it.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::backend::CodexProcess;
+use crate::seat::config_dir;
+
+/// Exit code for a cancelled run, as for an interrupted shell command.
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// `~/.config/codex-clean/runs/`.
+pub fn runs_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("runs"))
+}
+
+/// A run ID for runs that weren't given one with `--run-id`.
+pub fn generate_run_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    match COUNTER.fetch_add(1, Ordering::Relaxed) {
+        0 => std::process::id().to_string(),
+        n => format!("{}-{}", std::process::id(), n),
+    }
+}
+
+/// Run IDs become file names, and socket paths are short-limited.
+pub fn validate_run_id(id: &str) -> Result<()> {
+    let ok = !id.is_empty()
+        && id.len() <= 64
+        && !id.starts_with('.')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !ok {
+        bail!(
+            "invalid run ID '{}': use up to 64 letters, digits, '-', '_' or '.'",
+            id
+        );
+    }
+    Ok(())
+}
+
+/// The cancel switch for one run, shared between the control socket and
+/// whichever codex process the run currently has going.
+#[derive(Default)]
+pub struct Cancel {
+    inner: Mutex<CancelInner>,
+}
+
+#[derive(Default)]
+struct CancelInner {
+    cancelled: bool,
+    process: Option<Arc<Mutex<Box<dyn CodexProcess>>>>,
+}
+
+impl Cancel {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CancelInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Flip the switch, killing the current process if there is one.
+    pub fn cancel(&self) {
+        let mut inner = self.lock();
+        inner.cancelled = true;
+        if let Some(process) = &inner.process {
+            let _ = process.lock().unwrap_or_else(|e| e.into_inner()).kill();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    /// `process` is what a cancel should kill from now on. Killed at once
+    /// if the run was already cancelled.
+    pub fn watch(&self, process: &Arc<Mutex<Box<dyn CodexProcess>>>) {
+        let mut inner = self.lock();
+        if inner.cancelled {
+            let _ = process.lock().unwrap_or_else(|e| e.into_inner()).kill();
+        }
+        inner.process = Some(Arc::clone(process));
+    }
+
+    /// Stop tracking the process; returns whether the run was cancelled.
+    pub fn unwatch(&self) -> bool {
+        let mut inner = self.lock();
+        inner.process = None;
+        inner.cancelled
+    }
+}
+
+/// A run's cancel switch for library callers: put a clone in
+/// [`RunOptions::cancel`](crate::runner::RunOptions::cancel) and call
+/// [`CancelHandle::cancel`] from any thread. codex is killed, its streams
+/// drained, and the run returns what it had so far marked as cancelled (exit
+/// code [`CANCELLED_EXIT_CODE`]), just as for `codex-clean cancel`.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    inner: Arc<Cancel>,
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    pub(crate) fn shared(&self) -> Arc<Cancel> {
+        Arc::clone(&self.inner)
+    }
+}
+
+impl std::fmt::Debug for CancelHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelHandle").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+/// Make Ctrl-C cancel `handle`'s run rather than kill codex-clean outright,
+/// so the partial output still prints. A second Ctrl-C kills as usual.
+#[cfg(unix)]
+pub fn cancel_on_interrupt(handle: &CancelHandle) {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+    use std::sync::atomic::AtomicI32;
+
+    /// The write end of the pipe that wakes the waiting thread.
+    static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_sigint(_: libc::c_int) {
+        // Only async-signal-safe work here; the next SIGINT is fatal again.
+        unsafe {
+            libc::write(WAKE.load(Ordering::SeqCst), [1u8].as_ptr().cast(), 1);
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        eprintln!("Warning: Ctrl-C will stop codex-clean outright: {}", std::io::Error::last_os_error());
+        return;
+    }
+    // codex mustn't inherit either end.
+    for fd in fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    // A thread left over from an earlier call sees end-of-file and stops.
+    let earlier = WAKE.swap(fds[1], Ordering::SeqCst);
+    if earlier >= 0 {
+        unsafe {
+            libc::close(earlier);
+        }
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t);
+    }
+    let mut wake = unsafe { File::from_raw_fd(fds[0]) };
+    let handle = handle.clone();
+    std::thread::spawn(move || {
+        if wake.read_exact(&mut [0u8]).is_ok() {
+            eprintln!("Interrupted; stopping codex (Ctrl-C again to force).");
+            handle.cancel();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn cancel_on_interrupt(_handle: &CancelHandle) {}
+
+#[cfg(unix)]
+pub use unix::{request, request_in, ControlSocket};
+
+#[cfg(unix)]
+mod unix {
+    use std::fs;
+    use std::io::{BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+
+    use super::{runs_dir, socket_path, validate_run_id, Cancel};
+    use crate::seat::secure_create_dir_all;
+
+    /// A run's control socket. Listening stops and the file is removed on drop.
+    pub struct ControlSocket {
+        path: PathBuf,
+        closing: Arc<AtomicBool>,
+    }
+
+    impl ControlSocket {
+        pub fn bind(run_id: &str, cancel: Arc<Cancel>) -> Result<Self> {
+            Self::bind_in(&runs_dir()?, run_id, cancel)
+        }
+
+        pub fn bind_in(dir: &Path, run_id: &str, cancel: Arc<Cancel>) -> Result<Self> {
+            validate_run_id(run_id)?;
+            secure_create_dir_all(dir)?;
+            let path = socket_path(dir, run_id);
+            if path.exists() {
+                if UnixStream::connect(&path).is_ok() {
+                    bail!("run ID '{}' is already in use by a running codex-clean", run_id);
+                }
+                let _ = fs::remove_file(&path);
+            }
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("creating control socket {}", path.display()))?;
+            let closing = Arc::new(AtomicBool::new(false));
+            let info = serde_json::json!({
+                "run_id": run_id,
+                "pid": std::process::id(),
+                "started_at": chrono::Utc::now().to_rfc3339(),
+            })
+            .to_string();
+            let stop = Arc::clone(&closing);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let _ = serve(stream, &cancel, &info);
+                    }
+                }
+            });
+            Ok(Self { path, closing })
+        }
+    }
+
+    impl Drop for ControlSocket {
+        fn drop(&mut self) {
+            self.closing.store(true, Ordering::SeqCst);
+            // Wake the accept loop so it sees `closing` and exits.
+            let _ = UnixStream::connect(&self.path);
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn serve(stream: UnixStream, cancel: &Cancel, info: &str) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let reply = match line.trim() {
+            "cancel" => {
+                let already = cancel.is_cancelled();
+                cancel.cancel();
+                if already { "already cancelling" } else { "cancelled" }
+            }
+            "info" => info,
+            _ => "unknown command",
+        };
+        (&stream).write_all(format!("{}\n", reply).as_bytes())
+    }
+
+    /// Send `command` to the run with `run_id` and return its reply.
+    pub fn request(run_id: &str, command: &str) -> Result<String> {
+        request_in(&runs_dir()?, run_id, command)
+    }
+
+    pub fn request_in(dir: &Path, run_id: &str, command: &str) -> Result<String> {
+        validate_run_id(run_id)?;
+        let path = socket_path(dir, run_id);
+        let stream = match UnixStream::connect(&path) {
+            Ok(s) => s,
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+                if e.kind() == ErrorKind::ConnectionRefused {
+                    // Left behind by a run that crashed.
+                    let _ = fs::remove_file(&path);
+                }
+                bail!("no running codex-clean with run ID '{}'", run_id);
+            }
+            Err(e) => return Err(e).with_context(|| format!("connecting to {}", path.display())),
+        };
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        (&stream).write_all(format!("{}\n", command).as_bytes())?;
+        let mut reply = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut reply)
+            .with_context(|| format!("reading reply from run '{}'", run_id))?;
+        Ok(reply.trim_end().to_string())
+    }
+}
+
+/// Stand-ins so callers compile off Unix; there are no control sockets there.
+#[cfg(not(unix))]
+pub struct ControlSocket;
+
+#[cfg(not(unix))]
+impl ControlSocket {
+    pub fn bind(_run_id: &str, _cancel: Arc<Cancel>) -> Result<Self> {
+        bail!("run control sockets are only supported on Unix")
+    }
+}
+
+#[cfg(not(unix))]
+pub fn request(_run_id: &str, _command: &str) -> Result<String> {
+    bail!("`cancel` needs Unix domain sockets, which are only supported on Unix")
+}
+
+fn socket_path(dir: &Path, run_id: &str) -> PathBuf {
+    dir.join(format!("{}.sock", run_id))
+}
+
+/// IDs of runs that have a socket in `dir` (live or stale).
+pub fn run_ids_in(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut ids: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(|n| n.strip_suffix(".sock"))
+                .map(str::to_string)
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// `codex-clean cancel <run-id>`.
+pub fn cancel(run_id: &str) -> Result<()> {
+    let reply = request(run_id, "cancel")?;
+    eprintln!("Run {}: {}.", run_id, reply);
+    Ok(())
+}
+
+/// `codex-clean cancel` with no ID: list the runs that could be cancelled.
+pub fn list() -> Result<()> {
+    let mut found = false;
+    for id in run_ids_in(&runs_dir()?) {
+        // Stale sockets fail here and are removed by `request`.
+        let Ok(reply) = request(&id, "info") else { continue };
+        let info: serde_json::Value = serde_json::from_str(&reply).unwrap_or_default();
+        if !found {
+            println!("{:<20} {:>8}  STARTED", "RUN ID", "PID");
+            found = true;
+        }
+        println!(
+            "{:<20} {:>8}  {}",
+            id,
+            info["pid"].as_u64().map(|p| p.to_string()).unwrap_or_default(),
+            info["started_at"].as_str().unwrap_or("")
+        );
+    }
+    if !found {
+        eprintln!("No codex-clean runs in progress.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_ids_must_be_file_name_safe() {
+        assert!(validate_run_id("nightly-build_2.1").is_ok());
+        assert!(validate_run_id("").is_err());
+        assert!(validate_run_id("../x").is_err());
+        assert!(validate_run_id(".hidden").is_err());
+        assert!(validate_run_id(&"a".repeat(65)).is_err());
+        assert!(validate_run_id(&generate_run_id()).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ctrl_c_cancels_the_run() {
+        let handle = CancelHandle::new();
+        cancel_on_interrupt(&handle);
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+        let clock = std::time::Instant::now();
+        while !handle.is_cancelled() {
+            assert!(clock.elapsed() < std::time::Duration::from_secs(5), "the interrupt never cancelled the run");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancel_kills_watched_process() {
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("10");
+        let child: Box<dyn CodexProcess> = Box::new(
+            crate::backend::ChildProcess::spawn(cmd, Default::default()).unwrap(),
+        );
+        let child = Arc::new(Mutex::new(child));
+        let cancel = Cancel::default();
+        cancel.watch(&child);
+        cancel.cancel();
+        assert!(!child.lock().unwrap().wait().unwrap().success);
+        assert!(cancel.unwatch());
+    }
+
+    #[test]
+    fn cancel_handle_before_start_kills_on_watch() {
+        let handle = CancelHandle::new();
+        let also = handle.clone();
+        also.cancel();
+        assert!(handle.is_cancelled());
+
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("10");
+        let child: Box<dyn CodexProcess> = Box::new(
+            crate::backend::ChildProcess::spawn(cmd, Default::default()).unwrap(),
+        );
+        let child = Arc::new(Mutex::new(child));
+        handle.shared().watch(&child);
+        assert!(!child.lock().unwrap().wait().unwrap().success);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_answers_info_and_cancel_then_goes_away() {
+        let dir = tempfile::tempdir().unwrap();
+        let cancel = Arc::new(Cancel::default());
+        let socket = ControlSocket::bind_in(dir.path(), "r1", Arc::clone(&cancel)).unwrap();
+        assert_eq!(run_ids_in(dir.path()), vec!["r1"]);
+        assert!(ControlSocket::bind_in(dir.path(), "r1", Arc::new(Cancel::default())).is_err());
+
+        let info: serde_json::Value =
+            serde_json::from_str(&request_in(dir.path(), "r1", "info").unwrap()).unwrap();
+        assert_eq!(info["run_id"], "r1");
+        assert_eq!(info["pid"], std::process::id());
+
+        assert_eq!(request_in(dir.path(), "r1", "cancel").unwrap(), "cancelled");
+        assert!(cancel.is_cancelled());
+
+        drop(socket);
+        assert!(run_ids_in(dir.path()).is_empty());
+        let err = request_in(dir.path(), "r1", "cancel").unwrap_err();
+        assert!(err.to_string().contains("no running codex-clean"), "{}", err);
+    }
+}
@@ -0,0 +1,79 @@
+//! A conversation with codex for library embedders.
+//!
+//! [`Session`] keeps the session ID between turns: the first
+//! [`Session::send`] starts a session, and each later one resumes it with
+//! `codex exec resume <id>`, so callers never thread IDs through by hand.
+//! Resumed turns hold the session's lock (see [`crate::lock`]) like
+//! `codex-clean resume` does.
+
+use anyhow::anyhow;
+
+use crate::error::{Error, Result};
+use crate::lock::SessionLock;
+use crate::output::CodexOutput;
+use crate::proto::Transport;
+use crate::runner::{self, Mode, ResumeTarget, RunOptions};
+
+/// What one turn produced.
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// The agent's messages, joined as the CLI prints them.
+    pub text: String,
+    pub output: CodexOutput,
+}
+
+/// A codex session that can be sent prompt after prompt.
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: Option<String>,
+    args: Vec<String>,
+    opts: RunOptions,
+}
+
+impl Session {
+    /// A session that will start on the first [`Session::send`], running
+    /// codex with `args` and `opts` for every turn.
+    pub fn new(args: Vec<String>, opts: RunOptions) -> Self {
+        Self { id: None, args, opts }
+    }
+
+    /// Carry on an existing session.
+    pub fn resume(id: impl Into<String>, args: Vec<String>, opts: RunOptions) -> Self {
+        Self { id: Some(id.into()), args, opts }
+    }
+
+    /// The session ID, once codex has reported one.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Send `prompt` as the next turn and wait for the answer.
+    pub fn send(&mut self, prompt: &str) -> Result<Response> {
+        if prompt.trim().is_empty() {
+            return Err(Error::EmptyPrompt);
+        }
+        let (mode, opts) = match &self.id {
+            None => (Mode::Exec, self.opts.clone()),
+            // codex proto can't resume, so later turns always go through exec.
+            Some(id) => (
+                Mode::Resume(ResumeTarget::SessionId(id.clone())),
+                RunOptions { transport: Transport::Exec, ..self.opts.clone() },
+            ),
+        };
+        // As for `codex-clean resume`: no other process may add a turn
+        // to this session while ours runs.
+        let _lock = match &self.id {
+            Some(id) => Some(SessionLock::acquire(id, false)?),
+            None => None,
+        };
+        let output = runner::run(&self.args, prompt, mode, &opts)?;
+        if self.id.is_none() {
+            let id = output
+                .session_id
+                .clone()
+                .ok_or_else(|| Error::Other(anyhow!("codex didn't report a session ID")))?;
+            self.id = Some(id);
+        }
+        Ok(Response { text: output.aggregated_message(), output })
+    }
+}
@@ -0,0 +1,183 @@
+//! What to collect when codex-clean itself panics.
+//!
+//! [`install_panic_hook`] keeps Rust's usual panic message and then writes
+//! a diagnostics bundle, readable only by the user, to the temp directory:
+//! the command line, the config file, the last [`RECENT_EVENTS`] raw lines
+//! codex printed and a backtrace, as `codex-clean-crash-<time>-<pid>.json`.
+//! Its path is printed so it can be attached to a bug report. The bundle
+//! holds the prompt and whatever codex said, so it is worth a look before
+//! sharing.
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Raw event lines kept for the bundle.
+pub const RECENT_EVENTS: usize = 100;
+
+/// Longest event line kept, in bytes; the rest is cut off.
+const MAX_EVENT_BYTES: usize = 4096;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Remember a raw line of codex's event stream, forgetting the oldest
+/// once [`RECENT_EVENTS`] are kept.
+pub fn note_event(line: &str) {
+    keep(&mut RECENT.lock().unwrap_or_else(|e| e.into_inner()), line);
+}
+
+fn keep(recent: &mut VecDeque<String>, line: &str) {
+    // Reuse the oldest line's allocation once the buffer is full.
+    let mut kept = if recent.len() == RECENT_EVENTS { recent.pop_front().unwrap_or_default() } else { String::new() };
+    kept.clear();
+    if line.len() > MAX_EVENT_BYTES {
+        let mut end = MAX_EVENT_BYTES;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        kept.push_str(&line[..end]);
+        kept.push_str("...");
+    } else {
+        kept.push_str(line);
+    }
+    recent.push_back(kept);
+}
+
+fn recent_events() -> Vec<String> {
+    RECENT.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
+
+/// The contents of a diagnostics bundle.
+#[derive(Debug, Serialize)]
+pub struct Bundle {
+    pub version: String,
+    pub time: DateTime<Utc>,
+    pub os: String,
+    pub panic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub thread: String,
+    pub args: Vec<String>,
+    /// `config.toml` as written, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+    /// Oldest first.
+    pub events: Vec<String>,
+    pub backtrace: String,
+}
+
+impl Bundle {
+    /// Everything known about the panic described by `info`.
+    fn collect(info: &std::panic::PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let panic = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let config = crate::config::config_toml_path().ok().and_then(|p| std::fs::read_to_string(p).ok());
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            time: Utc::now(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            panic,
+            location: info.location().map(|l| l.to_string()),
+            thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            args: std::env::args().collect(),
+            config,
+            events: recent_events(),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+        }
+    }
+
+    /// Write the bundle into `dir`, returning its path. The bundle holds
+    /// the prompt and codex's output, so only the user may read it, and a
+    /// file already at the path (say a link planted in a shared temp
+    /// directory) is never written through.
+    pub fn write_in(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(format!(
+            "codex-clean-crash-{}-{}.json",
+            self.time.format("%Y%m%dT%H%M%S"),
+            std::process::id()
+        ));
+        let raw = serde_json::to_string_pretty(self).context("serialising the diagnostics bundle")?;
+        let mut opts = OpenOptions::new();
+        opts.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut f = opts.open(&path).with_context(|| format!("creating {}", path.display()))?;
+        f.write_all(raw.as_bytes()).with_context(|| format!("writing {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// After the usual panic message, write a diagnostics bundle and say
+/// where it is.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default(info);
+        match Bundle::collect(info).write_in(&std::env::temp_dir()) {
+            Ok(path) => eprintln!(
+                "codex-clean hit an internal error. Diagnostics were written to {}; \
+                 please attach them to a bug report (they include your prompt and codex's output).",
+                path.display()
+            ),
+            Err(e) => eprintln!("codex-clean hit an internal error; writing diagnostics failed: {:#}", e),
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_latest_events_are_kept() {
+        let mut recent = VecDeque::new();
+        for n in 0..RECENT_EVENTS + 5 {
+            keep(&mut recent, &format!("{{\"n\":{}}}", n));
+        }
+        keep(&mut recent, &"é".repeat(MAX_EVENT_BYTES));
+        let events: Vec<String> = recent.into_iter().collect();
+        assert_eq!(events.len(), RECENT_EVENTS);
+        assert!(!events.contains(&"{\"n\":5}".to_string()));
+        assert!(events.contains(&format!("{{\"n\":{}}}", RECENT_EVENTS + 4)));
+        let long = events.last().unwrap();
+        assert!(long.ends_with("...") && long.len() <= MAX_EVENT_BYTES + 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = Bundle {
+            version: "0.4.0".into(),
+            time: Utc::now(),
+            os: "linux x86_64".into(),
+            panic: "boom".into(),
+            location: Some("src/runner.rs:1:1".into()),
+            thread: "main".into(),
+            args: vec!["codex-clean".into(), "hi".into()],
+            config: None,
+            events,
+            backtrace: String::new(),
+        };
+        let path = bundle.write_in(dir.path()).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert!(bundle.write_in(dir.path()).is_err(), "an existing bundle is not overwritten");
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["panic"], "boom");
+        assert_eq!(saved["events"].as_array().unwrap().len(), RECENT_EVENTS);
+        assert!(saved.get("config").is_none());
+    }
+}
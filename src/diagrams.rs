@@ -0,0 +1,240 @@
+//! `--render-diagrams`: turn Mermaid and Graphviz blocks in the answer
+//! into pictures.
+//!
+//! A fenced block whose info string is `mermaid`, or `dot` / `graphviz`,
+//! is handed to the local renderer (`mmdc` from mermaid-cli, or Graphviz's
+//! `dot`) and saved as a PNG. When stdout is a terminal that can show
+//! images inline (kitty, or iTerm2 and the terminals that speak its
+//! protocol), the pictures are shown after the answer too.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::write_files;
+
+/// Which renderer a diagram needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Mermaid,
+    Graphviz,
+}
+
+impl Kind {
+    /// The kind for a code block's info string, if it is a diagram.
+    pub fn from_info(info: &str) -> Option<Self> {
+        match info.split_whitespace().next()?.to_ascii_lowercase().as_str() {
+            "mermaid" | "mmd" => Some(Kind::Mermaid),
+            "dot" | "graphviz" | "gv" => Some(Kind::Graphviz),
+            _ => None,
+        }
+    }
+
+    /// The program that renders it, and where to get it.
+    fn program(self) -> (&'static str, &'static str) {
+        match self {
+            Kind::Mermaid => ("mmdc", "npm install -g @mermaid-js/mermaid-cli"),
+            Kind::Graphviz => ("dot", "install Graphviz"),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Mermaid => "Mermaid",
+            Kind::Graphviz => "Graphviz",
+        })
+    }
+}
+
+/// One diagram in an answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagram {
+    pub kind: Kind,
+    pub source: String,
+}
+
+/// The diagrams in `answer`, in order.
+pub fn find(answer: &str) -> Vec<Diagram> {
+    write_files::fenced_blocks(answer)
+        .into_iter()
+        .filter_map(|(info, source)| {
+            let kind = Kind::from_info(&info)?;
+            (!source.trim().is_empty()).then_some(Diagram { kind, source })
+        })
+        .collect()
+}
+
+/// Where the `n`th (from 1) diagram of a run is saved in `dir`.
+pub fn image_path(dir: &Path, session_id: Option<&str>, n: usize) -> PathBuf {
+    match session_id {
+        Some(id) => dir.join(format!("diagram-{}-{}.png", id.chars().take(8).collect::<String>(), n)),
+        None => dir.join(format!("diagram-{}.png", n)),
+    }
+}
+
+/// Render `diagram` to the PNG `out`.
+pub fn render(diagram: &Diagram, out: &Path) -> Result<()> {
+    let (program, install) = diagram.kind.program();
+    let mut cmd = Command::new(program);
+    // mmdc only reads its input from a file.
+    let input = match diagram.kind {
+        Kind::Mermaid => {
+            let input = out.with_extension("mmd");
+            fs::write(&input, &diagram.source).with_context(|| format!("writing {}", input.display()))?;
+            cmd.arg("--quiet").arg("-i").arg(&input).arg("-o").arg(out);
+            Some(input)
+        }
+        Kind::Graphviz => {
+            cmd.arg("-Tpng").arg("-o").arg(out);
+            None
+        }
+    };
+    let result = (|| {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => anyhow!("`{}` was not found ({} to render {} diagrams)", program, install, diagram.kind),
+                _ => anyhow!(e).context(format!("starting `{}`", program)),
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if input.is_none() {
+                stdin.write_all(diagram.source.as_bytes()).with_context(|| format!("writing to `{}`", program))?;
+            }
+        }
+        let output = child.wait_with_output().with_context(|| format!("waiting for `{}`", program))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("`{}` failed: {}", program, stderr.trim());
+        }
+        Ok(())
+    })();
+    if let Some(input) = input {
+        let _ = fs::remove_file(input);
+    }
+    result
+}
+
+/// An inline image protocol the terminal understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+}
+
+impl Protocol {
+    /// The protocol of the terminal codex-clean is running in, from the
+    /// variables terminals set.
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        if !var("KITTY_WINDOW_ID").is_empty() || var("TERM").contains("kitty") || var("TERM").contains("ghostty") {
+            Some(Protocol::Kitty)
+        } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm" | "vscode") {
+            Some(Protocol::Iterm)
+        } else {
+            None
+        }
+    }
+
+    /// The escape sequence that shows the PNG `png`.
+    pub fn sequence(self, png: &[u8]) -> String {
+        let data = base64(png);
+        match self {
+            Protocol::Iterm => format!("\x1b]1337;File=inline=1;size={}:{}\x07\n", png.len(), data),
+            Protocol::Kitty => {
+                // kitty takes the data 4096 bytes at a time.
+                let chunks: Vec<&str> = data.as_bytes().chunks(4096).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect();
+                let mut out = String::new();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = u8::from(i + 1 < chunks.len());
+                    if i == 0 {
+                        out.push_str(&format!("\x1b_Gf=100,a=T,m={};{}\x1b\\", more, chunk));
+                    } else {
+                        out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                    }
+                }
+                out.push('\n');
+                out
+            }
+        }
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Render every diagram in `answer` into `dir`, reporting each to stderr,
+/// and show them inline with `show`. Returns the images written.
+pub fn render_all(answer: &str, dir: &Path, session_id: Option<&str>, show: Option<Protocol>) -> Vec<PathBuf> {
+    let diagrams = find(answer);
+    if diagrams.is_empty() {
+        return Vec::new();
+    }
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("Warning: can't render diagrams into {}: {}", dir.display(), e);
+        return Vec::new();
+    }
+    let mut written = Vec::new();
+    for (i, diagram) in diagrams.iter().enumerate() {
+        let path = image_path(dir, session_id, i + 1);
+        match render(diagram, &path) {
+            Ok(()) => {
+                eprintln!("Rendered the {} diagram to {}", diagram.kind, path.display());
+                if let Some(protocol) = show {
+                    if let Ok(png) = fs::read(&path) {
+                        print!("{}", protocol.sequence(&png));
+                    }
+                }
+                written.push(path);
+            }
+            Err(e) => eprintln!("Warning: can't render the {} diagram: {:#}", diagram.kind, e),
+        }
+    }
+    let _ = io::stdout().flush();
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagram_blocks_are_found_and_images_encoded() {
+        let answer = "Flow:\n\n```mermaid\ngraph TD; A-->B\n```\n\n```rust\nfn main() {}\n```\n\n```dot\ndigraph { a -> b }\n```\n\n```mermaid\n```";
+        let found = find(answer);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], Diagram { kind: Kind::Mermaid, source: "graph TD; A-->B\n".into() });
+        assert_eq!(found[1].kind, Kind::Graphviz);
+        assert_eq!(image_path(Path::new("out"), Some("0199a213-81c0"), 2), Path::new("out/diagram-0199a213-2.png"));
+
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(Protocol::Iterm.sequence(b"Ma"), "\x1b]1337;File=inline=1;size=2:TWE=\x07\n");
+        let kitty = Protocol::Kitty.sequence(&[0; 6000]);
+        assert!(kitty.starts_with("\x1b_Gf=100,a=T,m=1;AAAA"));
+        assert!(kitty.contains("\x1b\\\x1b_Gm=0;"));
+    }
+}
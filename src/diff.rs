@@ -0,0 +1,263 @@
+//! `codex-clean diff`: compare what two sessions ended up saying.
+//!
+//! Meant for re-running the same prompt after tweaking it or switching
+//! model: by default only each session's final agent message is compared;
+//! `--transcript` compares the whole user/assistant dialogue instead.
+//!
+//! [`DiffAgainst`] is the same diff for a single run (`--diff-against`):
+//! the answer compared with a file it was asked to rewrite.
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use similar::{DiffTag, TextDiff};
+
+use crate::sessions::{self, Role, SessionFile, Transcript};
+use crate::write_files;
+
+/// `--diff-against PATH`: print how the answer differs from PATH rather
+/// than the answer itself.
+#[derive(Debug, Clone)]
+pub struct DiffAgainst {
+    pub path: PathBuf,
+    /// PATH as it was before the run, so edits codex makes to it along the
+    /// way don't hide the change.
+    pub contents: String,
+}
+
+impl DiffAgainst {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).with_context(|| format!("reading --diff-against {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), contents })
+    }
+
+    /// What of `answer` stands in for the file: a code block marked with
+    /// its path (as for `--write-files`), else the longest code block, else
+    /// the whole answer.
+    pub fn proposed(&self, answer: &str) -> String {
+        let named = write_files::parse(answer).into_iter().find(|block| {
+            let block_path = Path::new(&block.path);
+            self.path.ends_with(block_path) || block_path.file_name() == self.path.file_name()
+        });
+        if let Some(block) = named {
+            return block.contents;
+        }
+        let longest = write_files::code_blocks(answer).into_iter().max_by_key(|code| code.len());
+        longest.unwrap_or_else(|| format!("{}\n", answer.trim_end()))
+    }
+
+    /// The unified diff from the file to what `answer` proposes, or `None`
+    /// when they are the same.
+    pub fn render(&self, answer: &str) -> Option<String> {
+        let new = self.proposed(answer);
+        if new == self.contents {
+            return None;
+        }
+        let label = self.path.display().to_string();
+        let diff = render(&label, &self.contents, &format!("{} (answer)", label), &new, Layout::Unified { context: 3 });
+        Some(diff.trim_end().to_string())
+    }
+}
+
+/// What to compare between the two sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content {
+    FinalMessage,
+    Transcript,
+}
+
+/// How to lay the differences out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `diff -u` style, with this many lines of context.
+    Unified { context: usize },
+    /// `sdiff` style, in this many columns overall.
+    SideBySide { width: usize },
+}
+
+/// Print the diff of sessions `a` and `b` (IDs, unique prefixes, or `@N`
+/// for the Nth most recent). Returns 0 when they match and 1 when they
+/// differ, like `diff(1)`.
+pub fn run(a: &str, b: &str, content: Content, layout: Layout) -> Result<i32> {
+    let a = sessions::lookup_session(a)?;
+    let b = sessions::lookup_session(b)?;
+    let old = session_text(&a, content)?;
+    let new = session_text(&b, content)?;
+    if old == new {
+        eprintln!("No differences between {} and {}.", a.id, b.id);
+        return Ok(0);
+    }
+    print!("{}", render(&a.id, &old, &b.id, &new, layout));
+    Ok(1)
+}
+
+fn session_text(session: &SessionFile, content: Content) -> Result<String> {
+    let transcript = sessions::read_transcript(&session.path)?;
+    let text = match content {
+        Content::FinalMessage => final_message(&transcript).unwrap_or_default(),
+        Content::Transcript => transcript_text(&transcript),
+    };
+    if text.is_empty() {
+        eprintln!("Warning: session {} has no recorded agent messages.", session.id);
+    }
+    Ok(text)
+}
+
+fn final_message(transcript: &Transcript) -> Option<String> {
+    let last = transcript.entries.iter().rev().find(|e| e.role == Role::Assistant)?;
+    Some(format!("{}\n", last.text.trim_end()))
+}
+
+/// The dialogue with `[user]` / `[assistant]` markers, as `fork` frames it.
+fn transcript_text(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    for (i, entry) in transcript.entries.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let who = match entry.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+        let _ = writeln!(out, "[{}]", who);
+        let _ = writeln!(out, "{}", entry.text.trim_end());
+    }
+    out
+}
+
+pub fn render(a_label: &str, old: &str, b_label: &str, new: &str, layout: Layout) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    match layout {
+        Layout::Unified { context } => diff
+            .unified_diff()
+            .context_radius(context)
+            .header(a_label, b_label)
+            .to_string(),
+        Layout::SideBySide { width } => side_by_side(&diff, (a_label, old), (b_label, new), width),
+    }
+}
+
+fn side_by_side(
+    diff: &TextDiff<'_, '_, str>,
+    (a_label, old): (&str, &str),
+    (b_label, new): (&str, &str),
+    width: usize,
+) -> String {
+    // Two columns and a 3-column gutter holding the change marker.
+    let col = width.saturating_sub(3).max(20) / 2;
+    let old: Vec<&str> = old.split_terminator('\n').collect();
+    let new: Vec<&str> = new.split_terminator('\n').collect();
+    let mut out = String::new();
+    row(&mut out, a_label, ' ', b_label, col);
+    let _ = writeln!(out, "{}   {}", "-".repeat(col), "-".repeat(col));
+    for op in diff.ops() {
+        let (tag, o, n) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => {
+                for (l, r) in old[o].iter().zip(&new[n]) {
+                    row(&mut out, l, ' ', r, col);
+                }
+            }
+            DiffTag::Delete => old[o].iter().for_each(|l| row(&mut out, l, '<', "", col)),
+            DiffTag::Insert => new[n].iter().for_each(|r| row(&mut out, "", '>', r, col)),
+            DiffTag::Replace => {
+                let (l, r) = (&old[o], &new[n]);
+                for i in 0..l.len().max(r.len()) {
+                    match (l.get(i), r.get(i)) {
+                        (Some(l), Some(r)) => row(&mut out, l, '|', r, col),
+                        (Some(l), None) => row(&mut out, l, '<', "", col),
+                        (None, Some(r)) => row(&mut out, "", '>', r, col),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn row(out: &mut String, left: &str, marker: char, right: &str, col: usize) {
+    let line = format!("{:<col$} {} {}", clip(left, col), marker, clip(right, col), col = col);
+    let _ = writeln!(out, "{}", line.trim_end());
+}
+
+/// Cut `s` to `width` characters, marking the cut with `…`.
+fn clip(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    let mut cut: String = s.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::TranscriptEntry;
+
+    fn transcript(entries: &[(Role, &str)]) -> Transcript {
+        Transcript {
+            session_id: None,
+            agent: None,
+            entries: entries
+                .iter()
+                .map(|(role, text)| TranscriptEntry { role: *role, text: text.to_string() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn final_message_is_the_last_assistant_entry() {
+        let t = transcript(&[
+            (Role::User, "q"),
+            (Role::Assistant, "a1"),
+            (Role::Assistant, "a2\n\n"),
+            (Role::User, "q2"),
+        ]);
+        assert_eq!(final_message(&t).as_deref(), Some("a2\n"));
+        assert_eq!(final_message(&transcript(&[(Role::User, "q")])), None);
+        assert_eq!(transcript_text(&t), "[user]\nq\n\n[assistant]\na1\n\n[assistant]\na2\n\n[user]\nq2\n");
+    }
+
+    #[test]
+    fn unified_diff_has_headers_and_hunks() {
+        let out = render("a", "one\ntwo\nthree\n", "b", "one\n2\nthree\n", Layout::Unified { context: 3 });
+        assert_eq!(out, "--- a\n+++ b\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n");
+    }
+
+    #[test]
+    fn side_by_side_marks_changes() {
+        let out = render("a", "same\nold\ngone\n", "b", "same\nnew\n", Layout::SideBySide { width: 23 });
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "a            b");
+        assert_eq!(lines[2], "same         same");
+        assert_eq!(lines[3], "old        | new");
+        assert_eq!(lines[4], "gone       <");
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn diff_against_picks_the_file_the_answer_proposes() {
+        let against = DiffAgainst { path: PathBuf::from("src/lib.rs"), contents: "fn a() {}\n".into() };
+        let named = "Sure.\n\n```sh\ncargo test --all-features\n```\n\n```rust src/lib.rs\nfn b() {}\n```\n";
+        assert_eq!(against.proposed(named), "fn b() {}\n");
+        let unnamed = "```sh\ncargo test\n```\n\n```rust\nfn a() {}\nfn c() {}\n```\n";
+        assert_eq!(against.proposed(unnamed), "fn a() {}\nfn c() {}\n");
+        assert_eq!(against.proposed("fn a() {}"), "fn a() {}\n");
+
+        assert_eq!(against.render("fn a() {}"), None);
+        assert_eq!(
+            against.render(named).unwrap(),
+            "--- src/lib.rs\n+++ src/lib.rs (answer)\n@@ -1 +1 @@\n-fn a() {}\n+fn b() {}"
+        );
+    }
+
+    #[test]
+    fn clip_marks_cut_lines() {
+        assert_eq!(clip("abcdef", 4), "abc…");
+        assert_eq!(clip("abcd", 4), "abcd");
+    }
+}
@@ -0,0 +1,126 @@
+//! The error type of the library API.
+//!
+//! Internally codex-clean reports failures with `anyhow`, which suits a
+//! CLI printing them. Programs embedding it need to tell failures apart, so
+//! the library entry points ([`crate::runner::run`] and the backends'
+//! spawn) return or wrap an [`Error`]; anything without a variant of its
+//! own arrives as [`Error::Other`]. The binary maps each variant to an exit
+//! code with [`Error::exit_code`].
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::control::CANCELLED_EXIT_CODE;
+use crate::events::ParseError;
+use crate::output::CodexOutput;
+use crate::runner::TIMEOUT_EXIT_CODE;
+
+#[derive(Debug)]
+pub enum Error {
+    /// There is no `codex` on PATH.
+    CodexNotFound,
+    /// codex (or the ssh / container CLI in front of it) couldn't be started.
+    SpawnFailed(io::Error),
+    /// codex's output couldn't be read.
+    StreamParse(ParseError),
+    /// codex was killed for running longer than this.
+    Timeout(Duration),
+    /// The run was stopped by `codex-clean cancel` or a cancel handle;
+    /// `partial` is what codex had produced by then.
+    Cancelled { partial: Box<CodexOutput> },
+    /// codex exited with `code`.
+    ChildFailed { code: i32, stderr: String },
+    /// The prompt was empty.
+    EmptyPrompt,
+    /// A `[quota]` limit has been reached (see [`crate::usage`]).
+    QuotaExceeded(String),
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// The process exit code the CLI uses for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::CodexNotFound => 127,
+            Self::Timeout(_) => TIMEOUT_EXIT_CODE,
+            Self::Cancelled { .. } => CANCELLED_EXIT_CODE,
+            Self::ChildFailed { code, .. } => *code,
+            // EX_TEMPFAIL, as when every seat is cooling: try again later.
+            Self::QuotaExceeded(_) => 75,
+            Self::SpawnFailed(_) | Self::StreamParse(_) | Self::EmptyPrompt | Self::Other(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CodexNotFound => write!(f, "codex not found on PATH; install the Codex CLI (https://github.com/openai/codex)"),
+            Self::SpawnFailed(e) => write!(f, "Failed to spawn codex process: {}", e),
+            Self::StreamParse(e) => write!(f, "Failed to read codex stdout: {}", e),
+            Self::Timeout(limit) => write!(f, "codex was killed after running longer than {}s", limit.as_secs()),
+            Self::Cancelled { .. } => write!(f, "the run was cancelled"),
+            Self::ChildFailed { code, stderr } if stderr.trim().is_empty() => write!(f, "codex exited with code {}", code),
+            Self::ChildFailed { code, stderr } => write!(f, "codex exited with code {}: {}", code, stderr.trim()),
+            Self::EmptyPrompt => write!(f, "Empty prompt provided"),
+            Self::QuotaExceeded(why) => write!(f, "{}; pass --override-quota to run anyway", why),
+            Self::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SpawnFailed(e) => Some(e),
+            Self::StreamParse(e) => Some(e),
+            Self::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Recovers the [`Error`] (or [`ParseError`]) an `anyhow` chain was
+/// started with, however much context was added on the way up.
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(e) => e,
+        };
+        match e.downcast::<ParseError>() {
+            Ok(err) => Self::StreamParse(err),
+            Err(e) => Self::Other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn typed_errors_survive_anyhow_context() {
+        let e: anyhow::Error = Err::<(), _>(Error::CodexNotFound).context("starting codex").unwrap_err();
+        let e = Error::from(e);
+        assert!(matches!(e, Error::CodexNotFound));
+        assert_eq!(e.exit_code(), 127);
+
+        let e = Error::from(anyhow::anyhow!("something else"));
+        assert!(matches!(&e, Error::Other(_)));
+        assert_eq!((e.to_string(), e.exit_code()), ("something else".to_string(), 1));
+    }
+
+    #[test]
+    fn exit_codes_follow_the_cli() {
+        assert_eq!(Error::Timeout(Duration::from_secs(5)).exit_code(), 124);
+        assert_eq!(Error::Cancelled { partial: Default::default() }.exit_code(), 130);
+        let failed = Error::ChildFailed { code: 3, stderr: "boom\n".into() };
+        assert_eq!((failed.exit_code(), failed.to_string()), (3, "codex exited with code 3: boom".to_string()));
+        assert_eq!(Error::QuotaExceeded("daily token quota reached".into()).exit_code(), 75);
+    }
+}
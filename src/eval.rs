@@ -0,0 +1,802 @@
+//! `codex-clean eval <suite.toml>`: a small prompt-regression harness.
+//!
+//! A suite lists cases, each a prompt with what its answer must (and must
+//! not) contain, and optionally a JSON schema the answer has to satisfy.
+//! Every case is run once per model and repetition, several at a time,
+//! and scored; the results are printed as a table, written as a JSON
+//! report, and optionally as JUnit XML for CI dashboards.
+//!
+//! ```toml
+//! parallel = 4
+//! repeat = 3
+//! models = ["o3", "gpt-5.5"]
+//! args = ["--sandbox", "read-only"]
+//! timeout_seconds = 300
+//!
+//! [[case]]
+//! name = "empty-input"
+//! prompt = "Is parse_range safe for empty input? End with VERDICT: yes or no"
+//! expect = ['VERDICT: (yes|no)']
+//! reject = ['(?i)i cannot']
+//!
+//! [[case]]
+//! name = "json-summary"
+//! prompt = "Summarise Cargo.toml as JSON with name and version"
+//! schema = "schemas/summary.json"
+//! repeat = 1
+//! ```
+//!
+//! The config and the selected profile apply as they do to any run: their
+//! codex arguments go ahead of the suite's `args`, and the prompt is
+//! wrapped and preprocessed as usual. A job whose suite or case picks the
+//! model leaves out the config's model, since codex rejects a second `-m`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{has_model_flag, without_model_flag};
+use crate::runner::{self, format_elapsed, Mode, RunOptions};
+
+/// How many cases run at once unless the suite or `--parallel` says.
+pub const DEFAULT_PARALLEL: usize = 4;
+
+/// The suite file as written.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuiteFile {
+    #[serde(default)]
+    parallel: Option<usize>,
+    #[serde(default)]
+    repeat: Option<u32>,
+    #[serde(default)]
+    models: Vec<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    timeout_seconds: Option<u64>,
+    #[serde(default, rename = "case")]
+    cases: Vec<CaseFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseFile {
+    name: String,
+    prompt: String,
+    #[serde(default)]
+    expect: Vec<String>,
+    #[serde(default)]
+    reject: Vec<String>,
+    #[serde(default)]
+    schema: Option<PathBuf>,
+    #[serde(default)]
+    models: Vec<String>,
+    #[serde(default)]
+    repeat: Option<u32>,
+}
+
+/// A loaded suite, its patterns compiled and schemas read.
+#[derive(Debug)]
+pub struct Suite {
+    /// The file name, without `.toml`.
+    pub name: String,
+    pub parallel: usize,
+    /// Codex arguments from the config and profile, ahead of `args`.
+    pub config_args: Vec<String>,
+    pub args: Vec<String>,
+    pub timeout: Option<Duration>,
+    pub cases: Vec<Case>,
+}
+
+#[derive(Debug)]
+pub struct Case {
+    pub name: String,
+    pub prompt: String,
+    pub expect: Vec<Regex>,
+    pub reject: Vec<Regex>,
+    pub schema: Option<Value>,
+    /// Empty means codex's default model.
+    pub models: Vec<String>,
+    pub repeat: u32,
+}
+
+impl Suite {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "eval".into());
+        Self::parse(&name, &raw, path.parent().unwrap_or(Path::new(".")))
+            .with_context(|| format!("loading suite {}", path.display()))
+    }
+
+    /// Parse a suite; schema paths are relative to `dir`.
+    pub fn parse(name: &str, raw: &str, dir: &Path) -> Result<Self> {
+        let file: SuiteFile = toml::from_str(raw)?;
+        if file.cases.is_empty() {
+            bail!("the suite has no [[case]] entries");
+        }
+        let mut cases = Vec::with_capacity(file.cases.len());
+        for case in file.cases {
+            if cases.iter().any(|c: &Case| c.name == case.name) {
+                bail!("case '{}' is defined twice", case.name);
+            }
+            if case.prompt.trim().is_empty() {
+                bail!("case '{}' has an empty prompt", case.name);
+            }
+            let compile = |patterns: &[String]| -> Result<Vec<Regex>> {
+                patterns
+                    .iter()
+                    .map(|p| Regex::new(p).with_context(|| format!("case '{}': invalid pattern '{}'", case.name, p)))
+                    .collect()
+            };
+            let schema = match &case.schema {
+                Some(rel) => {
+                    let path = dir.join(rel);
+                    let raw = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+                    Some(serde_json::from_str(&raw).with_context(|| format!("parsing {}", path.display()))?)
+                }
+                None => None,
+            };
+            cases.push(Case {
+                expect: compile(&case.expect)?,
+                reject: compile(&case.reject)?,
+                schema,
+                models: if case.models.is_empty() { file.models.clone() } else { case.models },
+                repeat: case.repeat.or(file.repeat).unwrap_or(1).max(1),
+                name: case.name,
+                prompt: case.prompt,
+            });
+        }
+        Ok(Self {
+            name: name.to_string(),
+            parallel: file.parallel.unwrap_or(DEFAULT_PARALLEL).max(1),
+            config_args: Vec::new(),
+            args: file.args,
+            timeout: file.timeout_seconds.map(Duration::from_secs),
+            cases,
+        })
+    }
+
+    /// Every (case, model, repetition) to run, in suite order.
+    fn jobs(&self) -> Vec<Job<'_>> {
+        let mut jobs = Vec::new();
+        for case in &self.cases {
+            let models: Vec<Option<&str>> = if case.models.is_empty() {
+                vec![None]
+            } else {
+                case.models.iter().map(|m| Some(m.as_str())).collect()
+            };
+            for model in models {
+                for run in 1..=case.repeat {
+                    jobs.push(Job { case, model, run });
+                }
+            }
+        }
+        jobs
+    }
+}
+
+struct Job<'a> {
+    case: &'a Case,
+    model: Option<&'a str>,
+    run: u32,
+}
+
+/// The result of one run of one case.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub case: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Which repetition, from 1.
+    pub run: u32,
+    pub passed: bool,
+    /// Why it failed, one entry per unmet expectation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+}
+
+impl CaseResult {
+    /// `case`, `case [model]`, with ` #run` when there are repetitions.
+    pub fn label(&self, with_run: bool) -> String {
+        let mut label = self.case.clone();
+        if let Some(model) = &self.model {
+            let _ = write!(label, " [{}]", model);
+        }
+        if with_run {
+            let _ = write!(label, " #{}", self.run);
+        }
+        label
+    }
+}
+
+/// What `eval` writes with `--report`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub suite: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub results: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&raw).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let raw = serde_json::to_string_pretty(self).context("serialising the eval report")?;
+        fs::write(path, raw).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+/// Run every case in `suite`, `suite.parallel` at a time, with `opts`
+/// (its backend in particular). Progress goes to stderr.
+pub fn run_suite(suite: &Suite, opts: &RunOptions) -> EvalReport {
+    let started_at = Utc::now();
+    let clock = Instant::now();
+    let jobs = suite.jobs();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<CaseResult>>> = Mutex::new(vec![None; jobs.len()]);
+    let opts = RunOptions { timeout: suite.timeout.or(opts.timeout), ..opts.clone() };
+    std::thread::scope(|scope| {
+        for _ in 0..suite.parallel.min(jobs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(i) else { break };
+                let result = run_job(suite, job, &opts);
+                let n = done.fetch_add(1, Ordering::SeqCst) + 1;
+                eprintln!(
+                    "[{}/{}] {}: {} ({})",
+                    n,
+                    jobs.len(),
+                    result.label(job.case.repeat > 1),
+                    if result.passed { "pass" } else { "FAIL" },
+                    format_elapsed(Duration::from_millis(result.duration_ms))
+                );
+                results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(result);
+            });
+        }
+    });
+    EvalReport {
+        suite: suite.name.clone(),
+        started_at,
+        duration_ms: clock.elapsed().as_millis() as u64,
+        results: results.into_inner().unwrap_or_else(|e| e.into_inner()).into_iter().flatten().collect(),
+    }
+}
+
+/// The codex arguments for `job`: the config's, the suite's, then the
+/// job's model.
+fn job_args(suite: &Suite, job: &Job) -> Vec<String> {
+    let mut args = if job.model.is_some() || has_model_flag(&suite.args) {
+        without_model_flag(&suite.config_args)
+    } else {
+        suite.config_args.clone()
+    };
+    args.extend(suite.args.iter().cloned());
+    if let Some(model) = job.model {
+        args.extend(["-m".to_string(), model.to_string()]);
+    }
+    args
+}
+
+fn run_job(suite: &Suite, job: &Job, opts: &RunOptions) -> CaseResult {
+    let args = job_args(suite, job);
+    let clock = Instant::now();
+    let outcome = runner::run(&args, &job.case.prompt, Mode::Exec, opts);
+    let mut result = CaseResult {
+        case: job.case.name.clone(),
+        model: job.model.map(String::from),
+        run: job.run,
+        duration_ms: clock.elapsed().as_millis() as u64,
+        ..Default::default()
+    };
+    match outcome {
+        Ok(output) => {
+            result.session_id = output.session_id.clone();
+            result.input_tokens = output.usage.map(|u| u.0);
+            result.output_tokens = output.usage.map(|u| u.2);
+            result.failures = score(job.case, &output.aggregated_message());
+        }
+        Err(e) => result.failures.push(format!("codex failed: {}", e)),
+    }
+    result.passed = result.failures.is_empty();
+    result
+}
+
+/// What `answer` gets wrong for `case`; empty when it passes.
+pub fn score(case: &Case, answer: &str) -> Vec<String> {
+    let mut failures = Vec::new();
+    if answer.trim().is_empty() {
+        failures.push("no answer".to_string());
+        return failures;
+    }
+    for re in &case.expect {
+        if !re.is_match(answer) {
+            failures.push(format!("answer doesn't match /{}/", re.as_str()));
+        }
+    }
+    for re in &case.reject {
+        if let Some(m) = re.find(answer) {
+            failures.push(format!("answer matches /{}/: {:?}", re.as_str(), m.as_str()));
+        }
+    }
+    if let Some(schema) = &case.schema {
+        match serde_json::from_str::<Value>(json_body(answer)) {
+            Ok(value) => failures.extend(check_schema(&value, schema, "$")),
+            Err(e) => failures.push(format!("answer isn't JSON: {}", e)),
+        }
+    }
+    failures
+}
+
+/// The answer without a surrounding code fence.
+fn json_body(answer: &str) -> &str {
+    let trimmed = answer.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// Check `value` against the common core of JSON Schema: `type`, `enum`,
+/// `required`, `properties`, `additionalProperties: false` and `items`.
+pub fn check_schema(value: &Value, schema: &Value, at: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| has_type(value, t)) {
+            errors.push(format!("{} should be {}, not {}", at, allowed.join(" or "), type_name(value)));
+            return errors;
+        }
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            errors.push(format!("{} should be one of {}", at, Value::Array(options.clone())));
+        }
+    }
+    if let Value::Object(map) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !map.contains_key(key) {
+                    errors.push(format!("{} is missing \"{}\"", at, key));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, v) in map {
+            match properties.and_then(|p| p.get(key)) {
+                Some(sub) => errors.extend(check_schema(v, sub, &format!("{}.{}", at, key))),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{} has unexpected \"{}\"", at, key))
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(sub)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            errors.extend(check_schema(item, sub, &format!("{}[{}]", at, i)));
+        }
+    }
+    errors
+}
+
+fn has_type(value: &Value, t: &str) -> bool {
+    match t {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// The pass rates per case and model, a total, and what went wrong.
+pub fn format_summary(report: &EvalReport) -> String {
+    let mut groups: Vec<(String, usize, usize, u64)> = Vec::new();
+    for r in &report.results {
+        let label = r.label(false);
+        let row = match groups.iter_mut().find(|g| g.0 == label) {
+            Some(row) => row,
+            None => {
+                groups.push((label, 0, 0, 0));
+                groups.last_mut().unwrap()
+            }
+        };
+        row.1 += r.passed as usize;
+        row.2 += 1;
+        row.3 += r.duration_ms;
+    }
+    let total = report.results.len();
+    let width = groups.iter().map(|g| g.0.len()).max().unwrap_or(0).max("TOTAL".len());
+    let mut out = format!("{:<width$}  {:>7}  {:>5}  {:>8}\n", "CASE", "PASSED", "RATE", "AVG TIME", width = width);
+    let row = |out: &mut String, name: &str, passed: usize, runs: usize, ms: Option<u64>| {
+        let line = format!(
+            "{:<width$}  {:>7}  {:>5}  {:>8}",
+            name,
+            format!("{}/{}", passed, runs),
+            format!("{:.0}%", rate(passed, runs) * 100.0),
+            ms.map_or_else(String::new, |ms| format_elapsed(Duration::from_millis(ms))),
+            width = width
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    };
+    for (name, passed, runs, ms) in &groups {
+        row(&mut out, name, *passed, *runs, Some(ms / (*runs as u64).max(1)));
+    }
+    row(&mut out, "TOTAL", report.passed(), total, None);
+
+    let failed: Vec<&CaseResult> = report.results.iter().filter(|r| !r.passed).collect();
+    if !failed.is_empty() {
+        out.push_str("\nFailures:\n");
+        for r in failed {
+            let _ = writeln!(out, "  {}: {}", r.label(true), r.failures.join("; "));
+        }
+    }
+    out
+}
+
+/// `passed` out of `runs`, as a fraction (1 when nothing ran).
+pub fn rate(passed: usize, runs: usize) -> f64 {
+    if runs == 0 {
+        1.0
+    } else {
+        passed as f64 / runs as f64
+    }
+}
+
+/// The report as JUnit XML: one testsuite, one testcase per run.
+pub fn junit_xml(report: &EvalReport) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+        xml_escape(&report.suite),
+        report.results.len(),
+        report.results.len() - report.passed(),
+        report.duration_ms as f64 / 1000.0,
+        report.started_at.format("%Y-%m-%dT%H:%M:%S"),
+    );
+    for r in &report.results {
+        let _ = write!(
+            out,
+            "  <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&report.suite),
+            xml_escape(&r.label(true)),
+            r.duration_ms as f64 / 1000.0
+        );
+        if r.passed {
+            out.push_str("/>\n");
+        } else {
+            let _ = write!(
+                out,
+                ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+                xml_escape(r.failures.first().map(String::as_str).unwrap_or("failed")),
+                xml_escape(&r.failures.join("\n"))
+            );
+        }
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// How a case (or the whole suite) did in the baseline and now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    /// `case [model]`, or `TOTAL` for the suite.
+    pub label: String,
+    pub before: (usize, usize),
+    pub after: (usize, usize),
+}
+
+impl Regression {
+    /// The drop in pass rate, in percentage points.
+    pub fn points(&self) -> f64 {
+        (rate(self.before.0, self.before.1) - rate(self.after.0, self.after.1)) * 100.0
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {}/{} -> {}/{} ({:.0}% -> {:.0}%)",
+            self.label,
+            self.before.0,
+            self.before.1,
+            self.after.0,
+            self.after.1,
+            rate(self.before.0, self.before.1) * 100.0,
+            rate(self.after.0, self.after.1) * 100.0
+        )
+    }
+}
+
+/// Runs passed and made, keyed by `case [model]`.
+pub fn pass_counts(report: &EvalReport) -> BTreeMap<String, (usize, usize)> {
+    let mut counts: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for r in &report.results {
+        let entry = counts.entry(r.label(false)).or_default();
+        entry.0 += r.passed as usize;
+        entry.1 += 1;
+    }
+    counts
+}
+
+/// The cases whose pass rate fell by more than `max_points` since
+/// `baseline`, and the suite's own when its total did. Cases new to the
+/// suite, or gone from it, aren't regressions.
+pub fn regressions(baseline: &EvalReport, current: &EvalReport, max_points: f64) -> Vec<Regression> {
+    let before = pass_counts(baseline);
+    let after = pass_counts(current);
+    let mut found: Vec<Regression> = after
+        .iter()
+        .filter_map(|(label, &after)| {
+            let &before = before.get(label)?;
+            Some(Regression { label: label.clone(), before, after })
+        })
+        .filter(|r| r.points() > max_points)
+        .collect();
+    // Only compare totals over the cases both reports ran.
+    let total = |counts: &BTreeMap<String, (usize, usize)>, other: &BTreeMap<String, (usize, usize)>| {
+        counts
+            .iter()
+            .filter(|(label, _)| other.contains_key(*label))
+            .fold((0, 0), |acc, (_, c)| (acc.0 + c.0, acc.1 + c.1))
+    };
+    let suite = Regression { label: "TOTAL".into(), before: total(&before, &after), after: total(&after, &before) };
+    if suite.points() > max_points {
+        found.push(suite);
+    }
+    found
+}
+
+/// What `eval` writes and compares against.
+#[derive(Debug, Clone)]
+pub struct Outputs<'a> {
+    /// The JSON report.
+    pub report: &'a Path,
+    pub junit: Option<&'a Path>,
+    /// A previous report to compare with.
+    pub baseline: Option<&'a Path>,
+    /// (`baseline`) How many percentage points a pass rate may drop.
+    pub max_regression: f64,
+}
+
+/// `codex-clean eval`: run the suite, with `config_args` (the codex
+/// arguments from the config and profile) ahead of its own, print the
+/// summary, write the reports. Returns 0 when every run passed and 1
+/// otherwise; against a baseline, 1 only when a pass rate fell too far.
+pub fn run(suite_path: &Path, parallel: Option<usize>, outputs: &Outputs, config_args: &[String], opts: &RunOptions) -> Result<i32> {
+    let mut suite = Suite::load(suite_path)?;
+    suite.config_args = config_args.to_vec();
+    if let Some(n) = parallel {
+        suite.parallel = n.max(1);
+    }
+    let baseline = outputs.baseline.map(EvalReport::load).transpose()?;
+    let report = run_suite(&suite, opts);
+    print!("\n{}", format_summary(&report));
+    report.save(outputs.report)?;
+    eprintln!("Wrote {}", outputs.report.display());
+    if let Some(path) = outputs.junit {
+        fs::write(path, junit_xml(&report)).with_context(|| format!("writing {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    let Some(baseline) = baseline else {
+        return Ok(if report.passed() == report.results.len() { 0 } else { 1 });
+    };
+    if baseline.suite != report.suite {
+        eprintln!("Warning: the baseline is for suite '{}', not '{}'", baseline.suite, report.suite);
+    }
+    let found = regressions(&baseline, &report, outputs.max_regression);
+    println!(
+        "\nBaseline ({}): {}/{} passed, now {}/{}",
+        baseline.started_at.format("%Y-%m-%d %H:%M"),
+        baseline.passed(),
+        baseline.results.len(),
+        report.passed(),
+        report.results.len()
+    );
+    if found.is_empty() {
+        println!("No regressions.");
+        return Ok(0);
+    }
+    println!("Regressions:");
+    for r in &found {
+        println!("  {}", r.describe());
+    }
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SUITE: &str = r#"
+repeat = 2
+models = ["o3", "gpt-5.5"]
+
+[[case]]
+name = "verdict"
+prompt = "Is it safe?"
+expect = ['VERDICT: (yes|no)']
+reject = ['(?i)i cannot']
+
+[[case]]
+name = "json"
+prompt = "Summarise as JSON"
+models = ["o3"]
+repeat = 1
+"#;
+
+    #[test]
+    fn suites_expand_into_case_model_and_repetition() {
+        let suite = Suite::parse("smoke", SUITE, Path::new(".")).unwrap();
+        assert_eq!(suite.parallel, DEFAULT_PARALLEL);
+        let jobs: Vec<String> =
+            suite.jobs().iter().map(|j| format!("{} {} {}", j.case.name, j.model.unwrap_or("-"), j.run)).collect();
+        assert_eq!(jobs, ["verdict o3 1", "verdict o3 2", "verdict gpt-5.5 1", "verdict gpt-5.5 2", "json o3 1"]);
+
+        assert!(Suite::parse("x", "", Path::new(".")).unwrap_err().to_string().contains("no [[case]]"));
+        let twice = "[[case]]\nname = 'a'\nprompt = 'p'\n[[case]]\nname = 'a'\nprompt = 'q'\n";
+        assert!(Suite::parse("x", twice, Path::new(".")).unwrap_err().to_string().contains("defined twice"));
+        assert!(Suite::parse("x", "[[case]]\nname = 'a'\nprompt = 'p'\nexpect = ['(']\n", Path::new(".")).is_err());
+        assert!(Suite::parse("x", "[[case]]\nname = 'a'\nprompt = 'p'\nexpcet = ['x']\n", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn config_args_go_first_without_their_model_when_the_job_has_one() {
+        let mut suite = Suite::parse("smoke", SUITE, Path::new(".")).unwrap();
+        suite.config_args = ["-m", "gpt-5.5", "--sandbox", "read-only"].map(String::from).to_vec();
+        suite.args = vec!["--full-auto".into()];
+        let jobs = suite.jobs();
+        assert_eq!(job_args(&suite, &jobs[0]), ["--sandbox", "read-only", "--full-auto", "-m", "o3"]);
+        suite.cases[0].models.clear();
+        assert_eq!(job_args(&suite, &suite.jobs()[0]), ["-m", "gpt-5.5", "--sandbox", "read-only", "--full-auto"]);
+        suite.args = vec!["--model=o3".into()];
+        assert_eq!(job_args(&suite, &suite.jobs()[0]), ["--sandbox", "read-only", "--model=o3"]);
+    }
+
+    #[test]
+    fn answers_are_scored_against_patterns_and_schema() {
+        let suite = Suite::parse("smoke", SUITE, Path::new(".")).unwrap();
+        let verdict = &suite.cases[0];
+        assert!(score(verdict, "Yes.\nVERDICT: yes").is_empty());
+        assert_eq!(score(verdict, "I cannot tell."), [
+            "answer doesn't match /VERDICT: (yes|no)/".to_string(),
+            "answer matches /(?i)i cannot/: \"I cannot\"".to_string(),
+        ]);
+        assert_eq!(score(verdict, "  "), ["no answer"]);
+
+        let mut json_case = Suite::parse("smoke", SUITE, Path::new(".")).unwrap().cases.remove(1);
+        json_case.schema = Some(json!({
+            "type": "object",
+            "required": ["name", "version"],
+            "properties": { "name": { "type": "string" }, "deps": { "type": "array", "items": { "type": "string" } } }
+        }));
+        assert!(score(&json_case, "```json\n{\"name\": \"demo\", \"version\": \"1.0\"}\n```").is_empty());
+        assert_eq!(score(&json_case, "{\"name\": 3, \"deps\": [\"a\", 1]}"), [
+            "$ is missing \"version\"".to_string(),
+            "$.deps[1] should be string, not number".to_string(),
+            "$.name should be string, not number".to_string(),
+        ]);
+        assert!(score(&json_case, "not json")[0].starts_with("answer isn't JSON"));
+    }
+
+    fn result(case: &str, model: Option<&str>, run: u32, failure: Option<&str>) -> CaseResult {
+        CaseResult {
+            case: case.into(),
+            model: model.map(String::from),
+            run,
+            passed: failure.is_none(),
+            failures: failure.into_iter().map(String::from).collect(),
+            duration_ms: 2000,
+            ..Default::default()
+        }
+    }
+
+    fn report() -> EvalReport {
+        EvalReport {
+            suite: "smoke".into(),
+            started_at: DateTime::parse_from_rfc3339("2026-10-14T12:00:00Z").unwrap().to_utc(),
+            duration_ms: 4500,
+            results: vec![
+                result("verdict", Some("o3"), 1, None),
+                result("verdict", Some("o3"), 2, Some("answer doesn't match /VERDICT/")),
+                result("json", None, 1, None),
+            ],
+        }
+    }
+
+    #[test]
+    fn summary_shows_rates_then_failures() {
+        assert_eq!(
+            format_summary(&report()),
+            "CASE           PASSED   RATE  AVG TIME\n\
+             verdict [o3]      1/2    50%        2s\n\
+             json              1/1   100%        2s\n\
+             TOTAL             2/3    67%\n\
+             \n\
+             Failures:\n  verdict [o3] #2: answer doesn't match /VERDICT/\n"
+        );
+    }
+
+    #[test]
+    fn junit_has_a_testcase_per_run() {
+        let xml = junit_xml(&report());
+        assert!(xml.contains("<testsuite name=\"smoke\" tests=\"3\" failures=\"1\" time=\"4.500\""), "{}", xml);
+        assert!(xml.contains("<testcase classname=\"smoke\" name=\"verdict [o3] #1\" time=\"2.000\"/>"));
+        assert!(xml.contains("<failure message=\"answer doesn&apos;t match /VERDICT/\">"));
+    }
+
+    #[test]
+    fn regressions_are_drops_beyond_the_threshold() {
+        let baseline = report();
+        let mut current = report();
+        current.results[0] = result("verdict", Some("o3"), 1, Some("no answer"));
+        current.results.push(result("new", None, 1, Some("no answer")));
+
+        let found = regressions(&baseline, &current, 0.0);
+        assert_eq!(found.iter().map(Regression::describe).collect::<Vec<_>>(), [
+            "verdict [o3]: 1/2 -> 0/2 (50% -> 0%)",
+            "TOTAL: 2/3 -> 1/3 (67% -> 33%)",
+        ]);
+        // The case fell 50 points, the suite 33.
+        assert_eq!(regressions(&baseline, &current, 40.0).len(), 1);
+        assert!(regressions(&baseline, &current, 50.0).is_empty());
+        assert!(regressions(&current, &baseline, 0.0).is_empty());
+    }
+}
@@ -1,5 +1,11 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
 use serde_json::Value;
 
+use crate::proto::Translator;
+
 /// Events we care about from codex JSON output
 #[derive(Debug, Clone)]
 pub enum Event {
@@ -13,12 +19,524 @@ pub enum Event {
     },
     TurnFailed { message: String },
     StreamError { message: String },
+    /// A shell command codex ran.
+    CommandExecuted(CommandRun),
+    /// Files codex edited.
+    FilesChanged(Vec<FileChange>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRun {
+    pub command: String,
+    /// `None` if codex didn't report one (e.g. the command was interrupted).
+    pub exit_code: Option<i64>,
+    /// What the command printed, stdout and stderr together, when codex
+    /// passed it on.
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub path: String,
+    /// `add`, `update`, or `delete` as codex reports it.
+    pub kind: String,
+}
+
+/// The event schemas codex has written to stdout, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// `{"id", "msg": {"type": ...}}` events: early `codex exec --json`,
+    /// and `codex proto` to this day.
+    Proto,
+    /// `exec --experimental-json`: `session.created`, and items tagged
+    /// with `item_type` (`assistant_message`, ...).
+    ExperimentalJson,
+    /// `exec --json`: `thread.started`, and items tagged with `type`.
+    Json,
+}
+
+impl Schema {
+    /// The schema `v` can only have come from, if it says.
+    /// Events every JSONL schema shares (`turn.completed`, `error`) don't.
+    pub fn detect(v: &Value) -> Option<Self> {
+        if v.get("msg").and_then(|m| m.get("type")).is_some() {
+            return Some(Self::Proto);
+        }
+        match v.get("type")?.as_str()? {
+            "session.created" => Some(Self::ExperimentalJson),
+            "thread.started" => Some(Self::Json),
+            t if t.starts_with("item.") => {
+                let item = v.get("item")?;
+                if item.get("item_type").is_some() {
+                    Some(Self::ExperimentalJson)
+                } else if item.get("type").is_some() {
+                    Some(Self::Json)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The `type`s [`json_event`] reads; the rest (`turn.started`,
+/// `item.started`, the `item.updated` deltas of a tool-heavy run) make up
+/// most of a stream and are skipped without being parsed.
+const JSON_EVENT_TYPES: [&str; 5] = ["thread.started", "item.completed", "turn.completed", "turn.failed", "error"];
+
+/// The `type` of an event written as codex writes them, `{"type":"...",`
+/// first; None for anything else, which gets parsed in full. So does a
+/// line that might hold more than one event (see [`resync`]), lest the
+/// second be skipped along with the first.
+fn leading_type(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix(r#"{"type":""#)?;
+    let end = rest.find('"')?;
+    let event_type = &rest[..end];
+    let plain = event_type.bytes().all(|b| b.is_ascii_lowercase() || b == b'.' || b == b'_');
+    (plain && !rest.contains(r#"{"type":""#)).then_some(event_type)
+}
+
+/// Turns codex stdout lines into [`Event`]s, whichever schema wrote them.
+/// The schema is taken from the first event that identifies it; until then
+/// lines are read as [`Schema::Json`], whose shared events read the same in
+/// every JSONL schema. Recordings made with older codex releases keep
+/// parsing this way after codex changes its output.
+#[derive(Debug, Default)]
+pub struct EventDecoder {
+    schema: Option<Schema>,
+    proto: Translator,
+}
+
+impl EventDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The schema detected so far.
+    pub fn schema(&self) -> Option<Schema> {
+        self.schema
+    }
+
+    /// The event on `line`, if it carries one we care about. Lines that
+    /// aren't JSON are skipped like unknown events.
+    pub fn feed(&mut self, line: &str) -> Option<Event> {
+        self.decode(line).ok().flatten()
+    }
+
+    /// [`Self::feed`], failing on lines that aren't JSON.
+    pub fn decode(&mut self, line: &str) -> Result<Option<Event>, serde_json::Error> {
+        if self.schema == Some(Schema::Json) && leading_type(line).is_some_and(|t| !JSON_EVENT_TYPES.contains(&t)) {
+            return Ok(None);
+        }
+        let v: Value = serde_json::from_str(line)?;
+        if self.schema.is_none() {
+            self.schema = Schema::detect(&v);
+        }
+        Ok(match self.schema.unwrap_or(Schema::Json) {
+            Schema::Json => json_event(&v),
+            Schema::ExperimentalJson => experimental_json_event(&v),
+            // Proto events are stateful (commands are reported at their end),
+            // so they go through the same translator as `--transport proto`.
+            Schema::Proto => self.proto.feed(line).events.first().and_then(json_event),
+        })
+    }
+}
+
+/// Why [`EventStream`] couldn't produce an event.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the stream failed; the stream ends here.
+    Io(io::Error),
+    /// Line `line` (1-based), `text`, isn't JSON. Later lines are still
+    /// read.
+    InvalidJson { line: usize, text: String, source: serde_json::Error },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "reading codex output: {}", e),
+            Self::InvalidJson { line, source, .. } => write!(f, "line {} is not JSON: {}", line, source),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidJson { source, .. } => Some(source),
+        }
+    }
+}
+
+/// The events in codex's output, read lazily from `reader`. Blank lines and
+/// events codex-clean doesn't know are skipped; lines that aren't JSON are
+/// yielded as errors, after which iteration can carry on.
+///
+/// Each line is read into one buffer that is reused for the next, and
+/// decoded from there, so a stream of hundreds of thousands of events
+/// doesn't cost a `String` per line (`benches/event_stream.rs` compares
+/// this with `BufRead::lines`).
+pub struct EventStream<R> {
+    reader: R,
+    line: Vec<u8>,
+    decoder: EventDecoder,
+    /// Decoded but not yet yielded, when a line held more than one event.
+    pending: VecDeque<Result<Event, ParseError>>,
+    lines_seen: usize,
+    line_number: usize,
+    /// Keep lines for a crash's diagnostics (see [`crate::diagnostics`]).
+    note: bool,
+}
+
+impl<R: BufRead> EventStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_decoder(reader, EventDecoder::new())
+    }
+
+    fn with_decoder(reader: R, decoder: EventDecoder) -> Self {
+        Self { reader, line: Vec::new(), decoder, pending: VecDeque::new(), lines_seen: 0, line_number: 0, note: true }
+    }
+}
+
+impl<R> EventStream<R> {
+    /// The schema detected so far.
+    pub fn schema(&self) -> Option<Schema> {
+        self.decoder.schema()
+    }
+
+    /// Non-blank lines read so far.
+    pub fn lines_seen(&self) -> usize {
+        self.lines_seen
+    }
+}
+
+impl<R: BufRead> Iterator for EventStream<R> {
+    type Item = Result<Event, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(ParseError::Io(e))),
+            }
+            // codex killed partway through a character leaves a line that
+            // isn't UTF-8; it is read with the bad bytes replaced, so the
+            // events before it (and any after) still count.
+            let line = String::from_utf8_lossy(frame(&self.line));
+            if self.note && !line.trim().is_empty() {
+                crate::diagnostics::note_event(&line);
+            }
+            decode_line(&mut self.decoder, &mut self.lines_seen, &mut self.line_number, &line, &mut self.pending);
+        }
+    }
+}
+
+/// Below this, [`decode_parallel`] doesn't bother with threads.
+const PARALLEL_MIN_BYTES: usize = 1 << 20;
+
+/// Everything [`decode_parallel`] found, as [`EventStream`] would have
+/// yielded it.
+#[derive(Debug)]
+pub struct Decoded {
+    pub items: Vec<Result<Event, ParseError>>,
+    pub lines_seen: usize,
+    pub schema: Option<Schema>,
+}
+
+/// Decode a whole recording held in memory, such as a multi-gigabyte
+/// `--record` log read back for analysis, on up to `workers` threads.
+///
+/// Lines are read in order until the schema is known. When it's today's
+/// `--json`, whose events don't depend on the lines before them, the rest
+/// is split into `workers` pieces on line boundaries, decoded at the same
+/// time and put back in order. Other schemas (and small inputs) are
+/// decoded on the calling thread. Either way the result is what
+/// [`EventStream`] gives, line numbers in errors included.
+pub fn decode_parallel(raw: &[u8], workers: usize) -> Decoded {
+    let mut stream = EventStream::new(io::Cursor::new(raw));
+    let mut items = Vec::new();
+    while stream.schema().is_none() {
+        match stream.next() {
+            Some(item) => items.push(item),
+            None => break,
+        }
+    }
+    items.extend(stream.pending.drain(..));
+    let start = stream.reader.position() as usize;
+    let parallel = workers > 1 && raw.len() - start >= PARALLEL_MIN_BYTES && stream.schema() == Some(Schema::Json);
+    if !parallel || items.iter().any(|i| matches!(i, Err(ParseError::Io(_)))) {
+        items.extend(&mut stream);
+        return Decoded { items, lines_seen: stream.lines_seen, schema: stream.schema() };
+    }
+
+    let chunks = split_lines(&raw[start..], workers);
+    // Each part's items, non-blank lines and lines.
+    type Part = (Vec<Result<Event, ParseError>>, usize, usize);
+    let decoded: Vec<Part> = std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let decoder = EventDecoder { schema: Some(Schema::Json), ..Default::default() };
+                    let mut part = EventStream::with_decoder(io::Cursor::new(*chunk), decoder);
+                    part.note = false;
+                    let items: Vec<_> = part.by_ref().collect();
+                    (items, part.lines_seen, part.line_number)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().expect("decoding thread panicked")).collect()
+    });
+
+    let mut lines_seen = stream.lines_seen;
+    let mut line_offset = stream.line_number;
+    for (part, seen, lines) in decoded {
+        lines_seen += seen;
+        for mut item in part {
+            if let Err(ParseError::InvalidJson { line, .. }) = &mut item {
+                *line += line_offset;
+            }
+            // A failed read ends the stream, as it does for `EventStream`.
+            let failed = matches!(item, Err(ParseError::Io(_)));
+            items.push(item);
+            if failed {
+                return Decoded { items, lines_seen, schema: Some(Schema::Json) };
+            }
+        }
+        line_offset += lines;
+    }
+    Decoded { items, lines_seen, schema: Some(Schema::Json) }
+}
+
+/// `raw` in about `pieces` parts, each ending at the end of a line.
+fn split_lines(raw: &[u8], pieces: usize) -> Vec<&[u8]> {
+    let target = raw.len().div_ceil(pieces.max(1)).max(1);
+    let mut parts = Vec::with_capacity(pieces);
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let end = match rest.get(target..).and_then(|tail| tail.iter().position(|&b| b == b'\n')) {
+            Some(newline) => target + newline + 1,
+            None => rest.len(),
+        };
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+    parts
+}
+
+/// `line` without its `\n` or `\r\n`.
+fn frame(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Decode `line` onto the end of `out`. A line that isn't JSON is
+/// searched for events run together or cut short (see [`resync`]) before
+/// it is given up on.
+fn decode_line(
+    decoder: &mut EventDecoder,
+    lines_seen: &mut usize,
+    line_number: &mut usize,
+    line: &str,
+    out: &mut VecDeque<Result<Event, ParseError>>,
+) {
+    *line_number += 1;
+    if line.trim().is_empty() {
+        return;
+    }
+    *lines_seen += 1;
+    let source = match decoder.decode(line) {
+        Ok(event) => return out.extend(event.map(Ok)),
+        Err(source) => source,
+    };
+    let parts = resync(line);
+    if !parts.iter().any(Result::is_ok) {
+        out.push_back(Err(ParseError::InvalidJson { line: *line_number, text: line.to_string(), source }));
+        return;
+    }
+    for part in parts {
+        let (text, decoded) = match part {
+            Ok(object) => (object, decoder.decode(object)),
+            Err(garbage) if garbage.trim().is_empty() => continue,
+            // Parsed for the error to go with it.
+            Err(garbage) => (garbage, serde_json::from_str::<Value>(garbage).map(|_| None)),
+        };
+        match decoded {
+            Ok(event) => out.extend(event.map(Ok)),
+            Err(source) => {
+                out.push_back(Err(ParseError::InvalidJson { line: *line_number, text: text.to_string(), source }))
+            }
+        }
+    }
+}
+
+/// Most places a garbled line is searched from for an object, so that a
+/// long line of garbage doesn't take quadratic time.
+const MAX_RESYNC_STARTS: usize = 64;
+
+/// A line that isn't one JSON value, split into the complete objects in it
+/// (`Ok`) and the text around them (`Err`). An object is found by counting
+/// braces outside strings from a `{"`; one that doesn't close, or doesn't
+/// parse, is searched again from its next `{"`. This recovers events that
+/// were written with no newline between them, and the event after one cut
+/// short when codex was killed mid-write.
+fn resync(line: &str) -> Vec<Result<&str, &str>> {
+    let mut parts = Vec::new();
+    let mut garbage_from = 0;
+    let mut from = 0;
+    for _ in 0..MAX_RESYNC_STARTS {
+        let Some(start) = line[from..].find("{\"").map(|i| from + i) else {
+            break;
+        };
+        match object_len(&line[start..]) {
+            Some(len) if serde_json::from_str::<serde::de::IgnoredAny>(&line[start..start + len]).is_ok() => {
+                if garbage_from < start {
+                    parts.push(Err(&line[garbage_from..start]));
+                }
+                parts.push(Ok(&line[start..start + len]));
+                from = start + len;
+                garbage_from = from;
+            }
+            _ => from = start + 1,
+        }
+    }
+    if garbage_from < line.len() {
+        parts.push(Err(&line[garbage_from..]));
+    }
+    parts
+}
+
+/// The length of the JSON object or array `text` starts with, by counting
+/// brackets outside strings; None if it doesn't close.
+fn object_len(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, b) in text.bytes().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// [`EventStream`] over an async reader (with the `async` feature).
+#[cfg(feature = "async")]
+pub struct AsyncEventStream<R> {
+    lines: futures_util::io::Lines<R>,
+    decoder: EventDecoder,
+    pending: VecDeque<Result<Event, ParseError>>,
+    lines_seen: usize,
+    line_number: usize,
+}
+
+#[cfg(feature = "async")]
+impl<R: futures_util::io::AsyncBufRead + Unpin> AsyncEventStream<R> {
+    pub fn new(reader: R) -> Self {
+        use futures_util::io::AsyncBufReadExt;
+        Self {
+            lines: reader.lines(),
+            decoder: EventDecoder::new(),
+            pending: VecDeque::new(),
+            lines_seen: 0,
+            line_number: 0,
+        }
+    }
+
+    /// The schema detected so far.
+    pub fn schema(&self) -> Option<Schema> {
+        self.decoder.schema()
+    }
+
+    /// Non-blank lines read so far.
+    pub fn lines_seen(&self) -> usize {
+        self.lines_seen
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: futures_util::io::AsyncBufRead + Unpin> futures_util::Stream for AsyncEventStream<R> {
+    type Item = Result<Event, ParseError>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.pending.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            let line = match std::pin::Pin::new(&mut this.lines).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(ParseError::Io(e)))),
+                Poll::Ready(Some(Ok(line))) => line,
+            };
+            decode_line(&mut this.decoder, &mut this.lines_seen, &mut this.line_number, &line, &mut this.pending);
+        }
+    }
 }
 
 /// Parse a JSON line permissively, extracting only events we care about.
 /// Returns None for unknown/malformed events (which we silently skip).
+///
+/// This reads the current `exec --json` schema only; use [`EventDecoder`]
+/// for streams that may come from other codex releases.
 pub fn extract_event(line: &str) -> Option<Event> {
-    let v: Value = serde_json::from_str(line).ok()?;
+    json_event(&serde_json::from_str(line).ok()?)
+}
+
+/// An `exec --experimental-json` event. It differs from `--json` only in
+/// the session event and how items are tagged.
+fn experimental_json_event(v: &Value) -> Option<Event> {
+    match v.get("type")?.as_str()? {
+        "session.created" => {
+            let thread_id = v.get("session_id")?.as_str()?.to_string();
+            Some(Event::ThreadStarted { thread_id })
+        }
+        "item.completed" => {
+            let mut item = v.get("item")?.clone();
+            let kind = match item.get("item_type")?.as_str()? {
+                "assistant_message" => "agent_message",
+                other => other,
+            }
+            .to_string();
+            item["type"] = Value::String(kind);
+            json_event(&serde_json::json!({"type": "item.completed", "item": item}))
+        }
+        _ => json_event(v),
+    }
+}
+
+fn json_event(v: &Value) -> Option<Event> {
     let event_type = v.get("type")?.as_str()?;
 
     match event_type {
@@ -28,11 +546,31 @@ pub fn extract_event(line: &str) -> Option<Event> {
         }
         "item.completed" => {
             let item = v.get("item")?;
-            if item.get("type")?.as_str()? == "agent_message" {
-                let text = item.get("text").and_then(|t| t.as_str()).map(String::from);
-                Some(Event::AgentMessage { text })
-            } else {
-                None
+            match item.get("type")?.as_str()? {
+                "agent_message" => {
+                    let text = item.get("text").and_then(|t| t.as_str()).map(String::from);
+                    Some(Event::AgentMessage { text })
+                }
+                "command_execution" => Some(Event::CommandExecuted(CommandRun {
+                    command: item.get("command")?.as_str()?.to_string(),
+                    exit_code: item.get("exit_code").and_then(|c| c.as_i64()),
+                    output: item.get("aggregated_output").and_then(|o| o.as_str()).map(String::from),
+                })),
+                "file_change" => {
+                    let changes = item
+                        .get("changes")?
+                        .as_array()?
+                        .iter()
+                        .filter_map(|c| {
+                            Some(FileChange {
+                                path: c.get("path")?.as_str()?.to_string(),
+                                kind: c.get("kind").and_then(|k| k.as_str()).unwrap_or("update").to_string(),
+                            })
+                        })
+                        .collect();
+                    Some(Event::FilesChanged(changes))
+                }
+                _ => None,
             }
         }
         "turn.completed" => {
@@ -67,6 +605,52 @@ pub fn extract_event(line: &str) -> Option<Event> {
     }
 }
 
+/// Parse a line from codex's on-disk session log (`sessions/.../rollout-*.jsonl`)
+/// into the same events `extract_event` yields for `exec --json` output.
+///
+/// Rollout lines wrap their content as `{"type": ..., "payload": {...}}`.
+/// Only the session header and a subset of `event_msg` payloads map onto
+/// events we render; everything else (response items, turn context,
+/// reasoning) returns None.
+pub fn extract_rollout_event(line: &str) -> Option<Event> {
+    let v: Value = serde_json::from_str(line).ok()?;
+    let payload = v.get("payload")?;
+
+    match v.get("type")?.as_str()? {
+        "session_meta" => {
+            let thread_id = payload.get("id")?.as_str()?.to_string();
+            Some(Event::ThreadStarted { thread_id })
+        }
+        "event_msg" => match payload.get("type")?.as_str()? {
+            "agent_message" => {
+                let text = payload.get("message").and_then(|t| t.as_str()).map(String::from);
+                Some(Event::AgentMessage { text })
+            }
+            "token_count" => {
+                // `info` is null until the first turn has reported usage.
+                let usage = payload.get("info")?.get("total_token_usage")?;
+                let field = |k: &str| usage.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+                Some(Event::TurnCompleted {
+                    input_tokens: field("input_tokens"),
+                    cached_input_tokens: field("cached_input_tokens"),
+                    output_tokens: field("output_tokens"),
+                    reasoning_output_tokens: field("reasoning_output_tokens"),
+                })
+            }
+            "error" => {
+                let message = payload
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("unknown stream error")
+                    .to_string();
+                Some(Event::StreamError { message })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Extract a human-readable error message from a codex `error` field, which
 /// can show up as several shapes across codex versions:
 ///   - missing entirely
@@ -141,6 +725,29 @@ mod tests {
         assert!(extract_event(json).is_none());
     }
 
+    #[test]
+    fn test_parse_command_execution() {
+        let json = r#"{"type":"item.completed","item":{"id":"item_1","type":"command_execution","command":"bash -lc ls","aggregated_output":"src\n","exit_code":0,"status":"completed"}}"#;
+        match extract_event(json).unwrap() {
+            Event::CommandExecuted(run) => {
+                assert_eq!(run, CommandRun { command: "bash -lc ls".into(), exit_code: Some(0), output: Some("src\n".into()) });
+            }
+            other => panic!("Expected CommandExecuted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_file_change() {
+        let json = r#"{"type":"item.completed","item":{"id":"item_2","type":"file_change","changes":[{"path":"src/a.rs","kind":"update"},{"path":"src/b.rs","kind":"add"},{"kind":"delete"}],"status":"completed"}}"#;
+        match extract_event(json).unwrap() {
+            Event::FilesChanged(changes) => {
+                let paths: Vec<_> = changes.iter().map(|c| (c.kind.as_str(), c.path.as_str())).collect();
+                assert_eq!(paths, vec![("update", "src/a.rs"), ("add", "src/b.rs")]);
+            }
+            other => panic!("Expected FilesChanged, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_ignore_malformed_json() {
         let json = r#"not valid json at all"#;
@@ -269,4 +876,245 @@ mod tests {
             _ => panic!("Expected StreamError"),
         }
     }
+
+    #[test]
+    fn test_rollout_session_meta() {
+        let json = r#"{"timestamp":"2026-04-28T12:00:00Z","type":"session_meta","payload":{"id":"0199a213-81c0-7800-8aa1-bbab2a035a53","cwd":"/tmp"}}"#;
+        match extract_rollout_event(json).unwrap() {
+            Event::ThreadStarted { thread_id } => {
+                assert_eq!(thread_id, "0199a213-81c0-7800-8aa1-bbab2a035a53");
+            }
+            _ => panic!("Expected ThreadStarted"),
+        }
+    }
+
+    #[test]
+    fn test_rollout_agent_message() {
+        let json = r#"{"timestamp":"2026-04-28T12:00:01Z","type":"event_msg","payload":{"type":"agent_message","message":"Hello"}}"#;
+        match extract_rollout_event(json).unwrap() {
+            Event::AgentMessage { text } => assert_eq!(text, Some("Hello".to_string())),
+            _ => panic!("Expected AgentMessage"),
+        }
+    }
+
+    #[test]
+    fn test_rollout_token_count() {
+        let json = r#"{"type":"event_msg","payload":{"type":"token_count","info":{"total_token_usage":{"input_tokens":10,"cached_input_tokens":4,"output_tokens":3,"reasoning_output_tokens":1},"last_token_usage":{"input_tokens":1}}}}"#;
+        match extract_rollout_event(json).unwrap() {
+            Event::TurnCompleted {
+                input_tokens,
+                cached_input_tokens,
+                output_tokens,
+                reasoning_output_tokens,
+            } => {
+                assert_eq!(
+                    (input_tokens, cached_input_tokens, output_tokens, reasoning_output_tokens),
+                    (10, 4, 3, 1)
+                );
+            }
+            _ => panic!("Expected TurnCompleted"),
+        }
+    }
+
+    #[test]
+    fn test_rollout_token_count_without_info_ignored() {
+        let json = r#"{"type":"event_msg","payload":{"type":"token_count","info":null}}"#;
+        assert!(extract_rollout_event(json).is_none());
+    }
+
+    #[test]
+    fn test_rollout_ignores_response_items() {
+        let json = r#"{"type":"response_item","payload":{"type":"message","role":"assistant","content":[]}}"#;
+        assert!(extract_rollout_event(json).is_none());
+        let json = r#"{"type":"event_msg","payload":{"type":"user_message","message":"hi"}}"#;
+        assert!(extract_rollout_event(json).is_none());
+    }
+
+    fn stream(lines: &[&str]) -> (Vec<Event>, Option<Schema>) {
+        let mut stream = EventDecoder::new();
+        let events = lines.iter().filter_map(|l| stream.feed(l)).collect();
+        (events, stream.schema())
+    }
+
+    #[test]
+    fn stream_reads_experimental_json() {
+        let (events, schema) = stream(&[
+            r#"{"type":"session.created","session_id":"s-old"}"#,
+            r#"{"type":"item.completed","item":{"id":"item_0","item_type":"command_execution","command":"ls","exit_code":0,"status":"completed"}}"#,
+            r#"{"type":"item.completed","item":{"id":"item_1","item_type":"assistant_message","text":"done"}}"#,
+            r#"{"type":"turn.completed","usage":{"input_tokens":5,"cached_input_tokens":0,"output_tokens":2}}"#,
+        ]);
+        assert_eq!(schema, Some(Schema::ExperimentalJson));
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], Event::ThreadStarted { thread_id } if thread_id == "s-old"));
+        assert!(matches!(&events[1], Event::CommandExecuted(c) if c.command == "ls"));
+        assert!(matches!(&events[2], Event::AgentMessage { text: Some(t) } if t == "done"));
+        assert!(matches!(events[3], Event::TurnCompleted { input_tokens: 5, .. }));
+    }
+
+    #[test]
+    fn stream_reads_proto_events() {
+        let (events, schema) = stream(&[
+            r#"{"id":"0","msg":{"type":"session_configured","session_id":"s-proto"}}"#,
+            r#"{"id":"1","msg":{"type":"agent_message","message":"hi"}}"#,
+            r#"{"id":"1","msg":{"type":"task_complete"}}"#,
+        ]);
+        assert_eq!(schema, Some(Schema::Proto));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], Event::AgentMessage { text: Some(t) } if t == "hi"));
+    }
+
+    #[test]
+    fn stream_schema_comes_from_the_first_identifying_event() {
+        // `error` is shared, so it doesn't settle the schema.
+        let (events, schema) = stream(&[
+            r#"{"type":"error","message":"reconnecting"}"#,
+            r#"{"type":"thread.started","thread_id":"t"}"#,
+            r#"{"type":"session.created","session_id":"ignored"}"#,
+        ]);
+        assert_eq!(schema, Some(Schema::Json));
+        assert_eq!(events.len(), 2);
+        assert_eq!(stream(&["not json", r#"{"type":"turn.started"}"#]).1, None);
+    }
+
+    #[test]
+    fn event_stream_iterates_lazily_and_reports_bad_lines() {
+        let data = "{\"type\":\"thread.started\",\"thread_id\":\"t\"}\n\nnot json\n{\"type\":\"turn.started\"}\n{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"hi\"}}\n";
+        let mut events = EventStream::new(io::Cursor::new(data));
+        assert!(matches!(events.next(), Some(Ok(Event::ThreadStarted { .. }))));
+        assert_eq!(events.lines_seen(), 1);
+        match events.next() {
+            Some(Err(ParseError::InvalidJson { line, text, .. })) => assert_eq!((line, text.as_str()), (3, "not json")),
+            other => panic!("expected a JSON error, got {:?}", other),
+        }
+        assert!(matches!(events.next(), Some(Ok(Event::AgentMessage { .. }))));
+        assert!(events.next().is_none());
+        assert_eq!((events.lines_seen(), events.schema()), (4, Some(Schema::Json)));
+    }
+
+    #[test]
+    fn unwanted_types_are_skipped_before_parsing() {
+        assert_eq!(leading_type(r#"{"type":"item.updated","item":{}}"#), Some("item.updated"));
+        assert_eq!(leading_type(r#"{"item":{},"type":"item.updated"}"#), None);
+        assert_eq!(leading_type(r#"{"type":"a\"b"}"#), None);
+
+        let mut decoder = EventDecoder::new();
+        // Until the schema is known every line is parsed, to detect it.
+        assert!(decoder.decode(r#"{"type":"turn.started"} trailing"#).is_err());
+        assert!(decoder.decode(r#"{"type":"thread.started","thread_id":"t"}"#).unwrap().is_some());
+        // Skipped lines aren't even checked for being JSON.
+        assert!(decoder.decode(r#"{"type":"item.updated","item":{"text":"cut off"#).unwrap().is_none());
+        assert!(decoder.decode(r#"{"type":"turn.failed","error":{"message":"x"}} trailing"#).is_err());
+        let message = r#"{"type":"item.completed","item":{"type":"agent_message","text":"hi"}}"#;
+        assert!(matches!(decoder.decode(message), Ok(Some(Event::AgentMessage { .. }))));
+    }
+
+    #[test]
+    fn events_run_together_or_cut_short_are_recovered() {
+        let message = |text: &str| format!(r#"{{"type":"item.completed","item":{{"id":"i","type":"agent_message","text":"{}"}}}}"#, text);
+        let data = format!(
+            "{}\n{}{}\n{{\"type\":\"item.updated\",\"item\":{{\"id\":\"i\",\"text\":\"cut {{ off{}\n{{\"type\":\"turn.started\"}}{}\n",
+            r#"{"type":"thread.started","thread_id":"t"}"#,
+            message("one"),
+            message(r#"two \"}{\" three"#),
+            message("four"),
+            message("five")
+        );
+        let items: Vec<_> = EventStream::new(io::Cursor::new(data)).collect();
+        let described: Vec<String> = items
+            .iter()
+            .map(|item| match item {
+                Ok(Event::AgentMessage { text }) => text.clone().unwrap_or_default(),
+                Ok(other) => format!("{:?}", other).split_whitespace().next().unwrap_or_default().to_string(),
+                Err(ParseError::InvalidJson { line, text, .. }) => format!("line {}: {}", line, text),
+                Err(e) => e.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "ThreadStarted",
+                "one",
+                "two \"}{\" three",
+                r#"line 3: {"type":"item.updated","item":{"id":"i","text":"cut { off"#,
+                "four",
+                "five"
+            ]
+        );
+
+        assert_eq!(resync("not json"), [Err("not json")]);
+        assert_eq!(resync(r#"{"a":1} {"b":[2]}x"#), [Ok(r#"{"a":1}"#), Err(" "), Ok(r#"{"b":[2]}"#), Err("x")]);
+    }
+
+    #[test]
+    fn parallel_decoding_matches_the_stream() {
+        let mut raw = String::from("{\"type\":\"thread.started\",\"thread_id\":\"t\"}\n");
+        for n in 0..40_000 {
+            match n % 4 {
+                0 => raw.push_str(&format!(
+                    "{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"m{}\"}}}}\n",
+                    n
+                )),
+                1 => raw.push_str("{\"type\":\"item.updated\",\"item\":{\"type\":\"reasoning\",\"text\":\"thinking\"}}\n"),
+                2 if n % 1000 == 2 => raw.push_str("not json\n\n"),
+                _ => raw.push_str("{\"type\":\"turn.started\"}\n"),
+            }
+        }
+        assert!(raw.len() >= PARALLEL_MIN_BYTES);
+        let describe = |items: &[Result<Event, ParseError>]| -> Vec<String> {
+            items
+                .iter()
+                .map(|i| match i {
+                    Ok(Event::AgentMessage { text }) => format!("message {:?}", text),
+                    Ok(other) => format!("{:?}", other),
+                    Err(ParseError::InvalidJson { line, .. }) => format!("bad line {}", line),
+                    Err(e) => e.to_string(),
+                })
+                .collect()
+        };
+        let mut stream = EventStream::new(io::Cursor::new(raw.as_bytes()));
+        let expected: Vec<_> = stream.by_ref().collect();
+        let parallel = decode_parallel(raw.as_bytes(), 4);
+        assert_eq!(describe(&parallel.items), describe(&expected));
+        assert_eq!((parallel.lines_seen, parallel.schema), (stream.lines_seen(), stream.schema()));
+        assert_eq!(decode_parallel(raw.as_bytes(), 1).items.len(), expected.len());
+
+        let parts = split_lines(b"ab\ncd\nef", 2);
+        assert_eq!(parts, [&b"ab\ncd\n"[..], &b"ef"[..]]);
+    }
+
+    #[test]
+    fn event_stream_frames_lines_as_lines_does() {
+        let data = "{\"type\":\"thread.started\",\"thread_id\":\"t\"}\r\n{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"no newline\"}}";
+        let mut events = EventStream::new(io::Cursor::new(data));
+        assert!(matches!(events.next(), Some(Ok(Event::ThreadStarted { thread_id })) if thread_id == "t"));
+        assert!(matches!(events.next(), Some(Ok(Event::AgentMessage { text: Some(t) })) if t == "no newline"));
+        assert!(events.next().is_none());
+
+        let mut bad = EventStream::new(io::Cursor::new(b"\xff\xfe\n".to_vec()));
+        assert!(matches!(bad.next(), Some(Err(ParseError::InvalidJson { line: 1, text, .. })) if text == "\u{fffd}\u{fffd}"));
+    }
+
+    #[test]
+    fn a_line_cut_inside_a_character_does_not_end_the_stream() {
+        let mut data = b"{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"done\"}}\n".to_vec();
+        let cut = "{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"caf\u{e9}\"}}";
+        data.extend_from_slice(&cut.as_bytes()[..cut.find('\u{e9}').unwrap() + 1]);
+        let items: Vec<_> = EventStream::new(io::Cursor::new(data)).collect();
+        assert!(matches!(&items[0], Ok(Event::AgentMessage { text: Some(t) }) if t == "done"), "{:?}", items);
+        assert!(items[1..].iter().all(|i| !matches!(i, Err(ParseError::Io(_)))), "{:?}", items);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_event_stream_matches_the_blocking_one() {
+        use futures_util::StreamExt;
+        use std::task::{Context, Poll, Waker};
+        let data = "{\"type\":\"thread.started\",\"thread_id\":\"t\"}\nnot json\n";
+        let mut events = AsyncEventStream::new(futures_util::io::Cursor::new(data));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(matches!(events.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(Event::ThreadStarted { .. })))));
+        assert!(matches!(events.poll_next_unpin(&mut cx), Poll::Ready(Some(Err(ParseError::InvalidJson { line: 2, .. })))));
+        assert!(matches!(events.poll_next_unpin(&mut cx), Poll::Ready(None)));
+    }
 }
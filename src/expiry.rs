@@ -0,0 +1,198 @@
+//! Warnings before resuming a session codex may have let go of.
+//!
+//! codex keeps a session's history server-side for a limited time, and
+//! resuming one past that fails, or quietly carries on without the
+//! earlier turns. Before a resume, [`check`] measures how long the session
+//! has been idle (its rollout file's modification time). Past
+//! `session_max_age_days` from the config ([`DEFAULT_MAX_AGE_DAYS`] unless
+//! set; `0` turns the check off) codex-clean warns and, when it can ask,
+//! offers a fresh session instead. That session's first prompt carries the
+//! old one's `codex-clean summarize` summary when there is one, and
+//! otherwise its last [`MAX_SEED_CHARS`] characters of dialogue, framed as
+//! `codex-clean fork` frames a transcript.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::fork;
+use crate::runner::{self, Mode, ResumeTarget, RunOptions};
+use crate::sessions::{self, SessionFile, Transcript, TranscriptEntry};
+use crate::summary::Summary;
+
+/// How many idle days make a session stale unless the config says.
+pub const DEFAULT_MAX_AGE_DAYS: u64 = 30;
+
+/// Most characters of dialogue a fresh session is seeded with when the
+/// stale one has no summary.
+pub const MAX_SEED_CHARS: usize = 8_000;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The configured threshold; `None` when the check is off.
+pub fn max_age(cfg: &Config) -> Option<Duration> {
+    match cfg.session_max_age_days.unwrap_or(DEFAULT_MAX_AGE_DAYS) {
+        0 => None,
+        days => Some(Duration::from_secs(days.saturating_mul(DAY.as_secs()))),
+    }
+}
+
+/// How long `session` has been idle, if that is longer than `max_age`.
+pub fn stale_age(session: &SessionFile, max_age: Duration, now: SystemTime) -> Option<Duration> {
+    let age = now.duration_since(session.modified).ok()?;
+    (age > max_age).then_some(age)
+}
+
+/// The session `target` resumes and its idle time, if it is stale. Sessions
+/// that can't be found locally aren't checked.
+pub fn check(target: &ResumeTarget, max_age: Duration) -> Option<(SessionFile, Duration)> {
+    let session = match target {
+        ResumeTarget::SessionId(id) => sessions::find_session(id).ok()?,
+        ResumeTarget::Last => sessions::latest_session().ok()?,
+    };
+    let age = stale_age(&session, max_age, SystemTime::now())?;
+    Some((session, age))
+}
+
+/// The warning for a session idle for `age`.
+pub fn warning(session_id: &str, age: Duration, max_age: Duration) -> String {
+    format!(
+        "Warning: session {} was last active {} day(s) ago, past the {}-day limit (session_max_age_days); codex may have expired it, and the resume can fail or lose its history.",
+        session_id,
+        age.as_secs() / DAY.as_secs(),
+        max_age.as_secs() / DAY.as_secs()
+    )
+}
+
+/// Warn about a stale session and, when a fresh session is possible, ask
+/// with `answer` whether to start one, seeded with its summary when
+/// `summarized`. True to start fresh.
+pub fn offer_fresh(
+    session_id: &str,
+    age: Duration,
+    max_age: Duration,
+    summarized: bool,
+    can_start_fresh: bool,
+    answer: impl FnOnce(&str) -> Option<String>,
+) -> bool {
+    eprintln!("{}", warning(session_id, age, max_age));
+    if !can_start_fresh {
+        return false;
+    }
+    let seed = if summarized { "its summary" } else { "its last turns" };
+    match answer(&format!("Start a fresh session seeded with {} instead? [y/N] ", seed)) {
+        Some(reply) => matches!(reply.trim().to_ascii_lowercase().as_str(), "y" | "yes"),
+        None => false,
+    }
+}
+
+/// Start a new session in place of the stale `session`, its first prompt
+/// being `prompt` after what the old session left: `summary`, or the end of
+/// its dialogue.
+pub fn start_fresh(
+    session: &SessionFile,
+    summary: Option<&Summary>,
+    codex_args: &[String],
+    prompt: &str,
+    opts: &RunOptions,
+) -> Result<i32> {
+    let seeded = match summary {
+        Some(summary) => {
+            eprintln!("Starting a fresh session from the summary of {}", session.id);
+            summary_seed(&session.id, &summary.summary, prompt)
+        }
+        None => {
+            eprintln!("Starting a fresh session from the last turns of {}", session.id);
+            let transcript = sessions::read_transcript(&session.path)?;
+            fork::seed_prompt(&session.id, &recent(&transcript, MAX_SEED_CHARS), prompt)
+        }
+    };
+    runner::run_codex_with_options(codex_args, &seeded, Mode::Exec, opts)
+}
+
+/// `prompt` after the summary of session `session_id`.
+fn summary_seed(session_id: &str, summary: &str, prompt: &str) -> String {
+    format!(
+        "The following is a summary of an earlier conversation (session {}). \
+         Treat it as your own prior context and continue from it; do not repeat it.\n\n\
+         <summary>\n{}\n</summary>\n\n{}",
+        session_id,
+        summary.trim_end(),
+        prompt
+    )
+}
+
+/// The end of `transcript`, at most `max_chars` characters of it. The
+/// earliest turn kept may start partway through, after `…`.
+fn recent(transcript: &Transcript, max_chars: usize) -> Transcript {
+    let mut left = max_chars;
+    let mut entries = Vec::new();
+    for entry in transcript.entries.iter().rev() {
+        let chars = entry.text.chars().count();
+        if chars <= left {
+            left -= chars;
+            entries.push(entry.clone());
+            continue;
+        }
+        if left > 0 {
+            let tail: String = entry.text.chars().skip(chars - left).collect();
+            entries.push(TranscriptEntry { role: entry.role, text: format!("…{}", tail) });
+        }
+        break;
+    }
+    entries.reverse();
+    Transcript { session_id: transcript.session_id.clone(), agent: transcript.agent.clone(), entries }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::Role;
+    use std::path::PathBuf;
+
+    #[test]
+    fn sessions_idle_past_the_limit_are_stale() {
+        let now = SystemTime::UNIX_EPOCH + DAY * 100;
+        let session = |idle_days: u32| SessionFile {
+            id: "0199a213-81c0-7800-8aa1-bbab2a035a53".into(),
+            path: PathBuf::from("rollout.jsonl"),
+            modified: now - DAY * idle_days,
+        };
+        let limit = max_age(&Config::default()).unwrap();
+        assert_eq!(limit, DAY * 30);
+        assert_eq!(stale_age(&session(45), limit, now), Some(DAY * 45));
+        assert_eq!(stale_age(&session(3), limit, now), None);
+        assert_eq!(max_age(&Config { session_max_age_days: Some(0), ..Default::default() }), None);
+        let forever = max_age(&Config { session_max_age_days: Some(u64::MAX), ..Default::default() }).unwrap();
+        assert_eq!(stale_age(&session(45), forever, now), None);
+        assert!(warning("abc", DAY * 45, limit).contains("last active 45 day(s) ago, past the 30-day limit"));
+
+        assert!(offer_fresh("abc", DAY * 45, limit, true, true, |q| {
+            assert!(q.contains("seeded with its summary"), "{}", q);
+            Some("y\n".into())
+        }));
+        assert!(!offer_fresh("abc", DAY * 45, limit, false, true, |q| {
+            assert!(q.contains("seeded with its last turns"), "{}", q);
+            None
+        }));
+        assert!(!offer_fresh("abc", DAY * 45, limit, false, false, |_| panic!("no prompt to start with")));
+    }
+
+    #[test]
+    fn a_fresh_session_gets_the_summary_or_the_last_turns() {
+        let seeded = summary_seed("abc", "Asked for a colour; blue was picked.\n", "Now a shade");
+        assert!(seeded.contains("summary of an earlier conversation (session abc)"));
+        assert!(seeded.ends_with("<summary>\nAsked for a colour; blue was picked.\n</summary>\n\nNow a shade"));
+
+        let turn = |role, text: &str| TranscriptEntry { role, text: text.into() };
+        let transcript = Transcript {
+            entries: vec![turn(Role::User, "first question"), turn(Role::Assistant, "a long answer"), turn(Role::User, "next")],
+            ..Default::default()
+        };
+        let kept = recent(&transcript, 10);
+        assert_eq!(kept.entries, [turn(Role::Assistant, "…answer"), turn(Role::User, "next")]);
+        assert_eq!(recent(&transcript, 1000), transcript);
+        assert_eq!(recent(&transcript, 4).entries, [turn(Role::User, "next")]);
+    }
+}
@@ -0,0 +1,383 @@
+//! `--output html <path>`: a run as one self-contained HTML page, for
+//! pasting into a code review thread or attaching to a ticket.
+//!
+//! The page has no external assets: styles are inline and nothing is
+//! scripted. Codex's markdown is rendered, but any raw HTML inside it is
+//! shown as text rather than passed through.
+//!
+//! `codex-clean export <session>` lays out a whole session's dialogue
+//! instead, read from its transcript: each prompt beside the response it
+//! got (HTML), or one after the other under `User` and `codex` (Markdown).
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use pulldown_cmark::{html, Event, Options, Parser};
+
+use crate::output::{format_usage, CodexOutput};
+use crate::sessions::{self, Role, Transcript};
+
+const STYLE: &str = "\
+body{font:15px/1.5 -apple-system,BlinkMacSystemFont,'Segoe UI',sans-serif;max-width:52rem;margin:2rem auto;padding:0 1rem;color:#1f2328}\
+h1{font-size:1.4rem}h2{font-size:1.1rem;border-bottom:1px solid #d0d7de;padding-bottom:.2rem;margin-top:2rem}\
+dl{display:grid;grid-template-columns:max-content 1fr;gap:.2rem 1rem;color:#57606a}dd{margin:0}\
+pre{background:#f6f8fa;padding:.8rem;overflow-x:auto;border-radius:6px}\
+code{font:13px ui-monospace,SFMono-Regular,Menlo,monospace;background:#f6f8fa;padding:.1rem .3rem;border-radius:4px}\
+pre code{background:none;padding:0}\
+table{border-collapse:collapse;width:100%}td,th{border:1px solid #d0d7de;padding:.3rem .6rem;text-align:left}\
+.fail{color:#cf222e}.ok{color:#1a7f37}.empty{color:#57606a;font-style:italic}\
+.kind{display:inline-block;min-width:4rem;font-weight:600}.error{color:#cf222e}";
+
+/// Added to [`STYLE`] for the two-column dialogue page.
+const DIALOGUE_STYLE: &str = "\
+body{max-width:90rem}.turn{display:grid;grid-template-columns:minmax(0,2fr) minmax(0,3fr);gap:1.5rem}\
+.turn>div{min-width:0}.who{font-weight:600;color:#57606a;margin:.5rem 0}pre{white-space:pre-wrap}";
+
+/// How `codex-clean export` writes a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogueFormat {
+    Html,
+    Markdown,
+}
+
+/// One prompt and the agent messages that answered it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn<'a> {
+    /// None for messages before the first prompt.
+    pub prompt: Option<&'a str>,
+    pub responses: Vec<&'a str>,
+}
+
+/// The transcript grouped into prompt/response turns.
+pub fn turns(transcript: &Transcript) -> Vec<Turn<'_>> {
+    let mut turns: Vec<Turn> = Vec::new();
+    for entry in &transcript.entries {
+        match (entry.role, turns.last_mut()) {
+            (Role::Assistant, Some(turn)) => turn.responses.push(&entry.text),
+            (Role::Assistant, None) => turns.push(Turn { prompt: None, responses: vec![&entry.text] }),
+            (Role::User, _) => turns.push(Turn { prompt: Some(&entry.text), responses: Vec::new() }),
+        }
+    }
+    turns
+}
+
+/// `codex-clean export <session>`: write the session's dialogue to `out`,
+/// or to stdout.
+pub fn export_session(session: &str, format: DialogueFormat, out: Option<&Path>) -> Result<()> {
+    let file = sessions::lookup_session(session)?;
+    let mut transcript = sessions::read_transcript(&file.path)?;
+    transcript.session_id.get_or_insert(file.id);
+    if transcript.entries.is_empty() {
+        eprintln!("Warning: session {} has no recorded dialogue.", transcript.session_id.as_deref().unwrap_or("?"));
+    }
+    let text = match format {
+        DialogueFormat::Html => render_dialogue_html(&transcript),
+        DialogueFormat::Markdown => render_dialogue_markdown(&transcript),
+    };
+    match out {
+        Some(path) => {
+            fs::write(path, text).with_context(|| format!("writing {}", path.display()))?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// The dialogue as an HTML page, prompts on the left and responses on
+/// the right.
+pub fn render_dialogue_html(transcript: &Transcript) -> String {
+    let agent = transcript.agent.as_deref().unwrap_or("codex");
+    let title = match &transcript.session_id {
+        Some(id) => format!("{} session {}", agent, id),
+        None => format!("{} session", agent),
+    };
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape(&title),
+        STYLE,
+        DIALOGUE_STYLE,
+        escape(&title)
+    );
+    let turns = turns(transcript);
+    if turns.is_empty() {
+        page.push_str("<p class=\"empty\">No dialogue recorded.</p>\n");
+    }
+    for (i, turn) in turns.iter().enumerate() {
+        let _ = writeln!(page, "<h2>Turn {}</h2>\n<div class=\"turn\">", i + 1);
+        page.push_str("<div>\n<p class=\"who\">User</p>\n");
+        match turn.prompt {
+            Some(prompt) => {
+                let _ = writeln!(page, "<pre>{}</pre>", escape(prompt.trim_end()));
+            }
+            None => page.push_str("<p class=\"empty\">No prompt recorded.</p>\n"),
+        }
+        let _ = writeln!(page, "</div>\n<div>\n<p class=\"who\">{}</p>", escape(agent));
+        if turn.responses.is_empty() {
+            page.push_str("<p class=\"empty\">No response.</p>\n");
+        }
+        for response in &turn.responses {
+            page.push_str(&markdown_to_html(response));
+        }
+        page.push_str("</div>\n</div>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// The dialogue as Markdown: each turn's prompt, quoted, then codex's
+/// (or an imported session's agent's) response.
+pub fn render_dialogue_markdown(transcript: &Transcript) -> String {
+    let agent = transcript.agent.as_deref().unwrap_or("codex");
+    let mut out = match &transcript.session_id {
+        Some(id) => format!("# {} session {}\n", agent, id),
+        None => format!("# {} session\n", agent),
+    };
+    let turns = turns(transcript);
+    if turns.is_empty() {
+        out.push_str("\n_No dialogue recorded._\n");
+    }
+    for (i, turn) in turns.iter().enumerate() {
+        let _ = write!(out, "\n## Turn {}\n\n**User**\n\n", i + 1);
+        match turn.prompt {
+            Some(prompt) => {
+                for line in prompt.trim_end().lines() {
+                    out.push_str(if line.is_empty() { ">" } else { "> " });
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            None => out.push_str("_No prompt recorded._\n"),
+        }
+        let _ = write!(out, "\n**{}**\n\n", agent);
+        if turn.responses.is_empty() {
+            out.push_str("_No response._\n");
+        }
+        for (j, response) in turn.responses.iter().enumerate() {
+            if j > 0 {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{}", response.trim_end());
+        }
+    }
+    out
+}
+
+/// Write the page for a finished run to `path`.
+pub fn write_html(path: &Path, prompt: &str, output: &CodexOutput, exit_code: i32) -> Result<()> {
+    fs::write(path, render_html(prompt, output, exit_code))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+pub fn render_html(prompt: &str, output: &CodexOutput, exit_code: i32) -> String {
+    let mut page = String::new();
+    let title = match &output.session_id {
+        Some(id) => format!("codex run {}", id),
+        None => "codex run".to_string(),
+    };
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<dl>\n",
+        escape(&title),
+        STYLE,
+        escape(&title)
+    );
+    if let Some(id) = &output.session_id {
+        let _ = writeln!(page, "<dt>Session</dt><dd><code>{}</code></dd>", escape(id));
+    }
+    let _ = writeln!(
+        page,
+        "<dt>Exported</dt><dd>{}</dd>",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %:z")
+    );
+    let status = if exit_code == 0 { "ok" } else { "fail" };
+    let _ = writeln!(page, "<dt>Exit code</dt><dd class=\"{}\">{}</dd>", status, exit_code);
+    if let Some(usage) = output.usage {
+        let _ = writeln!(page, "<dt>Usage</dt><dd>{}</dd>", escape(&format_usage(usage)));
+    }
+    page.push_str("</dl>\n");
+
+    let _ = writeln!(page, "<h2>Prompt</h2>\n<pre>{}</pre>", escape(prompt));
+
+    page.push_str("<h2>Response</h2>\n");
+    let message = output.aggregated_message();
+    if message.is_empty() {
+        page.push_str("<p class=\"empty\">No response.</p>\n");
+    } else {
+        page.push_str(&markdown_to_html(&message));
+    }
+
+    if !output.errors.is_empty() {
+        page.push_str("<h2>Errors</h2>\n<ul>\n");
+        for err in &output.errors {
+            let _ = writeln!(page, "<li class=\"error\">{}</li>", escape(err));
+        }
+        page.push_str("</ul>\n");
+    }
+
+    page.push_str("<h2>Commands</h2>\n");
+    if output.commands.is_empty() {
+        page.push_str("<p class=\"empty\">No commands run.</p>\n");
+    } else {
+        page.push_str("<table>\n<tr><th>Command</th><th>Exit</th></tr>\n");
+        for run in &output.commands {
+            let (class, code) = match run.exit_code {
+                Some(0) => ("ok", "0".to_string()),
+                Some(c) => ("fail", c.to_string()),
+                None => ("empty", "–".to_string()),
+            };
+            let _ = writeln!(
+                page,
+                "<tr><td><code>{}</code></td><td class=\"{}\">{}</td></tr>",
+                escape(&run.command),
+                class,
+                code
+            );
+        }
+        page.push_str("</table>\n");
+    }
+
+    page.push_str("<h2>File changes</h2>\n");
+    if output.file_changes.is_empty() {
+        page.push_str("<p class=\"empty\">No files changed.</p>\n");
+    } else {
+        page.push_str("<ul>\n");
+        for change in &output.file_changes {
+            let _ = writeln!(
+                page,
+                "<li><span class=\"kind\">{}</span> <code>{}</code></li>",
+                escape(&change.kind),
+                escape(&change.path)
+            );
+        }
+        page.push_str("</ul>\n");
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// Render markdown, showing any embedded HTML as text.
+fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{CommandRun, FileChange};
+    use crate::sessions::TranscriptEntry;
+
+    fn sample() -> CodexOutput {
+        let mut output = CodexOutput::new();
+        output.session_id = Some("s-1".into());
+        output.add_message("Fixed `parse_range`.\n\n<script>alert(1)</script>".into());
+        output.add_usage(10, 5, 2, 0);
+        output.commands.push(CommandRun { command: "cargo test <all>".into(), exit_code: Some(101), output: None });
+        output.file_changes.push(FileChange { path: "src/lib.rs".into(), kind: "update".into() });
+        output
+    }
+
+    #[test]
+    fn page_has_prompt_response_commands_and_changes() {
+        let page = render_html("fix & test", &sample(), 0);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>codex run s-1</title>"));
+        assert!(page.contains("<pre>fix &amp; test</pre>"));
+        assert!(page.contains("<code>parse_range</code>"));
+        assert!(page.contains("<code>cargo test &lt;all&gt;</code></td><td class=\"fail\">101</td>"));
+        assert!(page.contains("<span class=\"kind\">update</span> <code>src/lib.rs</code>"));
+        assert!(page.contains("Tokens: 10 input (5 cached), 2 output"));
+    }
+
+    #[test]
+    fn raw_html_in_response_is_escaped() {
+        let page = render_html("p", &sample(), 0);
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn empty_sections_say_so() {
+        let page = render_html("p", &CodexOutput::new(), 1);
+        assert!(page.contains("No response."));
+        assert!(page.contains("No commands run."));
+        assert!(page.contains("No files changed."));
+        assert!(page.contains("<dd class=\"fail\">1</dd>"));
+    }
+
+    fn dialogue() -> Transcript {
+        let entry = |role, text: &str| TranscriptEntry { role, text: text.into() };
+        Transcript {
+            session_id: Some("s-2".into()),
+            agent: None,
+            entries: vec![
+                entry(Role::User, "Why does <parse> fail?\n\nSee the test."),
+                entry(Role::Assistant, "Because of `split`."),
+                entry(Role::Assistant, "Fixed it."),
+                entry(Role::User, "Add a test"),
+            ],
+        }
+    }
+
+    #[test]
+    fn turns_pair_prompts_with_their_responses() {
+        let transcript = dialogue();
+        let turns = turns(&transcript);
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].responses, vec!["Because of `split`.", "Fixed it."]);
+        assert_eq!((turns[1].prompt, turns[1].responses.len()), (Some("Add a test"), 0));
+    }
+
+    #[test]
+    fn dialogue_page_puts_prompt_beside_response() {
+        let page = render_dialogue_html(&dialogue());
+        assert!(page.contains("<title>codex session s-2</title>"));
+        assert!(page.contains(
+            "<h2>Turn 1</h2>\n<div class=\"turn\">\n<div>\n<p class=\"who\">User</p>\n\
+             <pre>Why does &lt;parse&gt; fail?\n\nSee the test.</pre>\n</div>\n<div>\n<p class=\"who\">codex</p>\n\
+             <p>Because of <code>split</code>.</p>\n<p>Fixed it.</p>\n</div>\n</div>\n"
+        ));
+        assert!(page.contains("<h2>Turn 2</h2>") && page.contains("No response."));
+    }
+
+    #[test]
+    fn dialogue_markdown_alternates_with_attribution() {
+        assert_eq!(
+            render_dialogue_markdown(&dialogue()),
+            "# codex session s-2\n\n## Turn 1\n\n**User**\n\n> Why does <parse> fail?\n>\n> See the test.\n\n\
+             **codex**\n\nBecause of `split`.\n\nFixed it.\n\n## Turn 2\n\n**User**\n\n> Add a test\n\n\
+             **codex**\n\n_No response._\n"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! `codex-clean fork`: branch a new session off an existing one.
+//!
+//! `codex exec` has no native fork, so we replay the source session's
+//! dialogue as context at the top of a brand-new exec session. The original
+//! rollout file is only read, never resumed, so the source session is left
+//! exactly as it was.
+
+use std::fmt::Write as FmtWrite;
+
+use anyhow::Result;
+
+use crate::runner::{self, Mode, RunOptions};
+use crate::sessions::{self, Role, Transcript};
+
+/// Fork `session_id` into a new session whose first turn is `prompt`.
+/// Prints the new session's output (including its ID) like a normal run
+/// and returns codex's exit code.
+pub fn run(
+    session_id: &str,
+    codex_args: &[String],
+    prompt: &str,
+    opts: &RunOptions,
+) -> Result<i32> {
+    let source = sessions::find_session(session_id)?;
+    let transcript = sessions::read_transcript(&source.path)?;
+    if transcript.entries.is_empty() {
+        eprintln!(
+            "Warning: session '{}' has no recorded messages; the fork starts with no prior context.",
+            source.id
+        );
+    }
+    eprintln!("Forking session {}", source.id);
+    let seeded = seed_prompt(&source.id, &transcript, prompt);
+    runner::run_codex_with_options(codex_args, &seeded, Mode::Exec, opts)
+}
+
+/// Build the first prompt for the forked session: the source dialogue,
+/// framed so the model treats it as prior context, followed by the new
+/// instruction.
+pub fn seed_prompt(source_id: &str, transcript: &Transcript, prompt: &str) -> String {
+    let mut out = String::new();
+    if !transcript.entries.is_empty() {
+        let _ = writeln!(
+            out,
+            "The following is the transcript of an earlier conversation (session {}). \
+             Treat it as your own prior context and continue from it; do not repeat it.",
+            source_id
+        );
+        let _ = writeln!(out);
+        let _ = writeln!(out, "<transcript>");
+        for entry in &transcript.entries {
+            let who = match entry.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            let _ = writeln!(out, "[{}]", who);
+            let _ = writeln!(out, "{}", entry.text.trim_end());
+            let _ = writeln!(out);
+        }
+        let _ = writeln!(out, "</transcript>");
+        let _ = writeln!(out);
+    }
+    out.push_str(prompt);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::TranscriptEntry;
+
+    #[test]
+    fn seed_prompt_wraps_transcript_before_prompt() {
+        let t = Transcript {
+            session_id: Some("abc".into()),
+            agent: None,
+            entries: vec![
+                TranscriptEntry { role: Role::User, text: "Pick a colour".into() },
+                TranscriptEntry { role: Role::Assistant, text: "Blue.\n".into() },
+            ],
+        };
+        let seeded = seed_prompt("abc", &t, "Now pick a different one");
+        assert!(seeded.contains("session abc"));
+        assert!(seeded.contains("[user]\nPick a colour\n"));
+        assert!(seeded.contains("[assistant]\nBlue.\n"));
+        assert!(seeded.ends_with("</transcript>\n\nNow pick a different one"));
+    }
+
+    #[test]
+    fn seed_prompt_without_history_is_just_the_prompt() {
+        let seeded = seed_prompt("abc", &Transcript::default(), "hello");
+        assert_eq!(seeded, "hello");
+    }
+}
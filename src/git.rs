@@ -0,0 +1,411 @@
+//! Git integration around a run: snapshots (`--git-snapshot`,
+//! `--git-revert-on-failure`) and `--branch`.
+//!
+//! Before codex starts, the whole working tree (tracked files and
+//! untracked ones git doesn't ignore) is recorded as a commit object,
+//! through a scratch index, so neither the user's index nor their stash is
+//! touched. Afterwards codex-clean diffs the tree against it to show what
+//! the agent changed, and can put the files back as they were.
+//!
+//! `--branch` switches to a new branch before codex starts, carrying any
+//! uncommitted changes along, so the agent's edits never land on the
+//! branch the user was on. A run that fails without touching anything
+//! switches back and deletes the branch again.
+//!
+//! A run can span several repositories (`--workspace`, or codex's own
+//! `--add-dir`); each of them is snapshotted and branched the same way.
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+/// `-C DIR`, `--cd DIR` or `--cd=DIR` in codex args: where codex will work.
+pub fn codex_working_dir(args: &[String]) -> Option<PathBuf> {
+    let mut iter = args.iter();
+    let mut dir = None;
+    while let Some(arg) = iter.next() {
+        if arg == "-C" || arg == "--cd" {
+            dir = iter.next().map(PathBuf::from);
+        } else if let Some(d) = arg.strip_prefix("--cd=") {
+            dir = Some(PathBuf::from(d));
+        }
+    }
+    dir
+}
+
+/// Every directory codex may work in: the [`codex_working_dir`] (or the
+/// current one) and each `--add-dir DIR` / `--add-dir=DIR`, in order.
+pub fn codex_dirs(args: &[String]) -> Vec<PathBuf> {
+    let mut dirs = vec![codex_working_dir(args).unwrap_or_else(|| PathBuf::from("."))];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--add-dir" {
+            dirs.extend(iter.next().map(PathBuf::from));
+        } else if let Some(d) = arg.strip_prefix("--add-dir=") {
+            dirs.push(PathBuf::from(d));
+        }
+    }
+    dirs
+}
+
+/// The top level of the repository containing `dir`.
+pub fn toplevel(dir: &Path) -> Result<PathBuf> {
+    let root = git(dir, &["rev-parse", "--show-toplevel"])
+        .with_context(|| format!("{} is not in a git repository", dir.display()))?;
+    Ok(PathBuf::from(root.trim()))
+}
+
+/// Run git in `dir` and return its stdout.
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    git_with_index(dir, None, args)
+}
+
+fn git_with_index(dir: &Path, index: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir).args(args).stdin(Stdio::null());
+    if let Some(index) = index {
+        cmd.env("GIT_INDEX_FILE", index);
+    }
+    let out = cmd.output().with_context(|| format!("running git {}", args.join(" ")))?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// A scratch index, deleted on drop.
+struct ScratchIndex(PathBuf);
+
+impl ScratchIndex {
+    fn new(dir: &Path) -> Result<Self> {
+        let git_dir = git(dir, &["rev-parse", "--absolute-git-dir"])?;
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        Ok(Self(Path::new(git_dir.trim()).join(format!(
+            "codex-clean-index-{}-{}",
+            std::process::id(),
+            nonce
+        ))))
+    }
+}
+
+impl Drop for ScratchIndex {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The tree of everything git would add with `git add -A`, as it is now.
+fn write_worktree_tree(dir: &Path) -> Result<String> {
+    let index = ScratchIndex::new(dir)?;
+    // Starting from the real index lets git skip rehashing unchanged files.
+    let real = git(dir, &["rev-parse", "--path-format=absolute", "--git-path", "index"])?;
+    let _ = fs::copy(real.trim(), &index.0);
+    git_with_index(dir, Some(&index.0), &["add", "-A", "--", "."])?;
+    Ok(git_with_index(dir, Some(&index.0), &["write-tree"])?.trim().to_string())
+}
+
+/// One file codex changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// `A`dded, `M`odified, `D`eleted, or `T` (type changed).
+    pub status: char,
+    pub path: String,
+    /// None for binary files.
+    pub lines: Option<(u64, u64)>,
+}
+
+/// The working tree as it was before the run.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The repository's top level.
+    root: PathBuf,
+    tree: String,
+    /// A commit of `tree`, so it can be named in git commands.
+    pub commit: String,
+}
+
+impl Snapshot {
+    /// Record the working tree of the repository containing `dir`.
+    pub fn take(dir: &Path) -> Result<Self> {
+        let root = toplevel(dir)?;
+        let tree = write_worktree_tree(&root)?;
+        let head = git(&root, &["rev-parse", "--verify", "--quiet", "HEAD"]).ok();
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", "codex-clean snapshot"];
+        if let Some(head) = head.as_deref().map(str::trim).filter(|h| !h.is_empty()) {
+            args.extend(["-p", head]);
+        }
+        let commit = git(&root, &args)?.trim().to_string();
+        Ok(Self { root, tree, commit })
+    }
+
+    /// The top level of the repository.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// What differs between the snapshot and the working tree now.
+    pub fn changes(&self) -> Result<Vec<Change>> {
+        let now = write_worktree_tree(&self.root)?;
+        let statuses = git(&self.root, &["diff", "--no-renames", "--name-status", "-z", &self.tree, &now])?;
+        let numstat = git(&self.root, &["diff", "--no-renames", "--numstat", "-z", &self.tree, &now])?;
+        let mut lines = std::collections::HashMap::new();
+        for entry in numstat.split('\0').filter(|e| !e.is_empty()) {
+            let mut fields = entry.splitn(3, '\t');
+            let (Some(ins), Some(del), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let counts = ins.parse().ok().zip(del.parse().ok());
+            lines.insert(path.to_string(), counts);
+        }
+        let mut changes = Vec::new();
+        let mut fields = statuses.split('\0').filter(|e| !e.is_empty());
+        while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+            changes.push(Change {
+                status: status.chars().next().unwrap_or('M'),
+                path: path.to_string(),
+                lines: lines.get(path).copied().flatten(),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Put the working tree back as it was: files codex changed or deleted
+    /// are rewritten from the snapshot and files it added are removed. The
+    /// index, HEAD and any commits codex made are left alone.
+    pub fn restore(&self, changes: &[Change]) -> Result<()> {
+        let index = ScratchIndex::new(&self.root)?;
+        git_with_index(&self.root, Some(&index.0), &["read-tree", &self.tree])?;
+        for change in changes {
+            if change.status == 'A' {
+                let path = self.root.join(&change.path);
+                fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+            }
+        }
+        let restored: Vec<&str> =
+            changes.iter().filter(|c| c.status != 'A').map(|c| c.path.as_str()).collect();
+        if !restored.is_empty() {
+            let mut args = vec!["checkout-index", "-f", "--"];
+            args.extend(restored);
+            git_with_index(&self.root, Some(&index.0), &args)?;
+        }
+        Ok(())
+    }
+}
+
+/// The branch a run was moved onto, and the way back.
+#[derive(Debug)]
+pub struct BranchSwitch {
+    root: PathBuf,
+    pub name: String,
+    /// The branch (or, detached, the commit) the user was on.
+    previous: String,
+    head: String,
+    tree: String,
+}
+
+impl BranchSwitch {
+    /// Create branch `name` at HEAD in the repository containing `dir` and
+    /// switch to it. `auto` names it after `prompt`.
+    pub fn create(dir: &Path, name: &str, prompt: &str) -> Result<Self> {
+        let root = toplevel(dir)?;
+        let head = git(&root, &["rev-parse", "--verify", "HEAD"])
+            .context("--branch needs a repository with at least one commit")?
+            .trim()
+            .to_string();
+        let previous = match git(&root, &["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+            Ok(branch) => branch.trim().to_string(),
+            Err(_) => head.clone(),
+        };
+        let name = if name == "auto" { free_branch_name(&root, prompt)? } else { name.to_string() };
+        let tree = write_worktree_tree(&root)?;
+        git(&root, &["switch", "--quiet", "--create", &name])?;
+        Ok(Self { root, name, previous, head, tree })
+    }
+
+    /// Whether codex left the branch exactly as it was created: no commits
+    /// and no changes to the working tree.
+    fn untouched(&self) -> Result<bool> {
+        let head = git(&self.root, &["rev-parse", "HEAD"])?;
+        Ok(head.trim() == self.head && write_worktree_tree(&self.root)? == self.tree)
+    }
+
+    /// After a failed run: if codex did nothing on the branch, go back to
+    /// the previous one and delete it. Returns whether it did.
+    pub fn abandon_if_untouched(&self) -> Result<bool> {
+        if !self.untouched()? {
+            return Ok(false);
+        }
+        // The tree is as it was, so any uncommitted changes the user had
+        // simply come back along with them.
+        git(&self.root, &["switch", "--quiet", &self.previous])
+            .or_else(|_| git(&self.root, &["switch", "--quiet", "--detach", &self.previous]))?;
+        git(&self.root, &["branch", "--quiet", "-D", &self.name])?;
+        Ok(true)
+    }
+
+    /// The branch (or commit) the user was on before.
+    pub fn previous(&self) -> &str {
+        &self.previous
+    }
+
+    /// The top level of the repository.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+/// `codex/<first words of the prompt>`, with a number added if a branch of
+/// that name already exists.
+fn free_branch_name(root: &Path, prompt: &str) -> Result<String> {
+    let mut slug = String::new();
+    for word in prompt.split_whitespace().take(6) {
+        let word: String = word.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        if word.is_empty() {
+            continue;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+        if slug.len() > 40 {
+            break;
+        }
+    }
+    let base = if slug.is_empty() { "codex/run".to_string() } else { format!("codex/{}", slug) };
+    let exists = |name: &str| git(root, &["rev-parse", "--verify", "--quiet", &format!("refs/heads/{}", name)]).is_ok();
+    let mut name = base.clone();
+    let mut n = 2;
+    while exists(&name) {
+        name = format!("{}-{}", base, n);
+        n += 1;
+    }
+    Ok(name)
+}
+
+/// The post-run summary:
+///
+/// ```text
+/// codex changed 2 files (+14 -3):
+///   A  src/new.rs  +12
+///   M  src/lib.rs  +2 -3
+/// ```
+pub fn describe(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "codex left the working tree unchanged.\n".to_string();
+    }
+    let (ins, del) = changes
+        .iter()
+        .filter_map(|c| c.lines)
+        .fold((0, 0), |(i, d), (ci, cd)| (i + ci, d + cd));
+    let mut out = format!(
+        "codex changed {} file{} (+{} -{}):\n",
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" },
+        ins,
+        del
+    );
+    let width = changes.iter().map(|c| c.path.len()).max().unwrap_or(0);
+    for change in changes {
+        let counts = match change.lines {
+            Some((0, 0)) => String::new(),
+            Some((i, 0)) => format!("+{}", i),
+            Some((0, d)) => format!("-{}", d),
+            Some((i, d)) => format!("+{} -{}", i, d),
+            None => "binary".to_string(),
+        };
+        let _ = writeln!(out, "  {}  {:<width$}  {}", change.status, change.path, counts, width = width);
+    }
+    out.lines().map(str::trim_end).collect::<Vec<_>>().join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for args in [
+            &["init", "-q"][..],
+            &["config", "user.email", "dev@example.com"],
+            &["config", "user.name", "Dev"],
+        ] {
+            git(dir.path(), args).unwrap();
+        }
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        fs::write(dir.path().join("old.txt"), "bye\n").unwrap();
+        git(dir.path(), &["add", "."]).unwrap();
+        git(dir.path(), &["commit", "-qm", "init"]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn snapshot_reports_and_reverts_what_changed() {
+        let dir = repo();
+        // Uncommitted work from before the run is part of the snapshot.
+        fs::write(dir.path().join("wip.txt"), "mine\n").unwrap();
+        let snapshot = Snapshot::take(dir.path()).unwrap();
+        assert_eq!(git(dir.path(), &["stash", "list"]).unwrap(), "");
+
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn c() {}\nfn d() {}\n").unwrap();
+        fs::write(dir.path().join("new.rs"), "x\n").unwrap();
+        fs::remove_file(dir.path().join("old.txt")).unwrap();
+        let changes = snapshot.changes().unwrap();
+        assert_eq!(
+            describe(&changes),
+            "codex changed 3 files (+3 -2):\n  M  lib.rs   +2 -1\n  A  new.rs   +1\n  D  old.txt  -1\n"
+        );
+
+        snapshot.restore(&changes).unwrap();
+        assert!(snapshot.changes().unwrap().is_empty());
+        assert!(!dir.path().join("new.rs").exists());
+        assert_eq!(fs::read_to_string(dir.path().join("wip.txt")).unwrap(), "mine\n");
+        assert_eq!(git(dir.path(), &["status", "--porcelain"]).unwrap(), "?? wip.txt\n");
+    }
+
+    fn current_branch(dir: &Path) -> String {
+        git(dir, &["symbolic-ref", "--short", "HEAD"]).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn branch_is_created_and_abandoned_only_if_untouched() {
+        let dir = repo();
+        let start = current_branch(dir.path());
+        let switch = BranchSwitch::create(dir.path(), "auto", "Fix the off-by-one, please!").unwrap();
+        assert_eq!(switch.name, "codex/fix-the-offbyone-please");
+        assert_eq!(current_branch(dir.path()), switch.name);
+        assert!(switch.abandon_if_untouched().unwrap());
+        assert_eq!(current_branch(dir.path()), start);
+        assert!(git(dir.path(), &["branch", "--list", "codex/*"]).unwrap().is_empty());
+
+        // A taken name gets a number; edits keep the branch.
+        git(dir.path(), &["branch", "codex/fix"]).unwrap();
+        let switch = BranchSwitch::create(dir.path(), "auto", "fix").unwrap();
+        assert_eq!(switch.name, "codex/fix-2");
+        fs::write(dir.path().join("lib.rs"), "changed\n").unwrap();
+        assert!(!switch.abandon_if_untouched().unwrap());
+        assert_eq!(current_branch(dir.path()), "codex/fix-2");
+    }
+
+    #[test]
+    fn working_dir_follows_codex_cd() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(codex_working_dir(&args(&["-C", "/src", "-m", "o3"])), Some(PathBuf::from("/src")));
+        assert_eq!(codex_working_dir(&args(&["--cd=/x"])), Some(PathBuf::from("/x")));
+        assert_eq!(codex_working_dir(&args(&["-m", "o3"])), None);
+        assert_eq!(
+            codex_dirs(&args(&["--add-dir", "/web", "-C", "/api", "--add-dir=/docs"])),
+            [PathBuf::from("/api"), PathBuf::from("/web"), PathBuf::from("/docs")]
+        );
+        assert_eq!(codex_dirs(&args(&["-m", "o3"])), [PathBuf::from(".")]);
+    }
+}
@@ -0,0 +1,335 @@
+//! `codex-clean import`: sessions from other agent CLIs, kept beside
+//! codex's own.
+//!
+//! Each adapter reads one tool's session log into [`Imported`] sessions,
+//! which are written to [`sessions::imported_dir`] as rollout files in the
+//! shape codex writes them: a `session_meta` line, then a `user_message` or
+//! `agent_message` event per turn of the dialogue. So `export` and `diff`
+//! read them like any codex session, by ID, prefix or `@N`. Only the
+//! dialogue is kept; tool calls and their output are left out, as they are
+//! from codex's transcripts.
+//!
+//! An imported session's ID is the tool's own when it is a UUID, and
+//! otherwise derived from where the session came from, so importing the
+//! same log again replaces the sessions it gave before.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::sessions::{self, Role, TranscriptEntry};
+
+/// A tool whose session logs can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Claude Code's `~/.claude/projects/<project>/<session>.jsonl`.
+    ClaudeCode,
+    /// aider's `.aider.chat.history.md`, which holds every session started
+    /// in that directory, one after another.
+    Aider,
+}
+
+impl Source {
+    /// The name the sessions are filed under, and shown as the answering
+    /// agent when exported.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ClaudeCode => "claude-code",
+            Self::Aider => "aider",
+        }
+    }
+}
+
+/// One session read from another tool's log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imported {
+    pub id: String,
+    pub started: Option<DateTime<Utc>>,
+    pub cwd: Option<String>,
+    pub entries: Vec<(Option<DateTime<Utc>>, TranscriptEntry)>,
+}
+
+impl Imported {
+    fn last_active(&self) -> Option<DateTime<Utc>> {
+        self.entries.iter().rev().find_map(|(at, _)| *at).or(self.started)
+    }
+}
+
+/// The sessions in the log at `path`.
+pub fn read(source: Source, path: &Path) -> Result<Vec<Imported>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let reader = BufReader::new(file);
+    let origin = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let sessions = match source {
+        Source::ClaudeCode => read_claude_code(reader, &origin).map(|s| s.into_iter().collect()),
+        Source::Aider => read_aider(reader, &origin),
+    };
+    sessions.with_context(|| format!("reading {}", path.display()))
+}
+
+/// A session ID for something that has none of its own.
+fn derived_id(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let hex: String = hasher.finalize().iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// A Claude Code session: one JSON object per line, the dialogue in the
+/// `user` and `assistant` lines. `user` lines that only carry tool results,
+/// and sidechains (subagents), aren't part of it.
+fn read_claude_code(reader: impl BufRead, origin: &Path) -> Result<Option<Imported>> {
+    let mut session = Imported { id: String::new(), started: None, cwd: None, entries: Vec::new() };
+    for line in reader.lines() {
+        let line = line?;
+        let Ok(v) = serde_json::from_str::<Value>(&line) else { continue };
+        let at = v.get("timestamp").and_then(Value::as_str).and_then(|t| t.parse::<DateTime<Utc>>().ok());
+        if session.id.is_empty() {
+            if let Some(id) = v.get("sessionId").and_then(Value::as_str).filter(|id| sessions::is_session_uuid(id)) {
+                session.id = id.to_ascii_lowercase();
+            }
+        }
+        if session.cwd.is_none() {
+            session.cwd = v.get("cwd").and_then(Value::as_str).map(String::from);
+        }
+        session.started = session.started.or(at);
+        let role = match v.get("type").and_then(Value::as_str) {
+            Some("user") => Role::User,
+            Some("assistant") => Role::Assistant,
+            _ => continue,
+        };
+        if v.get("isSidechain").and_then(Value::as_bool) == Some(true) || v.get("isMeta").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+        let text = match v.pointer("/message/content") {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Array(blocks)) => blocks
+                .iter()
+                .filter(|b| b.get("type").and_then(Value::as_str) == Some("text"))
+                .filter_map(|b| b.get("text").and_then(Value::as_str))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            _ => continue,
+        };
+        if !text.trim().is_empty() {
+            session.entries.push((at, TranscriptEntry { role, text }));
+        }
+    }
+    if session.entries.is_empty() {
+        return Ok(None);
+    }
+    if session.id.is_empty() {
+        session.id = derived_id(&[Source::ClaudeCode.name(), &origin.to_string_lossy()]);
+    }
+    Ok(Some(session))
+}
+
+/// aider's chat history: each session starts with `# aider chat started
+/// at <local time>`. The user's messages are the lines starting `#### `,
+/// aider's own notes the lines starting `> `, and the rest is the model's
+/// answer.
+fn read_aider(reader: impl BufRead, origin: &Path) -> Result<Vec<Imported>> {
+    const STARTED: &str = "# aider chat started at ";
+    let mut sessions: Vec<Imported> = Vec::new();
+    let mut turn: Option<(Role, String)> = None;
+    let cwd = origin.parent().map(|p| p.display().to_string());
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(when) = line.strip_prefix(STARTED) {
+            finish_aider_turn(&mut sessions, &mut turn);
+            let started = NaiveDateTime::parse_from_str(when.trim(), "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|t| Local.from_local_datetime(&t).earliest())
+                .map(|t| t.with_timezone(&Utc));
+            let id = derived_id(&[Source::Aider.name(), &origin.to_string_lossy(), when.trim(), &sessions.len().to_string()]);
+            sessions.push(Imported { id, started, cwd: cwd.clone(), entries: Vec::new() });
+            continue;
+        }
+        if sessions.is_empty() {
+            // A history that doesn't start with a header is still one session.
+            let id = derived_id(&[Source::Aider.name(), &origin.to_string_lossy()]);
+            sessions.push(Imported { id, started: None, cwd: cwd.clone(), entries: Vec::new() });
+        }
+        let (role, text) = if let Some(prompt) = line.strip_prefix("#### ").or_else(|| (line == "####").then_some("")) {
+            (Role::User, prompt)
+        } else if line.starts_with("> ") || line == ">" {
+            continue;
+        } else {
+            (Role::Assistant, line.as_str())
+        };
+        match &mut turn {
+            Some((current, buf)) if *current == role => {
+                buf.push('\n');
+                buf.push_str(text);
+            }
+            _ => {
+                finish_aider_turn(&mut sessions, &mut turn);
+                turn = Some((role, text.to_string()));
+            }
+        }
+    }
+    finish_aider_turn(&mut sessions, &mut turn);
+    sessions.retain(|s| !s.entries.is_empty());
+    Ok(sessions)
+}
+
+fn finish_aider_turn(sessions: &mut [Imported], turn: &mut Option<(Role, String)>) {
+    let (Some((role, text)), Some(session)) = (turn.take(), sessions.last_mut()) else {
+        return;
+    };
+    let text = text.trim();
+    if !text.is_empty() {
+        session.entries.push((None, TranscriptEntry { role, text: text.to_string() }));
+    }
+}
+
+/// `session` as a rollout file's lines.
+fn rollout(source: Source, session: &Imported, origin: &Path, mut out: impl Write) -> io::Result<()> {
+    let at = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+    let meta = json!({
+        "timestamp": at(session.started),
+        "type": "session_meta",
+        "payload": {
+            "id": session.id,
+            "timestamp": at(session.started),
+            "cwd": session.cwd,
+            "agent": source.name(),
+            "imported_from": origin.display().to_string(),
+        },
+    });
+    writeln!(out, "{}", meta)?;
+    for (time, entry) in &session.entries {
+        let kind = match entry.role {
+            Role::User => "user_message",
+            Role::Assistant => "agent_message",
+        };
+        let event = json!({
+            "timestamp": at(*time),
+            "type": "event_msg",
+            "payload": { "type": kind, "message": entry.text },
+        });
+        writeln!(out, "{}", event)?;
+    }
+    out.flush()
+}
+
+/// Write `session` under `root`, replacing an earlier import of it, and
+/// date the file by when the session was last active.
+fn save(root: &Path, source: Source, session: &Imported, origin: &Path) -> Result<PathBuf> {
+    let dir = root.join(source.name());
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    // Whatever the previous import was dated, it is this session.
+    for old in sessions::list_sessions_in(&dir)?.into_iter().filter(|s| s.id == session.id) {
+        fs::remove_file(&old.path).with_context(|| format!("removing {}", old.path.display()))?;
+    }
+    let started = session.started.unwrap_or_else(Utc::now).with_timezone(&Local);
+    let path = dir.join(format!("rollout-{}-{}.jsonl", started.format("%Y-%m-%dT%H-%M-%S"), session.id));
+    let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    rollout(source, session, origin, io::BufWriter::new(&file)).with_context(|| format!("writing {}", path.display()))?;
+    if let Some(last) = session.last_active() {
+        let _ = file.set_modified(SystemTime::from(last));
+    }
+    Ok(path)
+}
+
+/// `codex-clean import --from <source> <files>...`.
+pub fn run(source: Source, files: &[PathBuf]) -> Result<()> {
+    let root = sessions::imported_dir()?;
+    let mut total = 0;
+    for path in files {
+        let origin = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let imported = read(source, path)?;
+        if imported.is_empty() {
+            eprintln!("Warning: no dialogue found in {}", path.display());
+        }
+        for session in &imported {
+            save(&root, source, session, &origin)?;
+            println!("{}  {} messages  {}", session.id, session.entries.len(), path.display());
+        }
+        total += imported.len();
+    }
+    eprintln!("Imported {} session{} into {}", total, if total == 1 { "" } else { "s" }, root.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn dialogue(session: &Imported) -> Vec<(Role, &str)> {
+        session.entries.iter().map(|(_, e)| (e.role, e.text.as_str())).collect()
+    }
+
+    #[test]
+    fn claude_code_sessions_keep_only_the_dialogue() {
+        let log = concat!(
+            r#"{"type":"summary","summary":"Fix tests"}"#, "\n",
+            r#"{"type":"user","sessionId":"4F1C2E3A-0000-4000-8000-00000000000A","cwd":"/work","timestamp":"2026-03-01T10:00:00.000Z","message":{"role":"user","content":"Fix the tests"}}"#, "\n",
+            r#"{"type":"assistant","sessionId":"4f1c2e3a-0000-4000-8000-00000000000a","timestamp":"2026-03-01T10:00:05.000Z","message":{"role":"assistant","content":[{"type":"text","text":"Looking."},{"type":"tool_use","name":"Bash","input":{}}]}}"#, "\n",
+            r#"{"type":"user","sessionId":"4f1c2e3a-0000-4000-8000-00000000000a","message":{"role":"user","content":[{"type":"tool_result","content":"ok"}]}}"#, "\n",
+            r#"{"type":"assistant","isSidechain":true,"message":{"role":"assistant","content":[{"type":"text","text":"subagent"}]}}"#, "\n",
+            r#"{"type":"assistant","timestamp":"2026-03-01T10:01:00.000Z","message":{"role":"assistant","content":[{"type":"text","text":"Fixed."}]}}"#, "\n",
+        );
+        let session = read_claude_code(Cursor::new(log), Path::new("/p/s.jsonl")).unwrap().unwrap();
+        assert_eq!(session.id, "4f1c2e3a-0000-4000-8000-00000000000a");
+        assert_eq!(session.cwd.as_deref(), Some("/work"));
+        assert_eq!(dialogue(&session), [(Role::User, "Fix the tests"), (Role::Assistant, "Looking."), (Role::Assistant, "Fixed.")]);
+        assert_eq!(session.last_active().unwrap().to_rfc3339(), "2026-03-01T10:01:00+00:00");
+    }
+
+    #[test]
+    fn aider_histories_split_into_sessions_that_read_back_as_transcripts() {
+        let history = "\
+# aider chat started at 2026-03-01 10:00:00
+
+> Aider v0.80.0
+> Main model: gpt-4o
+
+#### Add a --verbose flag
+#### to the CLI
+
+Here is the change:
+
+```rust
+verbose: bool,
+```
+
+> Applied edit to src/main.rs
+
+# aider chat started at 2026-03-02 09:00:00
+
+> Aider v0.80.0
+";
+        let origin = Path::new("/work/.aider.chat.history.md");
+        let sessions = read_aider(Cursor::new(history), origin).unwrap();
+        assert_eq!(sessions.len(), 1, "the second session has no dialogue");
+        let session = &sessions[0];
+        assert!(sessions::is_session_uuid(&session.id));
+        assert_eq!(
+            dialogue(session),
+            [(Role::User, "Add a --verbose flag\nto the CLI"), (Role::Assistant, "Here is the change:\n\n```rust\nverbose: bool,\n```")]
+        );
+        assert_eq!(read_aider(Cursor::new(history), origin).unwrap()[0].id, session.id);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = save(dir.path(), Source::Aider, session, origin).unwrap();
+        save(dir.path(), Source::Aider, session, origin).unwrap();
+        let listed = sessions::list_sessions_in(dir.path()).unwrap();
+        assert_eq!(listed.len(), 1, "importing again replaces the session");
+        assert_eq!(listed[0].path, path);
+        let transcript = sessions::read_transcript(&path).unwrap();
+        assert_eq!(transcript.session_id.as_deref(), Some(session.id.as_str()));
+        assert_eq!(transcript.agent.as_deref(), Some("aider"));
+        assert_eq!(transcript.entries.len(), 2);
+    }
+}
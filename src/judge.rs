@@ -0,0 +1,191 @@
+//! `--judge "<criteria>"`: grade the answer with a second codex call.
+//!
+//! Once the run has succeeded, its request and answer are sent to a fresh
+//! codex session (same model, read-only sandbox) together with the
+//! criteria, asking for a score out of 10 and a pass/fail verdict with a
+//! one-sentence reason. The verdict is printed to stderr and kept in the
+//! run report, so `stats` and scripts reading the reports can follow how a
+//! prompt is doing over time. The judge's tokens count towards the usage
+//! ledger like any other.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::output::CodexOutput;
+use crate::stats::model_from_args;
+
+/// Longest reason kept when the judge ignores the requested format.
+const MAX_REASON_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Fail,
+}
+
+/// What the judge made of an answer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Judgement {
+    pub criteria: String,
+    /// Out of 10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<Verdict>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub cached_input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+}
+
+impl Judgement {
+    /// Read the judge's reply in `output`.
+    pub fn from_output(criteria: &str, output: &CodexOutput) -> Self {
+        let (score, verdict, reason) = parse(&output.aggregated_message());
+        let usage = output.usage.unwrap_or_default();
+        Self {
+            criteria: criteria.to_string(),
+            score,
+            verdict,
+            reason,
+            input_tokens: usage.0,
+            cached_input_tokens: usage.1,
+            output_tokens: usage.2,
+        }
+    }
+
+    /// The `Judge: ...` line printed after the run.
+    pub fn describe(&self) -> String {
+        let verdict = match self.verdict {
+            Some(Verdict::Pass) => "pass",
+            Some(Verdict::Fail) => "fail",
+            None => "no verdict",
+        };
+        let mut line = format!("Judge: {}", verdict);
+        if let Some(score) = self.score {
+            line.push_str(&format!(" ({}/10)", score));
+        }
+        if !self.reason.is_empty() {
+            line.push_str(" - ");
+            line.push_str(&self.reason);
+        }
+        line
+    }
+}
+
+/// The judge's prompt for `answer` to `request` (empty for reviews).
+pub fn prompt(criteria: &str, request: &str, answer: &str) -> String {
+    let mut out = format!(
+        "You are grading an AI coding assistant's answer against these criteria:\n\n{}\n\n",
+        criteria.trim()
+    );
+    if !request.trim().is_empty() {
+        out.push_str(&format!("The request was:\n\n<request>\n{}\n</request>\n\n", request.trim()));
+    }
+    out.push_str(&format!(
+        "The answer was:\n\n<answer>\n{}\n</answer>\n\n\
+         Judge the answer as written; don't run commands or change files. Reply with exactly two lines:\n\
+         SCORE: <0 to 10>\n\
+         VERDICT: <pass or fail> - <one sentence saying why>",
+        answer.trim()
+    ));
+    out
+}
+
+/// The judge's codex arguments: the run's model and config overrides, in
+/// a read-only sandbox.
+pub fn args(run_args: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Some(model) = model_from_args(run_args) {
+        out.extend(["-m".to_string(), model]);
+    }
+    let mut iter = run_args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--config" => {
+                if let Some(value) = iter.next() {
+                    out.extend([arg.clone(), value.clone()]);
+                }
+            }
+            "--skip-git-repo-check" | "--oss" => out.push(arg.clone()),
+            _ => {}
+        }
+    }
+    out.extend(["--sandbox".to_string(), "read-only".to_string()]);
+    out
+}
+
+/// Score, verdict and reason from the judge's reply, as far as it can
+/// be read.
+pub fn parse(reply: &str) -> (Option<u8>, Option<Verdict>, String) {
+    static SCORE: OnceLock<Regex> = OnceLock::new();
+    static VERDICT: OnceLock<Regex> = OnceLock::new();
+    let score_re = SCORE.get_or_init(|| Regex::new(r"(?im)^[\s*_]*score[\s*_]*:[\s*_]*(\d+(?:\.\d+)?)").unwrap());
+    let verdict_re = VERDICT.get_or_init(|| {
+        Regex::new(r"(?im)^[\s*_]*verdict[\s*_]*:[\s*_]*(pass|fail)\w*[\s*_]*(?:[-–—:,.]\s*)?(.*)$").unwrap()
+    });
+    let score = score_re
+        .captures(reply)
+        .and_then(|c| c[1].parse::<f64>().ok())
+        .map(|s| s.round().clamp(0.0, 10.0) as u8);
+    let (verdict, reason) = match verdict_re.captures(reply) {
+        Some(c) => {
+            let verdict = if c[1].eq_ignore_ascii_case("pass") { Verdict::Pass } else { Verdict::Fail };
+            (Some(verdict), c[2].trim().to_string())
+        }
+        None => (None, reply.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("").chars().take(MAX_REASON_CHARS).collect()),
+    };
+    (score, verdict, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_read_even_when_decorated() {
+        assert_eq!(
+            parse("SCORE: 8\nVERDICT: pass - covers the empty case."),
+            (Some(8), Some(Verdict::Pass), "covers the empty case.".to_string())
+        );
+        assert_eq!(
+            parse("**Score:** 3.6/10\n**Verdict:** FAIL: the test is missing"),
+            (Some(4), Some(Verdict::Fail), "the test is missing".to_string())
+        );
+        assert_eq!(parse("I can't judge this.\nSorry."), (None, None, "I can't judge this.".to_string()));
+        assert_eq!(parse("SCORE: 42").0, Some(10));
+    }
+
+    #[test]
+    fn judge_keeps_the_model_but_not_the_sandbox() {
+        let run: Vec<String> = ["-m", "o3", "--sandbox", "workspace-write", "-c", "k=v", "--skip-git-repo-check"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            args(&run),
+            ["-m", "o3", "-c", "k=v", "--skip-git-repo-check", "--sandbox", "read-only"]
+        );
+        assert!(prompt("be brief", "", "ok").contains("<answer>\nok\n</answer>"));
+        assert!(!prompt("be brief", "", "ok").contains("<request>"));
+    }
+
+    #[test]
+    fn describe_is_one_line() {
+        let j = Judgement {
+            score: Some(7),
+            verdict: Some(Verdict::Pass),
+            reason: "fine".into(),
+            ..Default::default()
+        };
+        assert_eq!(j.describe(), "Judge: pass (7/10) - fine");
+        assert_eq!(Judgement::default().describe(), "Judge: no verdict");
+    }
+}
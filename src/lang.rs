@@ -0,0 +1,113 @@
+//! `--lang <code>`: ask codex to answer in a given language.
+//!
+//! The code is a BCP 47 style tag (`de`, `pt-BR`, `zh-TW`). It adds one
+//! standard sentence to the end of every prompt, so a team gets the same
+//! wording whichever prompt it is added to, and the run report records
+//! which language was asked for.
+
+use anyhow::{bail, Result};
+
+/// Languages named in the instruction; other well-formed codes are passed
+/// on as they are.
+const NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nb", "Norwegian Bokmål"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("pt-BR", "Brazilian Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+    ("zh-CN", "Simplified Chinese"),
+    ("zh-TW", "Traditional Chinese"),
+];
+
+/// `code` in its usual case (`pt-br` → `pt-BR`), or an error if it isn't
+/// a language tag.
+pub fn normalize(code: &str) -> Result<String> {
+    let mut parts = code.split(['-', '_']);
+    let primary = parts.next().unwrap_or_default();
+    if !(2..=3).contains(&primary.len()) || !primary.chars().all(|c| c.is_ascii_alphabetic()) {
+        bail!("'{}' is not a language code (expected e.g. de, ja or pt-BR)", code);
+    }
+    let mut tag = primary.to_ascii_lowercase();
+    for part in parts {
+        if !(2..=8).contains(&part.len()) || !part.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("'{}' is not a language code (expected e.g. de, ja or pt-BR)", code);
+        }
+        tag.push('-');
+        match part.len() {
+            2 => tag.push_str(&part.to_ascii_uppercase()),
+            4 => {
+                // Script subtags are title case: zh-Hant.
+                let lower = part.to_ascii_lowercase();
+                tag.push_str(&lower[..1].to_ascii_uppercase());
+                tag.push_str(&lower[1..]);
+            }
+            _ => tag.push_str(&part.to_ascii_lowercase()),
+        }
+    }
+    Ok(tag)
+}
+
+/// The sentence added to prompts for (normalized) `code`.
+pub fn instruction(code: &str) -> String {
+    let name = NAMES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .or_else(|| NAMES.iter().find(|(c, _)| Some(*c) == code.split('-').next()))
+        .map(|(_, name)| *name);
+    let language = match name {
+        Some(name) => format!("{} ({})", name, code),
+        None => format!("the language with code `{}`", code),
+    };
+    format!(
+        "Respond in {}, whatever language the request or the files are in. Leave code, identifiers, \
+         command output and quoted text unchanged.",
+        language
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_normalized_or_rejected() {
+        assert_eq!(normalize("DE").unwrap(), "de");
+        assert_eq!(normalize("pt_br").unwrap(), "pt-BR");
+        assert_eq!(normalize("zh-hant").unwrap(), "zh-Hant");
+        for bad in ["", "german", "d", "de-", "en-!!"] {
+            assert!(normalize(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn instruction_names_the_language_when_it_can() {
+        assert!(instruction("de").starts_with("Respond in German (de), "));
+        assert!(instruction("pt-BR").starts_with("Respond in Brazilian Portuguese (pt-BR), "));
+        assert!(instruction("fr-CA").starts_with("Respond in French (fr-CA), "));
+        assert!(instruction("eo").starts_with("Respond in the language with code `eo`, "));
+    }
+}
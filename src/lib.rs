@@ -1,6 +1,62 @@
+pub mod archive;
+pub mod backend;
+pub mod batch;
+pub mod bell;
+pub mod command_log;
+pub mod compat;
+pub mod config;
+pub mod config_cmd;
+pub mod confirm;
+pub mod content;
+pub mod continuation;
+pub mod control;
+pub mod conversation;
+pub mod diagnostics;
+pub mod diagrams;
+pub mod diff;
+pub mod error;
+pub mod eval;
 pub mod events;
+pub mod expiry;
+pub mod export;
+pub mod fork;
+pub mod git;
+pub mod import;
+pub mod judge;
+pub mod lang;
+pub mod listen;
+pub mod lock;
+pub mod metrics;
+pub mod notebook;
+pub mod observer;
 pub mod output;
+pub mod pager;
+pub mod preamble;
+pub mod preprocess;
+pub mod preview;
+pub mod proto;
 pub mod ratelimit;
+pub mod record;
+pub mod recover;
+pub mod redact;
+pub mod render;
 pub mod runner;
+pub mod safety;
 pub mod seat;
 pub mod seat_cmd;
+pub mod self_update;
+pub mod sessions;
+pub mod split;
+pub mod startup;
+pub mod stats;
+pub mod summary;
+pub mod tail;
+pub mod templates;
+pub mod title;
+pub mod trust;
+pub mod usage;
+pub mod window;
+pub mod write_files;
+
+pub use conversation::Session;
+pub use error::{Error, Result};
@@ -0,0 +1,237 @@
+//! `codex-clean listen`: answer prompts arriving on a named pipe.
+//!
+//! Each non-empty line written to the input FIFO is one prompt. Each answer
+//! is one JSON object per line (the same shape `--stdin-json` prints) on the
+//! paired output FIFO, so any language that can open a file can drive codex
+//! without an HTTP stack or argv quoting.
+//!
+//! Writers may come and go: when the last writer closes the input FIFO we
+//! simply reopen it and wait for the next one. Likewise a reader that goes
+//! away from the output FIFO only costs it the answers it wasn't there for.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::lock::SessionLock;
+use crate::runner::{self, Mode, ResumeTarget, RunOptions, RunOutcome};
+
+/// Where answers go when `--out` isn't given: `<input>.out`.
+pub fn default_output_path(input: &Path) -> PathBuf {
+    let mut name = input.as_os_str().to_owned();
+    name.push(".out");
+    PathBuf::from(name)
+}
+
+/// Which session each prompt goes to.
+#[derive(Debug, Default)]
+pub struct Conversation {
+    /// Resume one session for every prompt rather than starting fresh.
+    shared: bool,
+    session_id: Option<String>,
+}
+
+impl Conversation {
+    pub fn new(shared: bool) -> Self {
+        Self { shared, session_id: None }
+    }
+
+    /// The mode for the next prompt: a new session, unless shared and one
+    /// has already been started.
+    pub fn next_mode(&self) -> Mode {
+        match (&self.session_id, self.shared) {
+            (Some(id), true) => Mode::Resume(ResumeTarget::SessionId(id.clone())),
+            _ => Mode::Exec,
+        }
+    }
+
+    /// Remember the session a shared conversation should keep resuming.
+    pub fn record(&mut self, session_id: Option<&str>) {
+        if self.shared && self.session_id.is_none() {
+            self.session_id = session_id.map(str::to_string);
+        }
+    }
+}
+
+/// Serve prompts from `input` until interrupted.
+pub fn run(
+    input: &Path,
+    output: &Path,
+    shared: bool,
+    codex_args: &[String],
+    opts: &RunOptions,
+) -> Result<()> {
+    ensure_fifo(input)?;
+    ensure_fifo(output)?;
+    eprintln!(
+        "Listening on {} (answers on {}){}. Ctrl-C to stop.",
+        input.display(),
+        output.display(),
+        if shared { ", sharing one session" } else { "" }
+    );
+
+    let mut conversation = Conversation::new(shared);
+    let mut answers = AnswerPipe::new(output);
+    loop {
+        // Blocks until a writer opens the FIFO; EOF means the last writer
+        // closed it, so go round and wait for the next.
+        let reader = BufReader::new(
+            File::open(input).with_context(|| format!("opening {}", input.display()))?,
+        );
+        for line in reader.lines() {
+            let line = line.with_context(|| format!("reading {}", input.display()))?;
+            let prompt = line.trim();
+            if prompt.is_empty() {
+                continue;
+            }
+            let answer = answer(&mut conversation, prompt, codex_args, opts);
+            answers.send(&answer)?;
+        }
+    }
+}
+
+/// Run one prompt and build its JSON answer. Errors become `{"error"}`
+/// answers so one bad prompt doesn't stop the listener.
+fn answer(
+    conversation: &mut Conversation,
+    prompt: &str,
+    codex_args: &[String],
+    opts: &RunOptions,
+) -> serde_json::Value {
+    let mode = conversation.next_mode();
+    let lock = match &mode {
+        Mode::Resume(ResumeTarget::SessionId(id)) => match SessionLock::acquire(id, true) {
+            Ok(lock) => Some(lock),
+            Err(e) => return error_answer(&e),
+        },
+        _ => None,
+    };
+    let mut json = serde_json::Value::Null;
+    let mut session_id = None;
+    let result = runner::run_codex_presenting(codex_args, prompt, mode, opts, |o: &RunOutcome| {
+        json = o.to_json();
+        session_id = o.session_id().map(str::to_string);
+    });
+    drop(lock);
+    match result {
+        Ok(_) => {
+            conversation.record(session_id.as_deref());
+            json
+        }
+        Err(e) => error_answer(&e),
+    }
+}
+
+fn error_answer(e: &anyhow::Error) -> serde_json::Value {
+    eprintln!("Error: {:#}", e);
+    serde_json::json!({ "error": format!("{:#}", e), "exit_code": 1 })
+}
+
+/// The output FIFO, opened lazily and reopened after its reader leaves.
+struct AnswerPipe<'a> {
+    path: &'a Path,
+    file: Option<File>,
+}
+
+impl<'a> AnswerPipe<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self { path, file: None }
+    }
+
+    fn send(&mut self, answer: &serde_json::Value) -> Result<()> {
+        let line = format!("{}\n", answer);
+        // A reader that left gives EPIPE on the old handle; one reopen
+        // (which waits for a new reader) covers it.
+        for _ in 0..2 {
+            if self.file.is_none() {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .open(self.path)
+                    .with_context(|| format!("opening {}", self.path.display()))?;
+                self.file = Some(file);
+            }
+            let file = self.file.as_mut().expect("opened above");
+            match file.write_all(line.as_bytes()).and_then(|()| file.flush()) {
+                Ok(()) => return Ok(()),
+                Err(_) => self.file = None,
+            }
+        }
+        eprintln!("Warning: dropped an answer; nobody is reading {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Create `path` as a FIFO (mode 0600) if it doesn't exist; refuse anything
+/// else that's already there.
+#[cfg(unix)]
+pub fn ensure_fifo(path: &Path) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => return Ok(()),
+        Ok(_) => bail!("{} exists and is not a FIFO", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("checking {}", path.display())),
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("invalid path {}", path.display()))?;
+    // SAFETY: c_path is a valid NUL-terminated string for the call's duration.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("creating FIFO {}", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn ensure_fifo(_path: &Path) -> Result<()> {
+    bail!("`listen` needs named pipes, which are only supported on Unix");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_output_path_appends_out() {
+        assert_eq!(default_output_path(Path::new("/tmp/codex")), PathBuf::from("/tmp/codex.out"));
+    }
+
+    #[test]
+    fn conversation_resumes_first_session_only_when_shared() {
+        let mut shared = Conversation::new(true);
+        assert!(matches!(shared.next_mode(), Mode::Exec));
+        shared.record(Some("s1"));
+        shared.record(Some("s2"));
+        match shared.next_mode() {
+            Mode::Resume(ResumeTarget::SessionId(id)) => assert_eq!(id, "s1"),
+            _ => panic!("expected resume of s1"),
+        }
+
+        let mut fresh = Conversation::new(false);
+        fresh.record(Some("s1"));
+        assert!(matches!(fresh.next_mode(), Mode::Exec));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_fifo_creates_and_accepts_fifo_but_rejects_files() {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("in");
+        ensure_fifo(&fifo).unwrap();
+        let meta = std::fs::metadata(&fifo).unwrap();
+        assert!(meta.file_type().is_fifo());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        ensure_fifo(&fifo).unwrap();
+
+        let file = dir.path().join("regular");
+        std::fs::write(&file, "").unwrap();
+        assert!(ensure_fifo(&file).unwrap_err().to_string().contains("not a FIFO"));
+    }
+}
@@ -0,0 +1,191 @@
+//! Per-session advisory locks.
+//!
+//! Two codex-clean processes resuming the same session at once would
+//! interleave turns into one conversation. Each resume takes an exclusive
+//! lock on `~/.config/codex-clean/locks/<session-id>.lock` for the duration
+//! of the run; a second resume either fails fast naming the holder or, with
+//! `--wait`, queues behind it.
+//!
+//! Stale locks recover on their own: the lock is an `flock`-style advisory
+//! lock, so the kernel drops it when the holder exits or crashes, and a
+//! leftover file is simply re-locked by the next run. The holder removes
+//! its file on release; waiters that were queued on the removed file notice
+//! the path no longer refers to what they locked and retry.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+
+use crate::seat::{config_dir, secure_create_dir_all};
+
+/// `~/.config/codex-clean/locks/<session-id>.lock`.
+pub fn session_lock_path(session_id: &str) -> Result<PathBuf> {
+    Ok(config_dir()?.join("locks").join(format!("{}.lock", session_id)))
+}
+
+/// Exclusive lock on one session. Released (and its file removed) on drop.
+pub struct SessionLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl SessionLock {
+    /// Lock `session_id`. With `wait == false`, a session that is already
+    /// locked is an error naming the holding process; otherwise block until
+    /// it is released.
+    pub fn acquire(session_id: &str, wait: bool) -> Result<Self> {
+        let path = session_lock_path(session_id)?;
+        Self::acquire_at(&path, session_id, wait)
+    }
+
+    pub fn acquire_at(path: &Path, session_id: &str, wait: bool) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            secure_create_dir_all(parent)?;
+        }
+        let mut announced = false;
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .with_context(|| format!("opening {}", path.display()))?;
+
+            match file.try_lock_exclusive() {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || is_contended(&e) => {
+                    let holder = read_holder(&mut file);
+                    if !wait {
+                        bail!(
+                            "session {} is already being resumed by another codex-clean{}; \
+                             pass --wait to queue behind it",
+                            session_id,
+                            holder.map(|p| format!(" (pid {})", p)).unwrap_or_default()
+                        );
+                    }
+                    if !announced {
+                        eprintln!(
+                            "Waiting for another codex-clean{} to finish with session {}...",
+                            holder.map(|p| format!(" (pid {})", p)).unwrap_or_default(),
+                            session_id
+                        );
+                        announced = true;
+                    }
+                    file.lock_exclusive()
+                        .with_context(|| format!("locking {}", path.display()))?;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("locking {}", path.display()));
+                }
+            }
+
+            // The previous holder unlinks its file on release. If that
+            // happened while we were queued, we now hold a lock on an inode
+            // nobody else can see — start over on the current path.
+            if !still_linked(&file, path) {
+                continue;
+            }
+
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            writeln!(file, "{}", std::process::id())?;
+            file.flush()?;
+            return Ok(Self { file, path: path.to_path_buf() });
+        }
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        // Unlink while still holding the lock so no newcomer can lock the
+        // old inode after we let go.
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// fs2 reports contention as the platform's "lock held" error, which is
+/// not always mapped to `WouldBlock`.
+fn is_contended(e: &io::Error) -> bool {
+    e.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+fn read_holder(file: &mut File) -> Option<u32> {
+    let mut s = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut s).ok()?;
+    s.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn still_linked(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn still_linked(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_acquire_without_wait_names_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locks").join("s.lock");
+        let _held = SessionLock::acquire_at(&path, "s", false).unwrap();
+
+        let err = SessionLock::acquire_at(&path, "s", false).err().unwrap().to_string();
+        assert!(err.contains("already being resumed"), "{}", err);
+        assert!(err.contains(&format!("pid {}", std::process::id())), "{}", err);
+        assert!(err.contains("--wait"));
+    }
+
+    #[test]
+    fn release_removes_file_and_allows_reacquire() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.lock");
+        let lock = SessionLock::acquire_at(&path, "s", false).unwrap();
+        assert!(path.exists());
+        drop(lock);
+        assert!(!path.exists());
+        assert!(SessionLock::acquire_at(&path, "s", false).is_ok());
+    }
+
+    #[test]
+    fn leftover_unlocked_file_is_recovered() {
+        // Simulates a crashed holder: the file (with a dead pid) survives
+        // but nobody holds the lock.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.lock");
+        fs::write(&path, "999999\n").unwrap();
+        let _lock = SessionLock::acquire_at(&path, "s", false).unwrap();
+        let recorded = fs::read_to_string(&path).unwrap();
+        assert_eq!(recorded.trim(), std::process::id().to_string());
+    }
+
+    #[test]
+    fn waiter_acquires_after_holder_releases() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.lock");
+        let held = SessionLock::acquire_at(&path, "s", false).unwrap();
+
+        let waiter_path = path.clone();
+        let waiter = std::thread::spawn(move || {
+            SessionLock::acquire_at(&waiter_path, "s", true).map(|_| ())
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        drop(held);
+        waiter.join().unwrap().unwrap();
+    }
+}
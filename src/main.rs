@@ -1,9 +1,14 @@
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
 
-use codex_clean::{runner, seat_cmd};
+use codex_clean::{archive, backend, batch, bell, config, config_cmd, continuation, control, diagnostics, diff, eval, expiry, export, fork, import, lang, listen, lock, metrics, output, preamble, preprocess, proto, recover, redact, render, runner, safety, seat, seat_cmd, self_update, sessions, split, startup, stats, summary, tail, templates, title, trust, usage};
 
 #[derive(Parser)]
 #[command(name = "codex-clean")]
@@ -13,25 +18,426 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
+    #[command(flatten)]
+    run: RunFlags,
+
+    /// Read a JSON request ({"prompt", "args", "resume"}) from stdin and print the result as JSON
+    #[arg(long, conflicts_with = "args")]
+    stdin_json: bool,
+
+    /// Ask each level-1 heading of a markdown FILE as its own prompt, in one session, and print the file with the answers filled in
+    #[arg(long, value_name = "FILE", conflicts_with = "stdin_json")]
+    split_prompts: Option<PathBuf>,
+
+    /// (--split-prompts) Write the answers back into FILE instead of printing it
+    #[arg(long, requires = "split_prompts")]
+    in_place: bool,
+
     /// Arguments to pass to codex exec (e.g., -m gpt-5.2-codex --sandbox read-only)
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     args: Vec<String>,
 }
 
+/// Options for runs that spawn codex. Must come before any codex args.
+#[derive(Args, Debug, Default)]
+struct RunFlags {
+    /// Apply the named [profile.<name>] from ~/.config/codex-clean/config.toml
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Kill codex and exit 124 if it runs longer than SECS
+    #[arg(long, global = true, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Print a progress line to stderr every SECS while codex runs (keeps CI logs alive)
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    heartbeat: Option<u64>,
+
+    /// Print JSON progress objects to stderr while codex runs, every SECS (default 5), for wrapping programs
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "5",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    progress_json: Option<u64>,
+
+    /// Stop codex as soon as it sends its first agent message, and print just that
+    #[arg(long, global = true)]
+    first: bool,
+
+    /// If codex finishes without answering, resume the session with a nudge, up to N times
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    retry_on_empty: usize,
+
+    /// (--retry-on-empty) The prompt to nudge with [default: "Please provide your answer."]
+    #[arg(long, global = true, value_name = "TEXT", requires = "retry_on_empty")]
+    nudge_prompt: Option<String>,
+
+    /// If the answer was cut off (output limit, or an unclosed code block), ask codex to continue, up to N times
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    auto_continue: usize,
+
+    /// (--auto-continue) The prompt to continue with [default: "continue exactly where you left off, ..."]
+    #[arg(long, global = true, value_name = "TEXT", requires = "auto_continue")]
+    continue_prompt: Option<String>,
+
+    /// Stop codex as soon as its answer so far matches REGEX, and print what it said
+    #[arg(long, global = true, value_name = "REGEX", value_parser = regex::Regex::new)]
+    stop_on: Option<regex::Regex>,
+
+    /// Stop codex once its agent messages pass N bytes
+    #[arg(long, global = true, value_name = "N")]
+    max_output_bytes: Option<usize>,
+
+    /// What to do past --max-output-bytes: fail the run, or print the output up to the limit
+    #[arg(long, global = true, value_enum, default_value_t = OnOverflow::Abort, requires = "max_output_bytes")]
+    on_overflow: OnOverflow,
+
+    /// Don't print the `Session: <id>` header
+    #[arg(long, global = true, conflicts_with = "redact_session")]
+    no_session: bool,
+
+    /// Print a hash of the session ID instead of the ID itself
+    #[arg(long, global = true)]
+    redact_session: bool,
+
+    /// Don't print notes such as "No session ID received" or "No response received"
+    #[arg(long, global = true, conflicts_with = "warnings_as_errors")]
+    no_warnings: bool,
+
+    /// Exit 1 when a run that otherwise succeeded printed such a note
+    #[arg(long, global = true)]
+    warnings_as_errors: bool,
+
+    /// Wrap long lines of the answer to COLS columns, or to the terminal with `auto` (code blocks untouched)
+    #[arg(long, global = true, value_name = "COLS|auto", value_parser = parse_wrap)]
+    wrap: Option<Wrap>,
+
+    /// Strip markdown from the answer (headings, emphasis, backticks, link targets) for plain-text use
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// Drop conversational openers ("Sure! Here's...") and sign-offs ("Let me know if...") from the answer
+    #[arg(long, global = true)]
+    strip_preamble: bool,
+
+    /// Don't send long output to $PAGER (or less) when stdout is a terminal
+    #[arg(long, global = true)]
+    no_pager: bool,
+
+    /// Screen-reader friendly output: plain text between BEGIN RESPONSE and END RESPONSE, no pager or title sequences
+    #[arg(long, global = true, conflicts_with = "set_title")]
+    a11y: bool,
+
+    /// Print lines codex wrote to stdout that weren't JSON events (banners, update notices)
+    #[arg(long, global = true)]
+    show_noise: bool,
+
+    /// Only print the lines of codex's answer matching REGEX (JSON output keeps the full answer)
+    #[arg(long, global = true, value_name = "REGEX", value_parser = regex::Regex::new)]
+    grep: Option<regex::Regex>,
+
+    /// (--grep) Also print N lines after each match
+    #[arg(long, global = true, value_name = "N", default_value_t = 0, requires = "grep")]
+    after_context: usize,
+
+    /// (--grep) Also print N lines before each match
+    #[arg(long, global = true, value_name = "N", default_value_t = 0, requires = "grep")]
+    before_context: usize,
+
+    /// Print a diff from PATH to the code the answer proposes for it, instead of the answer
+    #[arg(long, global = true, value_name = "PATH", conflicts_with_all = ["plain", "wrap"])]
+    diff_against: Option<PathBuf>,
+
+    /// Also save the run as FORMAT to PATH (repeatable): text, json, markdown, html, github, or template
+    #[arg(long, global = true, num_args = 2, value_names = ["FORMAT", "PATH"])]
+    output: Vec<String>,
+
+    /// The template for `--output template PATH`, with {{message}}, {{session_id}}, {{exit_code}} and more
+    #[arg(long, global = true, value_name = "FILE")]
+    output_template: Option<PathBuf>,
+
+    /// Write the files the answer marks with `// FILE: path` or a path after its code fence
+    #[arg(long, global = true, conflicts_with = "ssh")]
+    write_files: bool,
+
+    /// Let --write-files replace files that already exist
+    #[arg(long, global = true, requires = "write_files")]
+    force: bool,
+
+    /// Preview the files --write-files would write (paths and diffstat) and ask about each
+    #[arg(long, global = true, requires = "write_files")]
+    confirm: bool,
+
+    /// Grade a successful answer against CRITERIA with a second, read-only codex run (recorded in the run report)
+    #[arg(long, global = true, value_name = "CRITERIA")]
+    judge: Option<String>,
+
+    /// Create and switch to a new git branch NAME before the run (`auto` names it after the prompt)
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "ssh")]
+    branch: Option<String>,
+
+    /// A root of a multi-root workspace (repeatable): codex works in the first and may write in all; context globs and git cover each
+    #[arg(long = "workspace", global = true, value_name = "DIR", conflicts_with_all = ["ssh", "docker", "podman"])]
+    workspaces: Vec<PathBuf>,
+
+    /// Snapshot the git working tree before the run and summarise what codex changed afterwards
+    #[arg(long, global = true, conflicts_with = "ssh")]
+    git_snapshot: bool,
+
+    /// Like --git-snapshot, and put the working tree back if the run fails
+    #[arg(long, global = true, conflicts_with = "ssh")]
+    git_revert_on_failure: bool,
+
+    /// Print how long codex took to start, send its first event and message, finish, and exit
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// Print where codex-clean's own time went before codex started
+    #[arg(long, global = true)]
+    profile_startup: bool,
+
+    /// Show the run's status in the terminal or tmux title (running…, done ✓, failed ✗)
+    #[arg(long, global = true)]
+    set_title: bool,
+
+    /// Ring the terminal bell (or run `bell_command` from the config) when the run ends or needs an answer
+    #[arg(long, global = true)]
+    bell: bool,
+
+    /// Save the full output of each shell command codex runs to DIR, with an index in DIR/summary.txt
+    #[arg(long, global = true, value_name = "DIR")]
+    command_log: Option<PathBuf>,
+
+    /// Append each run's prompt and answer, timestamped, to the markdown notebook PATH
+    #[arg(long, global = true, value_name = "PATH")]
+    notebook: Option<PathBuf>,
+
+    /// Render Mermaid and Graphviz blocks in the answer to PNGs (with mmdc / dot), shown inline in kitty and iTerm2
+    #[arg(long, global = true)]
+    render_diagrams: bool,
+
+    /// (--render-diagrams) Save the images in DIR (default: beside the --notebook or first --output, else the current directory)
+    #[arg(long, global = true, value_name = "DIR", requires = "render_diagrams")]
+    diagram_dir: Option<PathBuf>,
+
+    /// Don't ask before running codex with --dangerously-bypass-approvals-and-sandbox and the like
+    #[arg(long, global = true)]
+    yes: bool,
+
+    /// Show the prompt as it will be sent (context files, prefix, suffix, a token estimate) and ask before starting codex
+    #[arg(long, global = true)]
+    preview: bool,
+
+    /// Run even though a [quota] limit in config.toml has been reached
+    #[arg(long, global = true)]
+    override_quota: bool,
+
+    /// Send the prompt as typed, without the configured prompt_prefix / prompt_suffix
+    #[arg(long, global = true)]
+    no_wrap: bool,
+
+    /// Ask for the answer in this language (e.g. de, ja, pt-BR); recorded in the run report
+    #[arg(long, global = true, value_name = "CODE", value_parser = lang::normalize)]
+    lang: Option<String>,
+
+    /// Run codex on a remote machine over ssh (e.g. user@host or an ssh config alias)
+    #[arg(long, global = true, value_name = "HOST", conflicts_with_all = ["docker", "podman", "replay"])]
+    ssh: Option<String>,
+
+    /// Remote directory to run codex in (with --ssh)
+    #[arg(long, global = true, value_name = "DIR", requires = "ssh")]
+    ssh_cwd: Option<String>,
+
+    /// Environment variable for the remote codex (with --ssh; repeatable)
+    #[arg(long, global = true, value_name = "KEY=VALUE", requires = "ssh", value_parser = backend::parse_env_assignment)]
+    ssh_env: Vec<(String, String)>,
+
+    /// Run codex inside a throwaway docker container from IMAGE, with the current directory mounted
+    #[arg(long, global = true, value_name = "IMAGE", conflicts_with = "podman")]
+    docker: Option<String>,
+
+    /// Like --docker, using podman
+    #[arg(long, global = true, value_name = "IMAGE", conflicts_with = "replay")]
+    podman: Option<String>,
+
+    /// Name this run for `codex-clean cancel` (default: generated from the process ID)
+    #[arg(long, global = true, value_name = "ID", value_parser = parse_run_id)]
+    run_id: Option<String>,
+
+    /// Sandbox preset for codex: `read-only`, `workspace-write`, or `yolo` (no sandbox or approvals; always confirmed)
+    #[arg(long, global = true, value_enum, value_name = "MODE", conflicts_with = "replay")]
+    mode: Option<SandboxPreset>,
+
+    /// How to talk to codex: `exec` (default) or `proto`, which can interrupt codex mid-turn on timeout or cancel
+    #[arg(long, global = true, value_enum, default_value_t = Transport::Exec, conflicts_with = "replay")]
+    transport: Transport,
+
+    /// Don't run codex; read its JSON event stream from FILE instead
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "docker")]
+    replay: Option<PathBuf>,
+
+    /// With --replay, play only the recording's last turn
+    #[arg(long, global = true, requires = "replay")]
+    last_turn: bool,
+
+    /// Save codex's raw JSON event stream to FILE (zstd-compressed if it ends in .zst)
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Write the final answer, unrendered, to FILE; it exists (maybe empty) however the run ends
+    #[arg(long, global = true, value_name = "FILE")]
+    last_message_file: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OnOverflow {
+    #[default]
+    Abort,
+    Truncate,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Transport {
+    #[default]
+    Exec,
+    Proto,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SandboxPreset {
+    ReadOnly,
+    WorkspaceWrite,
+    Yolo,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum BatchNames {
+    Index,
+    Slug,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ExportFormat {
+    Html,
+    Markdown,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ImportFrom {
+    ClaudeCode,
+    Aider,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum StatsGroup {
+    Model,
+    Profile,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Wrap {
+    /// The terminal's width; no wrapping when stdout isn't a terminal.
+    Auto,
+    Columns(usize),
+}
+
+fn parse_wrap(s: &str) -> anyhow::Result<Wrap> {
+    if s == "auto" {
+        return Ok(Wrap::Auto);
+    }
+    match s.parse::<usize>() {
+        Ok(cols) if cols > 0 => Ok(Wrap::Columns(cols)),
+        _ => anyhow::bail!("expected a column count or `auto`, got '{}'", s),
+    }
+}
+
+fn parse_run_id(s: &str) -> anyhow::Result<String> {
+    control::validate_run_id(s)?;
+    Ok(s.to_string())
+}
+
+impl RunFlags {
+    /// Where `--render-diagrams` saves its images, if it was given.
+    fn diagram_dir(&self) -> Option<PathBuf> {
+        if !self.render_diagrams {
+            return None;
+        }
+        let outputs = self.output.chunks(2).filter_map(|pair| pair.get(1)).map(PathBuf::from);
+        let beside = self
+            .notebook
+            .clone()
+            .into_iter()
+            .chain(outputs)
+            .find_map(|p| p.parent().filter(|dir| !dir.as_os_str().is_empty()).map(Path::to_path_buf));
+        Some(self.diagram_dir.clone().or(beside).unwrap_or_else(|| PathBuf::from(".")))
+    }
+
+    /// What each `--output FORMAT PATH` should write.
+    fn outputs(&self) -> anyhow::Result<Vec<render::OutputFile>> {
+        let mut renderers = render::Renderers::builtin();
+        if let Some(path) = &self.output_template {
+            let template = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+            renderers.register("template", render::Template::new(template));
+        }
+        self.output
+            .chunks(2)
+            .map(|pair| match pair {
+                [format, path] => renderers.output_file(format, Path::new(path)),
+                _ => unreachable!("clap takes exactly two values for --output"),
+            })
+            .collect()
+    }
+
+    fn backend(&self) -> anyhow::Result<Arc<dyn backend::CodexBackend>> {
+        if let Some(host) = &self.ssh {
+            return Ok(Arc::new(backend::SshBackend {
+                host: host.clone(),
+                cwd: self.ssh_cwd.clone(),
+                env: self.ssh_env.clone(),
+            }));
+        }
+        if let Some(path) = &self.replay {
+            return Ok(Arc::new(backend::ReplayBackend { path: path.clone(), last_turn: self.last_turn }));
+        }
+        let container = match (&self.docker, &self.podman) {
+            (Some(image), _) => Some(("docker", image)),
+            (None, Some(image)) => Some(("podman", image)),
+            (None, None) => None,
+        };
+        match container {
+            Some((engine, image)) => Ok(Arc::new(backend::ContainerBackend::for_current_dir(
+                engine,
+                image,
+                &seat::codex_home()?,
+            )?)),
+            None => Ok(Arc::new(backend::LocalBackend)),
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Resume an existing session
     Resume {
-        /// Use the most recent session
+        /// Use the most recent session (`--last N` picks the Nth most recent)
         #[arg(long)]
         last: bool,
 
-        /// Session ID to resume (optional if --last is used)
+        /// Session ID (or unique prefix) to resume, or @N for the Nth most recent (optional if --last is used)
         session_id: Option<String>,
 
         /// Optional prompt for the resumed session
         #[arg(allow_hyphen_values = true)]
         prompt: Option<String>,
+
+        /// Queue behind another codex-clean resuming the same session instead of failing
+        #[arg(long)]
+        wait: bool,
     },
     /// Review code changes
     Review {
@@ -39,11 +445,321 @@ enum Commands {
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    /// Start a new session seeded with an existing session's transcript
+    Fork {
+        /// Session ID (or unique prefix) to branch from (left untouched)
+        session_id: String,
+
+        /// Arguments to pass to codex exec, followed by the prompt (or '-' for stdin)
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Answer newline-delimited prompts from a named pipe, writing JSON answers to a paired pipe
+    Listen {
+        /// FIFO to read prompts from (created if missing)
+        fifo: PathBuf,
+
+        /// FIFO to write answers to (default: <FIFO>.out; created if missing)
+        #[arg(long, value_name = "FIFO")]
+        out: Option<PathBuf>,
+
+        /// Keep resuming the first prompt's session instead of starting a new one per prompt
+        #[arg(long)]
+        shared: bool,
+
+        /// Arguments to pass to codex exec for every prompt
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Follow a session's on-disk log, rendering events as they are written
+    Tail {
+        /// Follow the most recent session
+        #[arg(long)]
+        last: bool,
+
+        /// Session ID (or unique prefix) to follow (optional if --last is used)
+        session_id: Option<String>,
+
+        /// Print the session so far and exit instead of following it
+        #[arg(long)]
+        no_follow: bool,
+    },
+    /// Diff the final agent messages (or full transcripts) of two sessions; exits 1 if they differ
+    Diff {
+        /// Session ID (or unique prefix), or @N for the Nth most recent
+        session_a: String,
+
+        /// Session to compare it with, in the same forms
+        session_b: String,
+
+        /// Compare the whole user/assistant dialogue instead of just the final answer
+        #[arg(long)]
+        transcript: bool,
+
+        /// Show the two sides in columns instead of a unified diff
+        #[arg(long, short = 'y')]
+        side_by_side: bool,
+
+        /// Lines of context around each change (unified diff)
+        #[arg(long, short = 'U', value_name = "N", default_value_t = 3, conflicts_with = "side_by_side")]
+        unified: usize,
+    },
+    /// Export a session's dialogue, each prompt paired with its response
+    Export {
+        /// Session ID (or unique prefix), or @N for the Nth most recent
+        session: String,
+
+        /// html puts prompts and responses side by side; markdown alternates them
+        #[arg(long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+
+        /// Write to PATH instead of stdout
+        #[arg(long, short = 'o', value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+    /// Ask codex for a short summary of a session, print it, and keep it in ~/.config/codex-clean/summaries/
+    Summarize {
+        /// Session ID (or unique prefix), or @N for the Nth most recent
+        session: String,
+
+        /// Also attach the summary's title to the session, shown wherever sessions are listed
+        #[arg(long)]
+        title: bool,
+
+        /// Arguments to pass to codex exec for the summary (e.g. -m to pick the model)
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Bundle a session's transcript, run reports, recording, written files and git diff into one file
+    Archive {
+        /// Session ID (or unique prefix), or @N for the Nth most recent
+        session: String,
+
+        /// Write to PATH (default: session-<ID>.tar.zst; a name not ending in .zst is left uncompressed)
+        #[arg(long, short = 'o', value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+    /// Unpack an archive made by `archive` and list what it holds
+    Unarchive {
+        /// Archive to unpack
+        archive: PathBuf,
+
+        /// Directory to unpack into
+        #[arg(short = 'C', long = "dir", value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Import other agent CLIs' session logs, for `export` and `diff` to read beside codex's sessions
+    Import {
+        /// The tool that wrote the logs
+        #[arg(long, value_enum)]
+        from: ImportFrom,
+
+        /// Session logs (Claude Code's <session>.jsonl, aider's .aider.chat.history.md)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Run each prompt in a file (one per line) as its own session, in turn
+    Batch {
+        /// File of prompts, one per line (`#` starts a comment), or - for stdin
+        file: PathBuf,
+
+        /// Keep each prompt's output, report, raw events and files in a subdirectory of DIR
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+
+        /// (--out-dir) Name the subdirectories 001, 002, ... or after each prompt
+        #[arg(long, value_enum, default_value_t = BatchNames::Index, requires = "out_dir")]
+        name_by: BatchNames,
+
+        /// Run the prompts in a random order
+        #[arg(long)]
+        shuffle: bool,
+
+        /// Run only K of the prompts, picked at random
+        #[arg(long, value_name = "K")]
+        sample: Option<usize>,
+
+        /// Seed --shuffle and --sample, to repeat an earlier order and subset
+        #[arg(long, value_name = "N")]
+        seed: Option<u64>,
+
+        /// Arguments to pass to codex exec for every prompt
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run a suite of prompts and score the answers against what they must contain
+    Eval {
+        /// Suite file: [[case]] entries with a prompt, expect/reject patterns and an optional schema
+        suite: PathBuf,
+
+        /// Where to write the JSON report
+        #[arg(long, value_name = "PATH", default_value = "eval-report.json")]
+        report: PathBuf,
+
+        /// Also write the results as JUnit XML
+        #[arg(long, value_name = "PATH")]
+        junit: Option<PathBuf>,
+
+        /// How many runs at once (overrides the suite's `parallel`)
+        #[arg(long, short = 'j', value_name = "N")]
+        parallel: Option<usize>,
+
+        /// Compare with an earlier report and fail only if a pass rate fell
+        #[arg(long, value_name = "REPORT")]
+        baseline: Option<PathBuf>,
+
+        /// (--baseline) Percentage points a pass rate may fall before failing
+        #[arg(long, value_name = "POINTS", default_value_t = 0.0, requires = "baseline")]
+        max_regression: f64,
+    },
+    /// Summarise your recent runs: duration, token usage, and failure rate
+    Stats {
+        /// How many of the most recent runs to include
+        #[arg(long, value_name = "N", default_value_t = 50, value_parser = clap::value_parser!(u64).range(1..))]
+        last: u64,
+
+        /// Show one row per model or per profile
+        #[arg(long, value_enum)]
+        by: Option<StatsGroup>,
+
+        /// Only count runs whose answers had code in LANG (rust, python, ...), and list them
+        #[arg(long, value_name = "LANG")]
+        code: Option<String>,
+    },
+    /// Show token usage (and estimated cost) for today, or this month with --month
+    Usage {
+        /// Cover the current calendar month, day by day
+        #[arg(long)]
+        month: bool,
+    },
+    /// Allow the .codex-clean.toml that applies in DIR to take effect (it is ignored until trusted)
+    Trust {
+        /// Directory whose project config to trust (default: the current one)
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Stop trusting it instead
+        #[arg(long)]
+        revoke: bool,
+    },
+    /// Stop a running codex-clean, printing its output so far; lists runs when no ID is given
+    Cancel {
+        /// Run ID (see --run-id)
+        run_id: Option<String>,
+    },
+    /// Print the answer so far of a run that codex-clean crashed during
+    Recover {
+        /// Run ID (default: the most recent run that left a recovery file)
+        run_id: Option<String>,
+
+        /// List the runs that can be recovered instead
+        #[arg(long, conflicts_with_all = ["run_id", "delete"])]
+        list: bool,
+
+        /// Remove the recovery file once it has been printed
+        #[arg(long)]
+        delete: bool,
+    },
+    /// Copy a --record file with tokens, keys and passwords replaced, to share it safely
+    Redact {
+        /// Recording to redact (plain or zstd)
+        recording: PathBuf,
+
+        /// Write to PATH (compressed if it ends in .zst) instead of stdout
+        #[arg(long, short = 'o', value_name = "PATH")]
+        out: Option<PathBuf>,
+
+        /// Also redact what REGEX matches (repeatable)
+        #[arg(long = "pattern", short = 'e', value_name = "REGEX")]
+        patterns: Vec<String>,
+    },
+    /// Manage reusable prompt templates in ~/.config/codex-clean/templates/
+    Templates {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+    /// Run codex with a saved prompt template, filling in its {{placeholders}}
+    RunTemplate {
+        /// Template to run (see `templates list`)
+        name: String,
+
+        /// Set a placeholder: --var pr=123 replaces {{pr}} (repeatable; before any codex args)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = backend::parse_env_assignment)]
+        vars: Vec<(String, String)>,
+
+        /// Read placeholder values from a JSON object; --var takes precedence
+        #[arg(long, value_name = "FILE")]
+        vars_json: Option<PathBuf>,
+
+        /// Arguments to pass to codex exec
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
     /// Manage ChatGPT seats (separate OAuth identities) for rotation across usage caps
     Seat {
         #[command(subcommand)]
         action: SeatAction,
     },
+    /// Inspect or modify ~/.config/codex-clean/config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Replace this binary with the latest GitHub release (checksum-verified)
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+
+        /// Reinstall even if this version is already the latest
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a value by dotted key (e.g. profile.review.args), or the whole file
+    Get {
+        /// Dotted key; omit to print the whole file
+        key: Option<String>,
+    },
+    /// Set a value by dotted key; VALUE is parsed as TOML (60, true, ["-m", "o3"]) or else taken as a string
+    Set {
+        /// Dotted key (e.g. profile.fast.timeout_seconds)
+        key: String,
+        /// New value
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+    /// Open the config file in $VISUAL / $EDITOR, validating before saving
+    Edit,
+    /// Print the config file's path
+    Path,
+    /// Check the config file for parse errors, unknown keys, and bad values
+    Doctor,
+}
+
+#[derive(Subcommand)]
+enum TemplateAction {
+    /// List saved templates with the first line of each
+    List,
+    /// Print a template
+    Show {
+        /// Template name
+        name: String,
+    },
+    /// Save a template from FILE, or from stdin
+    Add {
+        /// Template name (letters, digits, '-', '_' and '.')
+        name: String,
+        /// Read the template from this file instead of stdin
+        #[arg(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Replace an existing template of the same name
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -88,28 +804,230 @@ enum SeatAction {
 }
 
 fn main() -> ExitCode {
+    let started = std::time::Instant::now();
+    diagnostics::install_panic_hook();
     let cli = Cli::parse();
+    let run = cli.run;
+    if run.profile_startup {
+        startup::begin(started);
+        startup::mark("parse arguments");
+    }
+    if cli.stdin_json && cli.command.is_some() {
+        eprintln!("Error: --stdin-json runs a new or resumed session and can't be combined with a subcommand");
+        return ExitCode::from(2);
+    }
+    if cli.split_prompts.is_some() && cli.command.is_some() {
+        eprintln!("Error: --split-prompts runs its own session and can't be combined with a subcommand");
+        return ExitCode::from(2);
+    }
 
     let result = match cli.command {
         Some(Commands::Resume {
             last,
             session_id,
             prompt,
-        }) => run_resume(last, session_id, prompt),
-        Some(Commands::Review { args }) => run_review(args),
+            wait,
+        }) => run_resume(&run, last, session_id, prompt, wait),
+        Some(Commands::Review { args }) => run_review(&run, args),
+        Some(Commands::Fork { session_id, args }) => run_fork(&run, &session_id, args),
+        Some(Commands::Listen {
+            fifo,
+            out,
+            shared,
+            args,
+        }) => run_listen(&run, &fifo, out, shared, &args).map(|()| 0),
+        Some(Commands::Tail {
+            last,
+            session_id,
+            no_follow,
+        }) => run_tail(last, session_id, no_follow).map(|()| 0),
+        Some(Commands::Diff {
+            session_a,
+            session_b,
+            transcript,
+            side_by_side,
+            unified,
+        }) => {
+            let content = if transcript { diff::Content::Transcript } else { diff::Content::FinalMessage };
+            let layout = if side_by_side {
+                diff::Layout::SideBySide { width: output::terminal_width().unwrap_or(160) }
+            } else {
+                diff::Layout::Unified { context: unified }
+            };
+            diff::run(&session_a, &session_b, content, layout)
+        }
+        Some(Commands::Export { session, format, out }) => {
+            let format = match format {
+                ExportFormat::Html => export::DialogueFormat::Html,
+                ExportFormat::Markdown => export::DialogueFormat::Markdown,
+            };
+            export::export_session(&session, format, out.as_deref()).map(|()| 0)
+        }
+        Some(Commands::Summarize { session, title, args }) => run_summarize(&run, &session, title, &args),
+        Some(Commands::Archive { session, out }) => archive::archive(&session, out.as_deref()).map(|()| 0),
+        Some(Commands::Unarchive { archive, dir }) => archive::unarchive(&archive, &dir).map(|()| 0),
+        Some(Commands::Import { from, files }) => {
+            let source = match from {
+                ImportFrom::ClaudeCode => import::Source::ClaudeCode,
+                ImportFrom::Aider => import::Source::Aider,
+            };
+            import::run(source, &files).map(|()| 0)
+        }
+        Some(Commands::Batch {
+            file,
+            out_dir,
+            name_by,
+            shuffle,
+            sample,
+            seed,
+            args,
+        }) => {
+            let selection = batch::Selection { shuffle, sample, seed };
+            run_batch(&run, &file, &selection, out_dir.as_deref(), name_by, &args)
+        }
+        Some(Commands::Eval {
+            suite,
+            report,
+            junit,
+            parallel,
+            baseline,
+            max_regression,
+        }) => {
+            let outputs = eval::Outputs {
+                report: &report,
+                junit: junit.as_deref(),
+                baseline: baseline.as_deref(),
+                max_regression,
+            };
+            run_eval(&run, &suite, parallel, &outputs)
+        }
+        Some(Commands::Stats { last, by, code }) => {
+            let by = by.map(|b| match b {
+                StatsGroup::Model => stats::GroupBy::Model,
+                StatsGroup::Profile => stats::GroupBy::Profile,
+            });
+            stats::run(last as usize, by, code.as_deref()).map(|()| 0)
+        }
+        Some(Commands::Usage { month }) => usage::run(month).map(|()| 0),
+        Some(Commands::Trust { dir, revoke }) => trust::run(&dir, revoke).map(|()| 0),
+        Some(Commands::Cancel { run_id }) => match run_id {
+            Some(id) => control::cancel(&id),
+            None => control::list(),
+        }
+        .map(|()| 0),
+        Some(Commands::Recover { run_id, list, delete }) => recover::run(run_id.as_deref(), list, delete).map(|()| 0),
+        Some(Commands::Redact { recording, out, patterns }) => redact::run(&recording, out.as_deref(), &patterns).map(|()| 0),
+        Some(Commands::Templates { action }) => run_templates(action).map(|()| 0),
+        Some(Commands::RunTemplate {
+            name,
+            vars,
+            vars_json,
+            args,
+        }) => run_template(&run, &name, vars, vars_json, args),
         Some(Commands::Seat { action }) => run_seat(action).map(|()| 0),
-        None => run_exec(cli.args),
+        Some(Commands::Config { action }) => run_config(action).map(|()| 0),
+        Some(Commands::SelfUpdate { check, force }) => self_update::run(check, force).map(|()| 0),
+        None if cli.stdin_json => run_stdin_json(&run),
+        None => match &cli.split_prompts {
+            Some(file) => run_split_prompts(&run, file, cli.in_place, &cli.args),
+            None => run_exec(&run, cli.args),
+        },
     };
 
+    // Runs that failed before codex finished still need their title and bell.
+    let code = match &result {
+        Ok(code) => *code,
+        Err(_) => 1,
+    };
+    title::finish(code);
+    bell::finish(code);
     match result {
         Ok(code) => exit_code_from_child(code),
         Err(e) => {
             eprintln!("Error: {:#}", e);
-            ExitCode::from(1)
+            match e.downcast_ref::<codex_clean::Error>() {
+                Some(err) => exit_code_from_child(err.exit_code()),
+                None => ExitCode::from(1),
+            }
         }
     }
 }
 
+fn run_tail(last: bool, session_id: Option<String>, no_follow: bool) -> anyhow::Result<()> {
+    let target = match (last, session_id) {
+        (_, Some(id)) => tail::TailTarget::SessionId(id),
+        (true, None) => tail::TailTarget::Last,
+        (false, None) => anyhow::bail!("Either --last or SESSION_ID is required"),
+    };
+    tail::run(target, !no_follow)
+}
+
+fn run_listen(
+    run: &RunFlags,
+    fifo: &std::path::Path,
+    out: Option<PathBuf>,
+    shared: bool,
+    args: &[String],
+) -> anyhow::Result<()> {
+    let out = out.unwrap_or_else(|| listen::default_output_path(fifo));
+    let (codex_args, opts) = resolve_run(run, args, false)?;
+    listen::run(fifo, &out, shared, &codex_args, &opts)
+}
+
+fn run_batch(
+    run: &RunFlags,
+    file: &std::path::Path,
+    selection: &batch::Selection,
+    out_dir: Option<&std::path::Path>,
+    name_by: BatchNames,
+    args: &[String],
+) -> anyhow::Result<i32> {
+    let raw = if file == std::path::Path::new("-") {
+        let mut raw = String::new();
+        std::io::stdin().read_to_string(&mut raw).context("reading prompts from stdin")?;
+        raw
+    } else {
+        std::fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?
+    };
+    let prompts = batch::read_prompts(&raw);
+    if prompts.is_empty() {
+        anyhow::bail!("{} has no prompts", file.display());
+    }
+    if selection.seed.is_some() && !selection.shuffle && selection.sample.is_none() {
+        anyhow::bail!("--seed only applies to --shuffle and --sample");
+    }
+    let name_by = match name_by {
+        BatchNames::Index => batch::NameBy::Index,
+        BatchNames::Slug => batch::NameBy::Slug,
+    };
+    let (codex_args, mut opts) = resolve_run(run, args, false)?;
+    // One answer after another shouldn't stop in a pager each time.
+    opts.render.pager = false;
+    batch::run(&prompts, selection, &codex_args, &opts, out_dir, name_by)
+}
+
+fn run_summarize(run: &RunFlags, session: &str, title: bool, args: &[String]) -> anyhow::Result<i32> {
+    let (codex_args, mut opts) = resolve_run(run, args, true)?;
+    opts.render.pager = false;
+    summary::run(session, &codex_args, &opts, title)
+}
+
+fn run_split_prompts(run: &RunFlags, file: &Path, in_place: bool, args: &[String]) -> anyhow::Result<i32> {
+    let (codex_args, mut opts) = resolve_run(run, args, false)?;
+    opts.render.pager = false;
+    split::run(file, in_place, &codex_args, &opts)
+}
+
+fn run_eval(
+    run: &RunFlags,
+    suite: &std::path::Path,
+    parallel: Option<usize>,
+    outputs: &eval::Outputs,
+) -> anyhow::Result<i32> {
+    let (args, opts) = resolve_run(run, &[], false)?;
+    eval::run(suite, parallel, outputs, &args, &opts)
+}
+
 fn run_seat(action: SeatAction) -> anyhow::Result<()> {
     match action {
         SeatAction::Add {
@@ -125,8 +1043,192 @@ fn run_seat(action: SeatAction) -> anyhow::Result<()> {
     }
 }
 
-fn run_exec(args: Vec<String>) -> anyhow::Result<i32> {
-    let (codex_args, prompt_arg) = split_codex_args(&args)?;
+fn run_templates(action: TemplateAction) -> anyhow::Result<()> {
+    match action {
+        TemplateAction::List => templates::list(),
+        TemplateAction::Show { name } => templates::show(&name),
+        TemplateAction::Add { name, file, force } => templates::add(&name, file.as_deref(), force),
+    }
+}
+
+fn run_config(action: ConfigAction) -> anyhow::Result<()> {
+    match action {
+        ConfigAction::Get { key } => config_cmd::get(key.as_deref()),
+        ConfigAction::Set { key, value } => config_cmd::set(&key, &value),
+        ConfigAction::Edit => config_cmd::edit(),
+        ConfigAction::Path => config_cmd::path(),
+        ConfigAction::Doctor => config_cmd::doctor(),
+    }
+}
+
+fn run_exec(run: &RunFlags, args: Vec<String>) -> anyhow::Result<i32> {
+    let (codex_args, prompt) = resolve_prompt(&args)?;
+    let (codex_args, opts) = resolve_run(run, codex_args, true)?;
+    runner::run_codex_with_options(&codex_args, &prompt, runner::Mode::Exec, &opts)
+}
+
+fn run_template(
+    run: &RunFlags,
+    name: &str,
+    vars: Vec<(String, String)>,
+    vars_json: Option<PathBuf>,
+    args: Vec<String>,
+) -> anyhow::Result<i32> {
+    // Later entries win, so the JSON file goes first for --var to override.
+    let mut all_vars = match vars_json {
+        Some(path) => templates::load_vars_json(&path)?,
+        None => Vec::new(),
+    };
+    all_vars.extend(vars);
+    let prompt = templates::render(name, &templates::load(name)?, &all_vars)?;
+    let (codex_args, opts) = resolve_run(run, &args, true)?;
+    runner::run_codex_with_options(&codex_args, &prompt, runner::Mode::Exec, &opts)
+}
+
+fn run_fork(run: &RunFlags, session_id: &str, args: Vec<String>) -> anyhow::Result<i32> {
+    let (codex_args, prompt) = resolve_prompt(&args)?;
+    let (codex_args, opts) = resolve_run(run, codex_args, true)?;
+    fork::run(session_id, &codex_args, &prompt, &opts)
+}
+
+/// Resolve the config layers (environment, config file, profile, flags)
+/// into the codex args and options for this run.
+/// `interruptible` makes Ctrl-C stop codex and print what it had so far;
+/// only for commands that make a single run.
+fn resolve_run(
+    run: &RunFlags,
+    cli_args: &[String],
+    interruptible: bool,
+) -> anyhow::Result<(Vec<String>, runner::RunOptions)> {
+    let env = config::EnvSettings::from_env()?;
+    let cfg = config::Config::load_layered()?;
+    let mode = run.mode.map(|m| match m {
+        SandboxPreset::ReadOnly => safety::SandboxMode::ReadOnly,
+        SandboxPreset::WorkspaceWrite => safety::SandboxMode::WorkspaceWrite,
+        SandboxPreset::Yolo => safety::SandboxMode::Yolo,
+    });
+    // The preset goes where its flags would have been typed, and keeps the
+    // config's `sandbox` from being added.
+    let mut args = mode.map(|m| m.args()).unwrap_or_default();
+    args.extend_from_slice(cli_args);
+    let flags = config::FlagSettings {
+        profile: run.profile.as_deref(),
+        timeout_seconds: run.timeout,
+        no_wrap: run.no_wrap,
+        lang: run.lang.as_deref(),
+        args: &args,
+        workspaces: &run.workspaces,
+    };
+    let resolved = config::resolve(&cfg, &env, &flags)?;
+    startup::mark("load config");
+    let backend = run.backend()?;
+    let mut confirm_dangerous = resolved.confirm_dangerous;
+    if let Some(mode) = mode {
+        mode.check(&resolved.args, &backend.compat())?;
+        confirm_dangerous = mode.policy(confirm_dangerous);
+    }
+    if run.bell {
+        bell::arm(cfg.bell_command.clone());
+    }
+    if run.replay.is_none() {
+        safety::confirm(&resolved.args, backend.uses_local_auth(), confirm_dangerous, run.yes)?;
+        usage::check_quota(&cfg, run.override_quota)?;
+        startup::mark("safety and quota checks");
+    }
+    if run.set_title {
+        title::start();
+    }
+    let preprocessors = preprocess::Pipeline::configured(&resolved.preprocessors, &resolved.prompt_wrap, resolved.token_budget)
+        .context("reading `preprocessors` in the config")?;
+    let opts = runner::RunOptions {
+        timeout: resolved.timeout_seconds.map(Duration::from_secs),
+        prompt_wrap: resolved.prompt_wrap,
+        preprocessors: Some(preprocessors),
+        hooks: resolved.hooks,
+        backend,
+        run_id: run.run_id.clone(),
+        heartbeat: run.heartbeat.map(Duration::from_secs),
+        progress_json: run.progress_json.map(Duration::from_secs),
+        first_message_only: run.first,
+        stop_on: run.stop_on.clone(),
+        max_output_bytes: run.max_output_bytes,
+        truncate_overflow: run.on_overflow == OnOverflow::Truncate,
+        warnings_as_errors: run.warnings_as_errors,
+        outputs: run.outputs()?,
+        command_log: run.command_log.clone(),
+        notebook: run.notebook.clone(),
+        render_diagrams: run.diagram_dir(),
+        print_timings: run.timings,
+        retry_on_empty: run.retry_on_empty,
+        git_snapshot: run.git_snapshot,
+        git_revert_on_failure: run.git_revert_on_failure,
+        branch: run.branch.clone(),
+        write_files: run.write_files,
+        overwrite_files: run.force,
+        confirm_writes: run.confirm,
+        judge: run.judge.clone(),
+        record: run.record.clone().or_else(|| env.record.clone()),
+        report_path: None,
+        last_message_file: run.last_message_file.clone(),
+        preview: run.preview,
+        nudge_prompt: run.nudge_prompt.clone().unwrap_or_else(|| runner::DEFAULT_NUDGE_PROMPT.to_string()),
+        auto_continue: run.auto_continue,
+        continue_prompt: run.continue_prompt.clone().unwrap_or_else(|| continuation::DEFAULT_CONTINUE_PROMPT.to_string()),
+        profile: resolved.profile.clone(),
+        transport: match run.transport {
+            Transport::Exec => proto::Transport::Exec,
+            Transport::Proto => proto::Transport::Proto,
+        },
+        observer: run.set_title.then(|| Arc::new(title::TitleObserver) as Arc<dyn codex_clean::observer::EventObserver>),
+        counters: metrics::Counters::compile(&cfg.counters).context("reading [counters] in the config")?,
+        context_windows: cfg.context_windows.clone(),
+        session_max_age: expiry::max_age(&cfg),
+        cancel: interruptible.then(|| {
+            let handle = control::CancelHandle::new();
+            control::cancel_on_interrupt(&handle);
+            handle
+        }),
+        render: output::RenderOptions {
+            hide_warnings: run.no_warnings,
+            plain: run.plain || run.a11y || env.plain,
+            strip_preamble: if run.strip_preamble {
+                Some(preamble::PreambleFilter::new(&resolved.preamble_patterns, &resolved.signoff_patterns)?)
+            } else {
+                None
+            },
+            wrap: match run.wrap {
+                Some(Wrap::Auto) => output::terminal_width(),
+                Some(Wrap::Columns(cols)) => Some(cols),
+                None => None,
+            },
+            session_header: if run.no_session {
+                output::SessionHeader::Omit
+            } else if run.redact_session {
+                output::SessionHeader::Redact
+            } else {
+                output::SessionHeader::Show
+            },
+            grep: run.grep.clone().map(|pattern| output::LineFilter {
+                pattern,
+                before: run.before_context,
+                after: run.after_context,
+            }),
+            // Filled in by the runner once the branch exists.
+            branch: None,
+            pager: !run.no_pager && !run.a11y,
+            a11y: run.a11y,
+            show_noise: run.show_noise,
+            diff_against: run.diff_against.as_deref().map(diff::DiffAgainst::read).transpose()?,
+        },
+    };
+    startup::mark("resolve options");
+    Ok((resolved.args, opts))
+}
+
+/// Split trailing args into codex args and the prompt, reading the prompt
+/// from stdin when it is '-'.
+fn resolve_prompt(args: &[String]) -> anyhow::Result<(&[String], String)> {
+    let (codex_args, prompt_arg) = split_codex_args(args)?;
 
     // Handle stdin input
     let prompt = if prompt_arg == "-" {
@@ -136,37 +1238,203 @@ fn run_exec(args: Vec<String>) -> anyhow::Result<i32> {
     };
 
     if prompt.trim().is_empty() {
-        anyhow::bail!("Empty prompt provided");
+        return Err(codex_clean::Error::EmptyPrompt.into());
     }
+    startup::mark("read prompt");
 
-    runner::run_codex(&codex_args.to_vec(), &prompt, runner::Mode::Exec)
+    Ok((codex_args, prompt))
+}
+
+/// Which session a `resume` invocation asked for, before any disk lookup.
+#[derive(Debug, PartialEq, Eq)]
+enum ResumeRequest {
+    /// Nth most recent session (1 = most recent).
+    Recent(usize),
+    SessionId(String),
 }
 
 fn run_resume(
+    run: &RunFlags,
     last: bool,
     session_id: Option<String>,
     prompt: Option<String>,
+    wait: bool,
 ) -> anyhow::Result<i32> {
-    // When --last is used, both positionals are prompt fragments
-    // (e.g., `resume --last add error` → prompt "add error")
-    let (resume_target, actual_prompt) = if last {
+    let (request, actual_prompt) = parse_resume_request(last, session_id, prompt)?;
+    let (resume_target, lock) = lock_resume_target(request, wait)?;
+    // After the (possibly long) wait for the lock, which Ctrl-C should end.
+    let (codex_args, opts) = resolve_run(run, &[], true)?;
+    if let Some(max_age) = opts.session_max_age {
+        if let Some((session, age)) = expiry::check(&resume_target, max_age) {
+            let can_start_fresh = !actual_prompt.trim().is_empty();
+            let summary = summary::Summary::load(&session.id);
+            if expiry::offer_fresh(&session.id, age, max_age, summary.is_some(), can_start_fresh, safety::read_answer) {
+                drop(lock);
+                return expiry::start_fresh(&session, summary.as_ref(), &codex_args, &actual_prompt, &opts);
+            }
+        }
+    }
+    runner::run_codex_with_options(
+        &codex_args,
+        &actual_prompt,
+        runner::Mode::Resume(resume_target),
+        &opts,
+    )
+}
+
+/// Look up the session a resume asked for and lock it. The lock must be
+/// held for the whole run so a concurrent resume of the same session can't
+/// interleave turns.
+fn lock_resume_target(
+    request: ResumeRequest,
+    wait: bool,
+) -> anyhow::Result<(runner::ResumeTarget, Option<lock::SessionLock>)> {
+    let resume_target = match request {
+        // The most recent session is left to codex's own --last so the
+        // prompt keeps flowing through stdin exactly as before.
+        ResumeRequest::Recent(1) => runner::ResumeTarget::Last,
+        ResumeRequest::Recent(n) => {
+            runner::ResumeTarget::SessionId(sessions::nth_recent_session(n)?.id)
+        }
+        ResumeRequest::SessionId(id) => {
+            runner::ResumeTarget::SessionId(sessions::resolve_session_id(&id)?)
+        }
+    };
+
+    // For codex's own --last we lock the session discovery considers most
+    // recent (best effort: skipped if no session logs are visible).
+    let lock_id = match &resume_target {
+        runner::ResumeTarget::SessionId(id) => Some(id.clone()),
+        runner::ResumeTarget::Last => sessions::latest_session().ok().map(|s| s.id),
+    };
+    let lock = lock_id
+        .map(|id| lock::SessionLock::acquire(&id, wait))
+        .transpose()?;
+    Ok((resume_target, lock))
+}
+
+/// One `--stdin-json` request.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct JsonRequest {
+    /// Required for new sessions; optional when resuming.
+    #[serde(default)]
+    prompt: Option<String>,
+    /// Codex arguments, as would precede the prompt on the command line.
+    #[serde(default)]
+    args: Vec<String>,
+    /// `true` for the most recent session, or a session ID / prefix / `@N`.
+    #[serde(default)]
+    resume: Option<JsonResume>,
+    /// Queue behind another codex-clean resuming the same session.
+    #[serde(default)]
+    wait: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum JsonResume {
+    Last(bool),
+    Session(String),
+}
+
+impl JsonRequest {
+    /// The resume request (if any) and the prompt to send.
+    fn plan(&self) -> anyhow::Result<(Option<ResumeRequest>, String)> {
+        let prompt = self.prompt.clone().unwrap_or_default();
+        let resume = match &self.resume {
+            None | Some(JsonResume::Last(false)) => None,
+            Some(JsonResume::Last(true)) => Some(ResumeRequest::Recent(1)),
+            Some(JsonResume::Session(id)) => {
+                Some(parse_resume_request(false, Some(id.clone()), None)?.0)
+            }
+        };
+        if resume.is_none() && prompt.trim().is_empty() {
+            anyhow::bail!("request has no \"prompt\" (required unless \"resume\" is set)");
+        }
+        Ok((resume, prompt))
+    }
+}
+
+/// `--stdin-json`: read one [`JsonRequest`] from stdin, run it, and print
+/// the outcome as a JSON object on stdout. Failures before codex starts
+/// are reported the same way, as `{"error": ..., "exit_code": 1}`.
+fn run_stdin_json(run: &RunFlags) -> anyhow::Result<i32> {
+    match stdin_json_inner(run) {
+        Ok(code) => Ok(code),
+        Err(e) => {
+            let v = serde_json::json!({ "error": format!("{:#}", e), "exit_code": 1 });
+            println!("{}", v);
+            Ok(1)
+        }
+    }
+}
+
+fn stdin_json_inner(run: &RunFlags) -> anyhow::Result<i32> {
+    let raw = read_stdin()?;
+    let request: JsonRequest =
+        serde_json::from_str(&raw).context("parsing JSON request from stdin")?;
+    let (resume, prompt) = request.plan()?;
+    let (codex_args, opts) = resolve_run(run, &request.args, true)?;
+    let present = |outcome: &runner::RunOutcome| println!("{}", outcome.to_json());
+    let (mode, _lock) = match resume {
+        Some(resume) => {
+            let (target, lock) = lock_resume_target(resume, request.wait)?;
+            // Nobody to ask here, so only warn.
+            if let Some(max_age) = opts.session_max_age {
+                if let Some((session, age)) = expiry::check(&target, max_age) {
+                    eprintln!("{}", expiry::warning(&session.id, age, max_age));
+                }
+            }
+            (runner::Mode::Resume(target), lock)
+        }
+        None => (runner::Mode::Exec, None),
+    };
+    runner::run_codex_presenting(&codex_args, &prompt, mode, &opts, present)
+}
+
+fn parse_resume_request(
+    last: bool,
+    session_id: Option<String>,
+    prompt: Option<String>,
+) -> anyhow::Result<(ResumeRequest, String)> {
+    if last {
+        // `resume --last 2 fix it` selects the second most recent session;
+        // otherwise both positionals are prompt fragments
+        // (e.g., `resume --last add error` → prompt "add error")
+        if let Some(n) = session_id.as_deref().and_then(parse_recent_index) {
+            return Ok((ResumeRequest::Recent(n), prompt.unwrap_or_default()));
+        }
         let parts: Vec<&str> = [session_id.as_deref(), prompt.as_deref()]
             .into_iter()
             .flatten()
             .collect();
-        (runner::ResumeTarget::Last, parts.join(" "))
-    } else {
-        let id = session_id.ok_or_else(|| anyhow::anyhow!("Either --last or SESSION_ID is required"))?;
-        (runner::ResumeTarget::SessionId(id), prompt.unwrap_or_default())
+        return Ok((ResumeRequest::Recent(1), parts.join(" ")));
+    }
+
+    let id = session_id.ok_or_else(|| anyhow::anyhow!("Either --last or SESSION_ID is required"))?;
+    let request = match id.strip_prefix('@') {
+        Some(n) => ResumeRequest::Recent(parse_recent_index(n).ok_or_else(|| {
+            anyhow::anyhow!("'{}' is not a valid session index (use @1 for the most recent)", id)
+        })?),
+        None => ResumeRequest::SessionId(id),
     };
+    Ok((request, prompt.unwrap_or_default()))
+}
 
-    runner::run_codex(&[], &actual_prompt, runner::Mode::Resume(resume_target))
+/// Parse a 1-based "Nth most recent" index.
+fn parse_recent_index(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok().filter(|n| *n >= 1)
 }
 
-fn run_review(args: Vec<String>) -> anyhow::Result<i32> {
+fn run_review(run: &RunFlags, args: Vec<String>) -> anyhow::Result<i32> {
     // Pass all args through to codex exec review — it handles its own
     // flag and optional trailing prompt parsing. No heuristic needed.
-    runner::run_codex(&args, "", runner::Mode::Review)
+    let (args, opts) = resolve_run(run, &args, true)?;
+    runner::run_codex_with_options(&args, "", runner::Mode::Review, &opts)
 }
 
 fn read_stdin() -> anyhow::Result<String> {
@@ -178,7 +1446,7 @@ fn read_stdin() -> anyhow::Result<String> {
     Ok(lines.join("\n"))
 }
 
-fn split_codex_args<'a>(args: &'a [String]) -> anyhow::Result<(&'a [String], &'a String)> {
+fn split_codex_args(args: &[String]) -> anyhow::Result<(&[String], &String)> {
     if args.is_empty() {
         anyhow::bail!(
             "Usage: codex-clean [ARGS...] <prompt>\n\nNo prompt provided. Use '-' to read from stdin."
@@ -315,6 +1583,7 @@ mod tests {
                 last,
                 session_id,
                 prompt,
+                ..
             }) => {
                 assert!(last);
                 assert_eq!(session_id, Some("add".to_string()));
@@ -330,6 +1599,336 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fork_parses_session_args_and_prompt() {
+        let cli = Cli::parse_from([
+            "codex-clean",
+            "fork",
+            "session-123",
+            "-m",
+            "gpt-5.5",
+            "try another approach",
+        ]);
+        match cli.command {
+            Some(Commands::Fork { session_id, args }) => {
+                assert_eq!(session_id, "session-123");
+                let (codex_args, prompt) = split_codex_args(&args).unwrap();
+                assert_eq!(codex_args, &["-m".to_string(), "gpt-5.5".to_string()][..]);
+                assert_eq!(prompt, "try another approach");
+            }
+            _ => panic!("Expected fork command"),
+        }
+    }
+
+    #[test]
+    fn resume_last_with_index_selects_nth_session() {
+        let (req, prompt) =
+            parse_resume_request(true, Some("2".into()), Some("fix it".into())).unwrap();
+        assert_eq!(req, ResumeRequest::Recent(2));
+        assert_eq!(prompt, "fix it");
+    }
+
+    #[test]
+    fn resume_last_without_index_keeps_prompt_fragments() {
+        let (req, prompt) =
+            parse_resume_request(true, Some("add".into()), Some("error".into())).unwrap();
+        assert_eq!(req, ResumeRequest::Recent(1));
+        assert_eq!(prompt, "add error");
+    }
+
+    #[test]
+    fn resume_at_index_selects_nth_session() {
+        let (req, prompt) = parse_resume_request(false, Some("@3".into()), None).unwrap();
+        assert_eq!(req, ResumeRequest::Recent(3));
+        assert_eq!(prompt, "");
+        assert!(parse_resume_request(false, Some("@0".into()), None).is_err());
+        assert!(parse_resume_request(false, Some("@x".into()), None).is_err());
+    }
+
+    #[test]
+    fn resume_plain_id_passes_through() {
+        let (req, _) = parse_resume_request(false, Some("session-123".into()), None).unwrap();
+        assert_eq!(req, ResumeRequest::SessionId("session-123".into()));
+    }
+
+    #[test]
+    fn run_flags_parse_before_codex_args_and_after_subcommands() {
+        let cli = Cli::parse_from([
+            "codex-clean", "--profile", "fast", "--timeout", "30", "-m", "gpt-5.5", "hi",
+        ]);
+        assert_eq!(cli.run.profile.as_deref(), Some("fast"));
+        assert_eq!(cli.run.timeout, Some(30));
+        assert_eq!(cli.args, vec!["-m", "gpt-5.5", "hi"]);
+
+        let cli = Cli::parse_from(["codex-clean", "review", "--profile", "review", "--uncommitted"]);
+        assert_eq!(cli.run.profile.as_deref(), Some("review"));
+        match cli.command {
+            Some(Commands::Review { args }) => assert_eq!(args, vec!["--uncommitted"]),
+            _ => panic!("Expected review command"),
+        }
+    }
+
+    #[test]
+    fn on_overflow_needs_a_limit() {
+        let cli = Cli::parse_from(["codex-clean", "--max-output-bytes", "4096", "--on-overflow", "truncate", "hi"]);
+        assert_eq!(cli.run.max_output_bytes, Some(4096));
+        assert_eq!(cli.run.on_overflow, OnOverflow::Truncate);
+        let cli = Cli::parse_from(["codex-clean", "--max-output-bytes", "4096", "hi"]);
+        assert_eq!(cli.run.on_overflow, OnOverflow::Abort);
+        assert!(Cli::try_parse_from(["codex-clean", "--on-overflow", "truncate", "hi"]).is_err());
+    }
+
+    #[test]
+    fn transport_defaults_to_exec_and_excludes_replay() {
+        assert_eq!(Cli::parse_from(["codex-clean", "hi"]).run.transport, Transport::Exec);
+        let cli = Cli::parse_from(["codex-clean", "--transport", "proto", "hi"]);
+        assert_eq!(cli.run.transport, Transport::Proto);
+        assert!(Cli::try_parse_from(["codex-clean", "--transport", "proto", "--replay", "x.jsonl", "hi"]).is_err());
+    }
+
+    #[test]
+    fn grep_flags_parse_and_validate() {
+        let cli = Cli::parse_from(["codex-clean", "--grep", "^fn ", "--after-context", "2", "list functions"]);
+        assert_eq!(cli.run.grep.as_ref().map(|r| r.as_str()), Some("^fn "));
+        assert_eq!((cli.run.before_context, cli.run.after_context), (0, 2));
+        assert!(Cli::try_parse_from(["codex-clean", "--grep", "(", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--before-context", "1", "hi"]).is_err());
+    }
+
+    #[test]
+    fn diff_parses_sessions_and_layout() {
+        let cli = Cli::parse_from(["codex-clean", "diff", "@2", "@1", "-y", "--transcript"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Diff { session_a, session_b, transcript: true, side_by_side: true, unified: 3 })
+                if session_a == "@2" && session_b == "@1"
+        ));
+        let cli = Cli::parse_from(["codex-clean", "diff", "0199a2", "0199b4", "-U", "0"]);
+        assert!(matches!(cli.command, Some(Commands::Diff { transcript: false, unified: 0, .. })));
+        assert!(Cli::try_parse_from(["codex-clean", "diff", "a", "b", "-y", "-U", "1"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "diff", "a"]).is_err());
+    }
+
+    #[test]
+    fn stats_takes_a_window_and_grouping() {
+        let cli = Cli::parse_from(["codex-clean", "stats"]);
+        assert!(matches!(cli.command, Some(Commands::Stats { last: 50, by: None, code: None })));
+        let cli = Cli::parse_from(["codex-clean", "stats", "--last", "10", "--by", "model"]);
+        assert!(matches!(cli.command, Some(Commands::Stats { last: 10, by: Some(StatsGroup::Model), code: None })));
+        let cli = Cli::parse_from(["codex-clean", "stats", "--code", "rust"]);
+        assert!(matches!(cli.command, Some(Commands::Stats { code: Some(ref lang), .. }) if lang == "rust"));
+        assert!(Cli::try_parse_from(["codex-clean", "stats", "--last", "0"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "stats", "--by", "seat"]).is_err());
+    }
+
+    #[test]
+    fn usage_defaults_to_today() {
+        assert!(matches!(Cli::parse_from(["codex-clean", "usage"]).command, Some(Commands::Usage { month: false })));
+        assert!(matches!(Cli::parse_from(["codex-clean", "usage", "--month"]).command, Some(Commands::Usage { month: true })));
+    }
+
+    #[test]
+    fn self_update_is_kebab_case() {
+        let cli = Cli::parse_from(["codex-clean", "self-update", "--check"]);
+        assert!(matches!(cli.command, Some(Commands::SelfUpdate { check: true, force: false })));
+    }
+
+    #[test]
+    fn trust_defaults_to_current_dir() {
+        let cli = Cli::parse_from(["codex-clean", "trust"]);
+        assert!(matches!(cli.command, Some(Commands::Trust { dir, revoke: false }) if dir == std::path::Path::new(".")));
+        let cli = Cli::parse_from(["codex-clean", "trust", "--revoke", "../other"]);
+        assert!(matches!(cli.command, Some(Commands::Trust { dir, revoke: true }) if dir == std::path::Path::new("../other")));
+    }
+
+    #[test]
+    fn run_template_takes_vars_before_codex_args() {
+        let cli = Cli::parse_from(["codex-clean", "run-template", "review-pr", "--var", "pr=123", "--var", "base=main", "-m", "o3"]);
+        match cli.command {
+            Some(Commands::RunTemplate { name, vars, vars_json: None, args }) => {
+                assert_eq!(name, "review-pr");
+                assert_eq!(vars, vec![("pr".to_string(), "123".to_string()), ("base".to_string(), "main".to_string())]);
+                assert_eq!(args, vec!["-m", "o3"]);
+            }
+            _ => panic!("Expected run-template command"),
+        }
+        assert!(Cli::try_parse_from(["codex-clean", "run-template", "x", "--var", "novalue"]).is_err());
+    }
+
+    #[test]
+    fn output_takes_a_format_and_path() {
+        let cli = Cli::parse_from(["codex-clean", "--output", "html", "run.html", "hi"]);
+        let outputs = cli.run.outputs().unwrap();
+        assert_eq!((outputs[0].format.as_str(), outputs[0].path.as_path()), ("html", Path::new("run.html")));
+        assert_eq!(cli.args, vec!["hi"]);
+        let cli = Cli::parse_from(["codex-clean", "--output", "pdf", "run.pdf", "hi"]);
+        assert!(cli.run.outputs().is_err());
+        assert!(Cli::parse_from(["codex-clean", "hi"]).run.outputs().unwrap().is_empty());
+        let cli = Cli::parse_from(["codex-clean", "--output", "json", "a.json", "--output", "github", "-", "hi"]);
+        let formats: Vec<String> = cli.run.outputs().unwrap().into_iter().map(|o| o.format).collect();
+        assert_eq!(formats, ["json", "github"]);
+        assert!(Cli::parse_from(["codex-clean", "--output", "template", "t.txt", "hi"]).run.outputs().is_err());
+    }
+
+    #[test]
+    fn session_header_flags_are_exclusive() {
+        assert!(Cli::parse_from(["codex-clean", "--no-session", "hi"]).run.no_session);
+        assert!(Cli::parse_from(["codex-clean", "--redact-session", "hi"]).run.redact_session);
+        assert!(Cli::try_parse_from(["codex-clean", "--no-session", "--redact-session", "hi"]).is_err());
+    }
+
+    #[test]
+    fn warning_flags_are_exclusive() {
+        assert!(Cli::parse_from(["codex-clean", "--no-warnings", "hi"]).run.no_warnings);
+        assert!(Cli::parse_from(["codex-clean", "--warnings-as-errors", "hi"]).run.warnings_as_errors);
+        assert!(Cli::try_parse_from(["codex-clean", "--no-warnings", "--warnings-as-errors", "hi"]).is_err());
+    }
+
+    #[test]
+    fn wrap_takes_columns_or_auto() {
+        assert_eq!(Cli::parse_from(["codex-clean", "--wrap", "72", "hi"]).run.wrap, Some(Wrap::Columns(72)));
+        assert_eq!(Cli::parse_from(["codex-clean", "--wrap", "auto", "hi"]).run.wrap, Some(Wrap::Auto));
+        assert!(Cli::try_parse_from(["codex-clean", "--wrap", "0", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--wrap", "wide", "hi"]).is_err());
+    }
+
+    #[test]
+    fn progress_json_takes_an_optional_interval() {
+        assert_eq!(Cli::parse_from(["codex-clean", "--progress-json", "hi"]).run.progress_json, Some(5));
+        let cli = Cli::parse_from(["codex-clean", "--progress-json=2", "hi"]);
+        assert_eq!(cli.run.progress_json, Some(2));
+        assert_eq!(cli.args, ["hi"]);
+    }
+
+    #[test]
+    fn heartbeat_must_be_positive() {
+        let cli = Cli::parse_from(["codex-clean", "--heartbeat", "60", "hi"]);
+        assert_eq!(cli.run.heartbeat, Some(60));
+        assert!(Cli::try_parse_from(["codex-clean", "--heartbeat", "0", "hi"]).is_err());
+    }
+
+    fn json_request(raw: &str) -> JsonRequest {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn json_request_plans_new_session() {
+        let req = json_request(r#"{"prompt": "hi", "args": ["-m", "o3"]}"#);
+        assert_eq!(req.args, vec!["-m", "o3"]);
+        assert_eq!(req.plan().unwrap(), (None, "hi".to_string()));
+        assert!(json_request(r#"{"args": []}"#).plan().is_err());
+        assert!(json_request(r#"{"prompt": "  ", "resume": false}"#).plan().is_err());
+    }
+
+    #[test]
+    fn json_request_plans_resume() {
+        let (resume, prompt) = json_request(r#"{"resume": true}"#).plan().unwrap();
+        assert_eq!(resume, Some(ResumeRequest::Recent(1)));
+        assert_eq!(prompt, "");
+        let (resume, _) = json_request(r#"{"resume": "@2", "prompt": "more"}"#).plan().unwrap();
+        assert_eq!(resume, Some(ResumeRequest::Recent(2)));
+        let (resume, _) = json_request(r#"{"resume": "0199a213"}"#).plan().unwrap();
+        assert_eq!(resume, Some(ResumeRequest::SessionId("0199a213".into())));
+    }
+
+    #[test]
+    fn json_request_rejects_unknown_fields() {
+        assert!(serde_json::from_str::<JsonRequest>(r#"{"prompt": "hi", "prmpt": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn stdin_json_conflicts_with_trailing_args() {
+        assert!(Cli::try_parse_from(["codex-clean", "--stdin-json", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--stdin-json"]).unwrap().stdin_json);
+    }
+
+    #[test]
+    fn ssh_flags_build_remote_backend() {
+        let cli = Cli::parse_from([
+            "codex-clean", "--ssh", "dev@box", "--ssh-cwd", "~/src", "--ssh-env", "A=1", "--ssh-env", "B=2", "hi",
+        ]);
+        assert_eq!(
+            format!("{:?}", cli.run.backend().unwrap()),
+            r#"SshBackend { host: "dev@box", cwd: Some("~/src"), env: [("A", "1"), ("B", "2")] }"#
+        );
+        assert!(Cli::try_parse_from(["codex-clean", "--ssh-cwd", "/x", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--ssh", "h", "--ssh-env", "bad", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--ssh", "h", "--docker", "img", "hi"]).is_err());
+    }
+
+    #[test]
+    fn container_flags_pick_engine() {
+        let cli = Cli::parse_from(["codex-clean", "--podman", "img:1", "hi"]);
+        let debug = format!("{:?}", cli.run.backend().unwrap());
+        assert!(debug.starts_with(r#"ContainerBackend { engine: "podman", image: "img:1","#), "{}", debug);
+        assert!(Cli::try_parse_from(["codex-clean", "--docker", "a", "--podman", "b", "hi"]).is_err());
+    }
+
+    #[test]
+    fn archive_and_unarchive_take_their_paths() {
+        let cli = Cli::parse_from(["codex-clean", "archive", "@1", "-o", "s.tar"]);
+        assert!(matches!(cli.command, Some(Commands::Archive { session, out: Some(_) }) if session == "@1"));
+        let cli = Cli::parse_from(["codex-clean", "unarchive", "s.tar.zst"]);
+        assert!(matches!(cli.command, Some(Commands::Unarchive { dir, .. }) if dir == std::path::Path::new(".")));
+    }
+
+    #[test]
+    fn import_needs_a_source_and_a_file() {
+        let cli = Cli::parse_from(["codex-clean", "import", "--from", "claude-code", "a.jsonl", "b.jsonl"]);
+        assert!(matches!(cli.command, Some(Commands::Import { from: ImportFrom::ClaudeCode, files }) if files.len() == 2));
+        assert!(Cli::try_parse_from(["codex-clean", "import", "--from", "aider"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "import", "x.jsonl"]).is_err());
+    }
+
+    #[test]
+    fn redact_takes_an_output_and_extra_patterns() {
+        let cli = Cli::parse_from(["codex-clean", "redact", "run.jsonl", "-o", "out.jsonl", "-e", "a+", "--pattern", "b"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Redact { recording, out: Some(out), patterns })
+                if recording == std::path::Path::new("run.jsonl") && out == std::path::Path::new("out.jsonl") && patterns == ["a+", "b"]
+        ));
+    }
+
+    #[test]
+    fn cancel_takes_optional_run_id() {
+        let cli = Cli::parse_from(["codex-clean", "cancel", "nightly"]);
+        assert!(matches!(cli.command, Some(Commands::Cancel { run_id: Some(id) }) if id == "nightly"));
+        let cli = Cli::parse_from(["codex-clean", "cancel"]);
+        assert!(matches!(cli.command, Some(Commands::Cancel { run_id: None })));
+        let cli = Cli::parse_from(["codex-clean", "--run-id", "nightly", "hi"]);
+        assert_eq!(cli.run.run_id.as_deref(), Some("nightly"));
+        assert!(Cli::try_parse_from(["codex-clean", "--run-id", "../x", "hi"]).is_err());
+    }
+
+    #[test]
+    fn replay_flag_selects_replay_backend() {
+        let cli = Cli::parse_from(["codex-clean", "--replay", "run.jsonl", "hi"]);
+        let backend = cli.run.backend().unwrap();
+        assert_eq!(format!("{:?}", backend), r#"ReplayBackend { path: "run.jsonl", last_turn: false }"#);
+        assert!(!backend.uses_local_auth());
+        assert!(Cli::try_parse_from(["codex-clean", "--replay", "f", "--ssh", "h", "hi"]).is_err());
+        assert!(Cli::try_parse_from(["codex-clean", "--replay", "f", "--docker", "i", "hi"]).is_err());
+    }
+
+    #[test]
+    fn diff_against_takes_a_path_and_not_plain() {
+        let cli = Cli::parse_from(["codex-clean", "--diff-against", "src/lib.rs", "rewrite src/lib.rs"]);
+        assert_eq!(cli.run.diff_against, Some(PathBuf::from("src/lib.rs")));
+        assert_eq!(cli.args, vec!["rewrite src/lib.rs"]);
+        assert!(Cli::try_parse_from(["codex-clean", "--diff-against", "a.rs", "--plain", "hi"]).is_err());
+    }
+
+    #[test]
+    fn force_only_goes_with_write_files() {
+        let cli = Cli::parse_from(["codex-clean", "--write-files", "--force", "scaffold it"]);
+        assert!(cli.run.write_files && cli.run.force);
+        assert!(Cli::try_parse_from(["codex-clean", "--force", "hi"]).is_err());
+        // self-update keeps its own --force.
+        let cli = Cli::parse_from(["codex-clean", "self-update", "--force"]);
+        assert!(matches!(cli.command, Some(Commands::SelfUpdate { check: false, force: true })));
+    }
+
     #[test]
     fn exit_code_from_child_rejects_out_of_range() {
         assert_eq!(exit_code_from_child(-1), ExitCode::FAILURE);
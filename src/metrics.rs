@@ -0,0 +1,169 @@
+//! Named counters and gauges kept in each run's report.
+//!
+//! Counters defined in the config count what the run's answer contains,
+//! without a wrapper script to pick it apart afterwards:
+//!
+//! ```toml
+//! [counters.cargo_commands]
+//! command = "^cargo "
+//!
+//! [counters.test_files_changed]
+//! file = "^tests/"
+//! ```
+//!
+//! A program embedding codex-clean can add its own from its
+//! [`EventObserver`](crate::observer::EventObserver), whose
+//! [`metrics`](crate::observer::EventObserver::metrics) is called once the
+//! run is over. Either way they end up in the run report as `metrics`, and
+//! in the post hook's environment as JSON in `CODEX_CLEAN_METRICS`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::CounterRule;
+use crate::output::CodexOutput;
+
+/// One value in [`Metrics`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Metric {
+    /// A count of things, which only goes up.
+    Counter(u64),
+    /// A measurement, which is whatever it was last set to.
+    Gauge(f64),
+}
+
+/// The counters and gauges of one run, by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    values: BTreeMap<String, Metric>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `by` to counter `name`, which starts at 0. A gauge of the same
+    /// name is replaced.
+    pub fn add(&mut self, name: &str, by: u64) {
+        let value = match self.values.get(name) {
+            Some(Metric::Counter(n)) => n.saturating_add(by),
+            _ => by,
+        };
+        self.values.insert(name.to_string(), Metric::Counter(value));
+    }
+
+    /// Set gauge `name` to `value`.
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.values.insert(name.to_string(), Metric::Gauge(value));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Metric> {
+        self.values.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn into_map(self) -> BTreeMap<String, Metric> {
+        self.values
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Command,
+    Message,
+    File,
+}
+
+/// The `[counters]` of the config, ready to count with.
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+    rules: Vec<(String, Target, Regex)>,
+}
+
+impl Counters {
+    /// Check and compile `rules`.
+    pub fn compile(rules: &BTreeMap<String, CounterRule>) -> Result<Self> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for (name, rule) in rules {
+            let set = [
+                (Target::Command, &rule.command),
+                (Target::Message, &rule.message),
+                (Target::File, &rule.file),
+            ];
+            let mut patterns = set.iter().filter_map(|(target, pattern)| Some((*target, pattern.as_deref()?)));
+            let (Some((target, pattern)), None) = (patterns.next(), patterns.next()) else {
+                bail!("counter '{}' needs exactly one of `command`, `message` or `file`", name);
+            };
+            let re = Regex::new(pattern).with_context(|| format!("compiling the pattern of counter '{}'", name))?;
+            compiled.push((name.clone(), target, re));
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Count what in `output` each rule matches into `metrics`; a counter
+    /// that matched nothing is there as 0.
+    pub fn count(&self, output: &CodexOutput, metrics: &mut Metrics) {
+        for (name, target, re) in &self.rules {
+            let matched = match target {
+                Target::Command => output.commands.iter().filter(|c| re.is_match(&c.command)).count(),
+                Target::Message => output.messages.iter().filter(|m| re.is_match(m)).count(),
+                Target::File => output.file_changes.iter().filter(|f| re.is_match(&f.path)).count(),
+            };
+            metrics.add(name, matched as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{CommandRun, FileChange};
+
+    #[test]
+    fn counters_count_matching_commands_messages_and_files() {
+        let rule = |command: Option<&str>, message: Option<&str>, file: Option<&str>| CounterRule {
+            command: command.map(String::from),
+            message: message.map(String::from),
+            file: file.map(String::from),
+        };
+        let rules: BTreeMap<String, CounterRule> = [
+            ("cargo".to_string(), rule(Some("^cargo "), None, None)),
+            ("todos".to_string(), rule(None, Some("TODO"), None)),
+            ("tests".to_string(), rule(None, None, Some("^tests/"))),
+        ]
+        .into();
+        let output = CodexOutput {
+            messages: vec!["Fixed it. TODO: docs".into(), "Done".into()],
+            commands: ["cargo test", "ls", "cargo fmt"]
+                .iter()
+                .map(|c| CommandRun { command: c.to_string(), exit_code: Some(0), output: None })
+                .collect(),
+            file_changes: vec![FileChange { path: "src/lib.rs".into(), kind: "update".into() }],
+            ..Default::default()
+        };
+        let mut metrics = Metrics::new();
+        metrics.set("latency_ms", 12.5);
+        Counters::compile(&rules).unwrap().count(&output, &mut metrics);
+        assert_eq!(
+            serde_json::to_string(&metrics.into_map()).unwrap(),
+            r#"{"cargo":2,"latency_ms":12.5,"tests":0,"todos":1}"#
+        );
+
+        let both = [("x".to_string(), rule(Some("a"), Some("b"), None))].into();
+        assert!(Counters::compile(&both).unwrap_err().to_string().contains("exactly one"));
+        let bad = [("y".to_string(), rule(Some("("), None, None))].into();
+        assert!(Counters::compile(&bad).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! `--notebook PATH`: keep a markdown journal of runs.
+//!
+//! After each run a section is appended to the notebook: when it ran, the
+//! session, the prompt and the answer. Runs can finish at the same moment
+//! (a `batch` in one shell, an editor in another), so the append holds an
+//! exclusive lock on the notebook while it writes; an entry is never
+//! interleaved with another.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use fs2::FileExt;
+
+use crate::output::{format_usage, CodexOutput};
+
+/// The notebook section for one run, with links to the diagrams
+/// `--render-diagrams` drew from it.
+pub fn entry(at: DateTime<Local>, prompt: &str, output: &CodexOutput, exit_code: i32, images: &[PathBuf]) -> String {
+    let mut out = format!("## {}\n\n", at.format("%Y-%m-%d %H:%M:%S %:z"));
+    if let Some(id) = &output.session_id {
+        let _ = writeln!(out, "- Session: `{}`", id);
+    }
+    if exit_code != 0 {
+        let _ = writeln!(out, "- Exit code: {}", exit_code);
+    }
+    if let Some(usage) = output.usage {
+        let _ = writeln!(out, "- {}", format_usage(usage));
+    }
+    if !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+    out.push_str("### Prompt\n\n");
+    match prompt.trim_end() {
+        "" => out.push_str("_No prompt._\n"),
+        prompt => {
+            for line in prompt.lines() {
+                out.push_str(if line.is_empty() { ">" } else { "> " });
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out.push_str("\n### Response\n\n");
+    let message = output.aggregated_message();
+    match message.trim_end() {
+        "" => out.push_str("_No response._\n"),
+        message => {
+            let _ = writeln!(out, "{}", message);
+        }
+    }
+    for (i, image) in images.iter().enumerate() {
+        let _ = writeln!(out, "\n![Diagram {}](<{}>)", i + 1, image.display());
+    }
+    for err in &output.errors {
+        let _ = writeln!(out, "\n> **Error:** {}", err);
+    }
+    out
+}
+
+/// Append `entry` to the notebook at `path`, creating it if needed, with a
+/// blank line between it and the entry before.
+pub fn append(path: &Path, entry: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    file.lock_exclusive().with_context(|| format!("locking {}", path.display()))?;
+    let result = (|| {
+        // Only now, holding the lock, is the end of the file settled.
+        let len = file.seek(SeekFrom::End(0))?;
+        let mut tail = Vec::new();
+        if len > 0 {
+            file.seek(SeekFrom::End(-(len.min(2) as i64)))?;
+            file.read_to_end(&mut tail)?;
+        }
+        let separator = match tail.as_slice() {
+            [] | [.., b'\n', b'\n'] => "",
+            [.., b'\n'] => "\n",
+            _ => "\n\n",
+        };
+        file.write_all(format!("{}{}", separator, entry).as_bytes())?;
+        file.flush()
+    })();
+    let _ = FileExt::unlock(&file);
+    result.with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn entries_are_appended_one_after_another() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "# My notebook").unwrap();
+        let mut output = CodexOutput::new();
+        output.session_id = Some("s-1".into());
+        output.add_message("Use `?`.".into());
+        let at = Local.with_ymd_and_hms(2026, 3, 1, 9, 30, 0).unwrap();
+        let first = entry(at, "How do I\n\npropagate errors?", &output, 0, &[]);
+        assert!(first.starts_with("## 2026-03-01 09:30:00 "), "{}", first);
+        assert!(first.ends_with("- Session: `s-1`\n\n### Prompt\n\n> How do I\n>\n> propagate errors?\n\n### Response\n\nUse `?`.\n"), "{}", first);
+
+        append(&path, &first).unwrap();
+        append(&path, &entry(at, "Again", &CodexOutput::new(), 1, &[PathBuf::from("diagram-1.png")])).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written.matches("\n\n## 2026-03-01").count(), 2, "{}", written);
+        assert!(written.starts_with("# My notebook\n\n## "));
+        assert!(written.ends_with("- Exit code: 1\n\n### Prompt\n\n> Again\n\n### Response\n\n_No response._\n\n![Diagram 1](<diagram-1.png>)\n"), "{}", written);
+    }
+}
@@ -0,0 +1,51 @@
+//! Live callbacks for programs embedding codex-clean as a library.
+//!
+//! The runner normally hands back a [`CodexOutput`] once codex has
+//! finished. An editor or GUI that wants to update as the run goes can set
+//! [`RunOptions::observer`](crate::runner::RunOptions::observer) to an
+//! [`EventObserver`]; it is called from the thread reading codex's output,
+//! as each event arrives.
+//!
+//! With seat rotation a run can take several attempts, so
+//! `on_session_start` may be called more than once; events from an attempt
+//! that gets retried are reported like any other.
+
+use std::fmt::Debug;
+
+use crate::events::{CommandRun, Event};
+use crate::metrics::Metrics;
+use crate::output::CodexOutput;
+
+/// Called as a run progresses. Every method does nothing by default.
+pub trait EventObserver: Debug + Send + Sync {
+    /// codex started (or resumed) session `session_id`.
+    fn on_session_start(&self, _session_id: &str) {}
+
+    /// An agent message arrived.
+    fn on_message(&self, _text: &str) {}
+
+    /// codex finished running a shell command.
+    fn on_command(&self, _command: &CommandRun) {}
+
+    /// codex reported an error (a failed turn or a stream error).
+    fn on_error(&self, _message: &str) {}
+
+    /// The run is over, with this exit code and, when codex ran, the output
+    /// of the attempt that counted.
+    fn on_complete(&self, _exit_code: i32, _output: Option<&CodexOutput>) {}
+
+    /// Add counters and gauges of the observer's own to the run report
+    /// (see [`crate::metrics`]). Called once, after `on_complete`.
+    fn metrics(&self, _metrics: &mut Metrics) {}
+}
+
+/// Pass `event` to the matching `observer` callback.
+pub fn notify(observer: &dyn EventObserver, event: &Event) {
+    match event {
+        Event::ThreadStarted { thread_id } => observer.on_session_start(thread_id),
+        Event::AgentMessage { text: Some(text) } if !text.is_empty() => observer.on_message(text),
+        Event::CommandExecuted(run) => observer.on_command(run),
+        Event::TurnFailed { message } | Event::StreamError { message } => observer.on_error(message),
+        Event::AgentMessage { .. } | Event::TurnCompleted { .. } | Event::FilesChanged(_) => {}
+    }
+}
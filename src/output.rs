@@ -1,7 +1,19 @@
 use std::fmt::Write as FmtWrite;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::diff::DiffAgainst;
+use crate::events::{CommandRun, Event, FileChange};
+use crate::preamble::PreambleFilter;
+use crate::ratelimit::{self, Retry};
+use sha2::{Digest, Sha256};
+
+/// Noise lines kept for `--show-noise`; the rest are only counted.
+pub const MAX_NOISE_LINES: usize = 50;
 
 /// Collected results from parsing codex output
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct CodexOutput {
     pub session_id: Option<String>,
     pub messages: Vec<String>,
@@ -14,6 +26,18 @@ pub struct CodexOutput {
     pub events_recognized: usize,
     /// Errors surfaced by codex via `turn.failed` or stream `error` events
     pub errors: Vec<String>,
+    /// Shell commands codex ran, in order
+    pub commands: Vec<CommandRun>,
+    /// Files codex changed, in order (a file edited twice appears twice)
+    pub file_changes: Vec<FileChange>,
+    /// Requests codex retried after a rate limit or dropped connection;
+    /// these aren't counted as errors.
+    pub retries: Vec<Retry>,
+    /// Lines of stdout that weren't JSON, such as banners or update
+    /// notices, up to [`MAX_NOISE_LINES`] of them
+    pub noise: Vec<String>,
+    /// Number of lines of stdout that weren't JSON, kept or not
+    pub noise_lines: usize,
 }
 
 /// Rendered stdout/stderr strings
@@ -23,6 +47,307 @@ pub struct RenderedOutput {
     pub stderr: String,
 }
 
+/// How to render a run's output; the default is the full text.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub session_header: SessionHeader,
+    /// Leave out [`CodexOutput::warnings`] (`--no-warnings`).
+    pub hide_warnings: bool,
+    /// Only print the message lines this matches (`--grep`).
+    pub grep: Option<LineFilter>,
+    /// Wrap long message lines to this many columns (`--wrap`).
+    pub wrap: Option<usize>,
+    /// Strip markdown syntax from the message (`--plain`).
+    pub plain: bool,
+    /// Drop conversational openers and sign-offs (`--strip-preamble`).
+    pub strip_preamble: Option<PreambleFilter>,
+    /// The `--branch` codex worked on, shown under the session.
+    pub branch: Option<String>,
+    /// Send stdout through a pager when it is longer than the terminal
+    /// (see [`crate::pager`]).
+    pub pager: bool,
+    /// Mark where the answer starts and ends in words, for screen
+    /// readers (`--a11y`).
+    pub a11y: bool,
+    /// Print the lines of stdout that weren't JSON (`--show-noise`).
+    pub show_noise: bool,
+    /// Show the answer as a diff against a file (`--diff-against`); the
+    /// diff is not wrapped or stripped of markdown.
+    pub diff_against: Option<DiffAgainst>,
+}
+
+/// What the `Session: ...` line shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionHeader {
+    #[default]
+    Show,
+    /// No header line at all (`--no-session`).
+    Omit,
+    /// A stable hash in place of the ID (`--redact-session`), so runs can
+    /// still be told apart without revealing a resumable ID.
+    Redact,
+}
+
+impl SessionHeader {
+    fn line(self, session_id: &str) -> Option<String> {
+        match self {
+            SessionHeader::Show => Some(format!("Session: {}", session_id)),
+            SessionHeader::Omit => None,
+            SessionHeader::Redact => Some(format!("Session: {}", redact_session_id(session_id))),
+        }
+    }
+}
+
+/// `sha256:` and the first 12 hex digits of the ID's SHA-256.
+pub fn redact_session_id(session_id: &str) -> String {
+    let digest = Sha256::digest(session_id.as_bytes());
+    let hex: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", hex)
+}
+
+/// grep-style line selection with context.
+#[derive(Debug, Clone)]
+pub struct LineFilter {
+    pub pattern: Regex,
+    /// Lines of context to keep before each match.
+    pub before: usize,
+    /// Lines of context to keep after each match.
+    pub after: usize,
+}
+
+impl LineFilter {
+    /// The matching lines of `text` plus context, with `--` between
+    /// non-adjacent groups as grep does. `None` if nothing matched.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut keep = vec![false; lines.len()];
+        let mut any = false;
+        for (i, line) in lines.iter().enumerate() {
+            if self.pattern.is_match(line) {
+                any = true;
+                let end = (i + self.after).min(lines.len() - 1);
+                for k in &mut keep[i.saturating_sub(self.before)..=end] {
+                    *k = true;
+                }
+            }
+        }
+        if !any {
+            return None;
+        }
+        let mut out = Vec::new();
+        let mut last: Option<usize> = None;
+        for (i, line) in lines.iter().enumerate().filter(|(i, _)| keep[*i]) {
+            if last.is_some_and(|l| l + 1 != i) {
+                out.push("--");
+            }
+            out.push(line);
+            last = Some(i);
+        }
+        Some(out.join("\n"))
+    }
+}
+
+/// `text` with its markdown syntax removed: heading hashes, emphasis and
+/// strikethrough markers, backticks, code fences, quote markers, and link
+/// targets (the link text stays). Code keeps its content verbatim.
+pub fn strip_markdown(text: &str) -> String {
+    let mut out = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            out.push(line.to_string());
+            continue;
+        }
+        if is_rule(trimmed) {
+            continue;
+        }
+        let indent = &line[..line.len() - trimmed.len()];
+        let mut body = trimmed;
+        while let Some(rest) = body.strip_prefix('>') {
+            body = rest.trim_start();
+        }
+        let heading = body.trim_start_matches('#');
+        if heading.len() < body.len() && (heading.is_empty() || heading.starts_with(' ')) {
+            body = heading.trim_start();
+        }
+        let body = match body.strip_prefix("* ").or_else(|| body.strip_prefix("+ ")) {
+            Some(item) => format!("- {}", item),
+            None => body.to_string(),
+        };
+        out.push(format!("{}{}", indent, strip_inline(&body)));
+    }
+    out.join("\n")
+}
+
+/// `---`, `***`, `___` (3+ of one character, spaces allowed).
+fn is_rule(line: &str) -> bool {
+    let mut chars = line.chars().filter(|c| !c.is_whitespace());
+    let first = chars.next();
+    matches!(first, Some('-' | '*' | '_')) && {
+        let rest: Vec<char> = chars.collect();
+        rest.len() >= 2 && rest.iter().all(|&c| Some(c) == first)
+    }
+}
+
+/// Inline markup outside `code spans`; the spans lose only their backticks.
+fn strip_inline(line: &str) -> String {
+    static CODE: OnceLock<Regex> = OnceLock::new();
+    let code = CODE.get_or_init(|| Regex::new(r"`+([^`]+)`+").unwrap());
+    let mut out = String::new();
+    let mut last = 0;
+    for m in code.captures_iter(line) {
+        let whole = m.get(0).unwrap();
+        out.push_str(&strip_emphasis(&line[last..whole.start()]));
+        out.push_str(&m[1]);
+        last = whole.end();
+    }
+    out.push_str(&strip_emphasis(&line[last..]));
+    out
+}
+
+fn strip_emphasis(text: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (r"!?\[([^\]]*)\]\([^)]*\)", "$1"),
+            (r"\*\*(.+?)\*\*", "$1"),
+            (r"__(.+?)__", "$1"),
+            (r"~~(.+?)~~", "$1"),
+            (r"(^|[^\w*])\*([^*\s](?:[^*]*[^*\s])?)\*", "$1$2"),
+            // Only at word edges, so snake_case survives.
+            (r"(^|\W)_([^_\s](?:[^_]*[^_\s])?)_(\W|$)", "$1$2$3"),
+        ]
+        .into_iter()
+        .map(|(re, with)| (Regex::new(re).unwrap(), with))
+        .collect()
+    });
+    let mut text = text.to_string();
+    for (re, with) in patterns {
+        text = re.replace_all(&text, *with).into_owned();
+    }
+    text
+}
+
+/// Wrap each over-long prose line of `text` at word boundaries to fit
+/// `width` columns. Code fences, indented code, and table rows are left as
+/// they are; list items and quotes keep their marker as a hanging indent.
+/// Lines are never joined, so hand-made line breaks survive.
+pub fn reflow(text: &str, width: usize) -> String {
+    let mut out = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            out.push(line.to_string());
+        } else if in_fence
+            || line.chars().count() <= width
+            || line.starts_with("    ")
+            || line.starts_with('\t')
+            || trimmed.starts_with('|')
+        {
+            out.push(line.to_string());
+        } else {
+            out.extend(wrap_line(line, width));
+        }
+    }
+    out.join("\n")
+}
+
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let indent = line.len() - line.trim_start().len();
+    let body = &line[indent..];
+    let marker = list_marker_len(body);
+    let prefix = &line[..indent + marker];
+    // Quotes continue with the quote marker; everything else with spaces.
+    let hang = if body.starts_with('>') {
+        prefix.to_string()
+    } else {
+        " ".repeat(prefix.chars().count())
+    };
+
+    let mut lines = Vec::new();
+    let mut current = prefix.to_string();
+    let mut len = current.chars().count();
+    let mut empty = true;
+    for word in body[marker..].split_whitespace() {
+        let word_len = word.chars().count();
+        if !empty && len + 1 + word_len > width {
+            lines.push(std::mem::replace(&mut current, hang.clone()));
+            len = hang.chars().count();
+            empty = true;
+        }
+        if !empty {
+            current.push(' ');
+            len += 1;
+        }
+        current.push_str(word);
+        len += word_len;
+        empty = false;
+    }
+    lines.push(current);
+    lines
+}
+
+/// Bytes taken by a leading `- `, `* `, `+ `, `1. `, `2) `, or `> `.
+fn list_marker_len(body: &str) -> usize {
+    for bullet in ["- ", "* ", "+ ", "> "] {
+        if body.starts_with(bullet) {
+            return bullet.len();
+        }
+    }
+    let digits = body.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &body[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return digits + 2;
+    }
+    0
+}
+
+/// Columns of the terminal on stdout, when stdout is one.
+pub fn terminal_width() -> Option<usize> {
+    terminal_size().map(|(cols, _)| cols)
+}
+
+/// Columns and rows of the terminal on stdout, when stdout is one.
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    // SAFETY: TIOCGWINSZ only writes a winsize into the struct we pass.
+    unsafe {
+        if libc::isatty(libc::STDOUT_FILENO) != 1 {
+            return None;
+        }
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 || size.ws_col == 0 {
+            return None;
+        }
+        Some((size.ws_col as usize, size.ws_row as usize))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+/// Format a `(input, cached, output, reasoning)` usage tuple as the
+/// `Tokens: ...` summary line. Reasoning tokens are only shown when non-zero.
+pub fn format_usage((input, cached, output, reasoning): (u64, u64, u64, u64)) -> String {
+    if reasoning > 0 {
+        format!(
+            "Tokens: {} input ({} cached), {} output ({} reasoning)",
+            input, cached, output, reasoning
+        )
+    } else {
+        format!("Tokens: {} input ({} cached), {} output", input, cached, output)
+    }
+}
+
 fn normalize_error_key(s: &str) -> String {
     s.split_whitespace().collect::<Vec<_>>().join(" ")
 }
@@ -32,6 +357,34 @@ impl CodexOutput {
         Self::default()
     }
 
+    /// Fold one parsed event into the output.
+    pub fn apply(&mut self, event: Event) {
+        self.events_recognized += 1;
+        match event {
+            Event::ThreadStarted { thread_id } => self.add_thread_id(thread_id),
+            Event::AgentMessage { text } => {
+                if let Some(t) = text {
+                    self.add_message(t);
+                }
+            }
+            Event::TurnCompleted {
+                input_tokens,
+                cached_input_tokens,
+                output_tokens,
+                reasoning_output_tokens,
+            } => {
+                self.add_usage(input_tokens, cached_input_tokens, output_tokens, reasoning_output_tokens);
+            }
+            Event::StreamError { message } => match ratelimit::classify_retry(&message) {
+                Some(retry) => self.retries.push(retry),
+                None => self.add_error(message),
+            },
+            Event::TurnFailed { message } => self.add_error(message),
+            Event::CommandExecuted(run) => self.commands.push(run),
+            Event::FilesChanged(changes) => self.file_changes.extend(changes),
+        }
+    }
+
     /// Add a thread ID. Uses first seen, warns if multiple.
     pub fn add_thread_id(&mut self, thread_id: String) {
         if self.session_id.is_none() {
@@ -53,6 +406,14 @@ impl CodexOutput {
         }
     }
 
+    /// Keep a line of stdout that wasn't JSON.
+    pub fn add_noise(&mut self, line: String) {
+        self.noise_lines += 1;
+        if self.noise.len() < MAX_NOISE_LINES {
+            self.noise.push(line);
+        }
+    }
+
     /// Record an error surfaced by codex (turn.failed or stream error).
     /// Deduped — codex often emits the same error via both an `error`
     /// event and a `turn.failed` event. Comparison is whitespace-normalised
@@ -73,62 +434,128 @@ impl CodexOutput {
         self.messages.join("\n")
     }
 
-    /// Compose stdout/stderr strings for printing
-    pub fn render(&self) -> RenderedOutput {
-        let mut stdout = String::new();
-        let mut stderr = String::new();
+    /// Length of [`Self::aggregated_message`] without building it.
+    pub fn message_bytes(&self) -> usize {
+        let text: usize = self.messages.iter().map(String::len).sum();
+        text + self.messages.len().saturating_sub(1)
+    }
 
-        if self.multiple_threads_seen {
-            let _ = writeln!(stderr, "Warning: Multiple thread IDs seen, using first");
+    /// Cut the aggregated message to at most `limit` bytes (on a char
+    /// boundary) and mark the cut.
+    pub fn truncate_messages(&mut self, limit: usize) {
+        let mut text = self.aggregated_message();
+        if text.len() <= limit {
+            return;
         }
-
-        match &self.session_id {
-            Some(id) => {
-                let _ = writeln!(stdout, "Session: {}", id);
-            }
-            None => {
-                let _ = writeln!(stderr, "Warning: No session ID received");
-            }
+        let mut cut = limit;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
         }
+        text.truncate(cut);
+        let _ = write!(text, "\n[output truncated at {} bytes]", limit);
+        self.messages = vec![text];
+    }
 
+    /// Notes about a suspicious-looking run (`--no-warnings` hides them,
+    /// `--warnings-as-errors` fails the run on them).
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.multiple_threads_seen {
+            warnings.push("Warning: Multiple thread IDs seen, using first".to_string());
+        }
+        if self.session_id.is_none() {
+            warnings.push("Warning: No session ID received".to_string());
+        }
         if self.lines_seen > 0 && self.events_recognized == 0 {
-            let _ = writeln!(
-                stderr,
+            warnings.push(format!(
                 "Warning: Received {} lines from codex but none matched known event types \
                  (possible schema change in upstream codex)",
                 self.lines_seen
-            );
+            ));
+        }
+        if self.messages.is_empty() && self.session_id.is_some() && self.errors.is_empty() {
+            warnings.push("Note: No response received".to_string());
         }
+        if self.messages.is_empty() && self.noise_lines > 0 {
+            warnings.push(format!(
+                "Note: codex printed {} line(s) that weren't JSON events; --show-noise shows them",
+                self.noise_lines
+            ));
+        }
+        warnings
+    }
+
+    /// Compose stdout/stderr strings for printing
+    pub fn render(&self) -> RenderedOutput {
+        self.render_with(&RenderOptions::default())
+    }
+
+    /// [`Self::render`], shaped by `opts`.
+    pub fn render_with(&self, opts: &RenderOptions) -> RenderedOutput {
+        let mut stdout = String::new();
+        let mut stderr = String::new();
 
-        let message = self.aggregated_message();
-        if message.is_empty() {
-            if self.session_id.is_some() && self.errors.is_empty() {
-                let _ = writeln!(stderr, "Note: No response received");
+        if opts.show_noise {
+            for line in &self.noise {
+                let _ = writeln!(stderr, "codex stdout: {}", line);
             }
-        } else {
+            if self.noise_lines > self.noise.len() {
+                let _ = writeln!(stderr, "codex stdout: ({} more line(s))", self.noise_lines - self.noise.len());
+            }
+        }
+        if !opts.hide_warnings {
+            for warning in self.warnings() {
+                let _ = writeln!(stderr, "{}", warning);
+            }
+        }
+
+        if let Some(line) = self.session_id.as_ref().and_then(|id| opts.session_header.line(id)) {
+            let _ = writeln!(stdout, "{}", line);
+        }
+        if let Some(branch) = &opts.branch {
+            let _ = writeln!(stdout, "Branch: {}", branch);
+        }
+
+        let mut message = self.aggregated_message();
+        if let (Some(diff), false) = (&opts.diff_against, message.is_empty()) {
+            message = diff.render(&message).unwrap_or_else(|| {
+                let _ = writeln!(stderr, "Note: The answer matches {}", diff.path.display());
+                String::new()
+            });
+        } else if let Some(filter) = &opts.strip_preamble {
+            message = filter.apply(&message);
+        }
+        if let (Some(filter), false) = (&opts.grep, message.is_empty()) {
+            message = filter.apply(&message).unwrap_or_else(|| {
+                let _ = writeln!(stderr, "Note: No lines matched --grep");
+                String::new()
+            });
+        }
+        if opts.plain && opts.diff_against.is_none() {
+            message = strip_markdown(&message);
+        }
+        if let Some(width) = opts.wrap.filter(|_| opts.diff_against.is_none()) {
+            message = reflow(&message, width);
+        }
+        if !message.is_empty() {
             let _ = writeln!(stdout);
-            let _ = writeln!(stdout, "{}", message);
+            if opts.a11y {
+                let _ = writeln!(stdout, "BEGIN RESPONSE\n{}\nEND RESPONSE", message);
+            } else {
+                let _ = writeln!(stdout, "{}", message);
+            }
         }
 
         for err in &self.errors {
             let _ = writeln!(stderr, "Error from codex: {}", err);
         }
+        if let Some(line) = ratelimit::describe_retries(&self.retries) {
+            let _ = writeln!(stderr, "Retries: {}", line);
+        }
 
-        if let Some((input, cached, output, reasoning)) = self.usage {
+        if let Some(usage) = self.usage {
             let _ = writeln!(stdout);
-            if reasoning > 0 {
-                let _ = writeln!(
-                    stdout,
-                    "Tokens: {} input ({} cached), {} output ({} reasoning)",
-                    input, cached, output, reasoning
-                );
-            } else {
-                let _ = writeln!(
-                    stdout,
-                    "Tokens: {} input ({} cached), {} output",
-                    input, cached, output
-                );
-            }
+            let _ = writeln!(stdout, "{}", format_usage(usage));
         }
 
         RenderedOutput { stdout, stderr }
@@ -136,8 +563,13 @@ impl CodexOutput {
 
     /// Format and print the output
     pub fn print(&self) {
-        let rendered = self.render();
-        if !rendered.stdout.is_empty() {
+        self.print_with(&RenderOptions::default());
+    }
+
+    pub fn print_with(&self, opts: &RenderOptions) {
+        let rendered = self.render_with(opts);
+        let paged = opts.pager && crate::pager::page_if_long(&rendered.stdout);
+        if !rendered.stdout.is_empty() && !paged {
             print!("{}", rendered.stdout);
         }
         if !rendered.stderr.is_empty() {
@@ -186,6 +618,164 @@ mod tests {
         assert_eq!(output.aggregated_message(), "content");
     }
 
+    fn grep(pattern: &str, before: usize, after: usize) -> LineFilter {
+        LineFilter { pattern: Regex::new(pattern).unwrap(), before, after }
+    }
+
+    #[test]
+    fn line_filter_keeps_context_and_separates_groups() {
+        let text = "a\nmatch 1\nb\nc\nd\nmatch 2\ne";
+        assert_eq!(grep("match", 0, 0).apply(text).unwrap(), "match 1\n--\nmatch 2");
+        assert_eq!(grep("match", 1, 1).apply(text).unwrap(), "a\nmatch 1\nb\n--\nd\nmatch 2\ne");
+        assert_eq!(grep("match", 0, 3).apply(text).unwrap(), "match 1\nb\nc\nd\nmatch 2\ne");
+        assert_eq!(grep("nope", 2, 2).apply(text), None);
+    }
+
+    #[test]
+    fn render_with_grep_filters_message_only() {
+        let mut output = CodexOutput::new();
+        output.session_id = Some("abc".into());
+        output.add_message("keep this\ndrop that".into());
+        let opts = RenderOptions { grep: Some(grep("keep", 0, 0)), ..Default::default() };
+        let rendered = output.render_with(&opts);
+        assert!(rendered.stdout.contains("Session: abc"));
+        assert!(rendered.stdout.contains("keep this"));
+        assert!(!rendered.stdout.contains("drop that"));
+
+        let opts = RenderOptions { grep: Some(grep("absent", 0, 0)), ..Default::default() };
+        let rendered = output.render_with(&opts);
+        assert!(rendered.stderr.contains("No lines matched --grep"));
+        assert!(!rendered.stderr.contains("No response received"));
+    }
+
+    #[test]
+    fn diff_against_replaces_the_answer() {
+        let mut output = CodexOutput::new();
+        output.add_message("Here it is:\n\n```\nline one\nline **2**\n```".into());
+        let diff_against = DiffAgainst { path: "notes.md".into(), contents: "line one\nline two\n".into() };
+        let opts = RenderOptions { diff_against: Some(diff_against.clone()), plain: true, wrap: Some(5), ..Default::default() };
+        let rendered = output.render_with(&opts);
+        assert!(rendered.stdout.ends_with("\n line one\n-line two\n+line **2**\n"), "{}", rendered.stdout);
+        assert!(!rendered.stdout.contains("Here it is"));
+
+        let mut same = CodexOutput::new();
+        same.add_message("line one\nline two".into());
+        let rendered = same.render_with(&RenderOptions { diff_against: Some(diff_against), ..Default::default() });
+        assert!(rendered.stderr.contains("Note: The answer matches notes.md"));
+        assert!(!rendered.stdout.contains("line one"));
+    }
+
+    #[test]
+    fn a11y_marks_the_response_in_words() {
+        let mut output = CodexOutput::new();
+        output.session_id = Some("abc".into());
+        output.add_message("All **done**.".into());
+        let opts = RenderOptions { a11y: true, plain: true, ..Default::default() };
+        let rendered = output.render_with(&opts);
+        assert_eq!(rendered.stdout, "Session: abc\n\nBEGIN RESPONSE\nAll done.\nEND RESPONSE\n");
+        assert!(!output.render_with(&RenderOptions::default()).stdout.contains("BEGIN RESPONSE"));
+    }
+
+    #[test]
+    fn session_header_can_be_omitted_or_redacted() {
+        let mut output = CodexOutput::new();
+        output.session_id = Some("0199a213-81c0-7800-8aa1-bbab2a035a53".into());
+        output.add_message("hello".into());
+
+        let omit = RenderOptions { session_header: SessionHeader::Omit, ..Default::default() };
+        let rendered = output.render_with(&omit);
+        assert!(!rendered.stdout.contains("Session:"));
+        assert!(rendered.stdout.contains("hello"));
+        assert!(rendered.stderr.is_empty());
+
+        let redact = RenderOptions { session_header: SessionHeader::Redact, ..Default::default() };
+        let rendered = output.render_with(&redact);
+        assert!(!rendered.stdout.contains("0199a213"));
+        let hashed = redact_session_id("0199a213-81c0-7800-8aa1-bbab2a035a53");
+        assert!(rendered.stdout.contains(&format!("Session: {}", hashed)));
+        assert_eq!(hashed.len(), "sha256:".len() + 12);
+        assert_ne!(hashed, redact_session_id("another"));
+    }
+
+    #[test]
+    fn warnings_can_be_hidden() {
+        let mut output = CodexOutput::new();
+        output.multiple_threads_seen = true;
+        output.lines_seen = 2;
+        assert_eq!(output.warnings().len(), 3);
+        let quiet = RenderOptions { hide_warnings: true, ..Default::default() };
+        assert!(output.render_with(&quiet).stderr.is_empty());
+
+        output.add_error("boom".into());
+        let rendered = output.render_with(&quiet);
+        assert!(rendered.stderr.contains("Error from codex: boom"));
+        assert!(!rendered.stderr.contains("Warning"));
+    }
+
+    #[test]
+    fn reflow_wraps_prose_and_keeps_code() {
+        let text = "one two three four five six\n\
+                    ```\nlet long_code_line = call(with, many, arguments);\n```\n\
+                    - bullet item that is long\n\
+                    12. numbered item here ok\n\
+                    > quoted text that wraps\n\
+                    | a table | row that stays |\n\
+                    short";
+        assert_eq!(
+            reflow(text, 12),
+            "one two\nthree four\nfive six\n\
+             ```\nlet long_code_line = call(with, many, arguments);\n```\n\
+             - bullet\n  item that\n  is long\n\
+             12. numbered\n    item\n    here ok\n\
+             > quoted\n> text that\n> wraps\n\
+             | a table | row that stays |\n\
+             short"
+        );
+    }
+
+    #[test]
+    fn reflow_leaves_overlong_words_whole() {
+        assert_eq!(
+            reflow("see https://example.com/a/very/long/path now", 10),
+            "see\nhttps://example.com/a/very/long/path\nnow"
+        );
+    }
+
+    #[test]
+    fn strip_markdown_leaves_prose() {
+        let text = "## Summary\n\
+                    The **fix** is in `parse_*_args` and _maybe_ ~~not~~ elsewhere.\n\
+                    See [the docs](https://example.com) and my_var_name.\n\
+                    ---\n\
+                    * first *item*\n\
+                    > quoted\n\
+                    ```rust\n**not bold** in code\n```";
+        assert_eq!(
+            strip_markdown(text),
+            "Summary\n\
+             The fix is in parse_*_args and maybe not elsewhere.\n\
+             See the docs and my_var_name.\n\
+             - first item\n\
+             quoted\n\
+             **not bold** in code"
+        );
+        assert_eq!(strip_markdown("#hashtag stays"), "#hashtag stays");
+    }
+
+    #[test]
+    fn truncate_messages_cuts_on_char_boundary() {
+        let mut output = CodexOutput::new();
+        output.add_message("héllo".to_string());
+        output.add_message("world".to_string());
+        assert_eq!(output.message_bytes(), output.aggregated_message().len());
+
+        output.truncate_messages(100);
+        assert_eq!(output.messages.len(), 2);
+
+        output.truncate_messages(2);
+        assert_eq!(output.aggregated_message(), "h\n[output truncated at 2 bytes]");
+    }
+
     #[test]
     fn render_warns_on_multiple_threads() {
         let mut output = CodexOutput::new();
@@ -266,6 +856,24 @@ mod tests {
         assert!(!rendered.stderr.contains("No response received"));
     }
 
+    #[test]
+    fn retried_requests_are_summarised_not_reported_as_errors() {
+        let mut output = CodexOutput::new();
+        output.session_id = Some("abc".into());
+        for message in [
+            "stream error: unexpected status 429 Too Many Requests; retrying 1/5 in 30s…",
+            "stream error: stream disconnected before completion; retrying 1/5 in 8s…",
+        ] {
+            output.apply(Event::StreamError { message: message.into() });
+        }
+        output.add_message("done".into());
+        assert!(output.errors.is_empty());
+        assert_eq!(
+            output.render().stderr,
+            "Retries: 1 rate-limit retry, 1 network retry (total 38s waiting)\n"
+        );
+    }
+
     #[test]
     fn render_omits_usage_when_none() {
         let mut output = CodexOutput::new();
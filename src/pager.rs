@@ -0,0 +1,92 @@
+//! Paging long answers, as git does.
+//!
+//! When stdout is a terminal and the rendered output wouldn't fit on the
+//! screen, it is piped through `$CODEX_CLEAN_PAGER`, `$PAGER` or `less`
+//! instead of scrolling past. `less` gets `LESS=FRX` unless `LESS` is
+//! already set, so colours pass through and the text stays on screen after
+//! quitting. A pager of `cat` or an empty string turns paging off, as does
+//! `--no-pager`.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::output::terminal_size;
+
+/// Overrides `$PAGER` for codex-clean alone, like `GIT_PAGER`.
+pub const ENV_PAGER: &str = "CODEX_CLEAN_PAGER";
+
+/// The pager to use, or None when paging is turned off.
+pub fn pager_command() -> Option<String> {
+    let pager = std::env::var(ENV_PAGER)
+        .or_else(|_| std::env::var("PAGER"))
+        .unwrap_or_else(|_| "less".to_string());
+    let pager = pager.trim();
+    (!pager.is_empty() && pager != "cat").then(|| pager.to_string())
+}
+
+/// Whether `text` takes more than `rows` screen lines `cols` wide,
+/// leaving a line for the prompt.
+pub fn too_long(text: &str, cols: usize, rows: usize) -> bool {
+    let cols = cols.max(1);
+    let mut lines = 0;
+    for line in text.lines() {
+        lines += line.chars().count().div_ceil(cols).max(1);
+        if lines >= rows {
+            return true;
+        }
+    }
+    false
+}
+
+/// Show `text` in the pager if stdout is a terminal it doesn't fit on.
+/// False when it wasn't paged and still needs printing.
+pub fn page_if_long(text: &str) -> bool {
+    let Some((cols, rows)) = terminal_size() else {
+        return false;
+    };
+    if rows == 0 || !too_long(text, cols, rows) {
+        return false;
+    }
+    let Some(pager) = pager_command() else {
+        return false;
+    };
+    match page(&pager, text) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Warning: failed to run pager `{}`: {}", pager, e);
+            false
+        }
+    }
+}
+
+fn page(pager: &str, text: &str) -> io::Result<()> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(pager).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+    let mut child = cmd.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe; that's not an error.
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_means_more_screen_lines_than_rows() {
+        assert!(!too_long("a\nb\nc\n", 80, 4));
+        assert!(too_long("a\nb\nc\nd\n", 80, 4));
+        // Lines wider than the terminal take several rows.
+        assert!(too_long(&format!("{}\nb\n", "x".repeat(200)), 80, 4));
+        assert!(!too_long("", 80, 1));
+    }
+}
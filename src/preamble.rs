@@ -0,0 +1,163 @@
+//! `--strip-preamble`: drop the conversational filler around an answer.
+//!
+//! Models like to open with "Sure! Here's the updated function:" and close
+//! with "Let me know if you'd like any changes.", which is noise once the
+//! answer is piped into another tool. The filter looks at the first and
+//! last paragraphs (blocks separated by blank lines) and drops up to two
+//! at each end while they are short and match a filler pattern. Code
+//! blocks, headings and lists are never dropped, and an answer that is
+//! nothing but filler is left alone.
+//!
+//! `preamble_patterns` and `signoff_patterns` in the config add regexes to
+//! the built-in lists.
+
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+
+/// Openers such as "Sure!", "Certainly, here's ..." and "Here is the fix:".
+const LEADING: &[&str] = &[
+    r"^(sure|certainly|of course|absolutely|okay|ok|alright|great|got it|no problem|happy to help)[!,.]",
+    r"^(great|good) question\b",
+    r"^here(’s|'s| is| are)\b.*:$",
+    r"^(i'll|i will|let me)\b.*:$",
+];
+
+/// Closers such as "Let me know if ..." and "Hope this helps!".
+const TRAILING: &[&str] = &[
+    r"^(let me know|feel free|hope (this|that) helps|i hope (this|that) helps|happy (coding|to help))\b",
+    r"^(if you (have|need|want|'d like)|would you like|do you want|want me to|shall i|should i)\b",
+    r"^(i can also|i'm happy to|i am happy to)\b",
+    r"^(cheers|thanks|thank you|good luck|best regards)[!.]?$",
+];
+
+/// A paragraph this long is content, whatever it starts with.
+const MAX_FILLER_CHARS: usize = 240;
+/// How many paragraphs may be dropped at each end.
+const MAX_DROPPED: usize = 2;
+
+/// The compiled filler patterns.
+#[derive(Debug, Clone)]
+pub struct PreambleFilter {
+    leading: Vec<Regex>,
+    trailing: Vec<Regex>,
+}
+
+impl PreambleFilter {
+    /// The built-in patterns plus `extra_leading` and `extra_trailing`
+    /// (matched case-insensitively against a whole paragraph).
+    pub fn new(extra_leading: &[String], extra_trailing: &[String]) -> Result<Self> {
+        let compile = |builtin: &[&str], extra: &[String]| -> Result<Vec<Regex>> {
+            builtin
+                .iter()
+                .copied()
+                .chain(extra.iter().map(String::as_str))
+                .map(|p| {
+                    RegexBuilder::new(p)
+                        .case_insensitive(true)
+                        .build()
+                        .with_context(|| format!("invalid preamble pattern '{}'", p))
+                })
+                .collect()
+        };
+        Ok(Self { leading: compile(LEADING, extra_leading)?, trailing: compile(TRAILING, extra_trailing)? })
+    }
+
+    /// `message` without its filler paragraphs.
+    pub fn apply(&self, message: &str) -> String {
+        let paragraphs = paragraphs(message);
+        let text = |i: usize| {
+            let (from, to) = paragraphs[i];
+            &message[from..to]
+        };
+        let mut start = 0;
+        let mut end = paragraphs.len();
+        while start < end && start < MAX_DROPPED && is_filler(text(start), &self.leading) {
+            start += 1;
+        }
+        while end > start && paragraphs.len() - end < MAX_DROPPED && is_filler(text(end - 1), &self.trailing) {
+            end -= 1;
+        }
+        if start == end || (start == 0 && end == paragraphs.len()) {
+            return message.to_string();
+        }
+        // Keep the spacing between the paragraphs that are left.
+        message[paragraphs[start].0..paragraphs[end - 1].1].to_string()
+    }
+}
+
+/// The byte ranges of the blocks between blank lines outside code fences.
+fn paragraphs(message: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut in_fence = false;
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+    let mut offset = 0;
+    for line in message.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        if text.trim_start().starts_with("```") || text.trim_start().starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if text.trim().is_empty() && !in_fence {
+            if let Some(s) = start.take() {
+                out.push((s, end));
+            }
+        } else {
+            start.get_or_insert(offset);
+            end = offset + text.len();
+        }
+        offset += line.len();
+    }
+    if let Some(s) = start {
+        out.push((s, end));
+    }
+    out
+}
+
+fn is_filler(paragraph: &str, patterns: &[Regex]) -> bool {
+    let text = paragraph.trim();
+    let structured = text.starts_with(['#', '-', '*', '>', '|', '`', '~'])
+        || text.chars().next().is_some_and(|c| c.is_ascii_digit());
+    if structured || text.len() > MAX_FILLER_CHARS {
+        return false;
+    }
+    // Match the paragraph as one line so `^...$` patterns see all of it.
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    patterns.iter().any(|p| p.is_match(&flat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(message: &str) -> String {
+        PreambleFilter::new(&[], &[]).unwrap().apply(message)
+    }
+
+    #[test]
+    fn drops_openers_and_sign_offs() {
+        let message = "Sure! Here's the updated function:\n\n```rust\nfn add(a: i32, b: i32) -> i32 {\n\n    a + b\n}\n```\n\nThis keeps the signature.\n\nLet me know if you'd like tests too!";
+        assert_eq!(
+            strip(message),
+            "```rust\nfn add(a: i32, b: i32) -> i32 {\n\n    a + b\n}\n```\n\nThis keeps the signature."
+        );
+        assert_eq!(strip("Here is the fix:\n\nUse `?`.\n\nHope this helps."), "Use `?`.");
+    }
+
+    #[test]
+    fn leaves_substance_alone() {
+        // Content that merely starts with a filler word, and filler-only answers.
+        let long = format!("Sure, {}", "the parser needs a lookahead here. ".repeat(10));
+        assert_eq!(strip(&format!("{}\n\nDone.", long)), format!("{}\n\nDone.", long));
+        assert_eq!(strip("Sure!"), "Sure!");
+        assert_eq!(strip("- Sure: a list item\n\nBody"), "- Sure: a list item\n\nBody");
+        assert_eq!(strip("The answer is 42."), "The answer is 42.");
+        assert_eq!(strip("Great care is needed here.\n\nBest to run it twice."), "Great care is needed here.\n\nBest to run it twice.");
+    }
+
+    #[test]
+    fn configured_patterns_extend_the_lists() {
+        let filter = PreambleFilter::new(&[r"^as an ai\b".into()], &[r"^generated by\b".into()]).unwrap();
+        assert_eq!(filter.apply("As an AI, I think:\n\nUse X.\n\nGenerated by the bot."), "Use X.");
+        assert!(PreambleFilter::new(&["(".into()], &[]).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! What happens to a prompt between the command line and codex.
+//!
+//! Each step is a [`PromptPreprocessor`], and a [`Pipeline`] runs them in
+//! order, so a new step is added by writing one rather than by threading
+//! another option through every command. The built-in ones are known by
+//! name:
+//!
+//! - `template` fills the `{{date}}`, `{{cwd}}` and `{{env.NAME}}`
+//!   placeholders; others are left as typed.
+//! - `redact` replaces what looks like a secret, with the rules of
+//!   `codex-clean redact` (see [`crate::redact`]).
+//! - `wrap` adds `prompt_prefix` and `prompt_suffix`.
+//! - `context` puts the `context` files ahead of the prompt.
+//! - `lang` adds the `--lang` instruction.
+//! - `budget` refuses a prompt estimated at more than `token_budget`
+//!   tokens (see [`crate::preview::estimate_tokens`]).
+//!
+//! Unless the config's `preprocessors` lists others, a prompt goes
+//! through [`DEFAULT_ORDER`], which wraps it as [`PromptWrap::apply`]
+//! does. A program embedding codex-clean can push its own steps and pass
+//! the pipeline in [`RunOptions::preprocessors`](crate::runner::RunOptions::preprocessors).
+
+use std::env;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+
+use crate::config::PromptWrap;
+use crate::lang;
+use crate::preview::estimate_tokens;
+use crate::redact::Redactor;
+use crate::templates;
+
+/// The built-in steps, by name.
+pub const BUILTIN: &[&str] = &["template", "redact", "wrap", "context", "lang", "budget"];
+
+/// The steps a prompt goes through unless the config says otherwise.
+pub const DEFAULT_ORDER: &[&str] = &["wrap", "context", "lang"];
+
+/// Rewrites a prompt on its way to codex.
+pub trait PromptPreprocessor: Debug + Send + Sync {
+    /// The prompt as the next step should see it, or an error to stop the
+    /// run before codex starts.
+    fn process(&self, prompt: String) -> Result<String>;
+}
+
+/// Named steps run in order.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<(String, Arc<dyn PromptPreprocessor>)>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// [`DEFAULT_ORDER`] for `wrap`.
+    pub fn standard(wrap: &PromptWrap) -> Self {
+        Self::configured(&[], wrap, None).expect("the default steps are built in")
+    }
+
+    /// The pipeline the config's `preprocessors` names (or [`DEFAULT_ORDER`]
+    /// when it names none), taking its text from `wrap`. A `token_budget`
+    /// is checked last unless `budget` is listed somewhere else.
+    pub fn configured(names: &[String], wrap: &PromptWrap, token_budget: Option<usize>) -> Result<Self> {
+        let mut names: Vec<&str> = if names.is_empty() {
+            DEFAULT_ORDER.to_vec()
+        } else {
+            names.iter().map(String::as_str).collect()
+        };
+        if token_budget.is_some() && !names.contains(&"budget") {
+            names.push("budget");
+        }
+        let mut pipeline = Self::new();
+        for name in names {
+            match name {
+                "template" => pipeline.push(name, Template),
+                "redact" => pipeline.push(name, Redaction(Redactor::new(&[])?)),
+                "wrap" if wrap.prefix.is_some() || wrap.suffix.is_some() => {
+                    pipeline.push(name, Affix { prefix: wrap.prefix.clone(), suffix: wrap.suffix.clone() })
+                }
+                "context" => {
+                    if let Some(context) = &wrap.context {
+                        pipeline.push(name, Prepend(context.clone()));
+                    }
+                }
+                "lang" => {
+                    if let Some(code) = &wrap.lang {
+                        pipeline.push(name, Affix { prefix: None, suffix: Some(lang::instruction(code)) });
+                    }
+                }
+                "budget" => match token_budget {
+                    Some(max_tokens) => pipeline.push(name, TokenBudget(max_tokens)),
+                    None => bail!("the `budget` preprocessor needs a `token_budget`"),
+                },
+                "wrap" => {}
+                other => bail!("unknown prompt preprocessor '{}' (expected one of: {})", other, BUILTIN.join(", ")),
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// Add `stage` as `name`, after the others.
+    pub fn push(&mut self, name: &str, stage: impl PromptPreprocessor + 'static) {
+        self.stages.push((name.to_string(), Arc::new(stage)));
+    }
+
+    /// The names of the steps, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// `prompt` after every step. Empty prompts (review, or resume without
+    /// a new message) are left empty.
+    pub fn apply(&self, prompt: &str) -> Result<String> {
+        if prompt.is_empty() {
+            return Ok(String::new());
+        }
+        let mut prompt = prompt.to_string();
+        for (name, stage) in &self.stages {
+            prompt = stage.process(prompt).with_context(|| format!("prompt preprocessor `{}`", name))?;
+        }
+        Ok(prompt)
+    }
+}
+
+/// `parts` trimmed at the end and separated by blank lines, skipping empty
+/// ones, as [`PromptWrap::apply`] joins them.
+fn join(parts: &[Option<&str>]) -> String {
+    let parts: Vec<&str> = parts.iter().flatten().map(|p| p.trim_end()).filter(|p| !p.is_empty()).collect();
+    parts.join("\n\n")
+}
+
+/// `template`: fills the placeholders codex-clean knows the value of.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Template;
+
+impl Template {
+    /// The value of the placeholder `key`, if it is one of the built-in ones.
+    fn value(key: &str) -> Option<String> {
+        match key {
+            "date" => Some(Local::now().format("%Y-%m-%d").to_string()),
+            "cwd" => env::current_dir().ok().map(|dir| dir.display().to_string()),
+            _ => env::var(key.strip_prefix("env.")?).ok(),
+        }
+    }
+}
+
+impl PromptPreprocessor for Template {
+    fn process(&self, prompt: String) -> Result<String> {
+        let vars: Vec<(String, String)> =
+            templates::placeholders(&prompt).into_iter().filter_map(|key| Some((key.clone(), Self::value(&key)?))).collect();
+        Ok(if vars.is_empty() { prompt } else { templates::expand(&prompt, &vars) })
+    }
+}
+
+/// `redact`: replaces secrets, saying on stderr how many.
+#[derive(Debug, Clone)]
+pub struct Redaction(pub Redactor);
+
+impl PromptPreprocessor for Redaction {
+    fn process(&self, prompt: String) -> Result<String> {
+        let (redacted, found) = self.0.redact(&prompt);
+        if found == 0 {
+            return Ok(prompt);
+        }
+        eprintln!("Redacted {} secret(s) from the prompt", found);
+        Ok(redacted.into_owned())
+    }
+}
+
+/// `wrap` and `lang`: text before and after the prompt.
+#[derive(Debug, Clone, Default)]
+pub struct Affix {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+impl PromptPreprocessor for Affix {
+    fn process(&self, prompt: String) -> Result<String> {
+        Ok(join(&[self.prefix.as_deref(), Some(&prompt), self.suffix.as_deref()]))
+    }
+}
+
+/// `context`: the `context` files, already read and framed, ahead of the
+/// prompt.
+#[derive(Debug, Clone)]
+pub struct Prepend(pub String);
+
+impl PromptPreprocessor for Prepend {
+    fn process(&self, prompt: String) -> Result<String> {
+        Ok(join(&[Some(&self.0), Some(&prompt)]))
+    }
+}
+
+/// `budget`: the most tokens a prompt may be estimated at.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBudget(pub usize);
+
+impl PromptPreprocessor for TokenBudget {
+    fn process(&self, prompt: String) -> Result<String> {
+        let tokens = estimate_tokens(&prompt);
+        if tokens > self.0 {
+            bail!("the prompt is ~{} tokens, over the token_budget of {}; shorten it or raise the budget", tokens, self.0);
+        }
+        Ok(prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_pipeline_wraps_as_prompt_wrap_does() {
+        let wrap = PromptWrap {
+            context: Some("Files:\n\n<file path=\"a\">\nA\n</file>\n".into()),
+            prefix: Some("P".into()),
+            suffix: Some("S\n".into()),
+            lang: Some("de".into()),
+        };
+        let standard = Pipeline::standard(&wrap);
+        assert_eq!(standard.names().collect::<Vec<_>>(), DEFAULT_ORDER);
+        assert_eq!(standard.apply("Q\n").unwrap(), wrap.apply("Q\n"));
+        assert_eq!(standard.apply("").unwrap(), "");
+        assert_eq!(Pipeline::standard(&PromptWrap::default()).names().count(), 0);
+    }
+
+    #[test]
+    fn configured_steps_run_in_the_listed_order() {
+        let wrap = PromptWrap { prefix: Some("Key: sk-abcdefghijklmnopqrstuvwx".into()), ..Default::default() };
+        let names = |list: &[&str]| list.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        // Redacting before wrapping leaves the prefix alone.
+        let pipeline = Pipeline::configured(&names(&["template", "redact", "wrap"]), &wrap, Some(100)).unwrap();
+        assert_eq!(pipeline.names().collect::<Vec<_>>(), ["template", "redact", "wrap", "budget"]);
+        let sent = pipeline.apply("Today is {{date}}, {{nope}}; token sk-0123456789abcdefghijklmn").unwrap();
+        assert!(sent.starts_with("Key: sk-abcdefghijklmnopqrstuvwx\n\nToday is 2"), "{}", sent);
+        assert!(sent.ends_with(", {{nope}}; token [REDACTED:api-key]"), "{}", sent);
+
+        let over = Pipeline::configured(&[], &wrap, Some(5)).unwrap().apply("a longer prompt than that").unwrap_err();
+        assert!(format!("{:#}", over).contains("prompt preprocessor `budget`: the prompt is ~15 tokens, over the token_budget of 5"), "{:#}", over);
+        let unknown = Pipeline::configured(&names(&["wrap", "spellcheck"]), &wrap, None).unwrap_err();
+        assert!(unknown.to_string().contains("unknown prompt preprocessor 'spellcheck' (expected one of: template, redact"));
+        assert!(Pipeline::configured(&names(&["budget"]), &wrap, None).is_err());
+    }
+}
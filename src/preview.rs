@@ -0,0 +1,107 @@
+//! `--preview`: see the prompt as codex will get it, and say whether to
+//! send it.
+//!
+//! By the time a prompt reaches codex it may have been through a template
+//! and had the `context` files, `prompt_prefix` / `prompt_suffix` and the
+//! `--lang` instruction added around it. The preview lists those parts with
+//! a rough token count for each, prints the prompt in full, and asks before
+//! codex is started.
+
+use std::fmt::Write as _;
+
+use anyhow::{bail, Result};
+
+use crate::config::PromptWrap;
+use crate::lang;
+
+/// Characters per token for [`estimate_tokens`]; about right for English
+/// prose and code with the OpenAI tokenizers.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// A rough token count for `text`; codex-clean has no tokenizer.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// What the preview prints: the codex arguments, each part of the prompt
+/// with its size, and `full`, the prompt as it will be sent (after the
+/// [preprocessors](crate::preprocess)).
+pub fn describe(args: &[String], prompt: &str, wrap: &PromptWrap, full: &str) -> String {
+    let mut out = String::new();
+    let shown_args = if args.is_empty() { "(none)".to_string() } else { args.join(" ") };
+    let _ = writeln!(out, "codex arguments: {}", shown_args);
+    if full.is_empty() {
+        out.push_str("No prompt will be sent (a review, or a resume without a new message).\n");
+        return out;
+    }
+    out.push_str("Prompt parts:\n");
+    let mut part = |name: &str, text: &str, note: String| {
+        if !text.trim().is_empty() {
+            let _ = writeln!(out, "  {:<9} ~{} tokens{}", name, estimate_tokens(text), note);
+        }
+    };
+    if let Some(context) = &wrap.context {
+        let files: Vec<&str> = context
+            .lines()
+            .filter_map(|l| l.strip_prefix("<file path=\"")?.strip_suffix("\">"))
+            .collect();
+        part("context", context, format!(", {} file(s): {}", files.len(), files.join(", ")));
+    }
+    part("prefix", wrap.prefix.as_deref().unwrap_or_default(), String::new());
+    part("prompt", prompt, String::new());
+    part("suffix", wrap.suffix.as_deref().unwrap_or_default(), String::new());
+    if let Some(lang) = &wrap.lang {
+        part("language", &lang::instruction(lang), format!(" ({})", lang));
+    }
+    let _ = writeln!(
+        out,
+        "Total: {} characters, ~{} tokens (estimated at {} characters a token)",
+        full.chars().count(),
+        estimate_tokens(full),
+        CHARS_PER_TOKEN
+    );
+    let _ = writeln!(out, "----- prompt -----\n{}\n----- end of prompt -----", full);
+    out
+}
+
+/// Print the preview to stderr and ask with `answer` whether to go on.
+/// Anything but `y` stops the run before codex starts.
+pub fn confirm(
+    args: &[String],
+    prompt: &str,
+    wrap: &PromptWrap,
+    full: &str,
+    answer: impl FnOnce(&str) -> Option<String>,
+) -> Result<()> {
+    eprint!("{}", describe(args, prompt, wrap, full));
+    match answer("Send this to codex? [y/N] ") {
+        Some(reply) if matches!(reply.trim().to_ascii_lowercase().as_str(), "y" | "yes") => Ok(()),
+        Some(_) => bail!("not sent; codex was not started"),
+        None => bail!("--preview needs a terminal to ask on; codex was not started"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_lists_each_part_of_the_prompt() {
+        let wrap = PromptWrap {
+            context: Some("Project files included for context:\n\n<file path=\"src/lib.rs\">\npub fn a() {}\n</file>\n".into()),
+            prefix: Some("Be brief.".into()),
+            suffix: None,
+            lang: None,
+        };
+        let out = describe(&["-m".into(), "o3".into()], "Explain a", &wrap, &wrap.apply("Explain a"));
+        assert!(out.starts_with("codex arguments: -m o3\nPrompt parts:\n"), "{}", out);
+        assert!(out.contains("  context   ~21 tokens, 1 file(s): src/lib.rs\n  prefix    ~3 tokens\n  prompt    ~3 tokens\nTotal: "), "{}", out);
+        assert!(!out.contains("suffix"));
+        assert!(out.ends_with("Be brief.\n\nExplain a\n----- end of prompt -----\n"), "{}", out);
+        assert!(describe(&[], "", &wrap, "").contains("No prompt will be sent"));
+
+        assert!(confirm(&[], "hi", &wrap, "hi", |_| Some("y\n".into())).is_ok());
+        assert!(confirm(&[], "hi", &wrap, "hi", |_| Some("\n".into())).is_err());
+        assert!(confirm(&[], "hi", &wrap, "hi", |_| None).unwrap_err().to_string().contains("needs a terminal"));
+    }
+}
@@ -0,0 +1,376 @@
+//! `--transport proto`: drive codex through `codex proto` instead of `exec`.
+//!
+//! `codex proto` speaks codex's own submission/event protocol over
+//! stdin/stdout: we submit the prompt as a `user_input` op and read
+//! `{"id", "msg": {...}}` events until the task completes. Unlike `exec`,
+//! the process stays under our control for the whole turn, so a timeout or
+//! `codex-clean cancel` can send codex an `interrupt` (letting it close the
+//! turn cleanly) before the process is stopped.
+//!
+//! [`ProtoProcess`] wraps whatever process a backend started and
+//! translates the proto events into the `exec --json` stream the runner
+//! already parses, so rendering, seat rotation and reports work unchanged.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::backend::{CodexProcess, ProcessExit};
+use crate::runner::Mode;
+
+/// How the runner talks to codex.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// `codex exec --json`: one process per prompt, events on stdout.
+    #[default]
+    Exec,
+    /// `codex proto`: the submission/event protocol over stdin/stdout.
+    Proto,
+}
+
+/// Codex's argv for a proto run. Only new sessions are supported; codex
+/// args are passed through, so they must be ones `codex proto` accepts
+/// (`-c key=value` overrides).
+pub fn argv(args: &[String], mode: &Mode) -> Result<Vec<String>> {
+    if !matches!(mode, Mode::Exec) {
+        bail!("--transport proto only starts new sessions; use the default transport to resume or review");
+    }
+    // As with `exec`, nobody is there to approve commands.
+    let mut argv = vec!["proto".to_string(), "-c".to_string(), "approval_policy=never".to_string()];
+    argv.extend_from_slice(args);
+    Ok(argv)
+}
+
+/// How long codex gets to wind down after an `interrupt`.
+const INTERRUPT_GRACE: Duration = Duration::from_millis(500);
+
+type SharedStdin = Arc<Mutex<Option<Box<dyn Write + Send>>>>;
+
+/// A `codex proto` process presented as an `exec --json` one.
+pub struct ProtoProcess {
+    inner: Box<dyn CodexProcess>,
+    stdin: SharedStdin,
+    stdout: Option<Box<dyn Read + Send>>,
+}
+
+impl ProtoProcess {
+    /// Submit `prompt` to a freshly started `codex proto`.
+    pub fn start(mut inner: Box<dyn CodexProcess>, prompt: &str) -> Result<Self> {
+        let mut stdin = inner.take_stdin().context("codex proto needs a stdin pipe")?;
+        let submission = json!({
+            "id": "1",
+            "op": {"type": "user_input", "items": [{"type": "text", "text": prompt}]},
+        });
+        writeln!(stdin, "{}", submission).context("sending the prompt to codex proto")?;
+        stdin.flush()?;
+        let stdin: SharedStdin = Arc::new(Mutex::new(Some(stdin)));
+        let stdout = Box::new(ExecStream {
+            inner: BufReader::new(inner.take_stdout()),
+            translator: Translator::default(),
+            pending: Vec::new(),
+            stdin: Arc::clone(&stdin),
+        });
+        Ok(Self { inner, stdin, stdout: Some(stdout) })
+    }
+}
+
+impl CodexProcess for ProtoProcess {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        None
+    }
+
+    fn take_stdout(&mut self) -> Box<dyn Read + Send> {
+        self.stdout.take().expect("stdout taken once")
+    }
+
+    fn take_stderr(&mut self) -> Box<dyn Read + Send> {
+        self.inner.take_stderr()
+    }
+
+    /// Ask codex to interrupt the turn and shut down, giving it
+    /// [`INTERRUPT_GRACE`] to do so before the process is stopped.
+    fn kill(&mut self) -> io::Result<()> {
+        send(&self.stdin, &json!({"id": "interrupt", "op": {"type": "interrupt"}}));
+        shutdown(&self.stdin);
+        std::thread::sleep(INTERRUPT_GRACE);
+        self.inner.kill()
+    }
+
+    fn wait(&mut self) -> io::Result<ProcessExit> {
+        self.inner.wait()
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ProcessExit>> {
+        self.inner.try_wait()
+    }
+
+    fn cleanup(&mut self) {
+        self.inner.cleanup()
+    }
+}
+
+fn send(stdin: &SharedStdin, op: &Value) {
+    let mut guard = stdin.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(w) = guard.as_mut() {
+        let _ = writeln!(w, "{}", op).and_then(|_| w.flush());
+    }
+}
+
+/// Send `shutdown` and close stdin, after which codex proto exits.
+fn shutdown(stdin: &SharedStdin) {
+    send(stdin, &json!({"id": "shutdown", "op": {"type": "shutdown"}}));
+    stdin.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// codex proto's stdout, translated line by line into exec JSONL.
+struct ExecStream {
+    inner: BufReader<Box<dyn Read + Send>>,
+    translator: Translator,
+    pending: Vec<u8>,
+    stdin: SharedStdin,
+}
+
+impl Read for ExecStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Ok(0);
+            }
+            let step = self.translator.feed(&line);
+            if let Some(reply) = &step.reply {
+                send(&self.stdin, reply);
+            }
+            if step.done {
+                shutdown(&self.stdin);
+            }
+            for event in step.events {
+                self.pending.extend_from_slice(event.to_string().as_bytes());
+                self.pending.push(b'\n');
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+/// What one proto event turns into.
+#[derive(Debug, Default, PartialEq)]
+pub struct Step {
+    /// exec-format events to pass on.
+    pub events: Vec<Value>,
+    /// An op to send back to codex.
+    pub reply: Option<Value>,
+    /// The turn is over; codex can be shut down.
+    pub done: bool,
+}
+
+/// Maps proto events onto exec ones. Commands are only reported once they
+/// end, which is when exec reports them too.
+#[derive(Debug, Default)]
+pub struct Translator {
+    commands: HashMap<String, String>,
+    usage: Option<Value>,
+}
+
+impl Translator {
+    pub fn feed(&mut self, line: &str) -> Step {
+        let mut step = Step::default();
+        let Ok(v) = serde_json::from_str::<Value>(line) else {
+            return step;
+        };
+        let id = v.get("id").and_then(Value::as_str).unwrap_or_default();
+        let Some(msg) = v.get("msg") else {
+            return step;
+        };
+        let text = |key: &str| msg.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        match msg.get("type").and_then(Value::as_str).unwrap_or_default() {
+            "session_configured" => {
+                step.events.push(json!({"type": "thread.started", "thread_id": text("session_id")}));
+            }
+            "agent_message" => step.events.push(json!({
+                "type": "item.completed",
+                "item": {"type": "agent_message", "text": text("message")},
+            })),
+            "exec_command_begin" => {
+                let command = match msg.get("command") {
+                    Some(Value::Array(parts)) => {
+                        join_command(parts.iter().filter_map(Value::as_str))
+                    }
+                    _ => text("command"),
+                };
+                self.commands.insert(text("call_id"), command);
+            }
+            "exec_command_end" => {
+                let command = self.commands.remove(&text("call_id")).unwrap_or_default();
+                // Older releases send stdout and stderr separately.
+                let output = msg
+                    .get("aggregated_output")
+                    .and_then(Value::as_str)
+                    .map(String::from)
+                    .unwrap_or_else(|| text("stdout") + &text("stderr"));
+                step.events.push(json!({
+                    "type": "item.completed",
+                    "item": {
+                        "type": "command_execution",
+                        "command": command,
+                        "exit_code": msg.get("exit_code"),
+                        "aggregated_output": output,
+                    },
+                }));
+            }
+            "patch_apply_begin" => {
+                let changes: Vec<Value> = msg
+                    .get("changes")
+                    .and_then(Value::as_object)
+                    .map(|changes| {
+                        changes
+                            .iter()
+                            .map(|(path, change)| json!({"path": path, "kind": change_kind(change)}))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                step.events.push(json!({
+                    "type": "item.completed",
+                    "item": {"type": "file_change", "changes": changes},
+                }));
+            }
+            "token_count" => {
+                // Newer releases nest the running total under `info`.
+                let usage = msg.get("info").and_then(|i| i.get("total_token_usage")).unwrap_or(msg);
+                self.usage = Some(usage.clone());
+            }
+            "stream_error" => step.events.push(json!({"type": "error", "message": text("message")})),
+            "error" => {
+                step.events.push(json!({"type": "turn.failed", "error": {"message": text("message")}}));
+                step.done = true;
+            }
+            "turn_aborted" => {
+                step.events.push(json!({"type": "turn.failed", "error": {"message": "turn interrupted"}}));
+                step.done = true;
+            }
+            "task_complete" => {
+                if let Some(usage) = self.usage.take() {
+                    step.events.push(json!({"type": "turn.completed", "usage": usage}));
+                }
+                step.done = true;
+            }
+            kind @ ("exec_approval_request" | "apply_patch_approval_request") => {
+                let op = if kind == "exec_approval_request" { "exec_approval" } else { "patch_approval" };
+                step.reply = Some(json!({"id": id, "op": {"type": op, "id": id, "decision": "denied"}}));
+                step.events.push(json!({
+                    "type": "error",
+                    "message": "codex asked for approval, which a non-interactive run can't give; denied",
+                }));
+            }
+            _ => {}
+        }
+        step
+    }
+}
+
+/// `add`, `update` or `delete`, whether written `{"type": "add", ...}` or
+/// `{"add": {...}}`.
+fn change_kind(change: &Value) -> String {
+    change
+        .get("type")
+        .and_then(Value::as_str)
+        .or_else(|| change.as_object().and_then(|o| o.keys().next()).map(String::as_str))
+        .unwrap_or("update")
+        .to_string()
+}
+
+/// A command line for display, quoting words that need it.
+fn join_command<'a>(parts: impl Iterator<Item = &'a str>) -> String {
+    parts
+        .map(|p| {
+            if !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c)) {
+                p.to_string()
+            } else {
+                format!("'{}'", p.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{extract_event, Event};
+
+    fn events(lines: &[&str]) -> Vec<Value> {
+        let mut t = Translator::default();
+        lines.iter().flat_map(|l| t.feed(l).events).collect()
+    }
+
+    #[test]
+    fn proto_events_become_exec_events() {
+        let out = events(&[
+            r#"{"id":"0","msg":{"type":"session_configured","session_id":"s-1","model":"o3"}}"#,
+            r#"{"id":"1","msg":{"type":"task_started"}}"#,
+            r#"{"id":"1","msg":{"type":"exec_command_begin","call_id":"c1","command":["bash","-lc","ls src"]}}"#,
+            r#"{"id":"1","msg":{"type":"exec_command_end","call_id":"c1","exit_code":0,"stdout":"a.rs\n","stderr":""}}"#,
+            r#"{"id":"1","msg":{"type":"patch_apply_begin","changes":{"a.rs":{"update":{}},"b.rs":{"add":{}}}}}"#,
+            r#"{"id":"1","msg":{"type":"agent_message","message":"done"}}"#,
+            r#"{"id":"1","msg":{"type":"token_count","input_tokens":10,"cached_input_tokens":2,"output_tokens":3}}"#,
+            r#"{"id":"1","msg":{"type":"task_complete","last_agent_message":"done"}}"#,
+        ]);
+        let parsed: Vec<Event> = out.iter().filter_map(|v| extract_event(&v.to_string())).collect();
+        assert_eq!(parsed.len(), out.len(), "every translated event is understood");
+        assert!(matches!(&parsed[0], Event::ThreadStarted { thread_id } if thread_id == "s-1"));
+        assert!(matches!(&parsed[1], Event::CommandExecuted(c) if c.command == "bash -lc 'ls src'" && c.exit_code == Some(0)));
+        assert!(matches!(&parsed[1], Event::CommandExecuted(c) if c.output.as_deref() == Some("a.rs\n")));
+        assert!(matches!(&parsed[2], Event::FilesChanged(f) if f.len() == 2 && f[1].kind == "add"));
+        assert!(matches!(&parsed[3], Event::AgentMessage { text: Some(t) } if t == "done"));
+        assert!(matches!(parsed[4], Event::TurnCompleted { input_tokens: 10, output_tokens: 3, .. }));
+    }
+
+    #[test]
+    fn approvals_are_denied_and_errors_end_the_turn() {
+        let mut t = Translator::default();
+        let step = t.feed(r#"{"id":"7","msg":{"type":"exec_approval_request","call_id":"c","command":["rm","x"]}}"#);
+        assert_eq!(step.reply, Some(json!({"id": "7", "op": {"type": "exec_approval", "id": "7", "decision": "denied"}})));
+        assert!(!step.done);
+        let step = t.feed(r#"{"id":"1","msg":{"type":"error","message":"boom"}}"#);
+        assert!(step.done);
+        assert!(matches!(extract_event(&step.events[0].to_string()), Some(Event::TurnFailed { message }) if message == "boom"));
+        assert_eq!(t.feed("not json"), Step::default());
+    }
+
+    #[test]
+    fn argv_is_for_new_sessions_only() {
+        let args = vec!["-c".to_string(), "model=o3".to_string()];
+        assert_eq!(argv(&args, &Mode::Exec).unwrap(), ["proto", "-c", "approval_policy=never", "-c", "model=o3"]);
+        assert!(argv(&args, &Mode::Review).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn process_submits_prompt_and_shuts_down_after_the_turn() {
+        use crate::backend::{ChildProcess, SpawnOptions};
+        // Echo the submission back as the answer, then wait for shutdown.
+        let script = r#"read sub
+printf '%s\n' '{"id":"0","msg":{"type":"session_configured","session_id":"s"}}'
+printf '{"id":"1","msg":{"type":"agent_message","message":"%s"}}\n' "$(printf '%s' "$sub" | tr -d '"')"
+printf '%s\n' '{"id":"1","msg":{"type":"task_complete"}}'
+read op
+case "$op" in *shutdown*) exit 0;; *) exit 9;; esac"#;
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", script]);
+        let child = ChildProcess::spawn(cmd, SpawnOptions { stdin_prompt: true, ..Default::default() }).unwrap();
+        let mut proto = ProtoProcess::start(Box::new(child), "hello").unwrap();
+        let mut out = String::new();
+        proto.take_stdout().read_to_string(&mut out).unwrap();
+        assert!(proto.wait().unwrap().success);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("user_input") && lines[1].contains("hello"));
+    }
+}
@@ -3,8 +3,15 @@
 //! Pure parsing — no I/O. The strings we anchor on are sourced from
 //! `codex-rs/protocol/src/error.rs` and `codex-rs/login/src/auth/manager.rs`
 //! (codex 0.125.0). They're hardcoded English upstream; no i18n.
+//!
+//! Transient failures codex recovers from by itself (a dropped stream, a
+//! 429 from the API) arrive as `error` events announcing a retry; see
+//! [`classify_retry`].
+
+use std::sync::OnceLock;
 
 use chrono::{DateTime, Duration, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use regex::Regex;
 
 /// Classification of a codex run's failure mode, derived from the messages
 /// surfaced via `turn.failed` / `error` events (and stderr fallback).
@@ -133,6 +140,72 @@ pub fn apply_recovery_window(
     now + Duration::seconds(clamped as i64)
 }
 
+/// Why codex retried a request mid-run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryKind {
+    /// The API answered 429 / "rate limit".
+    RateLimit,
+    /// The connection dropped or the stream broke off.
+    Network,
+}
+
+/// One retry codex announced, and how long it said it would wait first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    pub kind: RetryKind,
+    pub wait: Option<std::time::Duration>,
+}
+
+/// Recognise a stream `error` event that only announces a retry, such as
+/// `stream error: ...; retrying 2/5 in 1.2s…` or `Reconnecting... 2/5`.
+/// Returns None for errors codex doesn't recover from.
+pub fn classify_retry(message: &str) -> Option<Retry> {
+    static ANNOUNCE: OnceLock<Regex> = OnceLock::new();
+    static WAIT: OnceLock<Regex> = OnceLock::new();
+    let announce = ANNOUNCE.get_or_init(|| Regex::new(r"(?i)\b(retrying|reconnecting\.*)\s+\d+/\d+").unwrap());
+    if !announce.is_match(message) {
+        return None;
+    }
+    let lower = message.to_lowercase();
+    let kind = if lower.contains("429") || lower.contains("rate limit") || lower.contains("too many requests") {
+        RetryKind::RateLimit
+    } else {
+        RetryKind::Network
+    };
+    // The delay is a Rust `Duration` debug string: `200ms`, `1.5s`, ...
+    let wait = WAIT.get_or_init(|| Regex::new(r"\bin (\d+(?:\.\d+)?)(ms|s)\b").unwrap());
+    let wait = wait.captures(message).and_then(|c| {
+        let n: f64 = c[1].parse().ok()?;
+        let secs = if &c[2] == "ms" { n / 1000.0 } else { n };
+        Some(std::time::Duration::from_secs_f64(secs))
+    });
+    Some(Retry { kind, wait })
+}
+
+/// `2 rate-limit retries, 1 network retry (total 38s waiting)`, or None
+/// when codex didn't retry anything.
+pub fn describe_retries(retries: &[Retry]) -> Option<String> {
+    if retries.is_empty() {
+        return None;
+    }
+    let count = |kind| retries.iter().filter(|r| r.kind == kind).count();
+    let mut parts = Vec::new();
+    for (kind, name) in [(RetryKind::RateLimit, "rate-limit"), (RetryKind::Network, "network")] {
+        match count(kind) {
+            0 => {}
+            1 => parts.push(format!("1 {} retry", name)),
+            n => parts.push(format!("{} {} retries", n, name)),
+        }
+    }
+    let mut line = parts.join(", ");
+    let waited: std::time::Duration = retries.iter().filter_map(|r| r.wait).sum();
+    if !waited.is_zero() {
+        let secs = waited.as_secs_f64().round() as u64;
+        line.push_str(&format!(" (total {}s waiting)", secs.max(1)));
+    }
+    Some(line)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -299,4 +372,31 @@ mod tests {
         assert_eq!(strip_ordinal_suffix("Apr 28, 2026"), "Apr 28, 2026");
         assert_eq!(strip_ordinal_suffix("5:32 PM"), "5:32 PM");
     }
+
+    #[test]
+    fn retry_announcements_are_classified() {
+        let retry = classify_retry("stream error: unexpected status 429 Too Many Requests; retrying 1/5 in 1.5s…").unwrap();
+        assert_eq!(retry, Retry { kind: RetryKind::RateLimit, wait: Some(std::time::Duration::from_millis(1500)) });
+        let retry = classify_retry("stream error: stream disconnected before completion; retrying 2/5 in 200ms…").unwrap();
+        assert_eq!(retry, Retry { kind: RetryKind::Network, wait: Some(std::time::Duration::from_millis(200)) });
+        assert_eq!(classify_retry("Reconnecting... 3/5").map(|r| (r.kind, r.wait)), Some((RetryKind::Network, None)));
+        assert_eq!(classify_retry("exceeded retry limit, last status: 429"), None);
+        assert_eq!(classify_retry("connection reset"), None);
+    }
+
+    #[test]
+    fn retries_are_summarised_in_one_line() {
+        let wait = |secs| Some(std::time::Duration::from_secs(secs));
+        let retries = [
+            Retry { kind: RetryKind::RateLimit, wait: wait(30) },
+            Retry { kind: RetryKind::Network, wait: wait(1) },
+            Retry { kind: RetryKind::RateLimit, wait: wait(7) },
+        ];
+        assert_eq!(
+            describe_retries(&retries).unwrap(),
+            "2 rate-limit retries, 1 network retry (total 38s waiting)"
+        );
+        assert_eq!(describe_retries(&[Retry { kind: RetryKind::Network, wait: None }]).unwrap(), "1 network retry");
+        assert_eq!(describe_retries(&[]), None);
+    }
 }
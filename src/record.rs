@@ -0,0 +1,365 @@
+//! `--record`: keep codex's raw JSON event stream.
+//!
+//! The stream of an agentic run gets large quickly, so a path ending in
+//! `.zst` is written zstd-compressed, by piping through the `zstd` command.
+//! Each attempt (a seat rotation, a `--retry-on-empty` nudge) is appended
+//! as its own zstd frame; `zstd -d` reads concatenated frames as one
+//! stream. Reading a recording back, as `--replay` does, goes through
+//! [`open`], which recognises compressed files by their magic number
+//! rather than their name.
+//!
+//! Beside a plain recording, `FILE.idx` lists where each line that starts
+//! a turn or completes an agent message begins, as `turn <offset>` and
+//! `message <offset>` lines; [`open_last_turn`] uses it to seek straight
+//! to the last turn of a long recording. A compressed recording can't be
+//! seeked into, so it has no index and is read through instead.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use anyhow::{Context, Result};
+
+/// The first bytes of every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How much of a line is kept to tell what kind of event it is; the
+/// `type` fields come first.
+const INDEX_HEAD_BYTES: usize = 256;
+
+/// Whether recordings to `path` are compressed.
+pub fn compresses(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+/// The index kept beside the plain recording at `path`.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".idx");
+    PathBuf::from(name)
+}
+
+/// Start an empty recording at `path`, dropping any old index.
+pub fn create(path: &Path) -> Result<()> {
+    File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    match std::fs::remove_file(index_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("removing {}", index_path(path).display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Where an attempt's raw stream goes: the file itself, with its index,
+/// or `zstd` compressing into it. Dropping it finishes the frame.
+pub enum Recorder {
+    Plain { file: File, index: Indexer },
+    Zstd { child: Child, stdin: Option<ChildStdin> },
+}
+
+impl Recorder {
+    /// Append to the recording at `path`, creating it if needed.
+    pub fn append(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        if !compresses(path) {
+            let offset = file.metadata().with_context(|| format!("reading {}", path.display()))?.len();
+            let index = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(index_path(path))
+                .with_context(|| format!("opening {}", index_path(path).display()))?;
+            return Ok(Self::Plain { file, index: Indexer::new(index, offset) });
+        }
+        let mut child = Command::new("zstd")
+            .args(["-q", "-c"])
+            .stdin(Stdio::piped())
+            .stdout(file)
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| zstd_error(e, path))?;
+        let stdin = child.stdin.take();
+        Ok(Self::Zstd { child, stdin })
+    }
+}
+
+fn zstd_error(e: io::Error, path: &Path) -> anyhow::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        anyhow::anyhow!("{} is zstd-compressed, which needs the `zstd` command; install it", path.display())
+    } else {
+        anyhow::Error::new(e).context("running zstd")
+    }
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain { file, index } => {
+                let n = file.write(buf)?;
+                index.saw(&buf[..n])?;
+                Ok(n)
+            }
+            Self::Zstd { stdin: Some(stdin), .. } => stdin.write(buf),
+            Self::Zstd { stdin: None, .. } => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain { file, index } => {
+                file.flush()?;
+                index.file.flush()
+            }
+            Self::Zstd { stdin: Some(stdin), .. } => stdin.flush(),
+            Self::Zstd { stdin: None, .. } => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Self::Zstd { child, stdin } = self {
+            // Closing zstd's input ends the frame; wait for it to be written.
+            drop(stdin.take());
+            match child.wait() {
+                Ok(status) if status.success() => {}
+                Ok(status) => eprintln!("Warning: zstd failed writing the recording ({})", status),
+                Err(e) => eprintln!("Warning: failed to wait for zstd: {}", e),
+            }
+        }
+    }
+}
+
+/// What an index line marks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    Turn,
+    Message,
+}
+
+impl Mark {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Turn => "turn",
+            Self::Message => "message",
+        }
+    }
+
+    /// The mark for a recorded line, going by its first bytes.
+    fn of(head: &[u8]) -> Option<Self> {
+        let head = String::from_utf8_lossy(head);
+        let head = head.replace(' ', "");
+        if head.starts_with(r#"{"type":"turn.started""#) {
+            Some(Self::Turn)
+        } else if head.starts_with(r#"{"type":"item.completed""#) && head.contains(r#""type":"agent_message""#) {
+            Some(Self::Message)
+        } else {
+            None
+        }
+    }
+}
+
+/// Notes the offset of each marked line as the recording is written.
+pub struct Indexer {
+    file: File,
+    /// Bytes of the recording so far.
+    offset: u64,
+    /// Where the line being written starts, and its first bytes.
+    line_start: u64,
+    head: Vec<u8>,
+}
+
+impl Indexer {
+    fn new(file: File, offset: u64) -> Self {
+        Self { file, offset, line_start: offset, head: Vec::new() }
+    }
+
+    fn saw(&mut self, buf: &[u8]) -> io::Result<()> {
+        for &b in buf {
+            if b == b'\n' {
+                if let Some(mark) = Mark::of(&self.head) {
+                    writeln!(self.file, "{} {}", mark.name(), self.line_start)?;
+                }
+                self.head.clear();
+                self.line_start = self.offset + 1;
+            } else if self.head.len() < INDEX_HEAD_BYTES {
+                self.head.push(b);
+            }
+            self.offset += 1;
+        }
+        Ok(())
+    }
+}
+
+/// The offsets in the index beside `path` that carry `mark`, or None
+/// when there is no index.
+pub fn read_index(path: &Path, mark: Mark) -> Result<Option<Vec<u64>>> {
+    let raw = match std::fs::read_to_string(index_path(path)) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", index_path(path).display())),
+    };
+    Ok(Some(
+        raw.lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(name, _)| *name == mark.name())
+            .filter_map(|(_, offset)| offset.parse().ok())
+            .collect(),
+    ))
+}
+
+/// Read the recording at `path` from the start of its last turn, after
+/// the lines that came before its first (codex's `thread.started`, which
+/// carries the session ID). All of it when it has no turns.
+pub fn open_last_turn(path: &Path) -> Result<Box<dyn Read + Send>> {
+    let indexed = read_index(path, Mark::Turn)?;
+    let size = std::fs::metadata(path).with_context(|| format!("reading {}", path.display()))?.len();
+    // An index can be missing, or stale when the recording was edited.
+    if let Some(turns) = indexed.filter(|t| t.last().is_some_and(|&last| last < size)) {
+        let (Some(&first), Some(&last)) = (turns.first(), turns.last()) else {
+            return open(path);
+        };
+        let mut file = File::open(path).with_context(|| format!("opening recording {}", path.display()))?;
+        let mut header = vec![0u8; first as usize];
+        file.read_exact(&mut header).with_context(|| format!("reading {}", path.display()))?;
+        file.seek(SeekFrom::Start(last)).with_context(|| format!("reading {}", path.display()))?;
+        return Ok(Box::new(Cursor::new(header).chain(file)));
+    }
+    let mut raw = Vec::new();
+    open(path)?.read_to_end(&mut raw).with_context(|| format!("reading {}", path.display()))?;
+    let turns = scan_turns(&raw);
+    let (Some(&first), Some(&last)) = (turns.first(), turns.last()) else {
+        return Ok(Box::new(Cursor::new(raw)));
+    };
+    let mut out = raw[..first as usize].to_vec();
+    out.extend_from_slice(&raw[last as usize..]);
+    Ok(Box::new(Cursor::new(out)))
+}
+
+/// Where each turn in `raw` starts, without an index.
+fn scan_turns(raw: &[u8]) -> Vec<u64> {
+    let mut turns = Vec::new();
+    let mut offset = 0u64;
+    let mut reader = BufReader::new(raw);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+        if Mark::of(&line[..line.len().min(INDEX_HEAD_BYTES)]) == Some(Mark::Turn) {
+            turns.push(offset);
+        }
+        offset += line.len() as u64;
+        line.clear();
+    }
+    turns
+}
+
+/// Read the recording at `path`, decompressing it if it is zstd.
+pub fn open(path: &Path) -> Result<Box<dyn Read + Send>> {
+    let mut file = File::open(path).with_context(|| format!("opening recording {}", path.display()))?;
+    let mut magic = [0u8; 4];
+    let n = read_up_to(&mut file, &mut magic).with_context(|| format!("reading {}", path.display()))?;
+    if magic[..n] != ZSTD_MAGIC {
+        let file = File::open(path).with_context(|| format!("opening recording {}", path.display()))?;
+        return Ok(Box::new(file));
+    }
+    let mut child = Command::new("zstd")
+        .args(["-d", "-c", "-q"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| zstd_error(e, path))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok(Box::new(Decompressed { child, stdout }))
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match file.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+/// `zstd -dc`'s output; a failed decompression is an error at the end.
+struct Decompressed {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for Decompressed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("zstd failed reading the recording ({})", status)));
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_recordings_read_back_as_they_are() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.jsonl");
+        assert!(!compresses(&path));
+        assert!(compresses(&dir.path().join("run.jsonl.zst")));
+        for line in ["{\"a\":1}\n", "{\"b\":2}\n"] {
+            Recorder::append(&path).unwrap().write_all(line.as_bytes()).unwrap();
+        }
+        let mut text = String::new();
+        open(&path).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "{\"a\":1}\n{\"b\":2}\n");
+    }
+
+    #[test]
+    fn the_index_finds_the_last_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.jsonl");
+        let thread = "{\"type\":\"thread.started\",\"thread_id\":\"t1\"}\n";
+        let turn = |n: u32| {
+            format!(
+                "{{\"type\":\"turn.started\"}}\n{{\"type\":\"item.completed\",\"item\":{{\"id\":\"i{}\",\"type\":\"agent_message\",\"text\":\"turn {}\"}}}}\n",
+                n, n
+            )
+        };
+        create(&path).unwrap();
+        // Written in pieces that split lines, then a second attempt.
+        let first = format!("{}{}", thread, turn(1));
+        let mut recorder = Recorder::append(&path).unwrap();
+        for piece in first.as_bytes().chunks(7) {
+            recorder.write_all(piece).unwrap();
+        }
+        drop(recorder);
+        Recorder::append(&path).unwrap().write_all(turn(2).as_bytes()).unwrap();
+
+        let second = (thread.len() + turn(1).len()) as u64;
+        assert_eq!(read_index(&path, Mark::Turn).unwrap(), Some(vec![thread.len() as u64, second]));
+        assert_eq!(read_index(&path, Mark::Message).unwrap().unwrap().len(), 2);
+        let mut text = String::new();
+        open_last_turn(&path).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, format!("{}{}", thread, turn(2)));
+
+        // Without the index the recording is scanned for the same answer.
+        std::fs::remove_file(index_path(&path)).unwrap();
+        let mut scanned = String::new();
+        open_last_turn(&path).unwrap().read_to_string(&mut scanned).unwrap();
+        assert_eq!(scanned, text);
+
+        create(&path).unwrap();
+        assert_eq!(read_index(&path, Mark::Turn).unwrap(), None);
+    }
+}
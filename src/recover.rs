@@ -0,0 +1,289 @@
+//! Keeping a run's answer safe from codex-clean itself crashing.
+//!
+//! While codex runs, the agent messages received so far are written every
+//! second or so to `~/.config/codex-clean/recovery/<run-id>.json`, and the
+//! file is removed once the answer has been printed. A file left behind
+//! means codex-clean died (a panic, the OOM killer, a closed terminal)
+//! before it could show what it had; `codex-clean recover` prints it.
+
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::control;
+use crate::output::CodexOutput;
+use crate::seat::{atomic_write, config_dir};
+
+/// How often new messages are written out, at most.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Characters of the prompt kept to tell runs apart.
+const PROMPT_PREVIEW_CHARS: usize = 60;
+
+/// `~/.config/codex-clean/recovery/`.
+pub fn recovery_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("recovery"))
+}
+
+/// What a recovery file holds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Saved {
+    pub run_id: String,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The first line of the prompt, shortened.
+    #[serde(default)]
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub messages: Vec<String>,
+}
+
+/// The recovery file of a run in progress, written out by a thread of its
+/// own so that the last messages before codex goes quiet aren't held back.
+#[derive(Debug)]
+pub struct Recovery {
+    shared: Arc<Shared>,
+    flusher: Option<(mpsc::Sender<()>, JoinHandle<()>)>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    saved: Saved,
+    /// Changed since it was last written.
+    dirty: bool,
+}
+
+impl Recovery {
+    /// Start the recovery file for run `run_id`, in `dir`.
+    pub fn start_in(dir: &Path, run_id: &str, prompt: &str) -> Result<Self> {
+        Self::start_every(dir, run_id, prompt, FLUSH_INTERVAL)
+    }
+
+    /// [`Self::start_in`] the usual directory.
+    pub fn start(run_id: &str, prompt: &str) -> Result<Self> {
+        Self::start_in(&recovery_dir()?, run_id, prompt)
+    }
+
+    fn start_every(dir: &Path, run_id: &str, prompt: &str, every: Duration) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let now = Utc::now();
+        let saved = Saved {
+            run_id: run_id.to_string(),
+            pid: std::process::id(),
+            started_at: now,
+            updated_at: now,
+            prompt: preview(prompt),
+            session_id: None,
+            messages: Vec::new(),
+        };
+        // Nothing is lost if this first, empty file doesn't survive a power
+        // cut, so it skips atomic_write's fsync, which would delay codex.
+        let path = dir.join(format!("{}.json", run_id));
+        let raw = serde_json::to_string(&saved).context("serialising the recovery file")?;
+        std::fs::write(&path, raw).with_context(|| format!("writing {}", path.display()))?;
+        let shared = Arc::new(Shared { path, state: Mutex::new(State { saved, dirty: false }) });
+        let (done, stop) = mpsc::channel::<()>();
+        let flushing = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stop.recv_timeout(every) {
+                // Losing the safety net isn't worth stopping the run for.
+                if let Err(e) = flushing.flush() {
+                    eprintln!("Warning: failed to update {}: {:#}", flushing.path.display(), e);
+                }
+            }
+        });
+        Ok(Self { shared, flusher: Some((done, handle)) })
+    }
+
+    /// Note the attempt's output so far; it is written out within a
+    /// second, or at once when `now` is set.
+    pub fn update(&self, output: &CodexOutput, now: bool) {
+        {
+            let mut state = self.shared.lock();
+            if state.saved.messages != output.messages || state.saved.session_id != output.session_id {
+                state.saved.messages.clone_from(&output.messages);
+                state.saved.session_id.clone_from(&output.session_id);
+                state.saved.updated_at = Utc::now();
+                state.dirty = true;
+            }
+        }
+        if now {
+            if let Err(e) = self.shared.flush() {
+                eprintln!("Warning: failed to update {}: {:#}", self.shared.path.display(), e);
+            }
+        }
+    }
+
+    /// The answer has been shown; the file is no longer needed.
+    pub fn finish(mut self) {
+        if let Some((done, handle)) = self.flusher.take() {
+            let _ = done.send(());
+            let _ = handle.join();
+        }
+        match std::fs::remove_file(&self.shared.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                eprintln!("Warning: failed to remove {}: {}", self.shared.path.display(), e)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write the file if it has changed.
+    fn flush(&self) -> Result<()> {
+        let mut state = self.lock();
+        if !state.dirty {
+            return Ok(());
+        }
+        let raw = serde_json::to_string(&state.saved).context("serialising the recovery file")?;
+        atomic_write(&self.path, raw.as_bytes())?;
+        state.dirty = false;
+        Ok(())
+    }
+}
+
+fn preview(prompt: &str) -> String {
+    let line = prompt.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    let mut out: String = line.chars().take(PROMPT_PREVIEW_CHARS).collect();
+    if line.chars().count() > PROMPT_PREVIEW_CHARS {
+        out.push_str("...");
+    }
+    out
+}
+
+/// Every recovery file in `dir`, oldest first. Unreadable ones are skipped.
+pub fn saved_in(dir: &Path) -> Vec<Saved> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut saved: Vec<Saved> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|raw| serde_json::from_str(&raw).ok())
+        .collect();
+    saved.sort_by_key(|s| s.started_at);
+    saved
+}
+
+/// Whether the run is still going, so its file isn't a leftover.
+fn in_progress(run_id: &str) -> bool {
+    control::request(run_id, "info").is_ok()
+}
+
+/// `codex-clean recover`: print what run `run_id` (default: the most
+/// recent one that isn't still going) had received, or with `list` show
+/// every run that left a file. `delete` removes the file afterwards.
+pub fn run(run_id: Option<&str>, list: bool, delete: bool) -> Result<()> {
+    let dir = recovery_dir()?;
+    let saved = saved_in(&dir);
+    if list {
+        if saved.is_empty() {
+            eprintln!("Nothing to recover.");
+            return Ok(());
+        }
+        println!("{:<20} {:<16} {:>8}  PROMPT", "RUN ID", "STARTED", "MESSAGES");
+        for s in &saved {
+            let running = if in_progress(&s.run_id) { " (running)" } else { "" };
+            println!(
+                "{:<20} {:<16} {:>8}  {}{}",
+                s.run_id,
+                s.started_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                s.messages.len(),
+                s.prompt,
+                running
+            );
+        }
+        return Ok(());
+    }
+    let found = match run_id {
+        Some(id) => saved.iter().find(|s| s.run_id == id),
+        None => saved.iter().rev().find(|s| !in_progress(&s.run_id)),
+    };
+    let Some(found) = found else {
+        match run_id {
+            Some(id) => bail!("no recovery file for run {}", id),
+            None => bail!("nothing to recover"),
+        }
+    };
+    eprintln!("{}", describe(found));
+    if !found.messages.is_empty() {
+        println!("{}", found.messages.join("\n"));
+    }
+    if delete {
+        let path = dir.join(format!("{}.json", found.run_id));
+        std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The line printed above a recovered answer.
+fn describe(saved: &Saved) -> String {
+    let mut line = format!(
+        "Run {} (started {}, last saved {}): {} message(s)",
+        saved.run_id,
+        saved.started_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+        saved.updated_at.with_timezone(&Local).format("%H:%M:%S"),
+        saved.messages.len()
+    );
+    if let Some(session) = &saved.session_id {
+        line.push_str(&format!("; resume it with `codex-clean resume {}`", session));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_saved_until_the_run_finishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let every = Duration::from_millis(10);
+        let recovery = Recovery::start_every(dir.path(), "r1", "\n  Fix the parser\nthen the tests", every).unwrap();
+        let file = dir.path().join("r1.json");
+        assert!(file.exists());
+
+        let mut output = CodexOutput {
+            session_id: Some("s1".into()),
+            messages: vec!["first".into()],
+            ..Default::default()
+        };
+        recovery.update(&output, true);
+        assert_eq!(saved_in(dir.path())[0].messages, ["first"]);
+        output.messages.push("second".into());
+        recovery.update(&output, false);
+
+        // The flusher writes it out shortly.
+        thread::sleep(every * 20);
+        let saved = saved_in(dir.path());
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].messages, ["first", "second"]);
+        assert_eq!(saved[0].session_id.as_deref(), Some("s1"));
+        assert_eq!(saved[0].prompt, "Fix the parser");
+        assert!(describe(&saved[0]).ends_with("2 message(s); resume it with `codex-clean resume s1`"));
+
+        recovery.finish();
+        assert!(!file.exists());
+        assert!(saved_in(dir.path()).is_empty());
+    }
+}
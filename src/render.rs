@@ -0,0 +1,325 @@
+//! Output formats for a finished run, chosen by name with `--output
+//! FORMAT PATH`.
+//!
+//! Each format is a [`Renderer`] in a [`Renderers`] map, so a new one is
+//! added by registering it rather than by touching the others. The
+//! built-in ones are `text` (what codex-clean prints), `json` (the
+//! `--stdin-json` result), `markdown`, `html` (a page to share),
+//! `github` (GitHub Actions workflow commands) and `template`, which fills
+//! `{{placeholders}}` in a template of the user's. A program embedding
+//! codex-clean can register its own and pass them in
+//! [`RunOptions::outputs`](crate::runner::RunOptions::outputs).
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::export;
+use crate::output::{format_usage, CodexOutput, RenderOptions};
+use crate::runner::RunOutcome;
+use crate::templates;
+
+/// What a renderer is given: the finished run.
+#[derive(Clone, Copy)]
+pub struct RunView<'a> {
+    /// The prompt as sent, after any prompt wrapping.
+    pub prompt: &'a str,
+    pub outcome: &'a RunOutcome,
+    pub output: &'a CodexOutput,
+    pub options: &'a RenderOptions,
+}
+
+/// Turns a finished run into one document.
+pub trait Renderer: Debug + Send + Sync {
+    fn render(&self, run: &RunView<'_>) -> String;
+}
+
+/// `--output FORMAT PATH`: render the run with `renderer` into `path`.
+#[derive(Debug, Clone)]
+pub struct OutputFile {
+    pub format: String,
+    pub renderer: Arc<dyn Renderer>,
+    pub path: PathBuf,
+}
+
+impl OutputFile {
+    pub fn write(&self, run: &RunView<'_>) -> Result<()> {
+        fs::write(&self.path, self.renderer.render(run)).with_context(|| format!("writing {}", self.path.display()))
+    }
+}
+
+/// The formats known by name.
+#[derive(Debug, Clone)]
+pub struct Renderers {
+    by_name: BTreeMap<String, Arc<dyn Renderer>>,
+}
+
+impl Default for Renderers {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Renderers {
+    /// The built-in formats, except `template`, which needs a template
+    /// (see [`Template`]).
+    pub fn builtin() -> Self {
+        let mut renderers = Self { by_name: BTreeMap::new() };
+        renderers.register("text", Text);
+        renderers.register("json", Json);
+        renderers.register("markdown", Markdown);
+        renderers.register("html", Html);
+        renderers.register("github", Github);
+        renderers
+    }
+
+    /// Add `renderer` as `name`, replacing any format of that name.
+    pub fn register(&mut self, name: &str, renderer: impl Renderer + 'static) {
+        self.by_name.insert(name.to_string(), Arc::new(renderer));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Renderer>> {
+        self.by_name.get(name).cloned()
+    }
+
+    /// Every name, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.by_name.keys().map(String::as_str)
+    }
+
+    /// The `--output` for `format` to `path`; unknown formats are an error
+    /// naming the known ones.
+    pub fn output_file(&self, format: &str, path: &Path) -> Result<OutputFile> {
+        let renderer = self.get(format).ok_or_else(|| {
+            let known: Vec<&str> = self.names().collect();
+            let hint = if format == "template" { "; `template` needs --output-template FILE" } else { "" };
+            anyhow!("unsupported --output format '{}' (expected one of: {}){}", format, known.join(", "), hint)
+        })?;
+        Ok(OutputFile { format: format.to_string(), renderer, path: path.to_path_buf() })
+    }
+}
+
+/// What codex-clean prints to stdout, without the warnings and errors it
+/// prints to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Text;
+
+impl Renderer for Text {
+    fn render(&self, run: &RunView<'_>) -> String {
+        run.output.render_with(run.options).stdout
+    }
+}
+
+/// The result object `--stdin-json` prints, on one line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Renderer for Json {
+    fn render(&self, run: &RunView<'_>) -> String {
+        format!("{}\n", run.outcome.to_json())
+    }
+}
+
+/// The prompt, the answer as it is, and what codex ran and changed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Markdown;
+
+impl Renderer for Markdown {
+    fn render(&self, run: &RunView<'_>) -> String {
+        let output = run.output;
+        let mut out = match &output.session_id {
+            Some(id) => format!("# codex run {}\n\n", id),
+            None => "# codex run\n\n".to_string(),
+        };
+        let _ = writeln!(out, "- Exit code: {}", run.outcome.exit_code);
+        if let Some(usage) = output.usage {
+            let _ = writeln!(out, "- {}", format_usage(usage));
+        }
+        out.push_str("\n## Prompt\n\n");
+        for line in run.prompt.trim_end().lines() {
+            out.push_str(if line.is_empty() { ">" } else { "> " });
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push_str("\n## Response\n\n");
+        let message = output.aggregated_message();
+        match message.trim_end() {
+            "" => out.push_str("_No response._\n"),
+            message => {
+                let _ = writeln!(out, "{}", message);
+            }
+        }
+        if !output.errors.is_empty() {
+            out.push_str("\n## Errors\n\n");
+            for err in &output.errors {
+                let _ = writeln!(out, "- {}", err);
+            }
+        }
+        if !output.commands.is_empty() {
+            out.push_str("\n## Commands\n\n| Command | Exit |\n| --- | --- |\n");
+            for command in &output.commands {
+                let code = command.exit_code.map_or("–".to_string(), |c| c.to_string());
+                let _ = writeln!(out, "| `{}` | {} |", command.command.replace('|', "\\|"), code);
+            }
+        }
+        if !output.file_changes.is_empty() {
+            out.push_str("\n## File changes\n\n");
+            for change in &output.file_changes {
+                let _ = writeln!(out, "- {} `{}`", change.kind, change.path);
+            }
+        }
+        out
+    }
+}
+
+/// A self-contained page (see [`export::render_html`]).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Html;
+
+impl Renderer for Html {
+    fn render(&self, run: &RunView<'_>) -> String {
+        export::render_html(run.prompt, run.output, run.outcome.exit_code)
+    }
+}
+
+/// GitHub Actions workflow commands: codex's errors and codex-clean's
+/// warnings as annotations, the answer in a collapsed group, and the usage
+/// as a notice.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Github;
+
+/// A workflow command's message, with the characters GitHub gives a
+/// meaning to escaped.
+fn workflow_message(text: &str) -> String {
+    text.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+impl Renderer for Github {
+    fn render(&self, run: &RunView<'_>) -> String {
+        let output = run.output;
+        let mut out = String::new();
+        for err in &output.errors {
+            let _ = writeln!(out, "::error title=codex::{}", workflow_message(err));
+        }
+        for warning in output.warnings() {
+            let _ = writeln!(out, "::warning title=codex-clean::{}", workflow_message(&warning));
+        }
+        let title = match &output.session_id {
+            Some(id) => format!("codex answer (session {})", id),
+            None => "codex answer".to_string(),
+        };
+        let _ = writeln!(out, "::group::{}", workflow_message(&title));
+        let message = output.aggregated_message();
+        // A line of the answer that looks like a workflow command would be
+        // run as one; GitHub skips lines between these markers.
+        let _ = writeln!(out, "::stop-commands::codex-clean-answer\n{}\n::codex-clean-answer::", message.trim_end());
+        out.push_str("::endgroup::\n");
+        if let Some(usage) = output.usage {
+            let _ = writeln!(out, "::notice title=codex usage::{}", workflow_message(&format_usage(usage)));
+        }
+        if run.outcome.exit_code != 0 && output.errors.is_empty() {
+            let _ = writeln!(out, "::error title=codex::codex-clean exited with code {}", run.outcome.exit_code);
+        }
+        out
+    }
+}
+
+/// The user's own format: a template whose `{{message}}`,
+/// `{{session_id}}`, `{{exit_code}}`, `{{prompt}}`, `{{usage}}`,
+/// `{{input_tokens}}`, `{{output_tokens}}`, `{{branch}}`, `{{errors}}` and
+/// `{{commands}}` are filled in (see [`templates::expand`]).
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    pub template: String,
+}
+
+impl Template {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self { template: template.into() }
+    }
+}
+
+impl Renderer for Template {
+    fn render(&self, run: &RunView<'_>) -> String {
+        let output = run.output;
+        let usage = output.usage;
+        let vars: Vec<(String, String)> = [
+            ("message", output.aggregated_message()),
+            ("session_id", output.session_id.clone().unwrap_or_default()),
+            ("exit_code", run.outcome.exit_code.to_string()),
+            ("prompt", run.prompt.to_string()),
+            ("usage", usage.map(format_usage).unwrap_or_default()),
+            ("input_tokens", usage.map(|u| u.0.to_string()).unwrap_or_default()),
+            ("output_tokens", usage.map(|u| u.2.to_string()).unwrap_or_default()),
+            ("branch", run.outcome.branch.clone().unwrap_or_default()),
+            ("errors", output.errors.join("\n")),
+            ("commands", output.commands.iter().map(|c| c.command.as_str()).collect::<Vec<_>>().join("\n")),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        templates::expand(&self.template, &vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::CommandRun;
+    use crate::runner::AttemptResult;
+
+    fn outcome() -> RunOutcome {
+        let output = CodexOutput {
+            session_id: Some("s-1".into()),
+            messages: vec!["Fixed it.\n::error::not really".into()],
+            usage: Some((10, 0, 5, 0)),
+            commands: vec![CommandRun { command: "cargo test".into(), exit_code: Some(0), output: None }],
+            ..Default::default()
+        };
+        let attempt = AttemptResult {
+            output,
+            stderr_buffer: Vec::new(),
+            stderr_truncated: false,
+            stderr_error: None,
+            exit_code: 0,
+            status_success: true,
+            child_exit: 0,
+            timed_out: None,
+            cancelled: false,
+            stopped_early: false,
+            output_limited: None,
+            timings: Default::default(),
+        };
+        RunOutcome { exit_code: 0, attempt: Some(attempt), branch: None }
+    }
+
+    #[test]
+    fn formats_are_found_by_name() {
+        let mut renderers = Renderers::builtin();
+        assert_eq!(renderers.names().collect::<Vec<_>>(), ["github", "html", "json", "markdown", "text"]);
+        let err = renderers.output_file("template", Path::new("x")).unwrap_err().to_string();
+        assert!(err.contains("expected one of: github, html, json, markdown, text") && err.contains("--output-template"), "{}", err);
+
+        renderers.register("template", Template::new("{{session_id}}: {{message}} ({{exit_code}}, {{output_tokens}} out) {{other}}"));
+        let outcome = outcome();
+        let run = RunView {
+            prompt: "fix it",
+            outcome: &outcome,
+            output: &outcome.attempt.as_ref().unwrap().output,
+            options: &RenderOptions::default(),
+        };
+        let render = |name: &str| renderers.get(name).unwrap().render(&run);
+        assert_eq!(render("template"), "s-1: Fixed it.\n::error::not really (0, 5 out) {{other}}");
+        assert!(render("text").starts_with("Session: s-1\n\nFixed it."));
+        assert!(render("json").starts_with(r#"{"errors":[],"exit_code":0,"message":"Fixed it."#));
+        assert!(render("markdown").contains("## Prompt\n\n> fix it\n\n## Response\n\nFixed it.\n"));
+        assert!(render("markdown").contains("| `cargo test` | 0 |"));
+        let github = render("github");
+        assert!(github.contains("::group::codex answer (session s-1)\n::stop-commands::codex-clean-answer\nFixed it.\n::error::not really\n::codex-clean-answer::\n::endgroup::\n"), "{}", github);
+        assert!(github.ends_with("::notice title=codex usage::Tokens: 10 input (0 cached), 5 output\n"), "{}", github);
+    }
+}
@@ -1,22 +1,61 @@
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::OpenOptions;
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use chrono::{Local, Utc};
+use regex::Regex;
 
-use crate::events::{extract_event, Event};
-use crate::output::CodexOutput;
+use crate::archive::{Artifacts, GitChanges, WrittenFile};
+use crate::backend::{CodexBackend, CodexProcess, LocalBackend, ProcessExit, SpawnOptions};
+use crate::command_log;
+use crate::compat::{self, Compat};
+use crate::config::{Hooks, PromptWrap};
+use crate::confirm;
+use crate::content;
+use crate::continuation::{self, CutOff};
+use crate::diagrams::{self, Protocol};
+use crate::control::{self, Cancel, CancelHandle, ControlSocket, CANCELLED_EXIT_CODE};
+use crate::error::Error;
+use crate::events::{decode_parallel, EventStream, ParseError};
+use crate::git;
+use crate::judge::{self, Judgement};
+use crate::metrics::{Counters, Metrics};
+use crate::notebook;
+use crate::observer::{self, EventObserver};
+use crate::output::{CodexOutput, RenderOptions};
+use crate::preprocess::Pipeline;
+use crate::preview;
+use crate::proto::{self, ProtoProcess, Transport};
 use crate::ratelimit::{self, FailureKind};
+use crate::record::Recorder;
+use crate::recover::Recovery;
+use crate::render::{OutputFile, RunView};
+use crate::safety;
+use crate::sessions;
+use crate::startup;
+use crate::stats::{self, RunReport, StageTimings};
+use crate::usage;
+use crate::window::Pressure;
 use crate::seat::{
     self, refresh_back, swap_active_auth, unmatched_log_path, CodexLock, SeatConfig,
     SeatPickError, SeatState,
 };
+use crate::write_files;
 
 const STDERR_CAP_BYTES: usize = 10 * 1024 * 1024;
 
+/// Exit code when codex is killed for exceeding `--timeout`, matching
+/// coreutils `timeout(1)`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
 /// Env vars we strip from the codex child process so the active seat's
 /// auth.json is the only thing in scope. `CODEX_HOME` is *not* on this list:
 /// we honour the user's setting and use it as the swap target.
@@ -47,6 +86,158 @@ pub enum Mode {
     Review,
 }
 
+/// Per-run options that aren't codex arguments (set by flags or a profile).
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Kill codex if it runs longer than this.
+    pub timeout: Option<Duration>,
+    pub prompt_wrap: PromptWrap,
+    /// What the prompt goes through before it is sent; `None` for
+    /// [`Pipeline::standard`] with `prompt_wrap`.
+    pub preprocessors: Option<Pipeline>,
+    pub hooks: Hooks,
+    pub backend: Arc<dyn CodexBackend>,
+    /// Name for `codex-clean cancel`; generated when not given.
+    pub run_id: Option<String>,
+    /// Print a progress line to stderr this often while codex runs.
+    pub heartbeat: Option<Duration>,
+    /// Print a JSON progress object to stderr this often while codex runs,
+    /// and when it starts and exits (`--progress-json`).
+    pub progress_json: Option<Duration>,
+    /// Stop codex as soon as its first agent message arrives.
+    pub first_message_only: bool,
+    /// Stop codex as soon as its agent messages so far match this.
+    pub stop_on: Option<Regex>,
+    /// Stop codex once its agent messages pass this many bytes.
+    pub max_output_bytes: Option<usize>,
+    /// On passing `max_output_bytes`, keep the output up to the limit
+    /// (marked as cut) instead of failing the run.
+    pub truncate_overflow: bool,
+    /// How the text output is rendered (JSON output is unaffected).
+    pub render: RenderOptions,
+    /// Exit 1 instead of 0 when the output has warnings.
+    pub warnings_as_errors: bool,
+    /// Also write the run in these formats (`--output`; see
+    /// [`crate::render`]).
+    pub outputs: Vec<OutputFile>,
+    /// Also save each shell command's full output in this directory
+    /// (see [`crate::command_log`]).
+    pub command_log: Option<PathBuf>,
+    /// Append the prompt and answer to this markdown notebook (see
+    /// [`crate::notebook`]).
+    pub notebook: Option<PathBuf>,
+    /// Render the answer's Mermaid and Graphviz blocks to PNGs in this
+    /// directory (see [`crate::diagrams`]).
+    pub render_diagrams: Option<PathBuf>,
+    /// The profile in effect, recorded in the run's report.
+    pub profile: Option<String>,
+    /// `exec --json` or `codex proto`.
+    pub transport: Transport,
+    /// Told about events as they arrive (see [`crate::observer`]).
+    pub observer: Option<Arc<dyn EventObserver>>,
+    /// The config's `[counters]`, kept in the run report (see
+    /// [`crate::metrics`]).
+    pub counters: Counters,
+    /// The config's `[context_windows]`, for the context window warning
+    /// (see [`crate::window`]).
+    pub context_windows: BTreeMap<String, u64>,
+    /// The config's `session_max_age_days`, for the stale-session warning
+    /// before a resume (see [`crate::expiry`]); `None` skips the check.
+    pub session_max_age: Option<Duration>,
+    /// Lets the caller cancel the run; `codex-clean cancel` works either way.
+    pub cancel: Option<CancelHandle>,
+    /// Print the stage timings to stderr once the run is over.
+    pub print_timings: bool,
+    /// When codex finishes a turn without saying anything, resume the
+    /// session with `nudge_prompt` up to this many times.
+    pub retry_on_empty: usize,
+    pub nudge_prompt: String,
+    /// When the answer was cut off (see [`crate::continuation`]), resume
+    /// the session with `continue_prompt` up to this many times and join
+    /// the pieces.
+    pub auto_continue: usize,
+    pub continue_prompt: String,
+    /// Snapshot the git working tree first and summarise what codex
+    /// changed in it afterwards (see [`crate::git`]).
+    pub git_snapshot: bool,
+    /// Snapshot as for `git_snapshot`, and put the tree back if the run fails.
+    pub git_revert_on_failure: bool,
+    /// Switch to this new git branch (`auto` to name it after the prompt)
+    /// before codex starts.
+    pub branch: Option<String>,
+    /// Write the files the answer marks out (see [`crate::write_files`]).
+    pub write_files: bool,
+    /// Let `write_files` replace files that already exist.
+    pub overwrite_files: bool,
+    /// Preview each file `write_files` would write and ask first (see
+    /// [`crate::confirm`]).
+    pub confirm_writes: bool,
+    /// Criteria to grade a successful answer against (see [`crate::judge`]).
+    pub judge: Option<String>,
+    /// Save codex's raw JSON event stream, every attempt of it, to this
+    /// file (see [`crate::record`]).
+    pub record: Option<PathBuf>,
+    /// Also write the run report here, besides the reports directory.
+    pub report_path: Option<PathBuf>,
+    /// Write the answer, unrendered, to this file once the run is over; it
+    /// is created (empty) however the run ends.
+    pub last_message_file: Option<PathBuf>,
+    /// Show the prompt as it will be sent and ask before starting codex
+    /// (see [`crate::preview`]).
+    pub preview: bool,
+}
+
+/// What `--retry-on-empty` sends unless `--nudge-prompt` says otherwise.
+pub const DEFAULT_NUDGE_PROMPT: &str = "Please provide your answer.";
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            prompt_wrap: PromptWrap::default(),
+            preprocessors: None,
+            hooks: Hooks::default(),
+            backend: Arc::new(LocalBackend),
+            run_id: None,
+            heartbeat: None,
+            progress_json: None,
+            first_message_only: false,
+            stop_on: None,
+            max_output_bytes: None,
+            truncate_overflow: false,
+            render: RenderOptions::default(),
+            warnings_as_errors: false,
+            outputs: Vec::new(),
+            command_log: None,
+            notebook: None,
+            render_diagrams: None,
+            profile: None,
+            transport: Transport::Exec,
+            observer: None,
+            counters: Counters::default(),
+            context_windows: BTreeMap::new(),
+            session_max_age: None,
+            cancel: None,
+            print_timings: false,
+            retry_on_empty: 0,
+            nudge_prompt: DEFAULT_NUDGE_PROMPT.to_string(),
+            auto_continue: 0,
+            continue_prompt: continuation::DEFAULT_CONTINUE_PROMPT.to_string(),
+            git_snapshot: false,
+            git_revert_on_failure: false,
+            branch: None,
+            write_files: false,
+            overwrite_files: false,
+            confirm_writes: false,
+            judge: None,
+            record: None,
+            report_path: None,
+            last_message_file: None,
+            preview: false,
+        }
+    }
+}
+
 /// Result of a single codex invocation, captured but not yet printed.
 pub struct AttemptResult {
     pub output: CodexOutput,
@@ -57,30 +248,720 @@ pub struct AttemptResult {
     pub exit_code: i32,
     pub status_success: bool,
     pub child_exit: i32,
+    /// Set when codex was killed for running past this timeout.
+    pub timed_out: Option<Duration>,
+    /// Set when codex was killed by `codex-clean cancel`.
+    pub cancelled: bool,
+    /// Set when codex was stopped on purpose: after its first message
+    /// (`--first`), on a `--stop-on` match, or for passing
+    /// `--max-output-bytes`.
+    pub stopped_early: bool,
+    /// The `--max-output-bytes` limit, when codex's output passed it.
+    pub output_limited: Option<usize>,
+    /// How long codex took to reach each stage of the attempt.
+    pub timings: StageTimings,
+}
+
+/// What a run ended with: the exit code to return and the attempt to
+/// surface to the user (absent when e.g. no seat was available to try).
+pub struct RunOutcome {
+    pub exit_code: i32,
+    pub attempt: Option<AttemptResult>,
+    /// The `--branch` the run was moved onto.
+    pub branch: Option<String>,
+}
+
+impl RunOutcome {
+    fn code(exit_code: i32) -> Self {
+        Self { exit_code, attempt: None, branch: None }
+    }
+
+    /// Surface `attempt` as the final result and take its exit code.
+    fn surface(attempt: AttemptResult) -> Self {
+        Self { exit_code: attempt.exit_code, attempt: Some(attempt), branch: None }
+    }
+
+    fn has_warnings(&self) -> bool {
+        self.attempt.as_ref().is_some_and(|a| !a.output.warnings().is_empty())
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.attempt.as_ref()?.output.session_id.as_deref()
+    }
+
+    /// The session of a turn that succeeded without a single agent
+    /// message, for `--retry-on-empty`.
+    fn unanswered_session(&self) -> Option<String> {
+        let attempt = self.attempt.as_ref()?;
+        if self.exit_code != 0 || attempt.stopped_early || !attempt.output.messages.is_empty() {
+            return None;
+        }
+        attempt.output.session_id.clone()
+    }
+
+    /// The session of an answer that was cut off, for `--auto-continue`,
+    /// and why it looks cut off. A run that failed only counts when it
+    /// failed for the cut.
+    fn cut_off_session(&self) -> Option<(String, CutOff)> {
+        let attempt = self.attempt.as_ref()?;
+        if attempt.stopped_early || attempt.cancelled || attempt.timed_out.is_some() {
+            return None;
+        }
+        let cut = continuation::detect(&attempt.output)?;
+        if self.exit_code != 0 && cut != CutOff::OutputLimit {
+            return None;
+        }
+        Some((attempt.output.session_id.clone()?, cut))
+    }
+
+    /// Take `next`, the continuation of this answer, as the outcome, with
+    /// the answer and what codex did so far joined onto it.
+    fn continued_by(self, mut next: RunOutcome) -> RunOutcome {
+        let (Some(prev), Some(attempt)) = (self.attempt, next.attempt.as_mut()) else {
+            return next;
+        };
+        let prev = prev.output;
+        let output = &mut attempt.output;
+        output.messages = vec![continuation::stitch(&prev.aggregated_message(), &output.aggregated_message())];
+        output.commands.splice(0..0, prev.commands);
+        output.file_changes.splice(0..0, prev.file_changes);
+        next
+    }
+
+    /// The default presentation: codex-clean's text output.
+    pub fn print(&self) {
+        self.print_with(&RenderOptions::default());
+    }
+
+    pub fn print_with(&self, render: &RenderOptions) {
+        if let Some(attempt) = &self.attempt {
+            match &self.branch {
+                Some(branch) => {
+                    let render = RenderOptions { branch: Some(branch.clone()), ..render.clone() };
+                    print_attempt_with(attempt, &render);
+                }
+                None => print_attempt_with(attempt, render),
+            }
+        }
+    }
+
+    /// Machine-readable summary of the run, for `--stdin-json`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let Some(attempt) = &self.attempt else {
+            return serde_json::json!({ "exit_code": self.exit_code });
+        };
+        let output = &attempt.output;
+        let mut v = serde_json::json!({
+            "session_id": output.session_id,
+            "message": output.aggregated_message(),
+            "messages": output.messages,
+            "errors": output.errors,
+            "exit_code": self.exit_code,
+        });
+        if let Some((input, cached, out, reasoning)) = output.usage {
+            v["usage"] = serde_json::json!({
+                "input_tokens": input,
+                "cached_input_tokens": cached,
+                "output_tokens": out,
+                "reasoning_output_tokens": reasoning,
+            });
+        }
+        if let Some(branch) = &self.branch {
+            v["branch"] = branch.as_str().into();
+        }
+        if attempt.timings != StageTimings::default() {
+            v["timings"] = serde_json::to_value(attempt.timings).unwrap_or_default();
+        }
+        if !output.retries.is_empty() {
+            let count = |kind| output.retries.iter().filter(|r| r.kind == kind).count();
+            let waited: Duration = output.retries.iter().filter_map(|r| r.wait).sum();
+            v["retries"] = serde_json::json!({
+                "rate_limit": count(ratelimit::RetryKind::RateLimit),
+                "network": count(ratelimit::RetryKind::Network),
+                "waited_seconds": waited.as_secs_f64(),
+            });
+        }
+        if attempt.cancelled {
+            v["cancelled"] = true.into();
+        }
+        if attempt.stopped_early {
+            v["stopped_early"] = true.into();
+        }
+        if let Some(limit) = attempt.output_limited {
+            v["output_limit_bytes"] = limit.into();
+        }
+        if let Some(limit) = attempt.timed_out {
+            v["timed_out_after_seconds"] = limit.as_secs().into();
+        }
+        if !attempt.status_success && !attempt.stderr_buffer.is_empty() {
+            v["stderr"] = String::from_utf8_lossy(&attempt.stderr_buffer).into_owned().into();
+        }
+        v
+    }
 }
 
 /// Run codex with the given arguments and prompt. Drives the multi-seat
 /// orchestration if seats are configured; otherwise behaves identically to
 /// the pre-seat version.
 pub fn run_codex(args: &[String], prompt: &str, mode: Mode) -> Result<i32> {
-    run_codex_with(args, prompt, mode, attempt_codex)
+    run_codex_with_options(args, prompt, mode, &RunOptions::default())
+}
+
+/// [`run_codex`] with a timeout, prompt wrapping, and pre/post hooks.
+pub fn run_codex_with_options(
+    args: &[String],
+    prompt: &str,
+    mode: Mode,
+    opts: &RunOptions,
+) -> Result<i32> {
+    run_codex_presenting(args, prompt, mode, opts, |o: &RunOutcome| o.print_with(&opts.render))
+}
+
+/// Run codex for the library API: nothing is printed, and a run that
+/// doesn't succeed comes back as an [`Error`] saying why. Hooks, reports
+/// and exports still happen as `opts` asks.
+pub fn run(args: &[String], prompt: &str, mode: Mode, opts: &RunOptions) -> crate::Result<CodexOutput> {
+    if matches!(mode, Mode::Exec) && prompt.trim().is_empty() {
+        return Err(Error::EmptyPrompt);
+    }
+    let mut attempt = None;
+    let exit_code = run_codex_presenting(args, prompt, mode, opts, |outcome| {
+        attempt = outcome.attempt.as_ref().map(|a| {
+            let stderr = String::from_utf8_lossy(&a.stderr_buffer).into_owned();
+            (a.output.clone(), a.timed_out, a.cancelled, stderr)
+        });
+    })?;
+    match attempt {
+        Some((_, Some(limit), _, _)) => Err(Error::Timeout(limit)),
+        Some((output, None, true, _)) => Err(Error::Cancelled { partial: Box::new(output) }),
+        Some((output, ..)) if exit_code == 0 => Ok(output),
+        Some((_, _, _, stderr)) => Err(Error::ChildFailed { code: exit_code, stderr }),
+        // No seat could be tried.
+        None => Err(Error::ChildFailed { code: exit_code, stderr: String::new() }),
+    }
+}
+
+/// Like [`run_codex_with_options`], but hands the outcome to `present`
+/// instead of printing it as text. `present` runs before the post hook so
+/// hook output follows the result.
+pub fn run_codex_presenting<P>(
+    args: &[String],
+    prompt: &str,
+    mode: Mode,
+    opts: &RunOptions,
+    present: P,
+) -> Result<i32>
+where
+    P: FnOnce(&RunOutcome),
+{
+    let sent = match &opts.preprocessors {
+        Some(pipeline) => pipeline.apply(prompt)?,
+        None => Pipeline::standard(&opts.prompt_wrap).apply(prompt)?,
+    };
+    if opts.preview {
+        preview::confirm(args, prompt, &opts.prompt_wrap, &sent, safety::read_answer)?;
+    }
+    let mut last_message = LastMessageFile(opts.last_message_file.clone());
+    if let Some(pre) = &opts.hooks.pre {
+        let status = hook_command(pre)
+            .status()
+            .with_context(|| format!("running pre hook `{}`", pre))?;
+        if !status.success() {
+            anyhow::bail!(
+                "pre hook `{}` failed ({}); not starting codex",
+                pre,
+                describe_status(&status)
+            );
+        }
+        startup::mark("pre hook");
+    }
+
+    let codex_dir = git::codex_working_dir(args).unwrap_or_else(|| PathBuf::from("."));
+    let snapshotting = opts.git_snapshot || opts.git_revert_on_failure;
+    let repos = if opts.branch.is_some() || snapshotting { repositories(&git::codex_dirs(args)) } else { Vec::new() };
+    let mut branch = BranchGuard(Vec::new());
+    if let Some(name) = &opts.branch {
+        for repo in &repos {
+            // Each repository gets the name the first one was given.
+            let name = branch.0.first().map_or(name.as_str(), |b| b.name.as_str()).to_string();
+            branch.0.push(git::BranchSwitch::create(repo, &name, prompt).context("switching to the --branch")?);
+        }
+    }
+    let snapshots = if snapshotting {
+        repos
+            .iter()
+            .map(|repo| git::Snapshot::take(repo).context("snapshotting the git working tree"))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    if !repos.is_empty() {
+        startup::mark("git");
+    }
+
+    let run_id = opts.run_id.clone().unwrap_or_else(control::generate_run_id);
+    let cancel = opts.cancel.as_ref().map(CancelHandle::shared).unwrap_or_default();
+    let _control = match ControlSocket::bind(&run_id, Arc::clone(&cancel)) {
+        Ok(socket) => Some(socket),
+        // A chosen ID that can't be used is the caller's problem to fix.
+        Err(e) if opts.run_id.is_some() => return Err(e),
+        Err(e) => {
+            eprintln!("Warning: `codex-clean cancel` unavailable for this run: {:#}", e);
+            None
+        }
+    };
+    startup::mark("control socket");
+    let recovery = match Recovery::start(&run_id, prompt) {
+        Ok(recovery) => Some(recovery),
+        Err(e) => {
+            eprintln!("Warning: `codex-clean recover` unavailable for this run: {:#}", e);
+            None
+        }
+    };
+    startup::mark("recovery file");
+
+    if let Some(path) = &opts.record {
+        // Attempts append to the recording; it starts empty for each run.
+        crate::record::create(path)?;
+        startup::mark("start recording");
+    }
+    let started_at = Utc::now();
+    let clock = Instant::now();
+    let prompt = sent;
+    let mode_name = match mode {
+        Mode::Exec => "exec",
+        Mode::Resume(_) => "resume",
+        Mode::Review => "review",
+    };
+    let codex_args = with_last_message_flag(args, opts);
+    let attempt = |a: &[String], p: &str, m: &Mode, scrub: bool| {
+        attempt_codex_cancellable(a, p, m, scrub, opts, Some(&cancel), recovery.as_ref())
+    };
+    let mut outcome = if opts.backend.uses_local_auth() {
+        orchestrate(&codex_args, &prompt, mode, attempt)?
+    } else {
+        // A remote codex uses its own machine's login; swapping our local
+        // seats around it would achieve nothing.
+        RunOutcome::surface(attempt(&codex_args, &prompt, &mode, false)?)
+    };
+    // A nudge resumes the session, which `codex proto` can't do.
+    let exec_opts = RunOptions { transport: Transport::Exec, ..opts.clone() };
+    let nudge = |a: &[String], p: &str, m: &Mode, scrub: bool| {
+        attempt_codex_cancellable(a, p, m, scrub, &exec_opts, Some(&cancel), recovery.as_ref())
+    };
+    for n in 1..=opts.retry_on_empty {
+        let Some(session_id) = outcome.unanswered_session() else {
+            break;
+        };
+        eprintln!(
+            "codex-clean: codex finished without an answer; asking again ({}/{})",
+            n, opts.retry_on_empty
+        );
+        let resume = Mode::Resume(ResumeTarget::SessionId(session_id));
+        outcome = if opts.backend.uses_local_auth() {
+            orchestrate(&codex_args, &opts.nudge_prompt, resume, nudge)?
+        } else {
+            RunOutcome::surface(nudge(&codex_args, &opts.nudge_prompt, &resume, false)?)
+        };
+    }
+    for n in 1..=opts.auto_continue {
+        let Some((session_id, cut)) = outcome.cut_off_session() else {
+            break;
+        };
+        eprintln!("codex-clean: {}; asking codex to continue ({}/{})", cut, n, opts.auto_continue);
+        let resume = Mode::Resume(ResumeTarget::SessionId(session_id));
+        let next = if opts.backend.uses_local_auth() {
+            orchestrate(&codex_args, &opts.continue_prompt, resume, nudge)?
+        } else {
+            RunOutcome::surface(nudge(&codex_args, &opts.continue_prompt, &resume, false)?)
+        };
+        outcome = outcome.continued_by(next);
+    }
+    outcome.branch = branch.0.first().map(|b| b.name.clone());
+    if opts.warnings_as_errors && outcome.exit_code == 0 && outcome.has_warnings() {
+        outcome.exit_code = 1;
+    }
+    if let Some(observer) = &opts.observer {
+        observer.on_complete(outcome.exit_code, outcome.attempt.as_ref().map(|a| &a.output));
+    }
+    let render_started = Instant::now();
+    present(&outcome);
+    if let Some(recovery) = recovery {
+        recovery.finish();
+    }
+    if let Some(attempt) = &outcome.attempt {
+        last_message.write(&attempt.output.aggregated_message());
+    }
+    if let Some(attempt) = outcome.attempt.as_mut() {
+        attempt.timings.render_ms = Some(render_started.elapsed().as_millis() as u64);
+        if opts.print_timings {
+            eprintln!("Timings: {}", attempt.timings.describe());
+        }
+    }
+    let mut artifacts = Artifacts { record: opts.record.as_deref().map(absolute), ..Default::default() };
+    if let (true, 0, Some(attempt)) = (opts.write_files, outcome.exit_code, &outcome.attempt) {
+        let blocks = write_files::parse(&attempt.output.aggregated_message());
+        if blocks.is_empty() {
+            eprintln!("Note: --write-files found no files in the answer.");
+        } else {
+            let written = write_files::plan(&codex_dir, &blocks, opts.overwrite_files).and_then(|writes| {
+                let writes = if opts.confirm_writes { confirm::review(writes, safety::read_answer)? } else { writes };
+                write_files::apply(&writes)
+            });
+            match written {
+                Ok(written) if written.is_empty() => eprintln!("No files were written."),
+                Ok(written) => {
+                    eprint!("{}", write_files::describe(&written));
+                    artifacts.files = written
+                        .iter()
+                        .map(|w| WrittenFile { path: w.path.clone(), target: absolute(&w.target) })
+                        .collect();
+                }
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    outcome.exit_code = 1;
+                }
+            }
+        }
+    }
+    let judgement = match (&opts.judge, outcome.exit_code, &outcome.attempt) {
+        (Some(criteria), 0, Some(attempt)) if !attempt.output.messages.is_empty() => {
+            judge_answer(criteria, args, &prompt, &attempt.output, opts, &cancel)
+        }
+        _ => None,
+    };
+    for snapshot in &snapshots {
+        if snapshots.len() > 1 {
+            eprintln!("In {}:", snapshot.root().display());
+        }
+        let after = report_git_changes(snapshot, outcome.exit_code, opts.git_revert_on_failure);
+        artifacts.git.extend(after.map(|after| GitChanges {
+            root: snapshot.root().to_path_buf(),
+            before: snapshot.commit.clone(),
+            after,
+        }));
+    }
+    let switches = std::mem::take(&mut branch.0);
+    for switch in switches.into_iter().filter(|_| outcome.exit_code != 0) {
+        match switch.abandon_if_untouched() {
+            Ok(true) => eprintln!(
+                "The run failed without changing anything; switched back to {} and deleted {}.",
+                switch.previous(),
+                switch.name
+            ),
+            Ok(false) => eprintln!("The run failed; codex's changes are on branch {}.", switch.name),
+            Err(e) => eprintln!("Warning: failed to clean up branch {}: {:#}", switch.name, e),
+        }
+    }
+    let usage = outcome.attempt.as_ref().and_then(|a| a.output.usage);
+    let context_window = outcome
+        .session_id()
+        .and_then(|id| sessions::find_session(id).ok())
+        .and_then(|session| Pressure::read(&session.path))
+        .map(|p| p.with_window(stats::model_from_args(args).as_deref(), &opts.context_windows));
+    if let (Some(pressure), Some(id)) = (&context_window, outcome.session_id()) {
+        if let Some(warning) = pressure.warning(id) {
+            eprintln!("{}", warning);
+        }
+    }
+    let mut metrics = Metrics::new();
+    if let Some(attempt) = &outcome.attempt {
+        opts.counters.count(&attempt.output, &mut metrics);
+    }
+    if let Some(observer) = &opts.observer {
+        observer.metrics(&mut metrics);
+    }
+    let report = RunReport {
+        started_at,
+        duration_ms: clock.elapsed().as_millis() as u64,
+        exit_code: outcome.exit_code,
+        mode: mode_name.to_string(),
+        model: stats::model_from_args(args),
+        profile: opts.profile.clone(),
+        workspaces: match git::codex_dirs(args) {
+            dirs if dirs.len() > 1 => dirs.iter().map(|dir| absolute(dir)).collect(),
+            _ => Vec::new(),
+        },
+        session_id: outcome.session_id().map(String::from),
+        input_tokens: usage.map(|u| u.0),
+        cached_input_tokens: usage.map(|u| u.1),
+        output_tokens: usage.map(|u| u.2),
+        timings: outcome.attempt.as_ref().map(|a| a.timings),
+        lang: opts.prompt_wrap.lang.clone(),
+        content: outcome.attempt.as_ref().and_then(|a| content::analyse(&a.output.aggregated_message())),
+        context_window,
+        judge: judgement,
+        noise_lines: outcome.attempt.as_ref().map(|a| a.output.noise_lines).filter(|&n| n > 0),
+        metrics: metrics.into_map(),
+        artifacts,
+    };
+    if let Err(e) = report.save() {
+        eprintln!("Warning: failed to save run report: {:#}", e);
+    }
+    if let Some(path) = &opts.report_path {
+        if let Err(e) = report.write_to(path) {
+            eprintln!("Warning: failed to write {}: {:#}", path.display(), e);
+        }
+    }
+    if let Err(e) = usage::record(&report) {
+        eprintln!("Warning: failed to update the usage ledger: {:#}", e);
+    }
+    if let Some(attempt) = &outcome.attempt {
+        let run = RunView { prompt: &prompt, outcome: &outcome, output: &attempt.output, options: &opts.render };
+        for file in &opts.outputs {
+            match file.write(&run) {
+                Ok(()) => eprintln!("Wrote {}", file.path.display()),
+                Err(e) => eprintln!("Warning: --output {} failed: {:#}", file.format, e),
+            }
+        }
+        let images = match &opts.render_diagrams {
+            Some(dir) => {
+                let show = if io::stdout().is_terminal() { Protocol::detect() } else { None };
+                diagrams::render_all(&attempt.output.aggregated_message(), dir, outcome.session_id(), show)
+            }
+            None => Vec::new(),
+        };
+        if let Some(path) = &opts.notebook {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let images: Vec<PathBuf> = images.iter().map(|i| i.strip_prefix(dir).unwrap_or(i).to_path_buf()).collect();
+            let entry = notebook::entry(Local::now(), &prompt, &attempt.output, outcome.exit_code, &images);
+            if let Err(e) = notebook::append(path, &entry) {
+                eprintln!("Warning: failed to add the run to the notebook: {:#}", e);
+            }
+        }
+    }
+    if let (Some(dir), Some(attempt)) = (&opts.command_log, &outcome.attempt) {
+        match command_log::write(dir, &attempt.output.commands) {
+            Ok(summary) => eprintln!(
+                "Saved the output of {} command(s); see {}",
+                attempt.output.commands.len(),
+                summary.display()
+            ),
+            Err(e) => eprintln!("Warning: failed to write the command log: {:#}", e),
+        }
+    }
+
+    if let Some(post) = &opts.hooks.post {
+        let mut cmd = hook_command(post);
+        cmd.env("CODEX_CLEAN_EXIT_CODE", outcome.exit_code.to_string());
+        if let Some(id) = outcome.session_id() {
+            cmd.env("CODEX_CLEAN_SESSION_ID", id);
+        }
+        if !report.metrics.is_empty() {
+            if let Ok(metrics) = serde_json::to_string(&report.metrics) {
+                cmd.env("CODEX_CLEAN_METRICS", metrics);
+            }
+        }
+        match cmd.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                eprintln!("Warning: post hook `{}` failed ({}).", post, describe_status(&status))
+            }
+            Err(e) => eprintln!("Warning: failed to run post hook `{}`: {}", post, e),
+        }
+    }
+    Ok(outcome.exit_code)
+}
+
+/// Ask a fresh codex session to grade `output` for `--judge`, printing
+/// its verdict. None (with a warning) when the judge itself failed.
+fn judge_answer(
+    criteria: &str,
+    args: &[String],
+    prompt: &str,
+    output: &CodexOutput,
+    opts: &RunOptions,
+    cancel: &Cancel,
+) -> Option<Judgement> {
+    eprintln!("Judging the answer...");
+    let judge_opts = RunOptions { timeout: opts.timeout, backend: Arc::clone(&opts.backend), ..RunOptions::default() };
+    let judge_prompt = judge::prompt(criteria, prompt, &output.aggregated_message());
+    match attempt_codex_cancellable(&judge::args(args), &judge_prompt, &Mode::Exec, false, &judge_opts, Some(cancel), None) {
+        Ok(attempt) if attempt.exit_code == 0 => {
+            let judgement = Judgement::from_output(criteria, &attempt.output);
+            eprintln!("{}", judgement.describe());
+            Some(judgement)
+        }
+        Ok(attempt) => {
+            eprintln!("Warning: the judge run failed (exit code {}); no verdict recorded.", attempt.exit_code);
+            None
+        }
+        Err(e) => {
+            eprintln!("Warning: the judge run failed: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Holds the `--branch` while the run is underway. If the run errors out
+/// before finishing, a branch codex never touched is removed again.
+struct BranchGuard(Vec<git::BranchSwitch>);
+
+/// `--last-message-file`, there once the run is over however it ended: the
+/// answer when there was one, and otherwise whatever codex left or an
+/// empty file.
+struct LastMessageFile(Option<PathBuf>);
+
+impl LastMessageFile {
+    fn write(&mut self, message: &str) {
+        let Some(path) = self.0.take() else {
+            return;
+        };
+        if message.is_empty() && path.exists() {
+            return;
+        }
+        if let Err(e) = write_replacing(&path, message.as_bytes()) {
+            eprintln!("Warning: failed to write {}: {:#}", path.display(), e);
+        }
+    }
+}
+
+impl Drop for LastMessageFile {
+    fn drop(&mut self) {
+        self.write("");
+    }
+}
+
+/// Write `path` by renaming a finished file over it, so whoever is watching
+/// it never reads half a message.
+fn write_replacing(path: &Path, data: &[u8]) -> Result<()> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.tmp.{}", name, std::process::id()));
+    fs::write(&tmp, data).with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| {
+        let _ = fs::remove_file(&tmp);
+        format!("renaming {} to {}", tmp.display(), path.display())
+    })
+}
+
+/// `args`, with codex asked to write `--last-message-file` too when it is
+/// a local codex known to have `--output-last-message`. That way the file
+/// is there even if codex-clean is killed; it is rewritten with the answer
+/// as printed afterwards either way.
+fn with_last_message_flag(args: &[String], opts: &RunOptions) -> Vec<String> {
+    let mut args = args.to_vec();
+    let Some(path) = &opts.last_message_file else {
+        return args;
+    };
+    let compat = opts.backend.compat();
+    let supported = opts.transport == Transport::Exec && compat.version.is_some() && compat.output_last_message;
+    let given = args.iter().any(|a| a == "-o" || a == "--output-last-message" || a.starts_with("--output-last-message="));
+    if supported && !given {
+        args.push("--output-last-message".to_string());
+        args.push(path.to_string_lossy().into_owned());
+    }
+    args
+}
+
+impl Drop for BranchGuard {
+    fn drop(&mut self) {
+        for switch in self.0.drain(..) {
+            let _ = switch.abandon_if_untouched();
+        }
+    }
+}
+
+/// Print what codex changed in the working tree since `snapshot`, and
+/// undo it when asked to and the run failed. Returns a snapshot commit of
+/// codex's changes, when there were any.
+fn report_git_changes(snapshot: &git::Snapshot, exit_code: i32, revert_on_failure: bool) -> Option<String> {
+    let changes = match snapshot.changes() {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Warning: failed to compare the working tree with its snapshot: {:#}", e);
+            return None;
+        }
+    };
+    eprint!("{}", git::describe(&changes));
+    if changes.is_empty() {
+        return None;
+    }
+    // Keeps codex's work reachable in case a failure was a false alarm.
+    let saved = git::Snapshot::take(snapshot.root()).map(|s| s.commit);
+    if !revert_on_failure || exit_code == 0 {
+        return saved.ok();
+    }
+    match snapshot.restore(&changes) {
+        Ok(()) => match &saved {
+            Ok(commit) => eprintln!(
+                "The run failed, so the working tree was put back as it was. codex's changes are \
+                 saved as {}; `git checkout {} -- .` brings them back.",
+                commit, commit
+            ),
+            Err(_) => eprintln!("The run failed, so the working tree was put back as it was."),
+        },
+        Err(e) => eprintln!(
+            "Warning: failed to revert codex's changes: {:#} (the tree before the run is commit {})",
+            e, snapshot.commit
+        ),
+    }
+    saved.ok()
+}
+
+/// The repositories `--branch` and `--git-snapshot` cover: the one codex
+/// works in, then each other one it was given a directory in. A directory
+/// outside git is left out with a warning, except codex's own, whose error
+/// the snapshot or branch reports.
+fn repositories(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for (i, dir) in dirs.iter().enumerate() {
+        match git::toplevel(dir) {
+            Ok(root) if roots.contains(&root) => {}
+            Ok(root) => roots.push(root),
+            Err(_) if i == 0 => roots.push(dir.clone()),
+            Err(_) => eprintln!(
+                "Warning: {} is not in a git repository; --branch and --git-snapshot leave it alone.",
+                dir.display()
+            ),
+        }
+    }
+    roots
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub(crate) fn hook_command(script: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(script);
+    cmd.stdin(Stdio::null());
+    // Keep our stdout clean for codex's output; hook chatter goes to stderr.
+    cmd.stdout(io::stderr());
+    cmd
+}
+
+fn describe_status(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => "killed by signal".to_string(),
+    }
 }
 
 /// Internal orchestration that drives the lock/swap/spawn/classify state
 /// machine. Generic over the codex attempt callback so tests can inject a
 /// fake spawner without touching real auth.json or running real codex.
 pub fn run_codex_with<F>(args: &[String], prompt: &str, mode: Mode, attempt: F) -> Result<i32>
+where
+    F: Fn(&[String], &str, &Mode, bool) -> Result<AttemptResult>,
+{
+    let outcome = orchestrate(args, prompt, mode, attempt)?;
+    outcome.print();
+    Ok(outcome.exit_code)
+}
+
+fn orchestrate<F>(args: &[String], prompt: &str, mode: Mode, attempt: F) -> Result<RunOutcome>
 where
     F: Fn(&[String], &str, &Mode, bool) -> Result<AttemptResult>,
 {
     let cfg_opt = SeatConfig::load().context("loading seats.toml")?;
+    startup::mark("load seats");
     let cfg = match cfg_opt {
         Some(c) if !c.seats.is_empty() => c,
         _ => {
             // Backwards-compat: no seats configured → run as today.
             let result = attempt(args, prompt, &mode, false)?;
-            print_attempt(&result);
-            return Ok(result.exit_code);
+            return Ok(RunOutcome::surface(result));
         }
     };
 
@@ -102,6 +983,7 @@ where
 
     let override_seat = env::var("CODEX_CLEAN_SEAT").ok().filter(|s| !s.is_empty());
     let mut state = SeatState::load()?;
+    startup::mark("lock seats");
     let max_attempts = cfg.rotation.max_retries.saturating_add(1);
     let mut last_failure: Option<AttemptResult> = None;
     let mut tried_seats: Vec<String> = Vec::new();
@@ -118,16 +1000,12 @@ where
                     .map(|n| format!(" (seat '{}')", n))
                     .unwrap_or_default();
                 eprintln!("All seats cooling; soonest available at {}{}.", when, who);
-                if let Some(prev) = last_failure {
-                    print_attempt(&prev);
-                }
-                return Ok(75);
+                return Ok(RunOutcome { exit_code: 75, attempt: last_failure, branch: None });
             }
             Err(e) => {
                 if let Some(prev) = last_failure {
                     eprintln!("{}", e);
-                    print_attempt(&prev);
-                    return Ok(prev.exit_code);
+                    return Ok(RunOutcome::surface(prev));
                 }
                 anyhow::bail!("{}", e);
             }
@@ -149,7 +1027,12 @@ where
         state.save()?;
 
         let attempt = attempt(args, prompt, &mode, true)?;
-        if let Err(e) = refresh_back(&chosen) {
+        let refreshed = refresh_back(&chosen);
+        if attempt.cancelled {
+            // Not the seat's fault; leave its state alone and stop here.
+            return Ok(RunOutcome::surface(attempt));
+        }
+        if let Err(e) = refreshed {
             // Codex may have refreshed the OAuth token during the run. If we
             // can't persist that refresh into the side store, the next swap
             // would install stale credentials. Surface it so the user knows
@@ -168,8 +1051,7 @@ where
                 entry.consecutive_failures = 0;
                 entry.cooldown_until = None;
                 state.save()?;
-                print_attempt(&attempt);
-                return Ok(attempt.exit_code);
+                return Ok(RunOutcome::surface(attempt));
             }
             FailureKind::AuthError => {
                 let entry = state.entry_mut(&chosen);
@@ -179,8 +1061,7 @@ where
                     "Seat '{}' has invalid credentials. Run: codex-clean seat login {}",
                     chosen, chosen
                 );
-                print_attempt(&attempt);
-                return Ok(attempt.exit_code);
+                return Ok(RunOutcome::surface(attempt));
             }
             FailureKind::RateLimit { recovery } => {
                 let cd = ratelimit::apply_recovery_window(
@@ -208,18 +1089,15 @@ where
             }
             FailureKind::Other => {
                 let _ = log_unmatched(&chosen, &attempt);
-                print_attempt(&attempt);
-                return Ok(attempt.exit_code);
+                return Ok(RunOutcome::surface(attempt));
             }
         }
     }
 
-    if let Some(prev) = last_failure {
-        print_attempt(&prev);
-        Ok(prev.exit_code)
-    } else {
-        Ok(1)
-    }
+    Ok(match last_failure {
+        Some(prev) => RunOutcome::surface(prev),
+        None => RunOutcome::code(1),
+    })
 }
 
 /// Classify an attempt's outcome, falling back to stderr text when the
@@ -295,7 +1173,11 @@ fn log_unmatched(seat: &str, attempt: &AttemptResult) -> Result<()> {
 /// Print captured stderr (when failure) and the formatted output. Mirrors
 /// the pre-seat printing behaviour exactly.
 pub fn print_attempt(attempt: &AttemptResult) {
-    if !attempt.status_success {
+    print_attempt_with(attempt, &RenderOptions::default());
+}
+
+pub fn print_attempt_with(attempt: &AttemptResult, render: &RenderOptions) {
+    if !attempt.status_success && !attempt.stopped_early {
         if !attempt.stderr_buffer.is_empty() {
             eprintln!("--- codex stderr ---");
             let _ = io::stderr().write_all(&attempt.stderr_buffer);
@@ -312,7 +1194,11 @@ pub fn print_attempt(attempt: &AttemptResult) {
             eprintln!("--- end stderr ---");
         }
 
-        if attempt.output.lines_seen == 0 {
+        if let Some(limit) = attempt.timed_out {
+            eprintln!("Codex was killed after running longer than {}s (--timeout)", limit.as_secs());
+        } else if attempt.cancelled {
+            eprintln!("Codex was cancelled; output so far follows");
+        } else if attempt.output.lines_seen == 0 {
             eprintln!("Codex exited with code {} and produced no JSON output", attempt.child_exit);
         } else if attempt.output.events_recognized == 0 {
             eprintln!(
@@ -324,7 +1210,7 @@ pub fn print_attempt(attempt: &AttemptResult) {
         eprintln!("Warning: Failed to capture codex stderr: {}", err);
     }
 
-    attempt.output.print();
+    attempt.output.print_with(render);
 }
 
 /// One codex spawn-and-collect cycle. Captures stdout/stderr but does not
@@ -336,95 +1222,173 @@ pub fn attempt_codex(
     mode: &Mode,
     scrub_env: bool,
 ) -> Result<AttemptResult> {
-    let mut cmd = Command::new("codex");
+    attempt_codex_with(args, prompt, mode, scrub_env, &RunOptions::default())
+}
 
-    // All modes use "codex exec" with --json for JSON output
-    cmd.arg("exec");
+/// [`attempt_codex`] on `opts.backend`, killing codex if it is still
+/// running after `opts.timeout`.
+pub fn attempt_codex_with(
+    args: &[String],
+    prompt: &str,
+    mode: &Mode,
+    scrub_env: bool,
+    opts: &RunOptions,
+) -> Result<AttemptResult> {
+    attempt_codex_cancellable(args, prompt, mode, scrub_env, opts, None, None)
+}
+
+/// [`attempt_codex_with`], also killing codex when `cancel` fires and
+/// keeping `recovery` up to date with the messages received.
+fn attempt_codex_cancellable(
+    args: &[String],
+    prompt: &str,
+    mode: &Mode,
+    scrub_env: bool,
+    opts: &RunOptions,
+    cancel: Option<&Cancel>,
+    recovery: Option<&Recovery>,
+) -> Result<AttemptResult> {
+    let timeout = opts.timeout;
+    let started = Instant::now();
+    let since_start = move || Some(started.elapsed().as_millis() as u64);
+    let mut timings = StageTimings::default();
+    let (argv, use_stdin_for_prompt) = match opts.transport {
+        Transport::Exec => codex_argv(args, prompt, mode, &opts.backend.compat())?,
+        Transport::Proto => (proto::argv(args, mode)?, true),
+    };
+    startup::mark("check codex version");
+    let spawn_opts = SpawnOptions {
+        stdin_prompt: use_stdin_for_prompt,
+        scrub_env: if scrub_env { SCRUB_ENV_VARS } else { &[] },
+    };
+    let record = opts.record.as_deref().map(Recorder::append).transpose()?;
+    let mut child = opts.backend.spawn(&argv, spawn_opts)?;
+    let mut stdin_writer = None;
 
-    if scrub_env {
-        for var in SCRUB_ENV_VARS {
-            cmd.env_remove(var);
+    if opts.transport == Transport::Proto {
+        child = Box::new(ProtoProcess::start(child, prompt)?);
+    } else if use_stdin_for_prompt {
+        if let Some(stdin) = child.take_stdin() {
+            stdin_writer = Some(feed_stdin(stdin, prompt.to_owned()));
         }
     }
+    timings.spawn_ms = since_start();
+    startup::mark("spawn codex");
+    startup::report();
 
-    let mut use_stdin_for_prompt = false;
+    let stderr = child.take_stderr();
+    let stderr_handle = thread::spawn(move || capture_stderr(stderr));
 
-    match mode {
-        Mode::Exec => {
-            cmd.arg("--json");
-            cmd.arg("--skip-git-repo-check");
-            cmd.args(args);
-            cmd.arg(prompt);
-        }
-        Mode::Resume(target) => {
-            cmd.arg("--json");
-            cmd.arg("--skip-git-repo-check");
-            cmd.arg("resume");
-            match target {
-                ResumeTarget::SessionId(id) => {
-                    cmd.arg(id);
-                    if !prompt.is_empty() {
-                        cmd.arg(prompt);
-                    }
-                }
-                ResumeTarget::Last => {
-                    cmd.arg("--last");
-                    if !prompt.is_empty() {
-                        use_stdin_for_prompt = true;
-                    }
-                }
-            }
-        }
-        Mode::Review => {
-            cmd.arg("review");
-            cmd.arg("--json");
-            cmd.arg("--skip-git-repo-check");
-            cmd.args(args);
-            if !prompt.is_empty() {
-                cmd.arg(prompt);
-            }
-        }
+    let stdout = match record {
+        Some(copy) => Box::new(Tee { inner: child.take_stdout(), copy }),
+        None => child.take_stdout(),
+    };
+    let child = Arc::new(Mutex::new(child));
+    let watchdog = timeout.map(|limit| spawn_watchdog(Arc::clone(&child), limit));
+    if let Some(cancel) = cancel {
+        cancel.watch(&child);
     }
 
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-    if use_stdin_for_prompt {
-        cmd.stdin(Stdio::piped());
-    } else {
-        cmd.stdin(Stdio::null());
+    let lines = Arc::new(AtomicUsize::new(0));
+    let heartbeat = opts.heartbeat.map(|every| {
+        let lines = Arc::clone(&lines);
+        spawn_heartbeat(every, move |elapsed| {
+            eprintln!("{}", heartbeat_line(elapsed, lines.load(Ordering::Relaxed)));
+        })
+    });
+    if opts.progress_json.is_some() {
+        eprintln!("{}", progress_json("started", started.elapsed(), 0, None));
     }
+    let progress = opts.progress_json.map(|every| {
+        let lines = Arc::clone(&lines);
+        spawn_heartbeat(every, move |_| {
+            eprintln!("{}", progress_json("running", started.elapsed(), lines.load(Ordering::Relaxed), None));
+        })
+    });
 
-    let mut child = cmd.spawn().context("Failed to spawn codex process")?;
-
-    if use_stdin_for_prompt {
-        if let Some(mut stdin) = child.stdin.take() {
-            writeln!(stdin, "{}", prompt)?;
-            stdin.flush()?;
+    let reader = BufReader::new(LineCounter { inner: stdout, lines });
+    let first_only = opts.first_message_only;
+    let over_limit =
+        |o: &CodexOutput| opts.max_output_bytes.is_some_and(|max| o.message_bytes() > max);
+    let matches_stop = |o: &CodexOutput| {
+        opts.stop_on.as_ref().is_some_and(|re| !o.messages.is_empty() && re.is_match(&o.aggregated_message()))
+    };
+    let parse_result = parse_codex_stream_observed(reader, opts.observer.as_deref(), |o: &CodexOutput| {
+        let now = since_start();
+        timings.first_event_ms = timings.first_event_ms.or(now);
+        if !o.messages.is_empty() {
+            timings.first_message_ms = timings.first_message_ms.or(now);
         }
+        timings.last_event_ms = now;
+        if let Some(recovery) = recovery {
+            recovery.update(o, false);
+        }
+        (first_only && !o.messages.is_empty()) || over_limit(o) || matches_stop(o)
+    });
+    let stopped_early = matches!(parse_result, Ok((_, true)));
+    if stopped_early {
+        let _ = lock_child(&child).kill();
     }
+    let parse_result = parse_result.map(|(output, _)| output);
 
-    let stderr = child.stderr.take().expect("stderr was piped");
-    let stderr_handle = thread::spawn(move || capture_stderr(stderr));
-
-    let stdout = child.stdout.take().expect("stdout was piped");
-    let reader = BufReader::new(stdout);
-    let parse_result = parse_codex_stream(reader);
-
+    for (done, handle) in heartbeat.into_iter().chain(progress) {
+        let _ = done.send(());
+        let _ = handle.join();
+    }
     if parse_result.is_err() {
-        let _ = child.kill();
+        let _ = lock_child(&child).kill();
     }
 
-    let status: ExitStatus = child.wait().context("Failed to wait for codex process")?;
+    // The watchdog stays up until codex has exited, as codex can keep
+    // running after closing stdout.
+    let status = wait_for_exit(&child);
+    let timed_out = watchdog.and_then(|(done, handle)| {
+        let _ = done.send(());
+        handle.join().expect("watchdog thread panicked")
+    });
+    let status = status.context("Failed to wait for codex process")?;
+    timings.exit_ms = since_start();
+    let cancelled = cancel.is_some_and(Cancel::unwatch);
+    if timed_out.is_some() || cancelled || stopped_early || parse_result.is_err() {
+        lock_child(&child).cleanup();
+    }
     let (stderr_buffer, stderr_truncated, stderr_error) =
         stderr_handle.join().expect("stderr thread panicked");
-    let output = parse_result.context("Failed to read codex stdout")?;
+    if let Some(writer) = stdin_writer {
+        writer.join().expect("stdin thread panicked");
+    }
+    let mut output = parse_result.map_err(|e| Error::StreamParse(ParseError::Io(e)))?;
+    if let Some(recovery) = recovery {
+        recovery.update(&output, true);
+    }
+    let output_limited = opts.max_output_bytes.filter(|_| over_limit(&output));
+    if let Some(max) = output_limited {
+        if opts.truncate_overflow {
+            output.truncate_messages(max);
+        } else {
+            output.messages.clear();
+            output.add_error(format!(
+                "agent output passed {} bytes (--max-output-bytes); codex was stopped",
+                max
+            ));
+        }
+    }
 
-    let child_exit = status.code().unwrap_or(1);
-    let exit_code = if child_exit == 0 && !output.errors.is_empty() {
+    let child_exit = status.code.unwrap_or(1);
+    let exit_code = if timed_out.is_some() {
+        TIMEOUT_EXIT_CODE
+    } else if cancelled {
+        CANCELLED_EXIT_CODE
+    } else if !output.errors.is_empty() && (child_exit == 0 || stopped_early) {
         1
+    } else if stopped_early {
+        0
     } else {
         child_exit
     };
+    if opts.progress_json.is_some() {
+        eprintln!("{}", progress_json("exited", started.elapsed(), output.lines_seen, Some(exit_code)));
+    }
 
     Ok(AttemptResult {
         output,
@@ -432,55 +1396,345 @@ pub fn attempt_codex(
         stderr_truncated,
         stderr_error,
         exit_code,
-        status_success: status.success(),
+        status_success: status.success,
         child_exit,
+        timed_out,
+        cancelled,
+        stopped_early,
+        output_limited,
+        timings,
     })
 }
 
-pub fn parse_codex_stream<R: BufRead>(reader: R) -> io::Result<CodexOutput> {
-    let mut output = CodexOutput::new();
+type SharedProcess = Arc<Mutex<Box<dyn CodexProcess>>>;
+
+fn lock_child(child: &Mutex<Box<dyn CodexProcess>>) -> std::sync::MutexGuard<'_, Box<dyn CodexProcess>> {
+    child.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Longest pause between checks in [`wait_for_exit`].
+const EXIT_POLL: Duration = Duration::from_millis(50);
+
+/// Wait for `child` to exit, locking it only to check, so the watchdog and
+/// `codex-clean cancel` can still kill it meanwhile.
+fn wait_for_exit(child: &Mutex<Box<dyn CodexProcess>>) -> io::Result<ProcessExit> {
+    let mut pause = Duration::from_millis(1);
+    loop {
+        if let Some(exit) = lock_child(child).try_wait()? {
+            return Ok(exit);
+        }
+        thread::sleep(pause);
+        pause = (pause * 2).min(EXIT_POLL);
+    }
+}
+
+/// Kill `child` unless the returned sender fires (or is dropped) within
+/// `limit`. The thread yields `Some(limit)` if it did the killing, which it
+/// doesn't when codex has already exited.
+fn spawn_watchdog(
+    child: SharedProcess,
+    limit: Duration,
+) -> (mpsc::Sender<()>, thread::JoinHandle<Option<Duration>>) {
+    let (done, rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || match rx.recv_timeout(limit) {
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            let mut child = lock_child(&child);
+            if !matches!(child.try_wait(), Ok(None)) {
+                return None;
+            }
+            let _ = child.kill();
+            Some(limit)
+        }
+        _ => None,
+    });
+    (done, handle)
+}
+
+/// Call `tick` with the elapsed time every `every` until the returned
+/// sender fires (or is dropped).
+fn spawn_heartbeat<F>(every: Duration, tick: F) -> (mpsc::Sender<()>, thread::JoinHandle<()>)
+where
+    F: Fn(Duration) + Send + 'static,
+{
+    let (done, rx) = mpsc::channel::<()>();
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(every) {
+            tick(start.elapsed());
+        }
+    });
+    (done, handle)
+}
+
+/// The `--heartbeat` progress line.
+fn heartbeat_line(elapsed: Duration, lines: usize) -> String {
+    format!(
+        "codex-clean: still running ({} elapsed, {} events so far)",
+        format_elapsed(elapsed),
+        lines
+    )
+}
+
+/// A `--progress-json` object: `{"phase":"running","elapsed":12.3,"events":42}`,
+/// with the exit code once codex has exited.
+fn progress_json(phase: &str, elapsed: Duration, events: usize, exit_code: Option<i32>) -> String {
+    #[derive(serde::Serialize)]
+    struct Progress<'a> {
+        phase: &'a str,
+        elapsed: f64,
+        events: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+    }
+    let elapsed = (elapsed.as_secs_f64() * 10.0).round() / 10.0;
+    serde_json::to_string(&Progress { phase, elapsed, events, exit_code }).expect("progress serialises")
+}
+
+/// `42s`, or `10m05s` from a minute up.
+pub(crate) fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Counts the lines passing through, for the heartbeat.
+struct LineCounter<R> {
+    inner: R,
+    lines: Arc<AtomicUsize>,
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let newlines = buf[..n].iter().filter(|&&b| b == b'\n').count();
+        self.lines.fetch_add(newlines, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Write the prompt to codex's stdin from its own thread, so a long one
+/// can't stall reading codex's output. Codex exiting before it has read it
+/// all is reported by codex, not here.
+fn feed_stdin(mut stdin: Box<dyn Write + Send>, prompt: String) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let written = stdin
+            .write_all(prompt.as_bytes())
+            .and_then(|()| stdin.write_all(b"\n"))
+            .and_then(|()| stdin.flush());
+        match written {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                eprintln!("Warning: failed to send the prompt to codex: {}", e)
+            }
+            _ => {}
+        }
+    })
+}
+
+/// Copies what passes through to a file, for `record`. A failed write
+/// loses the copy but not the run.
+struct Tee<R> {
+    inner: R,
+    copy: Recorder,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let _ = self.copy.write_all(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Prompts longer than this reach codex on stdin rather than as an
+/// argument. Linux refuses any single argument over 128 KiB, and a prompt
+/// that size is better streamed than copied into the argument list.
+pub const STDIN_PROMPT_BYTES: usize = 64 * 1024;
+
+/// Left spare below the system's argument limit, as POSIX advises, for
+/// whatever the backend adds (the codex path, container flags, ...).
+const ARG_HEADROOM: usize = 4096;
+
+/// Whether `prompt` must go to stdin: it is long, or `argv` plus the
+/// prompt would be more than the system lets a program be started with.
+fn prompt_needs_stdin(argv: &[String], prompt: &str) -> bool {
+    prompt.len() > STDIN_PROMPT_BYTES || !fits_command_line(argv, prompt, command_line_room())
+}
+
+/// Whether `argv` and `prompt` fit in `room` bytes, counting what the
+/// kernel does: each argument's bytes, its NUL, and its pointer.
+pub fn fits_command_line(argv: &[String], prompt: &str, room: Option<usize>) -> bool {
+    let Some(room) = room else {
+        return true;
+    };
+    let cost = |arg: &str| arg.len() + 1 + std::mem::size_of::<usize>();
+    cost("codex") + argv.iter().map(|a| cost(a)).sum::<usize>() + cost(prompt) <= room
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
+/// How many bytes of arguments a new process may be given: `ARG_MAX` less
+/// the environment it inherits (which shares the limit) on Unix, and the
+/// command-line limit on Windows.
+fn command_line_room() -> Option<usize> {
+    #[cfg(unix)]
+    {
+        let max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+        if max <= 0 {
+            return None;
         }
-        output.lines_seen += 1;
+        let env: usize = env::vars_os().map(|(k, v)| k.len() + v.len() + 2 + std::mem::size_of::<usize>()).sum();
+        Some((max as usize).saturating_sub(env).saturating_sub(ARG_HEADROOM))
+    }
+    #[cfg(windows)]
+    {
+        Some(32_767 - ARG_HEADROOM)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        None
+    }
+}
 
-        if let Some(event) = extract_event(&line) {
-            output.events_recognized += 1;
-            match event {
-                Event::ThreadStarted { thread_id } => {
-                    output.add_thread_id(thread_id);
+/// Codex's argv (after the program name) for `mode`, and whether the
+/// prompt must be written to stdin instead.
+fn codex_argv(
+    args: &[String],
+    prompt: &str,
+    mode: &Mode,
+    compat: &Compat,
+) -> Result<(Vec<String>, bool)> {
+    // All modes use "codex exec" with JSON output
+    let mut argv = vec!["exec".to_string()];
+    let mut use_stdin_for_prompt = false;
+    let json_flags = [compat.json_flag.to_string(), "--skip-git-repo-check".to_string()];
+
+    match mode {
+        Mode::Exec => {
+            argv.extend(json_flags);
+            argv.extend_from_slice(args);
+            if prompt_needs_stdin(&argv, prompt) {
+                // `-` has codex read the prompt from stdin.
+                argv.push("-".to_string());
+                use_stdin_for_prompt = true;
+            } else {
+                argv.push(prompt.to_string());
+            }
+        }
+        Mode::Resume(target) => {
+            if !compat.exec_resume {
+                let version = compat.version.map(compat::display).unwrap_or_default();
+                anyhow::bail!("codex {} has no `exec resume`; upgrade codex to resume sessions", version);
+            }
+            argv.extend(json_flags);
+            argv.extend_from_slice(args);
+            argv.push("resume".to_string());
+            match target {
+                ResumeTarget::SessionId(id) => {
+                    argv.push(id.clone());
+                    if prompt_needs_stdin(&argv, prompt) {
+                        argv.push("-".to_string());
+                        use_stdin_for_prompt = true;
+                    } else if !prompt.is_empty() {
+                        argv.push(prompt.to_string());
+                    }
                 }
-                Event::AgentMessage { text } => {
-                    if let Some(t) = text {
-                        output.add_message(t);
+                ResumeTarget::Last => {
+                    argv.push("--last".to_string());
+                    if !prompt.is_empty() {
+                        use_stdin_for_prompt = true;
                     }
                 }
-                Event::TurnCompleted {
-                    input_tokens,
-                    cached_input_tokens,
-                    output_tokens,
-                    reasoning_output_tokens,
-                } => {
-                    output.add_usage(
-                        input_tokens,
-                        cached_input_tokens,
-                        output_tokens,
-                        reasoning_output_tokens,
+            }
+        }
+        Mode::Review => {
+            argv.push("review".to_string());
+            argv.extend(json_flags);
+            argv.extend_from_slice(args);
+            if !prompt.is_empty() {
+                // `exec review` takes no prompt on stdin.
+                if prompt_needs_stdin(&argv, prompt) {
+                    anyhow::bail!(
+                        "the review prompt is too long to pass to codex ({} bytes); shorten it",
+                        prompt.len()
                     );
                 }
-                Event::TurnFailed { message } | Event::StreamError { message } => {
-                    output.add_error(message);
-                }
+                argv.push(prompt.to_string());
             }
         }
     }
+    Ok((argv, use_stdin_for_prompt))
+}
+
+pub fn parse_codex_stream<R: BufRead>(reader: R) -> io::Result<CodexOutput> {
+    parse_codex_stream_until(reader, |_: &CodexOutput| false).map(|(output, _)| output)
+}
 
+/// [`parse_codex_stream`], giving up as soon as `stop` returns true after
+/// an event. The flag says whether it did.
+pub fn parse_codex_stream_until<R, F>(reader: R, stop: F) -> io::Result<(CodexOutput, bool)>
+where
+    R: BufRead,
+    F: FnMut(&CodexOutput) -> bool,
+{
+    parse_codex_stream_observed(reader, None, stop)
+}
+
+/// [`parse_codex_stream`] for a whole stream already in memory, such as a
+/// large recording, decoded on every core (see [`decode_parallel`]).
+pub fn parse_codex_bytes(raw: &[u8]) -> io::Result<CodexOutput> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let decoded = decode_parallel(raw, workers);
+    let mut output = CodexOutput::new();
+    for item in decoded.items {
+        match item {
+            Ok(event) => output.apply(event),
+            Err(ParseError::InvalidJson { text, .. }) => output.add_noise(text),
+            Err(ParseError::Io(e)) => return Err(e),
+        }
+    }
+    output.lines_seen = decoded.lines_seen;
     Ok(output)
 }
 
+/// [`parse_codex_stream_until`], also telling `observer` about each event
+/// as it is read.
+pub fn parse_codex_stream_observed<R, F>(
+    reader: R,
+    observer: Option<&dyn EventObserver>,
+    mut stop: F,
+) -> io::Result<(CodexOutput, bool)>
+where
+    R: BufRead,
+    F: FnMut(&CodexOutput) -> bool,
+{
+    let mut output = CodexOutput::new();
+    let mut events = EventStream::new(reader);
+
+    while let Some(item) = events.next() {
+        output.lines_seen = events.lines_seen();
+        let event = match item {
+            Ok(event) => event,
+            // Not JSON: a banner or notice, kept aside for --show-noise.
+            Err(ParseError::InvalidJson { text, .. }) => {
+                output.add_noise(text);
+                continue;
+            }
+            Err(ParseError::Io(e)) => return Err(e),
+        };
+        if let Some(observer) = observer {
+            observer::notify(observer, &event);
+        }
+        output.apply(event);
+        if stop(&output) {
+            return Ok((output, true));
+        }
+    }
+    output.lines_seen = events.lines_seen();
+
+    Ok((output, false))
+}
+
 fn capture_stderr(stderr: impl Read) -> (Vec<u8>, bool, Option<io::Error>) {
     let mut reader = BufReader::new(stderr);
     let mut buffer = Vec::new();
@@ -542,6 +1796,20 @@ mod tests {
         assert_eq!(output.usage, Some((15228, 14208, 249, 64)));
     }
 
+    #[test]
+    fn parse_codex_stream_keeps_non_json_lines_aside() {
+        let data = "✨ Update available! 0.125.0 -> 0.126.0\n{\"type\":\"thread.started\",\"thread_id\":\"s1\"}\nRun `npm i -g @openai/codex` to update\n";
+        let output = parse_codex_stream(BufReader::new(Cursor::new(data))).unwrap();
+        assert_eq!(output.noise, ["✨ Update available! 0.125.0 -> 0.126.0", "Run `npm i -g @openai/codex` to update"]);
+        assert_eq!(output.noise_lines, 2);
+        assert!(output.render().stderr.contains("codex printed 2 line(s) that weren't JSON events"));
+        assert!(!output.render().stderr.contains("codex stdout:"));
+
+        let shown = output.render_with(&RenderOptions { show_noise: true, ..Default::default() });
+        assert!(shown.stderr.starts_with("codex stdout: ✨ Update available!"));
+        assert_eq!(parse_codex_bytes(data.as_bytes()).unwrap().noise_lines, 2);
+    }
+
     #[test]
     fn parse_codex_stream_captures_turn_failed() {
         let data = r#"
@@ -595,12 +1863,437 @@ not json at all
         assert!(rendered.stderr.contains("none matched known event types"));
     }
 
+    fn spawn_child(program: &str, args: &[&str]) -> Box<dyn CodexProcess> {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        Box::new(crate::backend::ChildProcess::spawn(cmd, SpawnOptions::default()).unwrap())
+    }
+
+    #[test]
+    fn attempt_runs_on_selected_backend() {
+        let opts = RunOptions {
+            backend: Arc::new(crate::backend::MockBackend {
+                stdout: include_str!("../tests/fixtures/sample_session.jsonl").to_string(),
+                stderr: "note from codex".to_string(),
+                exit_code: 0,
+            }),
+            ..RunOptions::default()
+        };
+        let attempt = attempt_codex_with(&[], "hi", &Mode::Exec, true, &opts).unwrap();
+        assert!(attempt.output.session_id.is_some());
+        assert_eq!(attempt.stderr_buffer, b"note from codex");
+        assert_eq!(attempt.exit_code, 0);
+        assert!(!opts.backend.uses_local_auth());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_kills_child_past_limit() {
+        let child = Arc::new(Mutex::new(spawn_child("sleep", &["10"])));
+        let (_done, handle) = spawn_watchdog(Arc::clone(&child), Duration::from_millis(50));
+        assert_eq!(handle.join().unwrap(), Some(Duration::from_millis(50)));
+        assert!(!lock_child(&child).wait().unwrap().success);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn waiting_leaves_the_watchdog_free_to_kill() {
+        let child = Arc::new(Mutex::new(spawn_child("sleep", &["10"])));
+        let (_done, handle) = spawn_watchdog(Arc::clone(&child), Duration::from_millis(50));
+        assert!(!wait_for_exit(&child).unwrap().success);
+        assert_eq!(handle.join().unwrap(), Some(Duration::from_millis(50)));
+
+        let child = Arc::new(Mutex::new(spawn_child("true", &[])));
+        assert!(wait_for_exit(&child).unwrap().success);
+        let (_done, handle) = spawn_watchdog(Arc::clone(&child), Duration::from_millis(1));
+        assert_eq!(handle.join().unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_stands_down_when_signalled() {
+        let child = Arc::new(Mutex::new(spawn_child("true", &[])));
+        let (done, handle) = spawn_watchdog(Arc::clone(&child), Duration::from_secs(10));
+        done.send(()).unwrap();
+        assert_eq!(handle.join().unwrap(), None);
+        assert!(lock_child(&child).wait().unwrap().success);
+    }
+
+    #[test]
+    fn parse_stops_at_first_message_when_asked() {
+        let data = include_str!("../tests/fixtures/sample_session.jsonl");
+        let (output, stopped) =
+            parse_codex_stream_until(Cursor::new(data), |o: &CodexOutput| !o.messages.is_empty())
+                .unwrap();
+        assert!(stopped);
+        assert_eq!(output.messages, vec!["This is a Rust project. "]);
+        assert!(output.usage.is_none());
+
+        let (output, stopped) = parse_codex_stream_until(Cursor::new(data), |_: &CodexOutput| false).unwrap();
+        assert!(!stopped);
+        assert_eq!(output.messages.len(), 2);
+    }
+
+    #[test]
+    fn first_message_only_is_a_clean_exit() {
+        let opts = RunOptions {
+            backend: Arc::new(crate::backend::MockBackend {
+                stdout: include_str!("../tests/fixtures/sample_session.jsonl").to_string(),
+                exit_code: 143,
+                ..Default::default()
+            }),
+            first_message_only: true,
+            ..RunOptions::default()
+        };
+        let attempt = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert!(attempt.stopped_early);
+        assert_eq!(attempt.exit_code, 0);
+        assert_eq!(attempt.output.messages.len(), 1);
+    }
+
+    #[test]
+    fn stop_on_ends_the_run_at_the_first_match() {
+        let mut opts = RunOptions {
+            backend: Arc::new(crate::backend::MockBackend {
+                stdout: include_str!("../tests/fixtures/sample_session.jsonl").to_string(),
+                exit_code: 143,
+                ..Default::default()
+            }),
+            stop_on: Some(Regex::new(r"Rust \w+").unwrap()),
+            ..RunOptions::default()
+        };
+        let attempt = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert!(attempt.stopped_early);
+        assert_eq!(attempt.exit_code, 0);
+        assert_eq!(attempt.output.messages, ["This is a Rust project. "]);
+
+        opts.stop_on = Some(Regex::new("never said").unwrap());
+        let attempt = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert!(!attempt.stopped_early);
+        assert_eq!(attempt.output.messages.len(), 2);
+    }
+
+    #[test]
+    fn max_output_bytes_aborts_or_truncates() {
+        let mut opts = RunOptions {
+            backend: Arc::new(crate::backend::MockBackend {
+                stdout: include_str!("../tests/fixtures/sample_session.jsonl").to_string(),
+                ..Default::default()
+            }),
+            max_output_bytes: Some(10),
+            ..RunOptions::default()
+        };
+        let aborted = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert_eq!(aborted.exit_code, 1);
+        assert_eq!(aborted.output_limited, Some(10));
+        assert!(aborted.output.messages.is_empty());
+        assert!(aborted.output.errors[0].contains("--max-output-bytes"));
+
+        opts.truncate_overflow = true;
+        let truncated = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert_eq!(truncated.exit_code, 0);
+        assert!(truncated.stopped_early);
+        assert_eq!(
+            truncated.output.aggregated_message(),
+            "This is a \n[output truncated at 10 bytes]"
+        );
+
+        opts.max_output_bytes = Some(1000);
+        let within = attempt_codex_with(&[], "hi", &Mode::Exec, false, &opts).unwrap();
+        assert_eq!(within.output_limited, None);
+        assert!(!within.stopped_early);
+    }
+
+    #[test]
+    fn last_message_file_exists_however_the_run_ends() {
+        let dir = tempfile::tempdir().unwrap();
+        let answered = dir.path().join("answer.txt");
+        LastMessageFile(Some(answered.clone())).write("All done.");
+        assert_eq!(fs::read_to_string(&answered).unwrap(), "All done.");
+
+        let failed = dir.path().join("failed.txt");
+        drop(LastMessageFile(Some(failed.clone())));
+        assert_eq!(fs::read_to_string(&failed).unwrap(), "");
+
+        // Nothing to say of our own: what codex wrote there stays.
+        fs::write(&answered, "from codex").unwrap();
+        LastMessageFile(Some(answered.clone())).write("");
+        assert_eq!(fs::read_to_string(&answered).unwrap(), "from codex");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn codex_is_asked_for_the_last_message_only_when_it_can() {
+        #[derive(Debug)]
+        struct NewCodex;
+        impl CodexBackend for NewCodex {
+            fn spawn(&self, _argv: &[String], _opts: SpawnOptions) -> Result<Box<dyn CodexProcess>> {
+                unreachable!()
+            }
+            fn compat(&self) -> Compat {
+                Compat::for_version((0, 124, 0))
+            }
+        }
+        let mut opts = RunOptions {
+            backend: Arc::new(NewCodex),
+            last_message_file: Some(PathBuf::from("out.txt")),
+            ..RunOptions::default()
+        };
+        assert_eq!(with_last_message_flag(&["-m".into(), "o3".into()], &opts), ["-m", "o3", "--output-last-message", "out.txt"]);
+        assert_eq!(with_last_message_flag(&["-o".into(), "mine.txt".into()], &opts), ["-o", "mine.txt"]);
+
+        opts.transport = Transport::Proto;
+        assert!(with_last_message_flag(&[], &opts).is_empty());
+        opts.transport = Transport::Exec;
+        opts.backend = Arc::new(crate::backend::MockBackend::default());
+        assert!(with_last_message_flag(&[], &opts).is_empty());
+    }
+
+    #[test]
+    fn heartbeat_ticks_until_stopped() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&ticks);
+        let (done, handle) = spawn_heartbeat(Duration::from_millis(20), move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        thread::sleep(Duration::from_millis(110));
+        done.send(()).unwrap();
+        handle.join().unwrap();
+        let seen = ticks.load(Ordering::Relaxed);
+        assert!(seen >= 2, "only {} ticks", seen);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(ticks.load(Ordering::Relaxed), seen);
+    }
+
+    #[test]
+    fn heartbeat_line_formats_elapsed() {
+        assert_eq!(
+            heartbeat_line(Duration::from_secs(42), 3),
+            "codex-clean: still running (42s elapsed, 3 events so far)"
+        );
+        assert!(heartbeat_line(Duration::from_secs(605), 0).contains("(10m05s elapsed"));
+    }
+
+    #[test]
+    fn progress_json_is_one_small_object() {
+        assert_eq!(
+            progress_json("running", Duration::from_millis(12_345), 42, None),
+            r#"{"phase":"running","elapsed":12.3,"events":42}"#
+        );
+        let exited: serde_json::Value =
+            serde_json::from_str(&progress_json("exited", Duration::from_secs(3), 7, Some(124))).unwrap();
+        assert_eq!(exited["exit_code"], 124);
+    }
+
+    #[test]
+    fn line_counter_counts_newlines() {
+        let lines = Arc::new(AtomicUsize::new(0));
+        let mut r = LineCounter { inner: Cursor::new("a\nb\n\nc"), lines: Arc::clone(&lines) };
+        io::copy(&mut r, &mut io::sink()).unwrap();
+        assert_eq!(lines.load(Ordering::Relaxed), 3);
+    }
+
+    fn argv(args: &[&str], prompt: &str, mode: Mode) -> (Vec<String>, bool) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        codex_argv(&args, prompt, &mode, &Compat::default()).unwrap()
+    }
+
+    #[test]
+    fn codex_argv_per_mode() {
+        assert_eq!(
+            argv(&["-m", "o3"], "hi", Mode::Exec),
+            (strs(&["exec", "--json", "--skip-git-repo-check", "-m", "o3", "hi"]), false)
+        );
+        assert_eq!(
+            argv(&["-m", "o3"], "more", Mode::Resume(ResumeTarget::SessionId("s1".into()))),
+            (strs(&["exec", "--json", "--skip-git-repo-check", "-m", "o3", "resume", "s1", "more"]), false)
+        );
+        assert_eq!(
+            argv(&[], "more", Mode::Resume(ResumeTarget::Last)),
+            (strs(&["exec", "--json", "--skip-git-repo-check", "resume", "--last"]), true)
+        );
+        assert_eq!(
+            argv(&["--uncommitted"], "", Mode::Review),
+            (strs(&["exec", "review", "--json", "--skip-git-repo-check", "--uncommitted"]), false)
+        );
+    }
+
+    #[test]
+    fn long_prompts_go_to_stdin() {
+        let long = "x".repeat(STDIN_PROMPT_BYTES + 1);
+        assert_eq!(argv(&[], &long, Mode::Exec), (strs(&["exec", "--json", "--skip-git-repo-check", "-"]), true));
+        assert_eq!(
+            argv(&[], &long, Mode::Resume(ResumeTarget::SessionId("s1".into()))),
+            (strs(&["exec", "--json", "--skip-git-repo-check", "resume", "s1", "-"]), true)
+        );
+        let (short, stdin) = argv(&[], &"x".repeat(STDIN_PROMPT_BYTES), Mode::Exec);
+        assert!(!stdin);
+        assert_eq!(short.last().map(String::len), Some(STDIN_PROMPT_BYTES));
+        assert!(codex_argv(&[], &long, &Mode::Review, &Compat::default()).is_err());
+    }
+
+    #[test]
+    fn command_line_room_counts_every_argument() {
+        let args = strs(&["exec", "--json"]);
+        // 3 arguments plus the prompt, each with a NUL and a pointer.
+        let needed = "codex".len() + "exec".len() + "--json".len() + "hi".len() + 4 * (1 + std::mem::size_of::<usize>());
+        assert!(fits_command_line(&args, "hi", Some(needed)));
+        assert!(!fits_command_line(&args, "hi", Some(needed - 1)));
+        assert!(fits_command_line(&args, &"x".repeat(1 << 20), None));
+    }
+
+    #[test]
+    fn codex_argv_follows_compat() {
+        let old = Compat::for_version((0, 48, 0));
+        let (argv, _) = codex_argv(&[], "hi", &Mode::Exec, &old).unwrap();
+        assert_eq!(argv, strs(&["exec", "--experimental-json", "--skip-git-repo-check", "hi"]));
+
+        let older = Compat::for_version((0, 40, 0));
+        let resume = Mode::Resume(ResumeTarget::Last);
+        let err = codex_argv(&[], "hi", &resume, &older).unwrap_err();
+        assert!(err.to_string().contains("0.40.0 has no `exec resume`"));
+    }
+
+    fn strs(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn run_outcome_json_summarises_attempt() {
+        let data = r#"
+{"type":"thread.started","thread_id":"s1"}
+{"type":"item.completed","item":{"type":"agent_message","text":"a"}}
+{"type":"item.completed","item":{"type":"agent_message","text":"b"}}
+{"type":"turn.completed","usage":{"input_tokens":10,"cached_input_tokens":4,"output_tokens":2,"reasoning_output_tokens":1}}
+"#;
+        let output = parse_codex_stream(BufReader::new(Cursor::new(data))).unwrap();
+        let attempt = AttemptResult {
+            output,
+            stderr_buffer: b"noise".to_vec(),
+            stderr_truncated: false,
+            stderr_error: None,
+            exit_code: 0,
+            status_success: true,
+            child_exit: 0,
+            timed_out: None,
+            cancelled: false,
+            stopped_early: false,
+            output_limited: None,
+            timings: Default::default(),
+        };
+        let v = RunOutcome::surface(attempt).to_json();
+        assert_eq!(v["session_id"], "s1");
+        assert_eq!(v["message"], "a\nb");
+        assert_eq!(v["messages"], serde_json::json!(["a", "b"]));
+        assert_eq!(v["usage"]["cached_input_tokens"], 4);
+        assert_eq!(v["exit_code"], 0);
+        assert!(v.get("stderr").is_none(), "stderr is only included on failure");
+
+        assert_eq!(RunOutcome::code(75).to_json(), serde_json::json!({ "exit_code": 75 }));
+    }
+
+    #[test]
+    fn cut_off_answers_are_continued_and_joined() {
+        let outcome = |data: &str, exit_code: i32| {
+            let output = parse_codex_stream(BufReader::new(Cursor::new(data.to_string()))).unwrap();
+            RunOutcome::surface(AttemptResult {
+                output,
+                stderr_buffer: Vec::new(),
+                stderr_truncated: false,
+                stderr_error: None,
+                exit_code,
+                status_success: exit_code == 0,
+                child_exit: exit_code,
+                timed_out: None,
+                cancelled: false,
+                stopped_early: false,
+                output_limited: None,
+                timings: Default::default(),
+            })
+        };
+        let first = outcome(
+            r#"{"type":"thread.started","thread_id":"s1"}
+{"type":"item.completed","item":{"type":"command_execution","command":"ls","exit_code":0}}
+{"type":"item.completed","item":{"type":"agent_message","text":"```sh\necho one"}}
+{"type":"turn.failed","error":{"message":"Incomplete response returned, reason: max_output_tokens"}}
+"#,
+            1,
+        );
+        assert_eq!(first.cut_off_session(), Some(("s1".to_string(), CutOff::OutputLimit)));
+        let next = outcome(
+            r#"{"type":"thread.started","thread_id":"s1"}
+{"type":"item.completed","item":{"type":"agent_message","text":"```sh\necho two\n```"}}
+"#,
+            0,
+        );
+        let joined = first.continued_by(next);
+        assert_eq!(joined.exit_code, 0);
+        assert_eq!(joined.cut_off_session(), None);
+        let output = &joined.attempt.as_ref().unwrap().output;
+        assert_eq!(output.aggregated_message(), "```sh\necho one\necho two\n```");
+        assert_eq!(output.commands.len(), 1);
+
+        // A failure that isn't the cut isn't continued.
+        let failed = outcome(
+            r#"{"type":"thread.started","thread_id":"s1"}
+{"type":"item.completed","item":{"type":"agent_message","text":"```sh\necho one"}}
+{"type":"turn.failed","error":{"message":"sandbox denied"}}
+"#,
+            1,
+        );
+        assert_eq!(failed.cut_off_session(), None);
+    }
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventObserver for Recorder {
+        fn on_session_start(&self, session_id: &str) {
+            self.0.lock().unwrap().push(format!("start {}", session_id));
+        }
+        fn on_message(&self, text: &str) {
+            self.0.lock().unwrap().push(format!("message {}", text));
+        }
+        fn on_command(&self, command: &crate::events::CommandRun) {
+            self.0.lock().unwrap().push(format!("command {}", command.command));
+        }
+        fn on_error(&self, message: &str) {
+            self.0.lock().unwrap().push(format!("error {}", message));
+        }
+    }
+
+    #[test]
+    fn observer_sees_events_as_they_are_parsed() {
+        let data = concat!(
+            r#"{"type":"thread.started","thread_id":"t1"}"#, "\n",
+            r#"{"type":"item.completed","item":{"type":"command_execution","command":"ls","exit_code":0}}"#, "\n",
+            r#"{"type":"item.completed","item":{"type":"agent_message","text":"hi"}}"#, "\n",
+            r#"{"type":"error","message":"hiccup"}"#, "\n",
+        );
+        let recorder = Recorder::default();
+        let (output, _) = parse_codex_stream_observed(Cursor::new(data), Some(&recorder), |_: &CodexOutput| false).unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            ["start t1", "command ls", "message hi", "error hiccup"]
+        );
+        assert_eq!(output.events_recognized, 4);
+        assert_eq!(output.messages, ["hi"]);
+    }
+
     #[test]
     fn parse_codex_stream_propagates_errors() {
-        // Invalid UTF-8 sequence should trigger an error from lines()
-        let data = b"\x80\x80";
-        let cursor = Cursor::new(&data[..]);
-        let err = parse_codex_stream(BufReader::new(cursor)).unwrap_err();
-        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "gone"))
+            }
+        }
+        let err = parse_codex_stream(BufReader::new(Broken)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        // A stream cut inside a character keeps the answer before the cut.
+        let data = b"{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"hi\"}}\n{\"type\":\"item.completed\",\"item\":{\"text\":\"caf\xc3";
+        let output = parse_codex_stream(BufReader::new(Cursor::new(&data[..]))).unwrap();
+        assert_eq!(output.messages, ["hi"]);
     }
 }
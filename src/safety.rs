@@ -0,0 +1,292 @@
+//! A confirmation gate in front of codex's most dangerous modes.
+//!
+//! codex-clean forwards codex arguments untouched, including the ones that
+//! let the agent run anything without asking:
+//! `--dangerously-bypass-approvals-and-sandbox` (and its `--yolo` alias),
+//! `--sandbox danger-full-access`, and `--full-auto` when the git tree
+//! already has uncommitted changes that the agent's edits would be mixed
+//! into. Before such a run codex-clean asks for a `y` on the terminal,
+//! unless `--yes` was given. With no terminal to ask on, the run is
+//! refused. `confirm_dangerous` in the config picks the policy.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::compat::{self, Compat};
+use crate::git;
+
+/// `confirm_dangerous` in `config.toml`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmPolicy {
+    /// Ask on the terminal; `--yes` answers for you.
+    #[default]
+    Ask,
+    /// Run without asking.
+    Allow,
+    /// Never run these modes, even with `--yes`.
+    Refuse,
+}
+
+/// Why a run needs confirming, or None if it doesn't. `check_tree` says
+/// whether the local working tree is the one codex will edit (not so for
+/// `--ssh` or containers).
+pub fn assess(args: &[String], check_tree: bool) -> Option<String> {
+    let mut iter = args.iter().map(String::as_str);
+    let mut full_auto = false;
+    while let Some(arg) = iter.next() {
+        match arg {
+            "--dangerously-bypass-approvals-and-sandbox" | "--yolo" => {
+                return Some(format!("`{}` lets codex run any command with no sandbox and no approvals", arg));
+            }
+            "-s" | "--sandbox" if iter.next() == Some("danger-full-access") => {
+                return Some("`--sandbox danger-full-access` lets codex write anywhere on this machine".to_string());
+            }
+            "-sdanger-full-access" | "--sandbox=danger-full-access" => {
+                return Some("`--sandbox danger-full-access` lets codex write anywhere on this machine".to_string());
+            }
+            "--full-auto" => full_auto = true,
+            _ => {}
+        }
+    }
+    if full_auto && check_tree {
+        let dir = git::codex_working_dir(args).unwrap_or_else(|| PathBuf::from("."));
+        if let Some(changes) = uncommitted_changes(&dir) {
+            return Some(format!(
+                "`--full-auto` lets codex edit files without asking, and the git tree has {} uncommitted change(s) its edits would be mixed into",
+                changes
+            ));
+        }
+    }
+    None
+}
+
+/// Apply `policy` to the run `args` describe, asking on the terminal when
+/// it says to.
+pub fn confirm(args: &[String], check_tree: bool, policy: ConfirmPolicy, yes: bool) -> Result<()> {
+    if policy == ConfirmPolicy::Allow {
+        return Ok(());
+    }
+    let Some(reason) = assess(args, check_tree) else {
+        return Ok(());
+    };
+    if policy == ConfirmPolicy::Refuse {
+        bail!("{}; refusing to run (confirm_dangerous = \"refuse\")", reason);
+    }
+    if yes {
+        return Ok(());
+    }
+    match ask(&format!("{}.\nRun codex anyway? [y/N] ", reason)) {
+        Some(true) => Ok(()),
+        Some(false) => bail!("not confirmed; codex was not started"),
+        None => bail!("{}; pass --yes to run it without a terminal to confirm on", reason),
+    }
+}
+
+/// `--mode`: a sandbox preset, so the codex flags for it needn't be
+/// remembered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    /// codex may read files and run commands, but not write.
+    ReadOnly,
+    /// codex may write inside the working directory.
+    WorkspaceWrite,
+    /// No sandbox and no approvals; always confirmed first.
+    Yolo,
+}
+
+impl SandboxMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ReadOnly => "read-only",
+            Self::WorkspaceWrite => "workspace-write",
+            Self::Yolo => "yolo",
+        }
+    }
+
+    /// The codex arguments the preset stands for.
+    pub fn args(self) -> Vec<String> {
+        match self {
+            Self::ReadOnly => vec!["--sandbox".to_string(), "read-only".to_string()],
+            Self::WorkspaceWrite => vec!["--sandbox".to_string(), "workspace-write".to_string()],
+            Self::Yolo => vec!["--dangerously-bypass-approvals-and-sandbox".to_string()],
+        }
+    }
+
+    /// The `confirm_dangerous` policy for a run in this mode: yolo is asked
+    /// about even when the config allows dangerous modes.
+    pub fn policy(self, configured: ConfirmPolicy) -> ConfirmPolicy {
+        match (self, configured) {
+            (Self::Yolo, ConfirmPolicy::Allow) => ConfirmPolicy::Ask,
+            _ => configured,
+        }
+    }
+
+    /// Check that the preset is the only thing in the run's codex `args`
+    /// (which include it) choosing a sandbox, and that the codex it runs
+    /// is a release the presets are known to be right for.
+    pub fn check(self, args: &[String], compat: &Compat) -> Result<()> {
+        let others: Vec<String> = sandbox_flags(args).into_iter().filter(|f| *f != self.args().join(" ")).collect();
+        if !others.is_empty() {
+            bail!(
+                "--mode {} conflicts with {} in the codex arguments; use one or the other",
+                self.name(),
+                others.iter().map(|f| format!("`{}`", f)).collect::<Vec<_>>().join(", ")
+            );
+        }
+        if let Some(version) = compat.version.filter(|&v| v < compat::MIN_SUPPORTED) {
+            bail!(
+                "--mode needs codex {} or newer to be sure of its flags, and codex {} is installed; pass the sandbox flags yourself or upgrade codex",
+                compat::display(compat::MIN_SUPPORTED),
+                compat::display(version)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Every flag in `args` that picks a sandbox, as `--sandbox <mode>` or
+/// the flag itself.
+fn sandbox_flags(args: &[String]) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut iter = args.iter().map(String::as_str);
+    while let Some(arg) = iter.next() {
+        match arg {
+            "-s" | "--sandbox" => flags.push(format!("--sandbox {}", iter.next().unwrap_or(""))),
+            "--full-auto" | "--dangerously-bypass-approvals-and-sandbox" | "--yolo" => flags.push(arg.to_string()),
+            _ => {
+                if let Some(mode) = arg.strip_prefix("--sandbox=").or_else(|| arg.strip_prefix("-s")) {
+                    flags.push(format!("--sandbox {}", mode));
+                }
+            }
+        }
+    }
+    flags
+}
+
+/// How many entries `git status --porcelain` lists for `dir`; None when
+/// the tree is clean or isn't a git checkout.
+fn uncommitted_changes(dir: &Path) -> Option<usize> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let count = String::from_utf8_lossy(&out.stdout).lines().filter(|l| !l.trim().is_empty()).count();
+    (count > 0).then_some(count)
+}
+
+/// Ask a yes/no question on the controlling terminal. None if there is
+/// none (stdin may carry the prompt, so the terminal is opened directly).
+fn ask(question: &str) -> Option<bool> {
+    let answer = read_answer(question)?;
+    Some(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Print `question` on the controlling terminal and read a line back.
+/// None if there is no terminal.
+#[cfg(unix)]
+pub fn read_answer(question: &str) -> Option<String> {
+    crate::bell::ask();
+    let tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    let mut out = &tty;
+    out.write_all(question.as_bytes()).ok()?;
+    out.flush().ok()?;
+    let mut line = String::new();
+    io::BufReader::new(&tty).read_line(&mut line).ok()?;
+    Some(line)
+}
+
+#[cfg(not(unix))]
+pub fn read_answer(question: &str) -> Option<String> {
+    use std::io::IsTerminal;
+
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    crate::bell::ask();
+    eprint!("{}", question);
+    io::stderr().flush().ok()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).ok()?;
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn dangerous_flags_are_recognised() {
+        assert!(assess(&strs(&["--yolo"]), false).unwrap().contains("no sandbox"));
+        assert!(assess(&strs(&["-m", "o3", "--dangerously-bypass-approvals-and-sandbox"]), false).is_some());
+        assert!(assess(&strs(&["--sandbox", "danger-full-access"]), false).is_some());
+        assert!(assess(&strs(&["--sandbox=danger-full-access"]), false).is_some());
+        assert_eq!(assess(&strs(&["--sandbox", "read-only"]), false), None);
+        // Without a local tree to look at, --full-auto is fine.
+        assert_eq!(assess(&strs(&["--full-auto"]), false), None);
+    }
+
+    #[test]
+    fn mode_presets_expand_and_refuse_conflicting_flags() {
+        let current = Compat::for_version((0, 125, 0));
+        let with = |mode: SandboxMode, rest: &[&str]| {
+            let mut args = strs(&["-m", "o3"]);
+            args.extend(mode.args());
+            args.extend(strs(rest));
+            mode.check(&args, &current)
+        };
+        assert_eq!(SandboxMode::ReadOnly.args(), strs(&["--sandbox", "read-only"]));
+        assert!(with(SandboxMode::ReadOnly, &["--json"]).is_ok());
+        let err = with(SandboxMode::ReadOnly, &["-sworkspace-write", "--full-auto"]).unwrap_err().to_string();
+        assert!(err.contains("conflicts with `--sandbox workspace-write`, `--full-auto`"), "{}", err);
+        assert!(with(SandboxMode::WorkspaceWrite, &["--sandbox=danger-full-access"]).is_err());
+        assert!(with(SandboxMode::Yolo, &[]).is_ok());
+        assert!(assess(&SandboxMode::Yolo.args(), false).is_some());
+
+        let old = Compat::for_version((0, 40, 0));
+        assert!(SandboxMode::ReadOnly.check(&SandboxMode::ReadOnly.args(), &old).unwrap_err().to_string().contains("0.40.0 is installed"));
+        assert!(SandboxMode::ReadOnly.check(&SandboxMode::ReadOnly.args(), &Compat::default()).is_ok());
+        assert_eq!(SandboxMode::Yolo.policy(ConfirmPolicy::Allow), ConfirmPolicy::Ask);
+        assert_eq!(SandboxMode::ReadOnly.policy(ConfirmPolicy::Allow), ConfirmPolicy::Allow);
+    }
+
+    #[test]
+    fn full_auto_needs_confirming_only_in_a_dirty_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git").arg("-C").arg(dir.path()).args(args).output().unwrap();
+        };
+        git(&["init", "-q"]);
+        let path = dir.path().to_string_lossy().into_owned();
+        let args = strs(&["--full-auto", "-C", &path]);
+        assert_eq!(assess(&args, true), None);
+
+        std::fs::write(dir.path().join("notes.txt"), "wip").unwrap();
+        assert!(assess(&args, true).unwrap().contains("1 uncommitted change(s)"));
+    }
+
+    #[test]
+    fn policy_and_yes_decide_without_asking() {
+        let yolo = strs(&["--yolo"]);
+        assert!(confirm(&yolo, false, ConfirmPolicy::Allow, false).is_ok());
+        assert!(confirm(&yolo, false, ConfirmPolicy::Ask, true).is_ok());
+        let err = confirm(&yolo, false, ConfirmPolicy::Refuse, true).unwrap_err();
+        assert!(err.to_string().contains("refusing to run"));
+        assert!(confirm(&strs(&["-m", "o3"]), false, ConfirmPolicy::Refuse, false).is_ok());
+    }
+}
@@ -501,7 +501,7 @@ pub fn pick_seat(
         .filter(|s| {
             let st = state.get(&s.name);
             !st.needs_login
-                && st.cooldown_until.map_or(true, |u| u <= now)
+                && st.cooldown_until.is_none_or(|u| u <= now)
         })
         .collect();
 
@@ -772,8 +772,7 @@ mod tests {
     #[test]
     fn pick_seat_round_robin_advances() {
         let c = cfg(&["a", "b", "c"], Strategy::RoundRobin);
-        let mut s = SeatState::default();
-        s.active_seat = Some("a".to_string());
+        let mut s = SeatState { active_seat: Some("a".to_string()), ..Default::default() };
         // All eligible (no cooldowns, no needs_login).
         assert_eq!(pick_seat(&c, &s, None, now()).unwrap(), "b");
         s.active_seat = Some("c".to_string());
@@ -783,8 +782,7 @@ mod tests {
     #[test]
     fn pick_seat_round_robin_skips_ineligible() {
         let c = cfg(&["a", "b", "c"], Strategy::RoundRobin);
-        let mut s = SeatState::default();
-        s.active_seat = Some("a".to_string());
+        let mut s = SeatState { active_seat: Some("a".to_string()), ..Default::default() };
         s.entry_mut("b").cooldown_until = Some(now() + chrono::Duration::minutes(30));
         // After a, b is cooling → c.
         assert_eq!(pick_seat(&c, &s, None, now()).unwrap(), "c");
@@ -829,8 +827,7 @@ mod tests {
 
     #[test]
     fn seat_state_round_trips_via_json() {
-        let mut s = SeatState::default();
-        s.active_seat = Some("a".to_string());
+        let mut s = SeatState { active_seat: Some("a".to_string()), ..Default::default() };
         s.entry_mut("a").last_used = Some(now());
         s.entry_mut("a").cooldown_until = Some(now() + chrono::Duration::hours(1));
         s.entry_mut("a").consecutive_failures = 2;
@@ -0,0 +1,242 @@
+//! `codex-clean self-update`: replace this binary with the latest release.
+//!
+//! Releases on GitHub carry one binary per platform, named
+//! `codex-clean-<arch>-<os>` (`codex-clean-x86_64-linux`,
+//! `codex-clean-aarch64-macos`, `codex-clean-x86_64-windows.exe`), and a
+//! `SHA256SUMS` file listing their digests. The download is checked against
+//! `SHA256SUMS`, smoke-tested with `--version`, and only then renamed over
+//! the running executable, so a failed update leaves the old binary working.
+//!
+//! HTTP goes through `curl`, which is on every platform we ship for.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/Talieisin/codex-clean/releases/latest";
+
+/// Overrides [`LATEST_RELEASE_URL`], for mirrors and testing.
+pub const ENV_RELEASE_URL: &str = "CODEX_CLEAN_RELEASE_URL";
+
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow!("release {} has no {} asset", self.tag_name, name))
+    }
+}
+
+/// The release asset holding the binary for this platform.
+pub fn asset_name() -> String {
+    format!(
+        "codex-clean-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+/// `1.2.3` from `v1.2.3` or `1.2.3`; pre-release suffixes sort as the
+/// release itself.
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let v = v.trim().trim_start_matches('v');
+    let core = v.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some((major, minor, patch))
+}
+
+/// Whether `latest` is newer than `current`.
+pub fn is_newer(latest: &str, current: &str) -> Result<bool> {
+    let parse = |v: &str| parse_version(v).ok_or_else(|| anyhow!("unrecognised version '{}'", v));
+    Ok(parse(latest)? > parse(current)?)
+}
+
+/// The digest for `asset` in a `sha256sum`-format listing.
+pub fn expected_digest(sums: &str, asset: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.split_once(char::is_whitespace)?;
+        // `sha256sum -b` marks binary mode with a leading `*`.
+        let name = name.trim_start().trim_start_matches('*');
+        (name == asset).then(|| digest.to_ascii_lowercase())
+    })
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn curl(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .args(["--user-agent", concat!("codex-clean/", env!("CARGO_PKG_VERSION"))])
+        .arg(url)
+        .output()
+        .context("running curl (is it installed?)")?;
+    if !output.status.success() {
+        bail!(
+            "downloading {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// `codex-clean self-update [--check] [--force]`.
+pub fn run(check_only: bool, force: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let url = std::env::var(ENV_RELEASE_URL)
+        .ok()
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| LATEST_RELEASE_URL.to_string());
+    let release: Release = serde_json::from_slice(&curl(&url)?)
+        .with_context(|| format!("parsing release information from {}", url))?;
+    let latest = release.tag_name.trim_start_matches('v');
+
+    if !is_newer(latest, current)? && !force {
+        eprintln!("codex-clean {} is up to date.", current);
+        return Ok(());
+    }
+    if check_only {
+        println!("codex-clean {} is available (installed: {}).", latest, current);
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let binary = release.asset(&name)?;
+    let sums = release.asset(CHECKSUMS_ASSET)?;
+    let sums = String::from_utf8(curl(&sums.browser_download_url)?)
+        .context("SHA256SUMS is not valid UTF-8")?;
+    let expected = expected_digest(&sums, &name)
+        .ok_or_else(|| anyhow!("SHA256SUMS has no entry for {}", name))?;
+    eprintln!("Downloading codex-clean {} ({})...", latest, name);
+    let bytes = curl(&binary.browser_download_url)?;
+    let actual = sha256_hex(&bytes);
+    if actual != expected {
+        bail!("checksum mismatch for {}: expected {}, got {}", name, expected, actual);
+    }
+
+    let exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("locating the running executable")?;
+    install(&exe, &bytes)?;
+    eprintln!("Updated codex-clean {} -> {} at {}.", current, latest, exe.display());
+    Ok(())
+}
+
+/// Put `bytes` in place of `exe`. The new binary is written next to it
+/// (so the final rename stays on one filesystem) and must answer
+/// `--version` before it replaces anything.
+pub fn install(exe: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = sibling(exe, "new");
+    let result = (|| -> Result<()> {
+        fs::write(&staged, bytes).with_context(|| format!("writing {}", staged.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+                .with_context(|| format!("making {} executable", staged.display()))?;
+        }
+        let status = Command::new(&staged)
+            .arg("--version")
+            .output()
+            .with_context(|| format!("running {}", staged.display()))?
+            .status;
+        if !status.success() {
+            bail!("the downloaded binary failed to run ({})", status);
+        }
+        // Windows won't overwrite a running executable, but will rename it.
+        let old = cfg!(windows).then(|| sibling(exe, "old"));
+        if let Some(old) = &old {
+            let _ = fs::remove_file(old);
+            fs::rename(exe, old).with_context(|| format!("moving {} aside", exe.display()))?;
+        }
+        let replaced = fs::rename(&staged, exe).with_context(|| format!("replacing {}", exe.display()));
+        if let (Err(_), Some(old)) = (&replaced, &old) {
+            // Put the old binary back rather than leave none.
+            let _ = fs::rename(old, exe);
+        }
+        replaced
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+fn sibling(exe: &Path, tag: &str) -> PathBuf {
+    let name = exe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    exe.with_file_name(format!(".{}.{}-{}", name, tag, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(is_newer("v0.10.0", "0.9.3").unwrap());
+        assert!(is_newer("0.4.1", "0.4.0").unwrap());
+        assert!(!is_newer("0.4.0", "0.4.0").unwrap());
+        assert!(!is_newer("0.4.0-rc.1", "0.4.0").unwrap());
+        assert!(!is_newer("1", "1.0.0").unwrap());
+        assert!(is_newer("nightly", "0.4.0").is_err());
+    }
+
+    #[test]
+    fn checksum_lines_are_matched_by_asset_name() {
+        let sums = "0ABC  codex-clean-x86_64-linux\n\
+                    def1 *codex-clean-aarch64-macos\n\
+                    9999  codex-clean-x86_64-linux.tar.gz\n";
+        assert_eq!(expected_digest(sums, "codex-clean-x86_64-linux").as_deref(), Some("0abc"));
+        assert_eq!(expected_digest(sums, "codex-clean-aarch64-macos").as_deref(), Some("def1"));
+        assert_eq!(expected_digest(sums, "codex-clean-x86_64-windows.exe"), None);
+    }
+
+    #[test]
+    fn asset_name_names_this_platform() {
+        let name = asset_name();
+        assert!(name.starts_with("codex-clean-"));
+        assert!(name.contains(std::env::consts::ARCH));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_replaces_only_with_a_working_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("codex-clean");
+        fs::write(&exe, "old").unwrap();
+
+        assert!(install(&exe, b"#!/bin/sh\nexit 3\n").is_err());
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1, "staged file left behind");
+
+        install(&exe, b"#!/bin/sh\necho codex-clean 9.9.9\n").unwrap();
+        assert!(fs::read_to_string(&exe).unwrap().contains("9.9.9"));
+    }
+}
@@ -0,0 +1,402 @@
+//! Discovery of codex's on-disk session logs.
+//!
+//! codex persists every session as a "rollout" file under
+//! `$CODEX_HOME/sessions/YYYY/MM/DD/rollout-<timestamp>-<session-id>.jsonl`.
+//! This module walks that tree so subcommands can resolve a session ID (or
+//! "the most recent session") to a file without asking codex. Sessions
+//! imported from other tools (see [`crate::import`]) are kept the same way
+//! under [`imported_dir`], and are found by the subcommands that only read
+//! a session.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde_json::Value;
+
+use crate::seat::{codex_home, config_dir};
+use crate::summary;
+
+/// A rollout file found under the sessions directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionFile {
+    /// Session (thread) ID parsed from the file name.
+    pub id: String,
+    pub path: PathBuf,
+    /// Last modification time; advances whenever the session is resumed.
+    pub modified: SystemTime,
+}
+
+/// `~/.codex/sessions`.
+pub fn sessions_dir() -> Result<PathBuf> {
+    Ok(codex_home()?.join("sessions"))
+}
+
+/// `~/.config/codex-clean/imported`, where `codex-clean import` keeps
+/// other tools' sessions.
+pub fn imported_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("imported"))
+}
+
+/// All sessions under `~/.codex/sessions`, most recently active first.
+pub fn list_sessions() -> Result<Vec<SessionFile>> {
+    list_sessions_in(&sessions_dir()?)
+}
+
+/// codex's sessions and imported ones together, most recently active first.
+/// codex can't resume an imported session, so only lookups for reading one
+/// include them.
+pub fn list_archive() -> Result<Vec<SessionFile>> {
+    let mut all = list_sessions()?;
+    all.extend(list_sessions_in(&imported_dir()?)?);
+    sort_recent_first(&mut all);
+    Ok(all)
+}
+
+/// All sessions under `root`, most recently active first. A missing root
+/// yields an empty list rather than an error: codex creates the directory
+/// lazily on first run.
+pub fn list_sessions_in(root: &Path) -> Result<Vec<SessionFile>> {
+    let mut out = Vec::new();
+    if root.exists() {
+        walk(root, &mut out)?;
+    }
+    sort_recent_first(&mut out);
+    Ok(out)
+}
+
+fn sort_recent_first(sessions: &mut [SessionFile]) {
+    // Ties (same mtime) are broken by file name, which embeds the creation
+    // timestamp, so the order is stable across calls.
+    sessions.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| b.path.cmp(&a.path)));
+}
+
+fn walk(dir: &Path, out: &mut Vec<SessionFile>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("reading {}", dir.display()))?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&path, out)?;
+            continue;
+        }
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        let Some(id) = session_id_from_file_name(name) else { continue };
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        out.push(SessionFile { id: id.to_string(), path, modified });
+    }
+    Ok(())
+}
+
+/// Extract the session ID from `rollout-2026-04-28T12-00-00-<uuid>.jsonl`.
+/// The ID is always the trailing 36-character UUID.
+pub fn session_id_from_file_name(name: &str) -> Option<&str> {
+    let stem = name.strip_prefix("rollout-")?.strip_suffix(".jsonl")?;
+    if stem.len() < 36 || !stem.is_char_boundary(stem.len() - 36) {
+        return None;
+    }
+    let id = &stem[stem.len() - 36..];
+    id.chars()
+        .all(|c| c.is_ascii_hexdigit() || c == '-')
+        .then_some(id)
+}
+
+/// Locate the rollout file for a full session ID or a unique prefix of one.
+pub fn find_session(id_or_prefix: &str) -> Result<SessionFile> {
+    find_session_in(list_sessions()?, id_or_prefix)
+}
+
+fn find_session_in(sessions: Vec<SessionFile>, id_or_prefix: &str) -> Result<SessionFile> {
+    if is_session_uuid(id_or_prefix) {
+        let id = id_or_prefix.to_ascii_lowercase();
+        return sessions.into_iter().find(|s| s.id == id).ok_or_else(|| {
+            anyhow!("no session log found for '{}' under {}", id, sessions_dir_display())
+        });
+    }
+    match_prefix(&sessions, id_or_prefix).cloned()
+}
+
+/// Turn user input into a full session ID suitable for `codex exec resume`.
+///
+/// Full IDs are validated as UUIDs but not looked up on disk (codex reports
+/// a missing session itself). Anything shorter is treated as a prefix and
+/// must match exactly one session under `~/.codex/sessions`.
+pub fn resolve_session_id(id_or_prefix: &str) -> Result<String> {
+    if is_session_uuid(id_or_prefix) {
+        return Ok(id_or_prefix.to_ascii_lowercase());
+    }
+    let sessions = list_sessions()?;
+    Ok(match_prefix(&sessions, id_or_prefix)?.id.clone())
+}
+
+/// True for a canonical 8-4-4-4-12 hex UUID.
+pub fn is_session_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Find the single session whose ID starts with `prefix`. Errors list the
+/// candidates when the prefix is ambiguous.
+pub fn match_prefix<'a>(sessions: &'a [SessionFile], prefix: &str) -> Result<&'a SessionFile> {
+    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        anyhow::bail!(
+            "'{}' is not a valid session ID (expected a UUID like 0199a213-81c0-7800-8aa1-bbab2a035a53 or a prefix of one)",
+            prefix
+        );
+    }
+    let prefix = prefix.to_ascii_lowercase();
+    let matches: Vec<&SessionFile> = sessions.iter().filter(|s| s.id.starts_with(&prefix)).collect();
+    match matches.as_slice() {
+        [] => Err(anyhow!(
+            "no session ID starts with '{}' under {}",
+            prefix,
+            sessions_dir_display()
+        )),
+        [only] => Ok(only),
+        many => {
+            let mut msg = format!("session prefix '{}' is ambiguous; candidates:", prefix);
+            for s in many.iter().take(MAX_LISTED_CANDIDATES) {
+                let when: DateTime<Local> = s.modified.into();
+                msg.push_str(&format!("\n  {}  (last active {})", s.id, when.format("%Y-%m-%d %H:%M")));
+                if let Some(title) = summary::title(&s.id) {
+                    msg.push_str(&format!("  {}", title));
+                }
+            }
+            if many.len() > MAX_LISTED_CANDIDATES {
+                msg.push_str(&format!("\n  ... and {} more", many.len() - MAX_LISTED_CANDIDATES));
+            }
+            Err(anyhow!(msg))
+        }
+    }
+}
+
+const MAX_LISTED_CANDIDATES: usize = 10;
+
+/// The most recently active session.
+pub fn latest_session() -> Result<SessionFile> {
+    list_sessions()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no sessions found under {}", sessions_dir_display()))
+}
+
+/// The `n`th most recently active session (1 = most recent).
+pub fn nth_recent_session(n: usize) -> Result<SessionFile> {
+    nth_recent_in(list_sessions()?, n)
+}
+
+fn nth_recent_in(sessions: Vec<SessionFile>, n: usize) -> Result<SessionFile> {
+    let count = sessions.len();
+    sessions.into_iter().nth(n.saturating_sub(1)).ok_or_else(|| {
+        anyhow!(
+            "asked for session #{} but only {} session(s) exist under {}",
+            n,
+            count,
+            sessions_dir_display()
+        )
+    })
+}
+
+/// A session given on the command line to read: an ID, a unique prefix,
+/// or `@N` for the Nth most recent, imported sessions included.
+pub fn lookup_session(session: &str) -> Result<SessionFile> {
+    match session.strip_prefix('@').map(str::parse::<usize>) {
+        Some(Ok(n)) if n > 0 => nth_recent_in(list_archive()?, n),
+        Some(_) => bail!("invalid session '{}': expected @N with N >= 1", session),
+        None => find_session_in(list_archive()?, session),
+    }
+}
+
+/// Who said a transcript entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    pub role: Role,
+    pub text: String,
+}
+
+/// The user/assistant dialogue recorded in a rollout file, in order.
+/// Tool calls, reasoning and command output are not included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub session_id: Option<String>,
+    /// The tool that answered, for an imported session; `None` is codex.
+    pub agent: Option<String>,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+/// Read the dialogue out of a rollout file.
+pub fn read_transcript(path: &Path) -> Result<Transcript> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut transcript = Transcript::default();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("reading {}", path.display()))?;
+        let Ok(v) = serde_json::from_str::<Value>(&line) else { continue };
+        let Some(payload) = v.get("payload") else { continue };
+        match v.get("type").and_then(|t| t.as_str()) {
+            Some("session_meta") if transcript.session_id.is_none() => {
+                transcript.session_id =
+                    payload.get("id").and_then(|i| i.as_str()).map(String::from);
+                transcript.agent = payload.get("agent").and_then(|a| a.as_str()).map(String::from);
+            }
+            Some("event_msg") => {
+                let role = match payload.get("type").and_then(|t| t.as_str()) {
+                    Some("user_message") => Role::User,
+                    Some("agent_message") => Role::Assistant,
+                    _ => continue,
+                };
+                let Some(text) = payload.get("message").and_then(|m| m.as_str()) else { continue };
+                if !text.trim().is_empty() {
+                    transcript.entries.push(TranscriptEntry { role, text: text.to_string() });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(transcript)
+}
+
+fn sessions_dir_display() -> String {
+    sessions_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "~/.codex/sessions".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID_A: &str = "0199a213-81c0-7800-8aa1-bbab2a035a53";
+    const ID_B: &str = "0199b456-0000-7000-8000-000000000001";
+
+    fn touch(root: &Path, rel: &str) -> PathBuf {
+        let p = root.join(rel);
+        fs::create_dir_all(p.parent().unwrap()).unwrap();
+        fs::write(&p, "{}\n").unwrap();
+        p
+    }
+
+    #[test]
+    fn session_id_from_file_name_extracts_uuid() {
+        let name = format!("rollout-2026-04-28T12-00-00-{}.jsonl", ID_A);
+        assert_eq!(session_id_from_file_name(&name), Some(ID_A));
+    }
+
+    #[test]
+    fn session_id_from_file_name_rejects_other_files() {
+        assert_eq!(session_id_from_file_name("history.jsonl"), None);
+        assert_eq!(session_id_from_file_name("rollout-short.jsonl"), None);
+        assert_eq!(
+            session_id_from_file_name(&format!("rollout-2026-04-28T12-00-00-{}.json", ID_A)),
+            None
+        );
+    }
+
+    fn session(id: &str) -> SessionFile {
+        SessionFile {
+            id: id.to_string(),
+            path: PathBuf::from(format!("rollout-{}.jsonl", id)),
+            modified: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn is_session_uuid_accepts_canonical_form_only() {
+        assert!(is_session_uuid(ID_A));
+        assert!(is_session_uuid(&ID_A.to_uppercase()));
+        assert!(!is_session_uuid("0199a213"));
+        assert!(!is_session_uuid("0199a213-81c0-7800-8aa1-bbab2a035a5z"));
+        assert!(!is_session_uuid("0199a21381c0-7800-8aa1-bbab2a035a53-"));
+    }
+
+    #[test]
+    fn match_prefix_finds_unique_session() {
+        let all = [session(ID_A), session(ID_B)];
+        assert_eq!(match_prefix(&all, "0199a2").unwrap().id, ID_A);
+        assert_eq!(match_prefix(&all, "0199B4").unwrap().id, ID_B);
+    }
+
+    #[test]
+    fn match_prefix_lists_candidates_when_ambiguous() {
+        let all = [session(ID_A), session(ID_B)];
+        let err = match_prefix(&all, "0199").unwrap_err().to_string();
+        assert!(err.contains("ambiguous"));
+        assert!(err.contains(ID_A));
+        assert!(err.contains(ID_B));
+    }
+
+    #[test]
+    fn match_prefix_rejects_missing_and_malformed() {
+        let all = [session(ID_A)];
+        assert!(match_prefix(&all, "ffff").unwrap_err().to_string().contains("no session ID"));
+        assert!(match_prefix(&all, "not-an-id!").unwrap_err().to_string().contains("not a valid"));
+        assert!(match_prefix(&all, "").is_err());
+    }
+
+    #[test]
+    fn read_transcript_collects_dialogue_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rollout.jsonl");
+        let lines = [
+            format!(r#"{{"type":"session_meta","payload":{{"id":"{}"}}}}"#, ID_A),
+            r#"{"type":"response_item","payload":{"type":"message","role":"user","content":[]}}"#.to_string(),
+            r#"{"type":"event_msg","payload":{"type":"user_message","message":"What is 2+2?"}}"#.to_string(),
+            r#"{"type":"event_msg","payload":{"type":"agent_reasoning","text":"thinking"}}"#.to_string(),
+            r#"{"type":"event_msg","payload":{"type":"agent_message","message":"4"}}"#.to_string(),
+            "not json".to_string(),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let t = read_transcript(&path).unwrap();
+        assert_eq!(t.session_id.as_deref(), Some(ID_A));
+        assert_eq!(
+            t.entries,
+            vec![
+                TranscriptEntry { role: Role::User, text: "What is 2+2?".into() },
+                TranscriptEntry { role: Role::Assistant, text: "4".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn list_sessions_in_missing_root_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = list_sessions_in(&dir.path().join("nope")).unwrap();
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn list_sessions_in_walks_date_tree_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = touch(
+            dir.path(),
+            &format!("2026/04/27/rollout-2026-04-27T09-00-00-{}.jsonl", ID_A),
+        );
+        touch(dir.path(), &format!("2026/04/28/rollout-2026-04-28T09-00-00-{}.jsonl", ID_B));
+        touch(dir.path(), "2026/04/28/unrelated.txt");
+        // Make the older file look older regardless of filesystem timestamp
+        // granularity.
+        let f = fs::File::options().write(true).open(&old).unwrap();
+        f.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+
+        let sessions = list_sessions_in(dir.path()).unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec![ID_B, ID_A]);
+    }
+}
@@ -0,0 +1,193 @@
+//! `--split-prompts FILE`: one markdown file of questions, answered in one
+//! session.
+//!
+//! Each level-1 heading (`# ...`) starts a question: the heading and the
+//! text under it, up to the next level-1 heading, are sent as one turn.
+//! The first question starts a session and the rest resume it (see
+//! [`crate::conversation::Session`]), so later answers can build on
+//! earlier ones. Text before the first heading is kept but not sent, and
+//! `#` lines inside code fences don't count as headings.
+//!
+//! Each answer goes under its question between [`ANSWER_START`] and
+//! [`ANSWER_END`] markers, and the filled-in file is printed or, with
+//! `--in-place`, written back. Asking again replaces the answers between
+//! the markers instead of adding more, and the old answers aren't sent.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::conversation::Session;
+use crate::runner::RunOptions;
+use crate::write_files;
+
+/// The line before each answer in the file.
+pub const ANSWER_START: &str = "<!-- codex-clean answer -->";
+/// The line after each answer in the file.
+pub const ANSWER_END: &str = "<!-- end codex-clean answer -->";
+
+/// One level-1 heading and what is under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The heading, without the `# `.
+    pub heading: String,
+    /// The text under the heading, less any earlier answer.
+    pub body: String,
+    pub answer: Option<String>,
+}
+
+impl Section {
+    /// What is sent to codex for this section.
+    pub fn prompt(&self) -> String {
+        match self.body.trim() {
+            "" => self.heading.clone(),
+            body => format!("{}\n\n{}", self.heading, body),
+        }
+    }
+}
+
+/// A split-prompts file: what comes before the first heading, then the
+/// sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub preamble: String,
+    pub sections: Vec<Section>,
+}
+
+enum Part {
+    Preamble,
+    Body,
+    Answer,
+}
+
+impl Document {
+    pub fn parse(raw: &str) -> Self {
+        let mut doc = Document { preamble: String::new(), sections: Vec::new() };
+        let mut part = Part::Preamble;
+        let mut fence: Option<String> = None;
+        for line in raw.split_inclusive('\n') {
+            let bare = line.trim_end_matches(['\n', '\r']);
+            match (&fence, write_files::fence_open(bare)) {
+                (None, Some((open, _))) => fence = Some(open.to_string()),
+                (Some(open), Some((close, info))) if close.starts_with(open.as_str()) && info.is_empty() => fence = None,
+                _ => {}
+            }
+            let in_fence = fence.is_some() || write_files::fence_open(bare).is_some();
+            if !in_fence {
+                if let Some(heading) = bare.strip_prefix("# ") {
+                    doc.sections.push(Section { heading: heading.trim().to_string(), body: String::new(), answer: None });
+                    part = Part::Body;
+                    continue;
+                }
+                if bare.trim() == ANSWER_START && !doc.sections.is_empty() {
+                    part = Part::Answer;
+                    continue;
+                }
+                if bare.trim() == ANSWER_END && matches!(part, Part::Answer) {
+                    part = Part::Body;
+                    continue;
+                }
+            }
+            match (&part, doc.sections.last_mut()) {
+                (Part::Body, Some(section)) => section.body.push_str(line),
+                (Part::Answer, Some(section)) => section.answer.get_or_insert_with(String::new).push_str(line),
+                _ => doc.preamble.push_str(line),
+            }
+        }
+        for section in &mut doc.sections {
+            section.body = section.body.trim().to_string();
+            section.answer = section.answer.take().map(|a| a.trim().to_string());
+        }
+        doc
+    }
+
+    /// The file again, with each section's answer under its text.
+    pub fn render(&self) -> String {
+        let mut out = self.preamble.clone();
+        for section in &self.sections {
+            while !out.is_empty() && !out.ends_with("\n\n") {
+                out.push('\n');
+            }
+            out.push_str("# ");
+            out.push_str(&section.heading);
+            out.push('\n');
+            if !section.body.is_empty() {
+                out.push('\n');
+                out.push_str(&section.body);
+                out.push('\n');
+            }
+            if let Some(answer) = &section.answer {
+                out.push('\n');
+                out.push_str(ANSWER_START);
+                out.push('\n');
+                out.push_str(answer);
+                out.push('\n');
+                out.push_str(ANSWER_END);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// Ask each section of `file` in turn in one session, then print the
+/// answered file, or write it back with `in_place`. A failed question
+/// ends the run; the answers so far are still kept.
+pub fn run(file: &Path, in_place: bool, codex_args: &[String], opts: &RunOptions) -> Result<i32> {
+    let raw = fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+    let mut doc = Document::parse(&raw);
+    if doc.sections.is_empty() {
+        anyhow::bail!("{} has no level-1 headings (`# ...`) to ask", file.display());
+    }
+    let mut session = Session::new(codex_args.to_vec(), opts.clone());
+    let total = doc.sections.len();
+    let mut code = 0;
+    for (i, section) in doc.sections.iter_mut().enumerate() {
+        eprintln!("[{}/{}] {}", i + 1, total, section.heading);
+        match session.send(&section.prompt()) {
+            Ok(response) => section.answer = Some(response.text.trim().to_string()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                eprintln!("Stopped after {} of {} question(s)", i, total);
+                code = e.exit_code();
+                break;
+            }
+        }
+    }
+    let out = doc.render();
+    if in_place {
+        fs::write(file, out).with_context(|| format!("writing {}", file.display()))?;
+        if let Some(id) = session.id() {
+            eprintln!("Answers written to {} (session {})", file.display(), id);
+        }
+    } else {
+        print!("{}", out);
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_split_the_file_and_answers_are_replaced() {
+        let raw = "Notes for the docs team.\n\n# What does `run` return?\n\nKeep it short.\n\n```sh\n# not a heading\n```\n\n# Is it safe?\n<!-- codex-clean answer -->\nOld answer.\n<!-- end codex-clean answer -->\n";
+        let mut doc = Document::parse(raw);
+        assert_eq!(doc.preamble, "Notes for the docs team.\n\n");
+        assert_eq!(doc.sections.len(), 2);
+        assert_eq!(doc.sections[0].prompt(), "What does `run` return?\n\nKeep it short.\n\n```sh\n# not a heading\n```");
+        assert_eq!(doc.sections[1].prompt(), "Is it safe?");
+        assert_eq!(doc.sections[1].answer.as_deref(), Some("Old answer."));
+
+        doc.sections[0].answer = Some("An exit code.".into());
+        doc.sections[1].answer = Some("Yes.".into());
+        let out = doc.render();
+        assert_eq!(
+            out,
+            "Notes for the docs team.\n\n# What does `run` return?\n\nKeep it short.\n\n```sh\n# not a heading\n```\n\n<!-- codex-clean answer -->\nAn exit code.\n<!-- end codex-clean answer -->\n\n# Is it safe?\n\n<!-- codex-clean answer -->\nYes.\n<!-- end codex-clean answer -->\n"
+        );
+        assert_eq!(Document::parse(&out).render(), out);
+    }
+}
@@ -0,0 +1,80 @@
+//! Where the time goes between codex-clean starting and codex starting
+//! (`--profile-startup`).
+//!
+//! The common `codex-clean "<prompt>"` run should cost a few milliseconds
+//! on top of codex itself. [`mark`] notes the end of each step on the way
+//! to spawning codex, and [`report`] prints them once, when codex has
+//! started. Both do nothing unless [`begin`] turned profiling on.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct Profile {
+    started: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+/// Start profiling, counting from `started` (the top of `main`).
+pub fn begin(started: Instant) {
+    *PROFILE.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some(Profile { started, last: started, phases: Vec::new() });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The step called `phase` has just finished.
+pub fn mark(phase: &'static str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(profile) = PROFILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        let now = Instant::now();
+        profile.phases.push((phase, now - profile.last));
+        profile.last = now;
+    }
+}
+
+/// Print the steps so far to stderr, the first time only: a retry or a
+/// nudge starts codex again, but not codex-clean.
+pub fn report() {
+    if !ENABLED.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    if let Some(profile) = PROFILE.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        eprintln!("{}", format_report(&profile.phases, profile.last - profile.started));
+    }
+}
+
+fn format_report(phases: &[(&str, Duration)], total: Duration) -> String {
+    let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1e3);
+    let steps: Vec<String> = phases
+        .iter()
+        .map(|(phase, took)| format!("{} {}", phase, ms(*took)))
+        .collect();
+    format!("codex-clean startup: {}; codex started after {}", steps.join(", "), ms(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_report_lists_each_step_and_the_total() {
+        let phases = [
+            ("parse arguments", Duration::from_micros(400)),
+            ("load config", Duration::from_micros(1200)),
+            ("load seats", Duration::ZERO),
+            ("spawn codex", Duration::from_millis(2)),
+        ];
+        assert_eq!(
+            format_report(&phases, Duration::from_micros(3600)),
+            "codex-clean startup: parse arguments 0.4ms, load config 1.2ms, load seats 0.0ms, spawn codex 2.0ms; codex started after 3.6ms"
+        );
+    }
+}
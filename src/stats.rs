@@ -0,0 +1,460 @@
+//! Run reports and `codex-clean stats`.
+//!
+//! Every run leaves a small JSON report in `~/.config/codex-clean/reports/`:
+//! when it ran, how long it took, its exit code and token usage, and the
+//! model and profile in effect. Prompts and answers are not recorded, only
+//! the paths of what the run left for `codex-clean archive` and the
+//! languages of the answer's code. Only the most recent [`KEEP_REPORTS`]
+//! are kept. `codex-clean stats` reads them back to show trends in your
+//! own usage, or with `--code LANG` to find runs whose answers had code in
+//! LANG.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Artifacts;
+use crate::content::Content;
+use crate::judge::Judgement;
+use crate::metrics::Metric;
+use crate::runner::format_elapsed;
+use crate::seat::{atomic_write, config_dir};
+use crate::window::Pressure;
+
+/// How many reports to keep; older ones are deleted as new ones arrive.
+pub const KEEP_REPORTS: usize = 500;
+
+/// `~/.config/codex-clean/reports/`.
+pub fn reports_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("reports"))
+}
+
+/// What one run is remembered by.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RunReport {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    /// `exec`, `resume`, or `review`.
+    pub mode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Every directory codex worked in, for a run over several
+    /// (`--workspace`, or codex's `--add-dir`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspaces: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<StageTimings>,
+    /// The `--lang` the answer was asked for in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// The languages of the answer's code and how much of it is code
+    /// (see [`crate::content`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Content>,
+    /// How full the session's context window was when the run ended (see
+    /// [`crate::window`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<Pressure>,
+    /// The `--judge` verdict on the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<Judgement>,
+    /// Lines of codex's stdout that weren't JSON, when there were any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_lines: Option<usize>,
+    /// Counters and gauges from the config and observers (see
+    /// [`crate::metrics`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, Metric>,
+    /// The recording, files and working tree changes the run left, for
+    /// `codex-clean archive`.
+    #[serde(default, skip_serializing_if = "Artifacts::is_empty")]
+    pub artifacts: Artifacts,
+}
+
+/// When each stage of a codex attempt was reached, in milliseconds after
+/// codex-clean started it; `render_ms` is how long printing the result took.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StageTimings {
+    /// codex's process is running and has its prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_event_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_message_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_event_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render_ms: Option<u64>,
+}
+
+impl StageTimings {
+    /// The `--timings` line: `spawn 14ms, first event 1.2s, ...`, leaving
+    /// out stages the run never reached.
+    pub fn describe(&self) -> String {
+        let stages = [
+            ("spawn", self.spawn_ms),
+            ("first event", self.first_event_ms),
+            ("first message", self.first_message_ms),
+            ("last event", self.last_event_ms),
+            ("exit", self.exit_ms),
+            ("render", self.render_ms),
+        ];
+        let parts: Vec<String> = stages
+            .iter()
+            .filter_map(|(name, ms)| Some(format!("{} {}", name, format_ms((*ms)?))))
+            .collect();
+        parts.join(", ")
+    }
+}
+
+/// `850ms` below a second, `12.3s` above.
+fn format_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+impl RunReport {
+    pub fn save(&self) -> Result<()> {
+        self.save_in(&reports_dir()?)
+    }
+
+    /// Write the report to `path` alone.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let raw = serde_json::to_string_pretty(self).context("serialising run report")?;
+        atomic_write(path, raw.as_bytes())
+    }
+
+    /// Write the report to `dir` and prune the oldest beyond [`KEEP_REPORTS`].
+    pub fn save_in(&self, dir: &Path) -> Result<()> {
+        // Timestamped names sort oldest first; the pid keeps concurrent
+        // runs from colliding, and the counter runs within one process
+        // (`eval` runs cases in parallel).
+        static SAVED: AtomicUsize = AtomicUsize::new(0);
+        let name = match SAVED.fetch_add(1, Ordering::Relaxed) {
+            0 => format!("{}-{}.json", self.started_at.format("%Y%m%dT%H%M%S%.3fZ"), std::process::id()),
+            n => format!("{}-{}-{}.json", self.started_at.format("%Y%m%dT%H%M%S%.3fZ"), std::process::id(), n),
+        };
+        let raw = serde_json::to_string_pretty(self).context("serialising run report")?;
+        atomic_write(&dir.join(name), raw.as_bytes())?;
+        let files = report_files(dir)?;
+        for old in &files[..files.len().saturating_sub(KEEP_REPORTS)] {
+            let _ = fs::remove_file(old);
+        }
+        Ok(())
+    }
+}
+
+fn report_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The model picked by codex args: `-m X`, `-mX`, `--model X`, or `--model=X`.
+pub fn model_from_args(args: &[String]) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-m" || arg == "--model" {
+            return iter.next().cloned();
+        }
+        if let Some(model) = arg.strip_prefix("--model=").or_else(|| arg.strip_prefix("-m")) {
+            return Some(model.to_string());
+        }
+    }
+    None
+}
+
+/// The `last` most recent reports in `dir`, oldest first. Unreadable
+/// reports are skipped.
+pub fn load_recent_in(dir: &Path, last: usize) -> Result<Vec<RunReport>> {
+    let files = report_files(dir)?;
+    Ok(files[files.len().saturating_sub(last)..]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|raw| serde_json::from_str(&raw).ok())
+        .collect())
+}
+
+/// What `stats` groups runs by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Model,
+    Profile,
+}
+
+/// Totals for one group of runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub runs: usize,
+    pub failed: usize,
+    pub total_duration: Duration,
+    /// Runs that reported token usage; the token totals cover only these.
+    pub with_usage: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Summary {
+    fn add(&mut self, report: &RunReport) {
+        self.runs += 1;
+        if report.exit_code != 0 {
+            self.failed += 1;
+        }
+        self.total_duration += Duration::from_millis(report.duration_ms);
+        if let (Some(input), Some(output)) = (report.input_tokens, report.output_tokens) {
+            self.with_usage += 1;
+            self.input_tokens += input;
+            self.output_tokens += output;
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.runs as f64
+        }
+    }
+
+    pub fn average_duration(&self) -> Duration {
+        self.total_duration / u32::try_from(self.runs.max(1)).unwrap_or(u32::MAX)
+    }
+
+    /// Average (input, output) tokens per run that reported usage.
+    pub fn average_tokens(&self) -> Option<(u64, u64)> {
+        let n = self.with_usage as u64;
+        (n > 0).then(|| (self.input_tokens / n, self.output_tokens / n))
+    }
+}
+
+/// Summaries keyed by group name (`all` when not grouping).
+pub fn summarise(reports: &[RunReport], by: Option<GroupBy>) -> BTreeMap<String, Summary> {
+    let mut groups: BTreeMap<String, Summary> = BTreeMap::new();
+    for report in reports {
+        let key = match by {
+            None => Some("all"),
+            Some(GroupBy::Model) => report.model.as_deref(),
+            Some(GroupBy::Profile) => report.profile.as_deref(),
+        };
+        groups.entry(key.unwrap_or("(none)").to_string()).or_default().add(report);
+    }
+    groups
+}
+
+/// The reports whose answers had code in `lang`.
+pub fn with_code<'a>(reports: &'a [RunReport], lang: &str) -> Vec<&'a RunReport> {
+    reports.iter().filter(|r| r.content.as_ref().is_some_and(|c| c.has_language(lang))).collect()
+}
+
+/// `codex-clean stats`, over only the runs with `code` in their answers
+/// when it is given; those runs are listed too.
+pub fn run(last: usize, by: Option<GroupBy>, code: Option<&str>) -> Result<()> {
+    let mut reports = load_recent_in(&reports_dir()?, last)?;
+    if let Some(lang) = code {
+        reports = with_code(&reports, lang).into_iter().cloned().collect();
+    }
+    let (Some(first), Some(latest)) = (reports.first(), reports.last()) else {
+        match code {
+            Some(lang) => eprintln!("No recorded run has {} code in its answer.", lang),
+            None => eprintln!("No runs recorded yet."),
+        }
+        return Ok(());
+    };
+    println!(
+        "Last {} run(s), {} to {}",
+        reports.len(),
+        first.started_at.format("%Y-%m-%d"),
+        latest.started_at.format("%Y-%m-%d")
+    );
+    println!();
+    print!("{}", format_table(&summarise(&reports, by)));
+    if code.is_some() {
+        println!();
+        print!("{}", format_runs(&reports));
+    }
+    Ok(())
+}
+
+/// One line per run: when it started, its session, and what its answer
+/// was made of.
+fn format_runs(reports: &[RunReport]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        let content = report.content.clone().unwrap_or_default();
+        out.push_str(&format!(
+            "{}  {:<36}  {:>3.0}% code  {}\n",
+            report.started_at.format("%Y-%m-%d %H:%M"),
+            report.session_id.as_deref().unwrap_or("-"),
+            content.code_ratio * 100.0,
+            content.languages.join(", ")
+        ));
+    }
+    out
+}
+
+fn format_table(groups: &BTreeMap<String, Summary>) -> String {
+    let width = groups.keys().map(|k| k.len()).max().unwrap_or(0).max(5);
+    let mut out = format!(
+        "{:<width$}  {:>5}  {:>7}  {:>8}  {:>10}  {:>10}\n",
+        "GROUP", "RUNS", "FAILED", "AVG TIME", "AVG INPUT", "AVG OUTPUT",
+        width = width
+    );
+    for (name, s) in groups {
+        let (input, output) = match s.average_tokens() {
+            Some((i, o)) => (i.to_string(), o.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        out.push_str(&format!(
+            "{:<width$}  {:>5}  {:>6.1}%  {:>8}  {:>10}  {:>10}\n",
+            name,
+            s.runs,
+            s.failure_rate() * 100.0,
+            format_elapsed(s.average_duration()),
+            input,
+            output,
+            width = width
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(secs: i64, exit_code: i32, model: Option<&str>, tokens: Option<(u64, u64)>) -> RunReport {
+        RunReport {
+            started_at: DateTime::from_timestamp(1_790_000_000 + secs, 0).unwrap(),
+            duration_ms: 30_000,
+            exit_code,
+            mode: "exec".into(),
+            model: model.map(String::from),
+            input_tokens: tokens.map(|t| t.0),
+            output_tokens: tokens.map(|t| t.1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reports_are_saved_pruned_and_loaded_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..(KEEP_REPORTS as i64 + 3) {
+            report(i, 0, None, None).save_in(dir.path()).unwrap();
+        }
+        assert_eq!(report_files(dir.path()).unwrap().len(), KEEP_REPORTS);
+
+        let recent = load_recent_in(dir.path(), 2).unwrap();
+        let newest = KEEP_REPORTS as i64;
+        assert_eq!(recent, vec![report(newest + 1, 0, None, None), report(newest + 2, 0, None, None)]);
+        assert!(load_recent_in(&dir.path().join("missing"), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn model_is_read_from_codex_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(model_from_args(&args(&["-C", "/tmp", "-m", "o3"])).as_deref(), Some("o3"));
+        assert_eq!(model_from_args(&args(&["--model=gpt-5.5"])).as_deref(), Some("gpt-5.5"));
+        assert_eq!(model_from_args(&args(&["-mo3"])).as_deref(), Some("o3"));
+        assert_eq!(model_from_args(&args(&["--sandbox", "read-only"])), None);
+    }
+
+    #[test]
+    fn summarise_groups_and_averages() {
+        let reports = [
+            report(0, 0, Some("o3"), Some((1000, 100))),
+            report(1, 1, Some("o3"), None),
+            report(2, 0, None, Some((3000, 300))),
+        ];
+        let all = summarise(&reports, None);
+        assert_eq!(all.len(), 1);
+        let s = &all["all"];
+        assert_eq!((s.runs, s.failed, s.with_usage), (3, 1, 2));
+        assert_eq!(s.average_tokens(), Some((2000, 200)));
+        assert_eq!(s.average_duration(), Duration::from_secs(30));
+        if let Ok(runs) = usize::try_from(1u64 << 32) {
+            let huge = Summary { runs, total_duration: Duration::from_secs(1 << 32), ..Default::default() };
+            assert!(huge.average_duration() >= Duration::from_secs(1));
+        }
+
+        let by_model = summarise(&reports, Some(GroupBy::Model));
+        assert_eq!(by_model.keys().collect::<Vec<_>>(), vec!["(none)", "o3"]);
+        assert_eq!(by_model["o3"].failure_rate(), 0.5);
+        assert_eq!(by_model["o3"].average_tokens(), Some((1000, 100)));
+    }
+
+    #[test]
+    fn timings_describe_reached_stages() {
+        let timings = StageTimings {
+            spawn_ms: Some(14),
+            first_event_ms: Some(1_230),
+            last_event_ms: Some(9_800),
+            exit_ms: Some(9_870),
+            render_ms: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            timings.describe(),
+            "spawn 14ms, first event 1.2s, last event 9.8s, exit 9.9s, render 0ms"
+        );
+        let raw = serde_json::to_value(timings).unwrap();
+        assert_eq!(raw["first_event_ms"], 1230);
+        assert!(raw.get("first_message_ms").is_none());
+    }
+
+    #[test]
+    fn runs_are_found_by_code_language() {
+        let mut rust = report(0, 0, None, None);
+        rust.session_id = Some("0199a213-81c0-7800-8aa1-bbab2a035a53".into());
+        rust.content = Some(Content { languages: vec!["rust".into(), "toml".into()], code_ratio: 0.62 });
+        let prose = RunReport { content: Some(Content::default()), ..report(1, 0, None, None) };
+        let reports = [rust.clone(), prose, report(2, 0, None, None)];
+        assert_eq!(with_code(&reports, "rs"), vec![&rust]);
+        assert!(with_code(&reports, "python").is_empty());
+        assert_eq!(
+            format_runs(&[rust]),
+            "2026-09-21 14:13  0199a213-81c0-7800-8aa1-bbab2a035a53   62% code  rust, toml\n"
+        );
+    }
+
+    #[test]
+    fn table_lines_up() {
+        let table = format_table(&summarise(&[report(0, 1, None, None)], None));
+        assert_eq!(
+            table,
+            "GROUP   RUNS   FAILED  AVG TIME   AVG INPUT  AVG OUTPUT\n\
+             all        1   100.0%       30s           -           -\n"
+        );
+    }
+}
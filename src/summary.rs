@@ -0,0 +1,159 @@
+//! `codex-clean summarize`: a short account of a long session.
+//!
+//! The session's dialogue is sent to a fresh codex session (same model,
+//! read-only sandbox, as for `--judge`) with a request for a title and a
+//! few lines saying what was asked, decided and left to do. The summary is
+//! printed and kept in `~/.config/codex-clean/summaries/<session-id>.json`.
+//! With `--title` the title is kept too, and is shown beside the session
+//! wherever codex-clean lists sessions to choose from.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::judge;
+use crate::runner::{self, Mode, RunOptions};
+use crate::seat::{atomic_write, config_dir};
+use crate::sessions::{self, Role, Transcript};
+
+/// Longest title kept when the reply's title line runs on.
+const MAX_TITLE_CHARS: usize = 80;
+
+/// A stored summary of one session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Summary {
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    /// The session's title, when it was attached with `--title`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub summary: String,
+}
+
+/// `~/.config/codex-clean/summaries/`.
+pub fn summaries_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("summaries"))
+}
+
+impl Summary {
+    /// The stored summary of `session_id`, if there is one.
+    pub fn load(session_id: &str) -> Option<Self> {
+        let raw = fs::read_to_string(summaries_dir().ok()?.join(format!("{}.json", session_id))).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = summaries_dir()?;
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let path = dir.join(format!("{}.json", self.session_id));
+        let raw = serde_json::to_string_pretty(self).context("serialising the summary")?;
+        atomic_write(&path, raw.as_bytes())?;
+        Ok(path)
+    }
+}
+
+/// The title attached to `session_id`, if any.
+pub fn title(session_id: &str) -> Option<String> {
+    Summary::load(session_id)?.title
+}
+
+/// The prompt asking codex to summarise `transcript`.
+pub fn prompt(transcript: &Transcript) -> String {
+    let mut out = String::from(
+        "Summarise the conversation below between a user and an AI coding assistant, so that someone \
+         coming back to it later can tell what it was about.\n\n<transcript>\n",
+    );
+    for entry in &transcript.entries {
+        let who = match entry.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+        let _ = writeln!(out, "[{}]\n{}\n", who, entry.text.trim_end());
+    }
+    out.push_str(
+        "</transcript>\n\n\
+         Don't run commands or change files. Reply with a first line `TITLE: <at most eight words>`, \
+         then a blank line, then at most eight short bullet points: what was asked, what was decided \
+         or changed, and what was left to do.",
+    );
+    out
+}
+
+/// The title and summary in codex's reply. The title line may be
+/// decorated (`**Title:**`) or missing.
+pub fn parse(reply: &str) -> (Option<String>, String) {
+    let reply = reply.trim();
+    let first = reply.lines().next().unwrap_or_default();
+    let bare = first.trim().trim_matches(['*', '_', '#', ' ']);
+    let title = bare
+        .get(..6)
+        .filter(|head| head.eq_ignore_ascii_case("title:"))
+        .map(|_| bare[6..].trim().trim_matches(['*', '_', '"', ' ']).chars().take(MAX_TITLE_CHARS).collect::<String>())
+        .filter(|t| !t.is_empty());
+    match title {
+        Some(title) => (Some(title), reply[first.len()..].trim().to_string()),
+        None => (None, reply.to_string()),
+    }
+}
+
+/// Summarise `session` (an ID, prefix or `@N`), print the summary and
+/// store it, attaching its title to the session with `attach_title`.
+pub fn run(session: &str, codex_args: &[String], opts: &RunOptions, attach_title: bool) -> Result<i32> {
+    let file = sessions::lookup_session(session)?;
+    let transcript = sessions::read_transcript(&file.path)?;
+    if transcript.entries.is_empty() {
+        bail!("session {} has no recorded dialogue to summarise", file.id);
+    }
+    eprintln!("Summarising session {} ({} message(s))...", file.id, transcript.entries.len());
+    let output = runner::run(&judge::args(codex_args), &prompt(&transcript), Mode::Exec, opts)?;
+    let (title, text) = parse(&output.aggregated_message());
+    if text.is_empty() {
+        bail!("codex returned an empty summary");
+    }
+    if let Some(title) = &title {
+        println!("{}\n", title);
+    }
+    println!("{}", text);
+    // A summary made without --title keeps the title attached before.
+    let attached = if attach_title { title.clone() } else { Summary::load(&file.id).and_then(|s| s.title) };
+    let stored = Summary { session_id: file.id.clone(), created_at: Utc::now(), title: attached, summary: text };
+    let path = stored.save()?;
+    match (&title, attach_title) {
+        (Some(title), true) => eprintln!("Saved to {}, titled \"{}\"", path.display(), title),
+        (None, true) => eprintln!("Saved to {}; codex gave no title to attach", path.display()),
+        (_, false) => eprintln!("Saved to {}", path.display()),
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::TranscriptEntry;
+
+    #[test]
+    fn replies_give_a_title_and_a_summary() {
+        assert_eq!(
+            parse("TITLE: Fix the parser panic\n\n- Asked why `parse_range` panics\n- Added a test"),
+            (Some("Fix the parser panic".into()), "- Asked why `parse_range` panics\n- Added a test".into())
+        );
+        assert_eq!(parse("**Title:** \"Release notes\"\n- Drafted").0.as_deref(), Some("Release notes"));
+        assert_eq!(parse("- Just bullets"), (None, "- Just bullets".into()));
+
+        let transcript = Transcript {
+            session_id: Some("s-1".into()),
+            agent: None,
+            entries: vec![
+                TranscriptEntry { role: Role::User, text: "Why does it panic?".into() },
+                TranscriptEntry { role: Role::Assistant, text: "An empty slice.\n".into() },
+            ],
+        };
+        let prompt = prompt(&transcript);
+        assert!(prompt.contains("<transcript>\n[user]\nWhy does it panic?\n\n[assistant]\nAn empty slice.\n\n</transcript>"), "{}", prompt);
+        assert!(prompt.ends_with("what was left to do."));
+    }
+}
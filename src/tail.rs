@@ -0,0 +1,221 @@
+//! `codex-clean tail`: follow a session's on-disk log from another terminal.
+//!
+//! Sessions started anywhere (the interactive TUI, another codex-clean, an
+//! IDE) are persisted by codex as rollout files. We poll the file for
+//! appended lines and render each recognised event through the same filter
+//! `exec` output goes through, so the view matches a normal codex-clean run.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::events::{extract_rollout_event, Event};
+use crate::output::{format_usage, CodexOutput};
+use crate::sessions::{self, SessionFile};
+
+/// How often to check the session log for new lines while following.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Which session to follow.
+pub enum TailTarget {
+    SessionId(String),
+    Last,
+}
+
+/// Resolve `target` to a rollout file and render it, optionally following
+/// it until interrupted.
+pub fn run(target: TailTarget, follow: bool) -> Result<()> {
+    let session = resolve(target)?;
+    let file = File::open(&session.path)
+        .with_context(|| format!("opening {}", session.path.display()))?;
+    let mut follower = LineFollower::new(file);
+    let mut renderer = TailRenderer::new(io::stdout(), io::stderr());
+
+    loop {
+        for line in follower.read_complete_lines()? {
+            renderer.handle_line(&line)?;
+        }
+        if !follow {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    if let Some(line) = follower.take_partial() {
+        renderer.handle_line(&line)?;
+    }
+    Ok(())
+}
+
+fn resolve(target: TailTarget) -> Result<SessionFile> {
+    match target {
+        TailTarget::SessionId(id) => sessions::find_session(&id),
+        TailTarget::Last => sessions::latest_session(),
+    }
+}
+
+/// Incremental line reader over a file that is still being appended to.
+/// A trailing line without its newline is held back until the writer
+/// finishes it, so we never try to parse half a JSON object.
+pub struct LineFollower<R> {
+    reader: BufReader<R>,
+    partial: Vec<u8>,
+}
+
+impl<R: io::Read> LineFollower<R> {
+    pub fn new(inner: R) -> Self {
+        Self { reader: BufReader::new(inner), partial: Vec::new() }
+    }
+
+    /// Read everything currently available and return the complete lines.
+    pub fn read_complete_lines(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let n = self.reader.read_until(b'\n', &mut self.partial)?;
+            if n == 0 {
+                break;
+            }
+            if self.partial.last() == Some(&b'\n') {
+                let line = String::from_utf8_lossy(&self.partial).trim_end().to_string();
+                self.partial.clear();
+                lines.push(line);
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Take whatever unterminated line is buffered. Used once we stop
+    /// following, when no more bytes are going to arrive.
+    pub fn take_partial(&mut self) -> Option<String> {
+        if self.partial.iter().all(|b| b.is_ascii_whitespace()) {
+            self.partial.clear();
+            return None;
+        }
+        let line = String::from_utf8_lossy(&self.partial).trim_end().to_string();
+        self.partial.clear();
+        Some(line)
+    }
+}
+
+/// Renders rollout events as they arrive, in the same shape as
+/// `CodexOutput::render` produces for a finished run.
+pub struct TailRenderer<O, E> {
+    out: O,
+    err: E,
+    state: CodexOutput,
+}
+
+impl<O: Write, E: Write> TailRenderer<O, E> {
+    pub fn new(out: O, err: E) -> Self {
+        Self { out, err, state: CodexOutput::new() }
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        self.state.lines_seen += 1;
+        let Some(event) = extract_rollout_event(line) else {
+            return Ok(());
+        };
+        self.state.events_recognized += 1;
+
+        match event {
+            Event::ThreadStarted { thread_id } => {
+                let first = self.state.session_id.is_none();
+                self.state.add_thread_id(thread_id);
+                if first {
+                    if let Some(id) = &self.state.session_id {
+                        writeln!(self.out, "Session: {}", id)?;
+                    }
+                }
+            }
+            Event::AgentMessage { text: Some(text) } if !text.is_empty() => {
+                writeln!(self.out)?;
+                writeln!(self.out, "{}", text)?;
+            }
+            Event::AgentMessage { .. } => {}
+            Event::TurnCompleted {
+                input_tokens,
+                cached_input_tokens,
+                output_tokens,
+                reasoning_output_tokens,
+            } => {
+                let usage = (input_tokens, cached_input_tokens, output_tokens, reasoning_output_tokens);
+                writeln!(self.out)?;
+                writeln!(self.out, "{}", format_usage(usage))?;
+            }
+            // Not produced by rollout logs.
+            Event::CommandExecuted(_) | Event::FilesChanged(_) => {}
+            Event::TurnFailed { message } | Event::StreamError { message } => {
+                let before = self.state.errors.len();
+                self.state.add_error(message);
+                if self.state.errors.len() > before {
+                    writeln!(self.err, "Error from codex: {}", self.state.errors[before])?;
+                }
+            }
+        }
+        self.out.flush()?;
+        self.err.flush()
+    }
+
+    #[cfg(test)]
+    fn into_parts(self) -> (O, E) {
+        (self.out, self.err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follower_holds_back_partial_lines() {
+        // Separate open file descriptions so the writer's offset doesn't
+        // move the follower's.
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = std::fs::OpenOptions::new().append(true).open(tmp.path()).unwrap();
+        writer.write_all(b"{\"a\":1}\n{\"b\":").unwrap();
+        let mut follower = LineFollower::new(File::open(tmp.path()).unwrap());
+
+        assert_eq!(follower.read_complete_lines().unwrap(), vec!["{\"a\":1}"]);
+
+        writer.write_all(b"2}\n").unwrap();
+        assert_eq!(follower.read_complete_lines().unwrap(), vec!["{\"b\":2}"]);
+        assert!(follower.take_partial().is_none());
+    }
+
+    #[test]
+    fn follower_take_partial_returns_unterminated_tail() {
+        let mut follower = LineFollower::new(&b"line one\nline two"[..]);
+        assert_eq!(follower.read_complete_lines().unwrap(), vec!["line one"]);
+        assert_eq!(follower.take_partial().as_deref(), Some("line two"));
+    }
+
+    #[test]
+    fn renderer_formats_rollout_events() {
+        let mut r = TailRenderer::new(Vec::new(), Vec::new());
+        let lines = [
+            r#"{"type":"session_meta","payload":{"id":"0199a213-81c0-7800-8aa1-bbab2a035a53"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"user_message","message":"hi"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"agent_message","message":"Hello there"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"token_count","info":{"total_token_usage":{"input_tokens":10,"cached_input_tokens":0,"output_tokens":2}}}}"#,
+            r#"{"type":"event_msg","payload":{"type":"error","message":"boom"}}"#,
+            r#"{"type":"event_msg","payload":{"type":"error","message":"boom"}}"#,
+        ];
+        for l in lines {
+            r.handle_line(l).unwrap();
+        }
+        let (out, err) = r.into_parts();
+        let out = String::from_utf8(out).unwrap();
+        let err = String::from_utf8(err).unwrap();
+        assert_eq!(
+            out,
+            "Session: 0199a213-81c0-7800-8aa1-bbab2a035a53\n\nHello there\n\nTokens: 10 input (0 cached), 2 output\n"
+        );
+        assert_eq!(err, "Error from codex: boom\n", "duplicate errors should be suppressed");
+    }
+}
@@ -0,0 +1,293 @@
+//! Reusable prompt templates: `codex-clean templates ...` and
+//! `codex-clean run-template <name>`.
+//!
+//! A template is a Markdown file in `~/.config/codex-clean/templates/`
+//! named `<name>.md`, holding a prompt with `{{placeholders}}` that
+//! `run-template --var key=value` (or `--vars-json file`) fills in. A
+//! placeholder left unfilled is an error before codex starts, rather than a
+//! literal `{{x}}` sent to the model. The directory is plain files so a team
+//! can keep it in a git checkout and share it.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+
+use crate::seat::{atomic_write, config_dir, secure_create_dir_all};
+
+const EXTENSION: &str = "md";
+
+/// `~/.config/codex-clean/templates/`.
+pub fn templates_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("templates"))
+}
+
+/// Template names become file names, so keep them to one safe path
+/// component.
+pub fn validate_name(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && !name.starts_with(['-', '.'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !ok {
+        bail!("invalid template name '{}' (use letters, digits, '-', '_' and '.')", name);
+    }
+    Ok(())
+}
+
+/// `codex-clean templates list`: each template's name and first line.
+pub fn list() -> Result<()> {
+    let dir = templates_dir()?;
+    let templates = list_in(&dir)?;
+    if templates.is_empty() {
+        eprintln!("No templates yet; add one with `codex-clean templates add <name>` ({}).", dir.display());
+        return Ok(());
+    }
+    let width = templates.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, summary) in templates {
+        println!("{:<width$}  {}", name, summary, width = width);
+    }
+    Ok(())
+}
+
+/// `codex-clean templates show <name>`.
+pub fn show(name: &str) -> Result<()> {
+    print!("{}", load(name)?);
+    Ok(())
+}
+
+/// `codex-clean templates add <name>`: save `file` (or stdin) as a template.
+pub fn add(name: &str, file: Option<&Path>, force: bool) -> Result<()> {
+    let text = match file {
+        Some(path) => fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?,
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).context("reading the template from stdin")?;
+            text
+        }
+    };
+    let path = add_in(&templates_dir()?, name, &text, force)?;
+    eprintln!("Saved template '{}' to {}.", name, path.display());
+    Ok(())
+}
+
+/// The text of template `name`.
+pub fn load(name: &str) -> Result<String> {
+    load_in(&templates_dir()?, name)
+}
+
+/// The variables in a `--vars-json` file: a JSON object whose values are
+/// strings, numbers or booleans.
+pub fn load_vars_json(path: &Path) -> Result<Vec<(String, String)>> {
+    let raw = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse_vars_json(&raw).with_context(|| format!("parsing {}", path.display()))
+}
+
+fn parse_vars_json(raw: &str) -> Result<Vec<(String, String)>> {
+    let Value::Object(map) = serde_json::from_str(raw)? else {
+        bail!("expected a JSON object of variables");
+    };
+    map.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s,
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => return Err(anyhow!("variable '{}' must be a string, number or boolean", key)),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Expand template `name` with `vars`, failing if any placeholder is left.
+pub fn render(name: &str, template: &str, vars: &[(String, String)]) -> Result<String> {
+    // Check the template rather than the result, so a value that happens to
+    // contain `{{...}}` isn't mistaken for a gap.
+    let missing: Vec<String> =
+        placeholders(template).into_iter().filter(|key| !vars.iter().any(|(k, _)| k == key)).collect();
+    if !missing.is_empty() {
+        let list: Vec<String> = missing.iter().map(|key| format!("{{{{{}}}}}", key)).collect();
+        bail!(
+            "template '{}' has unresolved placeholder(s) {}; set them with --var KEY=VALUE or --vars-json FILE",
+            name,
+            list.join(", ")
+        );
+    }
+    Ok(expand(template, vars))
+}
+
+/// The distinct placeholder keys in `text`, in order of first appearance.
+pub fn placeholders(text: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let key = after[..end].trim();
+        if is_key(key) && !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    keys
+}
+
+/// Whether `{{key}}` looks like a placeholder rather than, say, a literal
+/// `{{` in a code sample.
+fn is_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Replace each `{{key}}` (spaces inside the braces are allowed) whose key
+/// is in `vars`. Other placeholders are left as they are.
+pub fn expand(template: &str, vars: &[(String, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = after[..end].trim();
+        // Later --var flags win, as with repeated command-line options.
+        match vars.iter().rev().find(|(k, _)| k == key) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn template_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    Ok(dir.join(format!("{}.{}", name, EXTENSION)))
+}
+
+fn list_in(dir: &Path) -> Result<Vec<(String, String)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut templates = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        templates.push((name.to_string(), summary(&text)));
+    }
+    templates.sort();
+    Ok(templates)
+}
+
+/// The first non-blank line, without any Markdown heading marker.
+fn summary(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.trim_start_matches('#').trim().to_string())
+        .unwrap_or_default()
+}
+
+fn load_in(dir: &Path, name: &str) -> Result<String> {
+    let path = template_path(dir, name)?;
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            bail!("no template named '{}' (see `codex-clean templates list`)", name)
+        }
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+fn add_in(dir: &Path, name: &str, text: &str, force: bool) -> Result<PathBuf> {
+    let path = template_path(dir, name)?;
+    if text.trim().is_empty() {
+        bail!("template '{}' is empty", name);
+    }
+    if path.exists() && !force {
+        bail!("template '{}' already exists; pass --force to replace it", name);
+    }
+    secure_create_dir_all(dir)?;
+    atomic_write(&path, text.as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn expand_fills_known_placeholders_only() {
+        let text = "Review PR {{pr}} against {{ base }}; ignore {{other}} and {{unclosed";
+        assert_eq!(
+            expand(text, &vars(&[("pr", "123"), ("base", "main"), ("pr", "124")])),
+            "Review PR 124 against main; ignore {{other}} and {{unclosed"
+        );
+    }
+
+    #[test]
+    fn render_reports_every_unresolved_placeholder() {
+        let text = "Review PR {{pr}} on {{ repo }}, then {{pr}} again. Keep `{{ }}` and {{a b}}.";
+        let err = render("review-pr", text, &vars(&[])).unwrap_err().to_string();
+        assert!(err.contains("'review-pr'") && err.contains("{{pr}}, {{repo}};"), "{}", err);
+        assert_eq!(
+            render("review-pr", text, &vars(&[("pr", "1"), ("repo", "{{x}}")])).unwrap(),
+            "Review PR 1 on {{x}}, then 1 again. Keep `{{ }}` and {{a b}}."
+        );
+    }
+
+    #[test]
+    fn vars_json_takes_scalars() {
+        assert_eq!(
+            parse_vars_json(r#"{"pr": 123, "base": "main", "draft": false}"#).unwrap(),
+            vars(&[("base", "main"), ("draft", "false"), ("pr", "123")])
+        );
+        assert!(parse_vars_json(r#"{"files": ["a"]}"#).unwrap_err().to_string().contains("'files'"));
+        assert!(parse_vars_json("[1]").is_err());
+    }
+
+    #[test]
+    fn add_list_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        assert!(list_in(&templates).unwrap().is_empty());
+
+        add_in(&templates, "review-pr", "# Review a pull request\n\nReview PR {{pr}}.\n", false).unwrap();
+        add_in(&templates, "explain", "Explain {{path}}.\n", false).unwrap();
+        fs::write(templates.join("notes.txt"), "not a template").unwrap();
+        assert_eq!(
+            list_in(&templates).unwrap(),
+            vec![
+                ("explain".to_string(), "Explain {{path}}.".to_string()),
+                ("review-pr".to_string(), "Review a pull request".to_string()),
+            ]
+        );
+        assert_eq!(load_in(&templates, "explain").unwrap(), "Explain {{path}}.\n");
+
+        let err = add_in(&templates, "explain", "Explain it.", false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        add_in(&templates, "explain", "Explain it.", true).unwrap();
+        assert_eq!(load_in(&templates, "explain").unwrap(), "Explain it.");
+
+        assert!(load_in(&templates, "missing").unwrap_err().to_string().contains("no template named"));
+        assert!(add_in(&templates, "../escape", "x", false).is_err());
+        assert!(add_in(&templates, "blank", "  \n", false).is_err());
+    }
+}
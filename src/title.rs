@@ -0,0 +1,106 @@
+//! `--set-title`: show a run's status in the terminal or tmux title.
+//!
+//! With several codex runs going in different tabs or tmux windows, the
+//! title says which are still working: `codex: running…` once the run
+//! starts, then `codex: done ✓` or `codex: failed ✗` as soon as codex
+//! finishes, before the answer is printed. The title is written to the
+//! controlling terminal with an OSC 2 sequence, so it works with stdout
+//! and stderr redirected; inside tmux (or screen) the window name is set
+//! too. Without a terminal nothing is written.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::observer::EventObserver;
+use crate::output::CodexOutput;
+
+const IDLE: u8 = 0;
+const RUNNING: u8 = 1;
+const FINISHED: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(IDLE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Done,
+    Failed,
+}
+
+impl Status {
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Running => "codex: running…",
+            Self::Done => "codex: done ✓",
+            Self::Failed => "codex: failed ✗",
+        }
+    }
+}
+
+/// Set the title to `codex: running…`.
+pub fn start() {
+    STATE.store(RUNNING, Ordering::SeqCst);
+    write_title(Status::Running);
+}
+
+/// Set the title from the run's exit code, once, if [`start`] was called.
+pub fn finish(exit_code: i32) {
+    if STATE.compare_exchange(RUNNING, FINISHED, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+        write_title(if exit_code == 0 { Status::Done } else { Status::Failed });
+    }
+}
+
+/// Calls [`finish`] when the run completes, before its output is printed.
+#[derive(Debug, Default)]
+pub struct TitleObserver;
+
+impl EventObserver for TitleObserver {
+    fn on_complete(&self, exit_code: i32, _output: Option<&CodexOutput>) {
+        finish(exit_code);
+    }
+}
+
+/// The escape sequences setting the title to `title`: OSC 2, and under a
+/// multiplexer also its window name.
+pub fn sequence(title: &str, multiplexer: bool) -> String {
+    let mut out = format!("\x1b]2;{}\x07", title);
+    if multiplexer {
+        out.push_str(&format!("\x1bk{}\x1b\\", title));
+    }
+    out
+}
+
+fn write_title(status: Status) {
+    let multiplexer = std::env::var_os("TMUX").is_some()
+        || std::env::var("TERM").is_ok_and(|t| t.starts_with("screen") || t.starts_with("tmux"));
+    let seq = sequence(status.title(), multiplexer);
+    #[cfg(unix)]
+    if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(seq.as_bytes());
+        let _ = tty.flush();
+    }
+    #[cfg(not(unix))]
+    {
+        use std::io::IsTerminal;
+
+        let mut err = std::io::stderr();
+        if err.is_terminal() {
+            let _ = err.write_all(seq.as_bytes());
+            let _ = err.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_set_the_title_and_the_tmux_window_name() {
+        assert_eq!(sequence(Status::Running.title(), false), "\x1b]2;codex: running…\x07");
+        assert_eq!(
+            sequence(Status::Failed.title(), true),
+            "\x1b]2;codex: failed ✗\x07\x1bkcodex: failed ✗\x1b\\"
+        );
+    }
+}
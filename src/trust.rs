@@ -0,0 +1,206 @@
+//! Which project configs codex-clean is allowed to use.
+//!
+//! A `.codex-clean.toml` can add codex arguments and run hooks, so one
+//! arriving with a cloned repository must not take effect on its own. As
+//! with direnv, a project config is only applied once `codex-clean trust
+//! <dir>` has approved it, and the approval covers the file's exact
+//! contents: after any change it is ignored again until re-trusted.
+//!
+//! Approvals live in `~/.config/codex-clean/trusted.json`, keyed by the
+//! config file's canonical path.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{find_project_config, PROJECT_CONFIG_FILE};
+use crate::seat::{atomic_write, config_dir};
+
+/// `~/.config/codex-clean/trusted.json`.
+pub fn trusted_json_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("trusted.json"))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub configs: BTreeMap<PathBuf, TrustEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrustEntry {
+    /// SHA-256 of the file as it was when trusted.
+    pub sha256: String,
+    pub trusted_at: DateTime<Utc>,
+}
+
+/// Whether a project config may be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    Trusted,
+    /// Never trusted (or the trust was revoked).
+    Untrusted,
+    /// Trusted once, but the file has changed since.
+    Changed,
+}
+
+impl TrustStore {
+    pub fn load() -> Result<Self> {
+        Self::load_from(&trusted_json_path()?)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if raw.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&raw).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&trusted_json_path()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let raw = serde_json::to_string_pretty(self).context("serialising trusted.json")?;
+        atomic_write(path, raw.as_bytes())
+    }
+
+    /// Check `config` (a canonical path) whose current contents are `raw`.
+    pub fn check(&self, config: &Path, raw: &str) -> Trust {
+        match self.configs.get(config) {
+            None => Trust::Untrusted,
+            Some(entry) if entry.sha256 == digest(raw) => Trust::Trusted,
+            Some(_) => Trust::Changed,
+        }
+    }
+
+    pub fn trust(&mut self, config: &Path, raw: &str) {
+        let entry = TrustEntry { sha256: digest(raw), trusted_at: Utc::now() };
+        self.configs.insert(config.to_path_buf(), entry);
+    }
+
+    /// Returns whether `config` was trusted.
+    pub fn revoke(&mut self, config: &Path) -> bool {
+        self.configs.remove(config).is_some()
+    }
+}
+
+fn digest(raw: &str) -> String {
+    Sha256::digest(raw.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Check the project config at `path` against the user's approvals,
+/// printing why it is being ignored when it isn't trusted.
+pub fn check_project_config(path: &Path, raw: &str) -> Result<Trust> {
+    let canonical = fs::canonicalize(path)
+        .with_context(|| format!("resolving {}", path.display()))?;
+    let trust = TrustStore::load()?.check(&canonical, raw);
+    let dir = canonical.parent().unwrap_or(&canonical).display();
+    match trust {
+        Trust::Trusted => {}
+        Trust::Untrusted => eprintln!(
+            "Warning: ignoring {} because it isn't trusted. Review it, then run `codex-clean trust {}`.",
+            canonical.display(),
+            dir
+        ),
+        Trust::Changed => eprintln!(
+            "Warning: ignoring {} because it changed after it was trusted. Review it, then run `codex-clean trust {}` again.",
+            canonical.display(),
+            dir
+        ),
+    }
+    Ok(trust)
+}
+
+/// `codex-clean trust [--revoke] <dir>`: approve (or stop approving) the
+/// project config that applies in `dir`.
+pub fn run(dir: &Path, revoke: bool) -> Result<()> {
+    run_with(&trusted_json_path()?, dir, revoke)
+}
+
+/// [`run`], with the approvals kept in `trusted`.
+fn run_with(trusted: &Path, dir: &Path, revoke: bool) -> Result<()> {
+    let dir = fs::canonicalize(dir).with_context(|| format!("resolving {}", dir.display()))?;
+    let found = find_project_config(&dir)
+        .ok_or_else(|| anyhow!("no {} in {} or any parent directory", PROJECT_CONFIG_FILE, dir.display()))?;
+    // Keyed as `check_project_config` looks it up, through a symlinked
+    // `.codex-clean.toml` to the file it points at.
+    let config = fs::canonicalize(&found).with_context(|| format!("resolving {}", found.display()))?;
+    let mut store = TrustStore::load_from(trusted)?;
+    if revoke {
+        if !store.revoke(&config) {
+            bail!("{} was not trusted", config.display());
+        }
+        store.save_to(trusted)?;
+        eprintln!("No longer trusting {}.", config.display());
+        return Ok(());
+    }
+    let raw = fs::read_to_string(&config)
+        .with_context(|| format!("reading {}", config.display()))?;
+    store.trust(&config, &raw);
+    store.save_to(trusted)?;
+    eprintln!("Trusted {}.", config.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trust_covers_exact_contents() {
+        let config = Path::new("/work/repo/.codex-clean.toml");
+        let mut store = TrustStore::default();
+        assert_eq!(store.check(config, "model = 'a'\n"), Trust::Untrusted);
+
+        store.trust(config, "model = 'a'\n");
+        assert_eq!(store.check(config, "model = 'a'\n"), Trust::Trusted);
+        assert_eq!(store.check(config, "model = 'b'\n"), Trust::Changed);
+        assert_eq!(store.check(Path::new("/elsewhere/.codex-clean.toml"), "model = 'a'\n"), Trust::Untrusted);
+
+        assert!(store.revoke(config));
+        assert!(!store.revoke(config));
+        assert_eq!(store.check(config, "model = 'a'\n"), Trust::Untrusted);
+    }
+
+    #[test]
+    fn store_round_trips_via_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trusted.json");
+        assert_eq!(TrustStore::load_from(&path).unwrap(), TrustStore::default());
+
+        let mut store = TrustStore::default();
+        store.trust(Path::new("/work/repo/.codex-clean.toml"), "sandbox = 'read-only'\n");
+        store.save_to(&path).unwrap();
+        assert_eq!(TrustStore::load_from(&path).unwrap(), store);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_symlinked_project_config_is_trusted_where_it_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let trusted = dir.path().join("trusted.json");
+        let repo = dir.path().join("repo");
+        fs::create_dir(&repo).unwrap();
+        fs::write(dir.path().join("shared.toml"), "model = 'a'\n").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("shared.toml"), repo.join(PROJECT_CONFIG_FILE)).unwrap();
+
+        run_with(&trusted, &repo, false).unwrap();
+        let canonical = fs::canonicalize(repo.join(PROJECT_CONFIG_FILE)).unwrap();
+        assert_eq!(TrustStore::load_from(&trusted).unwrap().check(&canonical, "model = 'a'\n"), Trust::Trusted);
+
+        run_with(&trusted, &repo, true).unwrap();
+        assert_eq!(TrustStore::load_from(&trusted).unwrap().check(&canonical, "model = 'a'\n"), Trust::Untrusted);
+        assert!(run_with(&trusted, &repo, true).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! The usage ledger and `codex-clean usage`.
+//!
+//! Run reports (see [`crate::stats`]) only keep the most recent runs, so
+//! they can't answer "how much did I use this month?". The ledger in
+//! `~/.config/codex-clean/usage.json` keeps running totals instead: one
+//! entry per local day, model and profile with the number of runs and the
+//! tokens they used. `codex-clean usage` shows today's totals, or the
+//! month's with `--month`, with a cost estimate for models priced under
+//! `[pricing.<model>]` in the config.
+//!
+//! The ledger also backs `[quota]`: before each run, [`check_quota`]
+//! compares today's and this week's totals with the configured limits and
+//! either refuses to start codex or warns, until the window resets.
+//! `--override-quota` runs anyway.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Pricing, Quota, QuotaAction};
+use crate::error::Error;
+use crate::seat::{atomic_write, config_dir};
+use crate::stats::RunReport;
+
+/// Entries older than this many days are dropped as new ones are added.
+const KEEP_DAYS: i64 = 400;
+
+/// `~/.config/codex-clean/usage.json`.
+pub fn usage_json_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("usage.json"))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Ledger {
+    #[serde(default)]
+    pub entries: Vec<LedgerEntry>,
+}
+
+/// The totals for one day, model and profile.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LedgerEntry {
+    pub day: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub runs: u64,
+    pub input_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl LedgerEntry {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    fn add(&mut self, other: &LedgerEntry) {
+        self.runs += other.runs;
+        self.input_tokens += other.input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+impl Pricing {
+    /// Estimated cost in US dollars of `entry`'s tokens.
+    pub fn cost(&self, entry: &LedgerEntry) -> f64 {
+        let cached = entry.cached_input_tokens.min(entry.input_tokens);
+        let uncached = entry.input_tokens - cached;
+        let cached_rate = self.cached_input.unwrap_or(self.input);
+        (uncached as f64 * self.input + cached as f64 * cached_rate + entry.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+impl Ledger {
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        if raw.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&raw).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Add one run to its day's entry.
+    pub fn record(&mut self, report: &RunReport) {
+        // A `--judge` pass runs on the same model.
+        let judge = report.judge.as_ref();
+        let run = LedgerEntry {
+            day: report.started_at.with_timezone(&Local).date_naive(),
+            model: report.model.clone(),
+            profile: report.profile.clone(),
+            runs: 1,
+            input_tokens: report.input_tokens.unwrap_or(0) + judge.map_or(0, |j| j.input_tokens),
+            cached_input_tokens: report.cached_input_tokens.unwrap_or(0) + judge.map_or(0, |j| j.cached_input_tokens),
+            output_tokens: report.output_tokens.unwrap_or(0) + judge.map_or(0, |j| j.output_tokens),
+        };
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.day == run.day && e.model == run.model && e.profile == run.profile)
+        {
+            Some(entry) => entry.add(&run),
+            None => self.entries.push(run.clone()),
+        }
+        let oldest = run.day - Duration::days(KEEP_DAYS);
+        self.entries.retain(|e| e.day > oldest);
+    }
+
+    /// The entries from `from` to `to` inclusive.
+    pub fn between(&self, from: NaiveDate, to: NaiveDate) -> Vec<&LedgerEntry> {
+        self.entries.iter().filter(|e| e.day >= from && e.day <= to).collect()
+    }
+}
+
+/// Add `report` to the ledger at [`usage_json_path`]. Concurrent runs
+/// take turns through a lock file next to it.
+pub fn record(report: &RunReport) -> Result<()> {
+    record_in(&usage_json_path()?, report)
+}
+
+pub fn record_in(path: &Path, report: &RunReport) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let lock_path = path.with_extension("json.lock");
+    let lock = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("opening {}", lock_path.display()))?;
+    lock.lock_exclusive().with_context(|| format!("locking {}", lock_path.display()))?;
+    let mut ledger = Ledger::load_from(path)?;
+    ledger.record(report);
+    let raw = serde_json::to_string_pretty(&ledger).context("serialising usage.json")?;
+    let result = atomic_write(path, raw.as_bytes());
+    let _ = lock.unlock();
+    result
+}
+
+/// The first `quota` limit the ledger has reached as of `today`, as a
+/// sentence saying how much was used and when the window resets.
+pub fn quota_reached(
+    ledger: &Ledger,
+    quota: &Quota,
+    pricing: &BTreeMap<String, Pricing>,
+    today: NaiveDate,
+) -> Option<String> {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let windows = [
+        ("daily", "today", "resets at midnight", today, quota.daily_tokens, quota.daily_cost),
+        ("weekly", "this week", "resets on Monday", monday, quota.weekly_tokens, quota.weekly_cost),
+    ];
+    for (name, period, resets, from, token_limit, cost_limit) in windows {
+        let entries = ledger.between(from, today);
+        if let Some(limit) = token_limit {
+            let used: u64 = entries.iter().map(|e| e.total_tokens()).sum();
+            if used >= limit {
+                return Some(format!(
+                    "{} token quota reached ({} of {} tokens used {}); it {}",
+                    name, used, limit, period, resets
+                ));
+            }
+        }
+        if let Some(limit) = cost_limit {
+            let used: f64 = entries
+                .iter()
+                .filter_map(|e| e.model.as_deref().and_then(|m| pricing.get(m)).map(|p| p.cost(e)))
+                .sum();
+            if used >= limit {
+                return Some(format!(
+                    "{} cost quota reached (${:.2} of ${:.2} spent {}); it {}",
+                    name, used, limit, period, resets
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// Check `[quota]` before a run: an error if a limit is reached and the
+/// action is `refuse`, a warning if it is `warn` or `overridden`.
+pub fn check_quota(cfg: &Config, overridden: bool) -> Result<()> {
+    let Some(quota) = &cfg.quota else {
+        return Ok(());
+    };
+    let ledger = Ledger::load_from(&usage_json_path()?)?;
+    let Some(reached) = quota_reached(&ledger, quota, &cfg.pricing, Local::now().date_naive()) else {
+        return Ok(());
+    };
+    if overridden {
+        eprintln!("Warning: {}; running anyway (--override-quota)", reached);
+    } else if quota.action == QuotaAction::Warn {
+        eprintln!("Warning: {}", reached);
+    } else {
+        return Err(Error::QuotaExceeded(reached).into());
+    }
+    Ok(())
+}
+
+/// `codex-clean usage [--month]`.
+pub fn run(month: bool) -> Result<()> {
+    let ledger = Ledger::load_from(&usage_json_path()?)?;
+    let pricing = Config::load_layered()?.pricing;
+    let today = Local::now().date_naive();
+    print!("{}", format_usage(&ledger, &pricing, today, month));
+    Ok(())
+}
+
+fn format_usage(ledger: &Ledger, pricing: &BTreeMap<String, Pricing>, today: NaiveDate, month: bool) -> String {
+    let from = if month { today.with_day(1).unwrap_or(today) } else { today };
+    let entries = ledger.between(from, today);
+    let mut out = if month {
+        format!("Usage for {} ({} to {})\n", today.format("%B %Y"), from, today)
+    } else {
+        format!("Usage for {}\n", today)
+    };
+    if entries.is_empty() {
+        out.push_str("\nNo runs recorded.\n");
+        return out;
+    }
+    let cost = |entry: &LedgerEntry, model: Option<&str>| model.and_then(|m| pricing.get(m)).map(|p| p.cost(entry));
+
+    if month {
+        let mut days: BTreeMap<String, (LedgerEntry, Option<f64>)> = BTreeMap::new();
+        for e in &entries {
+            let (total, spent) = days.entry(e.day.to_string()).or_default();
+            total.add(e);
+            if let Some(c) = cost(e, e.model.as_deref()) {
+                *spent = Some(spent.unwrap_or(0.0) + c);
+            }
+        }
+        out.push('\n');
+        out.push_str(&format_table("DAY", &days));
+    }
+
+    let mut groups: BTreeMap<String, (LedgerEntry, Option<f64>)> = BTreeMap::new();
+    let mut total = (LedgerEntry::default(), None);
+    let mut unpriced = false;
+    for e in &entries {
+        let name = format!("{} / {}", e.model.as_deref().unwrap_or("(default)"), e.profile.as_deref().unwrap_or("-"));
+        let spent = cost(e, e.model.as_deref());
+        unpriced |= spent.is_none();
+        for (sum, sum_cost) in [groups.entry(name).or_default(), &mut total] {
+            sum.add(e);
+            if let Some(c) = spent {
+                *sum_cost = Some(sum_cost.unwrap_or(0.0) + c);
+            }
+        }
+    }
+    groups.insert("TOTAL".to_string(), total);
+    out.push('\n');
+    out.push_str(&format_table("MODEL / PROFILE", &groups));
+    if unpriced {
+        out.push_str("\nCost is estimated only for models with [pricing.<model>] in config.toml.\n");
+    }
+    out
+}
+
+fn format_table(heading: &str, rows: &BTreeMap<String, (LedgerEntry, Option<f64>)>) -> String {
+    let width = rows.keys().map(|k| k.len()).max().unwrap_or(0).max(heading.len());
+    let mut out = format!(
+        "{:<width$}  {:>5}  {:>11}  {:>11}  {:>11}  {:>9}\n",
+        heading, "RUNS", "INPUT", "CACHED", "OUTPUT", "COST",
+        width = width
+    );
+    // TOTAL sorts among the groups by name; print it last.
+    let (totals, groups): (Vec<_>, Vec<_>) = rows.iter().partition(|(name, _)| *name == "TOTAL");
+    for (name, (e, cost)) in groups.into_iter().chain(totals) {
+        out.push_str(&format!(
+            "{:<width$}  {:>5}  {:>11}  {:>11}  {:>11}  {:>9}\n",
+            name,
+            e.runs,
+            e.input_tokens,
+            e.cached_input_tokens,
+            e.output_tokens,
+            cost.map_or_else(|| "-".to_string(), |c| format!("${:.2}", c)),
+            width = width
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn report(day: u32, model: Option<&str>, tokens: (u64, u64, u64)) -> RunReport {
+        RunReport {
+            started_at: Local.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap().to_utc(),
+            model: model.map(String::from),
+            input_tokens: Some(tokens.0),
+            cached_input_tokens: Some(tokens.1),
+            output_tokens: Some(tokens.2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn runs_accumulate_per_day_model_and_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        record_in(&path, &report(13, Some("o3"), (1000, 200, 50))).unwrap();
+        record_in(&path, &report(14, Some("o3"), (2000, 0, 100))).unwrap();
+        record_in(&path, &report(14, Some("o3"), (500, 500, 10))).unwrap();
+        record_in(&path, &report(14, None, (10, 0, 1))).unwrap();
+
+        let ledger = Ledger::load_from(&path).unwrap();
+        assert_eq!(ledger.entries.len(), 3);
+        let day = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let today = ledger.between(day, day);
+        assert_eq!(today[0].runs, 2);
+        assert_eq!(today[0].total_tokens(), 2610);
+        assert_eq!(today[0].cached_input_tokens, 500);
+    }
+
+    #[test]
+    fn cost_charges_cached_input_at_its_own_rate() {
+        let entry = LedgerEntry { input_tokens: 1_000_000, cached_input_tokens: 400_000, output_tokens: 100_000, ..Default::default() };
+        let pricing = Pricing { input: 2.0, cached_input: Some(0.5), output: 8.0 };
+        assert!((pricing.cost(&entry) - (1.2 + 0.2 + 0.8)).abs() < 1e-9);
+        let no_cache_rate = Pricing { cached_input: None, ..pricing };
+        assert!((no_cache_rate.cost(&entry) - (2.0 + 0.8)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn month_view_lists_days_then_models() {
+        let mut ledger = Ledger::default();
+        ledger.record(&report(1, Some("o3"), (1_000_000, 0, 0)));
+        ledger.record(&report(14, Some("gpt-5.5"), (10, 0, 5)));
+        let pricing = BTreeMap::from([("o3".to_string(), Pricing { input: 2.0, cached_input: None, output: 8.0 })]);
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+        let month = format_usage(&ledger, &pricing, today, true);
+        assert!(month.starts_with("Usage for October 2026 (2026-10-01 to 2026-10-14)\n\nDAY"), "{}", month);
+        assert!(month.contains("\n2026-10-01      1      1000000"), "{}", month);
+        let total = month.lines().find(|l| l.starts_with("TOTAL")).unwrap();
+        assert!(total.ends_with("$2.00"), "{}", total);
+        assert!(month.contains("Cost is estimated only"));
+
+        let day = format_usage(&ledger, &pricing, today, false);
+        assert!(day.contains("gpt-5.5 / -") && !day.contains("o3"), "{}", day);
+        let empty = format_usage(&Ledger::default(), &pricing, today, false);
+        assert!(empty.ends_with("No runs recorded.\n"));
+    }
+
+    #[test]
+    fn quotas_cover_today_and_the_week_since_monday() {
+        // 2026-10-12 is a Monday; 2026-10-11 belongs to the week before.
+        let mut ledger = Ledger::default();
+        ledger.record(&report(11, Some("o3"), (5_000_000, 0, 0)));
+        ledger.record(&report(12, Some("o3"), (600_000, 0, 0)));
+        ledger.record(&report(14, Some("o3"), (400_000, 0, 100_000)));
+        let pricing = BTreeMap::from([("o3".to_string(), Pricing { input: 2.0, cached_input: None, output: 8.0 })]);
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let check = |quota: Quota| quota_reached(&ledger, &quota, &pricing, today);
+
+        assert_eq!(check(Quota::default()), None);
+        assert_eq!(check(Quota { daily_tokens: Some(600_000), ..Default::default() }), None);
+        assert_eq!(
+            check(Quota { daily_tokens: Some(500_000), ..Default::default() }).unwrap(),
+            "daily token quota reached (500000 of 500000 tokens used today); it resets at midnight"
+        );
+        assert_eq!(
+            check(Quota { weekly_tokens: Some(1_000_000), ..Default::default() }).unwrap(),
+            "weekly token quota reached (1100000 of 1000000 tokens used this week); it resets on Monday"
+        );
+        assert_eq!(check(Quota { weekly_tokens: Some(2_000_000), ..Default::default() }), None);
+        assert_eq!(
+            check(Quota { daily_cost: Some(1.5), ..Default::default() }).unwrap(),
+            "daily cost quota reached ($1.60 of $1.50 spent today); it resets at midnight"
+        );
+        assert_eq!(check(Quota { weekly_cost: Some(3.0), ..Default::default() }), None);
+    }
+}
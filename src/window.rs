@@ -0,0 +1,170 @@
+//! How full a session's context window is.
+//!
+//! Every resume sends the whole session back to the model, so a long
+//! session creeps towards the model's context limit, after which codex
+//! compacts or drops earlier turns. codex's session log records a
+//! `token_count` event after each model call, whose `last_token_usage` is
+//! what that call held and which, in newer versions, names the model's
+//! `model_context_window`. After a run, [`Pressure::read`] takes the last
+//! of them; when codex doesn't say, the window comes from the
+//! `[context_windows]` table in the config or [`builtin_window`]. Past
+//! [`WARN_PERCENT`] codex-clean warns and suggests carrying on from a
+//! summary instead.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How full the window may get before codex-clean warns.
+pub const WARN_PERCENT: u64 = 80;
+
+/// Context windows of models codex commonly runs, by name prefix, longest
+/// prefix first. These are the windows codex itself works to, which leave
+/// room for the answer.
+const BUILTIN_WINDOWS: &[(&str, u64)] = &[
+    ("gpt-5.5", 272_000),
+    ("gpt-5", 272_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("codex-mini", 200_000),
+    ("o4-mini", 200_000),
+    ("o3", 200_000),
+];
+
+/// The built-in context window for `model`, matched by prefix.
+pub fn builtin_window(model: &str) -> Option<u64> {
+    BUILTIN_WINDOWS.iter().find(|(prefix, _)| model.starts_with(prefix)).map(|&(_, window)| window)
+}
+
+/// The window for `model`: the config's `[context_windows]` entry (an
+/// exact name, or else the longest matching prefix), then the built-in one.
+pub fn window_for(model: &str, configured: &BTreeMap<String, u64>) -> Option<u64> {
+    configured
+        .get(model)
+        .or_else(|| {
+            configured
+                .iter()
+                .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map(|(_, window)| window)
+        })
+        .copied()
+        .or_else(|| builtin_window(model))
+}
+
+/// How much of the context window a session's last model call used.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pressure {
+    /// Tokens the last call held: its input and output.
+    pub tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Pressure {
+    /// The last usage recorded in the session log at `path`, if any.
+    pub fn read(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let mut pressure: Option<Self> = None;
+        let mut model = None;
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else { break };
+            let Ok(v) = serde_json::from_str::<Value>(&line) else { continue };
+            let Some(payload) = v.get("payload") else { continue };
+            match (v.get("type").and_then(Value::as_str), payload.get("type").and_then(Value::as_str)) {
+                (Some("turn_context"), _) => {
+                    model = payload.get("model").and_then(Value::as_str).map(String::from).or(model);
+                }
+                (Some("event_msg"), Some("token_count")) => {
+                    // `info` is null until the first call has reported usage.
+                    let Some(info) = payload.get("info").filter(|i| !i.is_null()) else { continue };
+                    let Some(last) = info.get("last_token_usage") else { continue };
+                    let field = |k: &str| last.get(k).and_then(Value::as_u64).unwrap_or(0);
+                    pressure = Some(Self {
+                        tokens: field("input_tokens") + field("output_tokens"),
+                        window: info.get("model_context_window").and_then(Value::as_u64),
+                        model: None,
+                    });
+                }
+                _ => {}
+            }
+        }
+        let mut pressure = pressure?;
+        pressure.model = model;
+        Some(pressure)
+    }
+
+    /// Fill in the window from the config or the built-in table when codex
+    /// didn't report it, for the session's model or else `fallback_model`.
+    pub fn with_window(mut self, fallback_model: Option<&str>, configured: &BTreeMap<String, u64>) -> Self {
+        if self.model.is_none() {
+            self.model = fallback_model.map(String::from);
+        }
+        if self.window.is_none() {
+            self.window = self.model.as_deref().and_then(|m| window_for(m, configured));
+        }
+        self
+    }
+
+    /// How full the window is, when it is known.
+    pub fn percent(&self) -> Option<u64> {
+        self.window.filter(|&w| w > 0).map(|w| self.tokens * 100 / w)
+    }
+
+    /// The warning for a window past [`WARN_PERCENT`], if it is.
+    pub fn warning(&self, session_id: &str) -> Option<String> {
+        let percent = self.percent().filter(|&p| p >= WARN_PERCENT)?;
+        let model = self.model.as_deref().map_or("the ".to_string(), |m| format!("{}'s ", m));
+        Some(format!(
+            "Warning: session {} fills {}% of {}{}-token context window ({} tokens); codex will soon compact or drop its earlier turns. \
+             Consider `codex-clean summarize {} --title` and starting a fresh session from the summary.",
+            session_id,
+            percent,
+            model,
+            self.window.unwrap_or_default(),
+            self.tokens,
+            session_id
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_usage_in_the_log_is_measured_against_the_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rollout.jsonl");
+        std::fs::write(
+            &path,
+            [
+                r#"{"type":"session_meta","payload":{"id":"s-1"}}"#,
+                r#"{"type":"turn_context","payload":{"model":"gpt-5.5","cwd":"/src"}}"#,
+                r#"{"type":"event_msg","payload":{"type":"token_count","info":null}}"#,
+                r#"{"type":"event_msg","payload":{"type":"token_count","info":{"last_token_usage":{"input_tokens":90000,"output_tokens":1000}}}}"#,
+                r#"{"type":"event_msg","payload":{"type":"token_count","info":{"last_token_usage":{"input_tokens":230000,"cached_input_tokens":200000,"output_tokens":2000}}}}"#,
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let pressure = Pressure::read(&path).unwrap().with_window(Some("o3"), &BTreeMap::new());
+        assert_eq!(pressure, Pressure { tokens: 232_000, window: Some(272_000), model: Some("gpt-5.5".into()) });
+        assert_eq!(pressure.percent(), Some(85));
+        let warning = pressure.warning("s-1").unwrap();
+        assert!(warning.contains("fills 85% of gpt-5.5's 272000-token context window") && warning.contains("summarize s-1"), "{}", warning);
+
+        let configured = BTreeMap::from([("gpt-5".to_string(), 1_000_000), ("my-model".to_string(), 8_000)]);
+        assert_eq!(window_for("gpt-5.5-codex", &configured), Some(1_000_000));
+        assert_eq!(window_for("my-model", &configured), Some(8_000));
+        assert_eq!(window_for("o3-pro", &configured), Some(200_000));
+        assert_eq!(window_for("unknown", &configured), None);
+        assert_eq!(Pressure { tokens: 10, window: None, model: None }.warning("s-1"), None);
+    }
+}
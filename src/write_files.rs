@@ -0,0 +1,390 @@
+//! `--write-files`: save the files an answer spells out.
+//!
+//! Scaffolding prompts ("generate a crate with these modules") come back as
+//! one answer holding several files. Two conventions mark where each file
+//! starts:
+//!
+//! - a fenced code block whose info string names the path
+//!   (```` ```rust src/main.rs ````, ```` ```src/main.rs ````,
+//!   ```` ```rust title="src/main.rs" ````), or whose first line is a
+//!   sentinel;
+//! - a sentinel line, `// FILE: src/main.rs` (or with `#`, `--`, `;`,
+//!   `/* */` or `<!-- -->` around it). A fenced block straight after it
+//!   is the file; otherwise the file is every line up to the next sentinel
+//!   or the end of the answer.
+//!
+//! Paths are relative to codex's working directory and may not leave it.
+//! Nothing is overwritten unless `--force` is given; if any file exists,
+//! none are written. With `--confirm`, each file is previewed and asked
+//! about first.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+use crate::confirm::PendingWrite;
+
+/// One file found in an answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileBlock {
+    pub path: String,
+    pub contents: String,
+}
+
+/// What [`write`] did with one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Written {
+    pub path: PathBuf,
+    /// Where it was written.
+    pub target: PathBuf,
+    /// It already existed and was replaced.
+    pub replaced: bool,
+    pub lines: usize,
+}
+
+/// The files marked in `answer`, in order. A path given twice keeps its
+/// last contents.
+pub fn parse(answer: &str) -> Vec<FileBlock> {
+    let mut blocks: Vec<FileBlock> = Vec::new();
+    let mut lines = answer.lines();
+    // A sentinel outside any fence: the path, and the lines so far.
+    let mut section: Option<(String, Vec<&str>)> = None;
+    while let Some(line) = lines.next() {
+        if let Some((fence, info)) = fence_open(line) {
+            if let Some((path, body)) = &section {
+                // A fence straight after a sentinel is that file's body.
+                if body.iter().all(|l| l.trim().is_empty()) {
+                    let path = path.clone();
+                    section = None;
+                    let body = fence_body(&mut lines, fence);
+                    push(&mut blocks, path, &body);
+                    continue;
+                }
+            }
+            if section.is_none() {
+                let mut body = fence_body(&mut lines, fence);
+                let path = match path_from_info(info) {
+                    Some(path) => Some(path),
+                    None => match body.first().and_then(|first| sentinel(first)) {
+                        Some(path) => {
+                            body.remove(0);
+                            Some(path)
+                        }
+                        None => None,
+                    },
+                };
+                if let Some(path) = path {
+                    push(&mut blocks, path, &body);
+                }
+                continue;
+            }
+        }
+        if let Some(path) = sentinel(line) {
+            if let Some((prev, body)) = section.take() {
+                push(&mut blocks, prev, &trim_blank_end(&body));
+            }
+            section = Some((path, Vec::new()));
+            continue;
+        }
+        if let Some((_, body)) = &mut section {
+            body.push(line);
+        }
+    }
+    if let Some((path, body)) = section {
+        push(&mut blocks, path, &trim_blank_end(&body));
+    }
+    blocks
+}
+
+/// The contents of every fenced code block in `answer`, in order, whether
+/// or not it names a file.
+pub fn code_blocks(answer: &str) -> Vec<String> {
+    fenced_blocks(answer).into_iter().map(|(_, contents)| contents).collect()
+}
+
+/// Every fenced code block in `answer` as its info string (`rust`,
+/// `mermaid`, ...) and contents, in order.
+pub fn fenced_blocks(answer: &str) -> Vec<(String, String)> {
+    let mut blocks = Vec::new();
+    let mut lines = answer.lines();
+    while let Some(line) = lines.next() {
+        if let Some((fence, info)) = fence_open(line) {
+            let mut contents = fence_body(&mut lines, fence).join("\n");
+            if !contents.is_empty() {
+                contents.push('\n');
+            }
+            blocks.push((info.to_string(), contents));
+        }
+    }
+    blocks
+}
+
+/// Write `blocks` under `dir`. Refuses to write anything if a path is
+/// unsafe, or exists and `force` is false.
+pub fn write(dir: &Path, blocks: &[FileBlock], force: bool) -> Result<Vec<Written>> {
+    apply(&plan(dir, blocks, force)?)
+}
+
+/// Check `blocks` as [`write`] does and say what writing them would do,
+/// without touching anything (see [`crate::confirm`]).
+pub fn plan(dir: &Path, blocks: &[FileBlock], force: bool) -> Result<Vec<PendingWrite>> {
+    let mut writes = Vec::with_capacity(blocks.len());
+    let mut existing = Vec::new();
+    for block in blocks {
+        let path = checked_path(&block.path)?;
+        let target = dir.join(&path);
+        check_inside(dir, &target, &path)?;
+        if target.is_dir() {
+            bail!("{} is a directory", path.display());
+        }
+        let old = if target.exists() {
+            existing.push(path.display().to_string());
+            Some(fs::read_to_string(&target).unwrap_or_default())
+        } else {
+            None
+        };
+        writes.push(PendingWrite { path, target, old, new: block.contents.clone() });
+    }
+    if !existing.is_empty() && !force {
+        bail!(
+            "the answer would overwrite {}; nothing was written (pass --force to replace existing files)",
+            existing.join(", ")
+        );
+    }
+    Ok(writes)
+}
+
+/// Carry out `writes`.
+pub fn apply(writes: &[PendingWrite]) -> Result<Vec<Written>> {
+    let mut written = Vec::with_capacity(writes.len());
+    for w in writes {
+        if let Some(parent) = w.target.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        fs::write(&w.target, &w.new).with_context(|| format!("writing {}", w.target.display()))?;
+        written.push(Written { path: w.path.clone(), target: w.target.clone(), replaced: w.old.is_some(), lines: w.new.lines().count() });
+    }
+    Ok(written)
+}
+
+/// The manifest printed after writing.
+pub fn describe(written: &[Written]) -> String {
+    let mut out = format!("Wrote {} file(s) from the answer:\n", written.len());
+    for w in written {
+        let action = if w.replaced { "replaced" } else { "created" };
+        let lines = if w.lines == 1 { "line" } else { "lines" };
+        out.push_str(&format!("  {:<8}  {} ({} {})\n", action, w.path.display(), w.lines, lines));
+    }
+    out
+}
+
+fn push(blocks: &mut Vec<FileBlock>, path: String, body: &[&str]) {
+    let mut contents = body.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    blocks.retain(|b| b.path != path);
+    blocks.push(FileBlock { path, contents });
+}
+
+/// A fence opening: its marker (e.g. "```") and the info string.
+pub(crate) fn fence_open(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|c| *c == marker).count();
+    if len < 3 {
+        return None;
+    }
+    Some((&trimmed[..len], trimmed[len..].trim()))
+}
+
+/// The lines up to the closing fence (or the end of the answer).
+fn fence_body<'a>(lines: &mut impl Iterator<Item = &'a str>, fence: &str) -> Vec<&'a str> {
+    let mut body = Vec::new();
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with(fence) && trimmed.trim_start_matches(fence.chars().next().unwrap()).is_empty() {
+            break;
+        }
+        body.push(line);
+    }
+    body
+}
+
+fn trim_blank_end<'a>(body: &[&'a str]) -> Vec<&'a str> {
+    let end = body.iter().rposition(|l| !l.trim().is_empty()).map_or(0, |i| i + 1);
+    body[..end].to_vec()
+}
+
+/// The path in a sentinel line such as `// FILE: src/main.rs`.
+fn sentinel(line: &str) -> Option<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"^\s*(?://+|#+|--|;+|/\*|<!--)?\s*FILE:\s*`?([^\s`]+)`?\s*(?:\*/|-->)?\s*$").unwrap()
+    });
+    re.captures(line).map(|c| c[1].to_string())
+}
+
+/// The path named in a fence's info string, if any.
+fn path_from_info(info: &str) -> Option<String> {
+    for (i, token) in info.split_whitespace().enumerate() {
+        for key in ["title=", "file=", "path=", "filename="] {
+            if let Some(value) = token.strip_prefix(key) {
+                return Some(value.trim_matches(|c| c == '"' || c == '\'').to_string());
+            }
+        }
+        // ```rust:src/main.rs
+        if let Some((_, path)) = token.split_once(':').filter(|(_, p)| looks_like_path(p)) {
+            return Some(path.to_string());
+        }
+        // The first word is normally the language; a path there means it
+        // was left out.
+        if (i > 0 || token.contains(['/', '.'])) && looks_like_path(token) {
+            return Some(token.to_string());
+        }
+    }
+    None
+}
+
+fn looks_like_path(s: &str) -> bool {
+    !s.is_empty()
+        && !s.contains("://")
+        && !s.contains(['{', '}', '=', ','])
+        && (s.contains('/') || looks_like_file_name(s))
+}
+
+/// `name.ext` or a dotfile such as `.env`, where the extension has a
+/// letter in it, so that a version such as `3.11` isn't taken for a file.
+fn looks_like_file_name(s: &str) -> bool {
+    s.rsplit_once('.').is_some_and(|(_, ext)| {
+        ext.chars().any(|c| c.is_ascii_alphabetic()) && ext.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+    })
+}
+
+/// `path` as a relative path that stays inside the directory it is
+/// joined to.
+fn checked_path(path: &str) -> Result<PathBuf> {
+    let p = Path::new(path);
+    let safe = !path.is_empty() && p.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !safe {
+        bail!("refusing to write '{}': paths must be relative and stay inside the working directory", path);
+    }
+    Ok(p.components().filter(|c| matches!(c, Component::Normal(_))).collect())
+}
+
+/// Refuse `target` when a symlink on the way to it (or the file itself)
+/// leads out of `dir`: the deepest part of it that exists must resolve to
+/// somewhere inside `dir`.
+fn check_inside(dir: &Path, target: &Path, path: &Path) -> Result<()> {
+    // `dir` itself may be a symlink, or not made yet.
+    for existing in target.ancestors().take_while(|p| *p != dir) {
+        let Ok(meta) = fs::symlink_metadata(existing) else { continue };
+        let root = fs::canonicalize(dir).with_context(|| format!("resolving {}", dir.display()))?;
+        let resolved = match fs::canonicalize(existing) {
+            Ok(resolved) => resolved,
+            // Writing through a dangling link creates whatever it names.
+            Err(_) if meta.file_type().is_symlink() => {
+                bail!("refusing to write '{}': {} is a symlink to nowhere", path.display(), existing.display())
+            }
+            Err(e) => return Err(e).with_context(|| format!("resolving {}", existing.display())),
+        };
+        if !resolved.starts_with(&root) {
+            bail!(
+                "refusing to write '{}': it leads through a symlink to {}, outside the working directory",
+                path.display(),
+                resolved.display()
+            );
+        }
+        return Ok(());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(path: &str, contents: &str) -> FileBlock {
+        FileBlock { path: path.into(), contents: contents.into() }
+    }
+
+    #[test]
+    fn parses_both_conventions() {
+        let answer = "Here is the crate.\n\n\
+            ```toml Cargo.toml\n[package]\nname = \"demo\"\n```\n\n\
+            ```rust\n// FILE: src/lib.rs\npub fn hi() {}\n```\n\n\
+            // FILE: src/main.rs\n```rust\nfn main() {}\n```\n\n\
+            ```rust\nfn not_a_file() {}\n```\n\n\
+            # FILE: scripts/run.sh\necho run\n\n\
+            <!-- FILE: `docs/index.md` -->\n# Docs\n";
+        assert_eq!(
+            parse(answer),
+            vec![
+                block("Cargo.toml", "[package]\nname = \"demo\"\n"),
+                block("src/lib.rs", "pub fn hi() {}\n"),
+                block("src/main.rs", "fn main() {}\n"),
+                block("scripts/run.sh", "echo run\n"),
+                block("docs/index.md", "# Docs\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn info_strings_name_paths_in_several_ways() {
+        assert_eq!(path_from_info("rust src/main.rs"), Some("src/main.rs".into()));
+        assert_eq!(path_from_info("src/main.rs"), Some("src/main.rs".into()));
+        assert_eq!(path_from_info("rust title=\"src/a.rs\""), Some("src/a.rs".into()));
+        assert_eq!(path_from_info("rust:src/b.rs"), Some("src/b.rs".into()));
+        assert_eq!(path_from_info("rust"), None);
+        assert_eq!(path_from_info("rust,ignore"), None);
+        assert_eq!(path_from_info("{.python}"), None);
+        assert_eq!(path_from_info("python 3.11"), None);
+        assert_eq!(path_from_info("rust 1.75.0"), None);
+        assert_eq!(path_from_info("sh .env"), Some(".env".into()));
+        assert_eq!(path_from_info("text README"), None);
+    }
+
+    #[test]
+    fn write_refuses_to_clobber_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("keep.txt"), "mine\n").unwrap();
+        let blocks = vec![block("src/new.rs", "a\nb\n"), block("./keep.txt", "theirs\n")];
+
+        let err = write(dir.path(), &blocks, false).unwrap_err();
+        assert!(err.to_string().contains("overwrite keep.txt"), "{}", err);
+        assert!(!dir.path().join("src/new.rs").exists());
+
+        let written = write(dir.path(), &blocks, true).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("keep.txt")).unwrap(), "theirs\n");
+        assert_eq!(
+            describe(&written),
+            "Wrote 2 file(s) from the answer:\n  created   src/new.rs (2 lines)\n  replaced  keep.txt (1 line)\n"
+        );
+
+        for bad in ["../escape.txt", "/etc/passwd", "a/../../b"] {
+            assert!(write(dir.path(), &[block(bad, "x")], true).is_err(), "{}", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_refuses_paths_through_symlinks_out_of_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("out")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("f.txt"), dir.path().join("link.txt")).unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("src"), dir.path().join("inside")).unwrap();
+
+        for bad in ["out/escape.txt", "out/new/dir/escape.txt", "link.txt"] {
+            let err = write(dir.path(), &[block(bad, "x")], true).unwrap_err();
+            assert!(err.to_string().starts_with(&format!("refusing to write '{}'", bad)), "{}", err);
+        }
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+        write(dir.path(), &[block("inside/ok.rs", "x")], true).unwrap();
+        assert!(dir.path().join("src/ok.rs").exists());
+    }
+}
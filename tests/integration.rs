@@ -99,6 +99,99 @@ fn unknown_events_are_silently_skipped() {
     assert_eq!(output.messages.len(), 2);
 }
 
+#[test]
+fn fixture_tool_activity_records_commands_and_file_changes() {
+    let output = parse_fixture("tool_activity.jsonl");
+    let commands: Vec<_> = output
+        .commands
+        .iter()
+        .map(|c| (c.command.as_str(), c.exit_code))
+        .collect();
+    assert_eq!(
+        commands,
+        vec![("bash -lc 'cargo test'", Some(101)), ("bash -lc 'cargo test'", Some(0))]
+    );
+    let changes: Vec<_> = output
+        .file_changes
+        .iter()
+        .map(|c| (c.kind.as_str(), c.path.as_str()))
+        .collect();
+    assert_eq!(changes, vec![("update", "src/lib.rs"), ("add", "tests/new_case.rs")]);
+    assert_eq!(output.messages.len(), 1);
+    assert_eq!(output.commands[0].output.as_deref(), Some("test result: FAILED. 1 failed\n"));
+}
+
+/// `--command-log`: every command's output lands in its own file, indexed
+/// by the summary.
+#[test]
+fn command_log_saves_each_commands_output() {
+    use std::sync::Arc;
+
+    use codex_clean::backend::ReplayBackend;
+    use codex_clean::runner::{self, Mode, RunOptions};
+
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-command-log");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let dir = tempfile::tempdir().unwrap();
+    let opts = RunOptions {
+        backend: Arc::new(ReplayBackend { path: "tests/fixtures/tool_activity.jsonl".into(), last_turn: false }),
+        command_log: Some(dir.path().join("commands")),
+        ..Default::default()
+    };
+    runner::run(&[], "fix the tests", Mode::Exec, &opts).unwrap();
+
+    let logs = dir.path().join("commands");
+    let summary = std::fs::read_to_string(logs.join("summary.txt")).unwrap();
+    assert_eq!(
+        summary,
+        " 101  001-cargo-test.log  bash -lc 'cargo test'\n   0  002-cargo-test.log  bash -lc 'cargo test'\n"
+    );
+    let first = std::fs::read_to_string(logs.join("001-cargo-test.log")).unwrap();
+    assert!(first.ends_with("\ntest result: FAILED. 1 failed\n"), "{}", first);
+}
+
+/// `--write-files`: the files an answer marks out are written under
+/// codex's working directory, and existing ones are left alone without
+/// `--force`.
+#[test]
+fn write_files_saves_the_files_the_answer_marks() {
+    use std::sync::Arc;
+
+    use codex_clean::backend::ReplayBackend;
+    use codex_clean::runner::{self, Mode, RunOptions};
+
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-write-files");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let dir = tempfile::tempdir().unwrap();
+    let args = vec!["-C".to_string(), dir.path().display().to_string()];
+    let mut opts = RunOptions {
+        backend: Arc::new(ReplayBackend { path: "tests/fixtures/scaffold_answer.jsonl".into(), last_turn: false }),
+        write_files: true,
+        ..Default::default()
+    };
+    let code = runner::run_codex_presenting(&args, "scaffold a crate", Mode::Exec, &opts, |_| {}).unwrap();
+    assert_eq!(code, 0);
+    assert_eq!(std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(), "[package]\nname = \"demo\"\n");
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("src/main.rs")).unwrap(),
+        "fn main() {\n    println!(\"hi\");\n}\n"
+    );
+
+    std::fs::write(dir.path().join("Cargo.toml"), "mine\n").unwrap();
+    let code = runner::run_codex_presenting(&args, "scaffold a crate", Mode::Exec, &opts, |_| {}).unwrap();
+    assert_eq!(code, 1);
+    assert_eq!(std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap(), "mine\n");
+
+    opts.overwrite_files = true;
+    let code = runner::run_codex_presenting(&args, "scaffold a crate", Mode::Exec, &opts, |_| {}).unwrap();
+    assert_eq!(code, 0);
+    assert!(std::fs::read_to_string(dir.path().join("Cargo.toml")).unwrap().contains("demo"));
+}
+
 /// Regression test for the stdin hang.
 ///
 /// codex >= 0.123 reads additional input from stdin until EOF. If codex-clean
@@ -199,3 +292,203 @@ fn does_not_hang_when_parent_stdin_stays_open() {
         }
     }
 }
+
+/// `Session` starts a session on the first `send` and resumes it by ID on
+/// the next, as the library API promises.
+#[test]
+fn session_resumes_by_id_after_the_first_turn() {
+    use std::sync::{Arc, Mutex};
+
+    use codex_clean::backend::{CodexBackend, CodexProcess, ReplayBackend, SpawnOptions};
+    use codex_clean::runner::RunOptions;
+    use codex_clean::Session;
+
+    #[derive(Debug)]
+    struct Recording {
+        replay: ReplayBackend,
+        argvs: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl CodexBackend for Recording {
+        fn spawn(&self, argv: &[String], opts: SpawnOptions) -> anyhow::Result<Box<dyn CodexProcess>> {
+            self.argvs.lock().unwrap().push(argv.to_vec());
+            self.replay.spawn(argv, opts)
+        }
+
+        fn uses_local_auth(&self) -> bool {
+            false
+        }
+    }
+
+    // Reports and control sockets go here rather than the real config dir.
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-session-api");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let backend = Arc::new(Recording {
+        replay: ReplayBackend { path: "tests/fixtures/sample_session.jsonl".into(), last_turn: false },
+        argvs: Mutex::new(Vec::new()),
+    });
+    let opts = RunOptions { backend: backend.clone(), ..Default::default() };
+    let mut session = Session::new(vec!["-m".into(), "o3".into()], opts);
+    assert_eq!(session.id(), None);
+
+    let first = session.send("What is this project?").unwrap();
+    assert!(first.text.contains("Rust project"));
+    assert_eq!(session.id(), Some("0199a213-81c0-7800-8aa1-bbab2a035a53"));
+    session.send("And its tests?").unwrap();
+    assert!(session.send("  ").is_err());
+
+    let argvs = backend.argvs.lock().unwrap();
+    assert_eq!(argvs.len(), 2);
+    assert_eq!(argvs[0].last().map(String::as_str), Some("What is this project?"));
+    let resume = argvs[1].iter().position(|a| a == "resume").expect("second turn resumes");
+    assert_eq!(argvs[1][resume + 1..], ["0199a213-81c0-7800-8aa1-bbab2a035a53", "And its tests?"]);
+}
+
+/// `--retry-on-empty`: a turn that ends without an answer is resumed with
+/// the nudge prompt until codex says something.
+#[test]
+fn empty_answers_are_nudged_by_resuming_the_session() {
+    use std::sync::{Arc, Mutex};
+
+    use codex_clean::backend::{CodexBackend, CodexProcess, ReplayBackend, SpawnOptions};
+    use codex_clean::runner::{self, Mode, RunOptions};
+
+    /// Silent for the first `silent` turns, then answers.
+    #[derive(Debug)]
+    struct Reluctant {
+        silent: usize,
+        argvs: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl CodexBackend for Reluctant {
+        fn spawn(&self, argv: &[String], opts: SpawnOptions) -> anyhow::Result<Box<dyn CodexProcess>> {
+            let mut argvs = self.argvs.lock().unwrap();
+            argvs.push(argv.to_vec());
+            let path = if argvs.len() <= self.silent {
+                "tests/fixtures/empty_answer.jsonl"
+            } else {
+                "tests/fixtures/sample_session.jsonl"
+            };
+            ReplayBackend { path: path.into(), last_turn: false }.spawn(argv, opts)
+        }
+
+        fn uses_local_auth(&self) -> bool {
+            false
+        }
+    }
+
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-retry-on-empty");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let backend = Arc::new(Reluctant { silent: 2, argvs: Mutex::new(Vec::new()) });
+    let opts = RunOptions { backend: backend.clone(), retry_on_empty: 3, ..Default::default() };
+    let output = runner::run(&[], "What is this project?", Mode::Exec, &opts).unwrap();
+    assert!(output.aggregated_message().contains("Rust project"));
+
+    let argvs = backend.argvs.lock().unwrap();
+    assert_eq!(argvs.len(), 3, "two nudges, then an answer");
+    assert_eq!(
+        argvs[2][argvs[2].len() - 3..],
+        ["resume", "0199a213-81c0-7800-8aa1-bbab2a035a53", runner::DEFAULT_NUDGE_PROMPT]
+    );
+    drop(argvs);
+
+    // Without the flag the empty answer is returned as it is.
+    let backend = Arc::new(Reluctant { silent: 1, argvs: Mutex::new(Vec::new()) });
+    let opts = RunOptions { backend: backend.clone(), ..Default::default() };
+    let output = runner::run(&[], "What is this project?", Mode::Exec, &opts).unwrap();
+    assert!(output.messages.is_empty());
+    assert_eq!(backend.argvs.lock().unwrap().len(), 1);
+}
+
+/// `codex-clean eval`: every case runs once per model and repetition, in
+/// parallel, and each run is scored on its own.
+#[test]
+fn eval_scores_every_run_of_every_case() {
+    use std::sync::Arc;
+
+    use codex_clean::backend::ReplayBackend;
+    use codex_clean::eval::{self, Suite};
+    use codex_clean::runner::RunOptions;
+
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-eval");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let dir = tempfile::tempdir().unwrap();
+    let suite_path = dir.path().join("smoke.toml");
+    std::fs::write(
+        &suite_path,
+        "models = ['o3', 'gpt-5.5']\nrepeat = 2\n\n\
+         [[case]]\nname = 'language'\nprompt = 'What is this project?'\nexpect = ['Rust project', '(?i)cargo']\n\n\
+         [[case]]\nname = 'python'\nprompt = 'Is it Python?'\nexpect = ['Python']\nmodels = ['o3']\n",
+    )
+    .unwrap();
+    let suite = Suite::load(&suite_path).unwrap();
+    let opts = RunOptions {
+        backend: Arc::new(ReplayBackend { path: "tests/fixtures/sample_session.jsonl".into(), last_turn: false }),
+        ..Default::default()
+    };
+    let report = eval::run_suite(&suite, &opts);
+
+    assert_eq!(report.suite, "smoke");
+    let runs: Vec<(String, bool)> = report.results.iter().map(|r| (r.label(true), r.passed)).collect();
+    assert_eq!(
+        runs,
+        [
+            ("language [o3] #1".to_string(), true),
+            ("language [o3] #2".to_string(), true),
+            ("language [gpt-5.5] #1".to_string(), true),
+            ("language [gpt-5.5] #2".to_string(), true),
+            ("python [o3] #1".to_string(), false),
+            ("python [o3] #2".to_string(), false),
+        ]
+    );
+    assert_eq!(report.results[4].failures, ["answer doesn't match /Python/"]);
+    assert_eq!(report.results[0].session_id.as_deref(), Some("0199a213-81c0-7800-8aa1-bbab2a035a53"));
+
+    let saved = dir.path().join("report.json");
+    report.save(&saved).unwrap();
+    assert_eq!(eval::EvalReport::load(&saved).unwrap(), report);
+}
+
+/// `batch --out-dir`: each prompt's output, report, raw events and files
+/// land in a directory of their own.
+#[test]
+fn batch_keeps_each_prompt_in_its_own_directory() {
+    use std::sync::Arc;
+
+    use codex_clean::backend::ReplayBackend;
+    use codex_clean::batch::{self, NameBy};
+    use codex_clean::runner::RunOptions;
+
+    let home = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("codex-clean-batch");
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("CODEX_CLEAN_HOME", &home);
+
+    let out = tempfile::tempdir().unwrap();
+    let prompts = batch::read_prompts("Scaffold a crate\n# skipped\nScaffold a crate\n");
+    let opts = RunOptions {
+        backend: Arc::new(ReplayBackend { path: "tests/fixtures/scaffold_answer.jsonl".into(), last_turn: false }),
+        ..Default::default()
+    };
+    let code = batch::run(&prompts, &Default::default(), &[], &opts, Some(out.path()), NameBy::Slug).unwrap();
+    assert_eq!(code, 0);
+
+    for name in ["scaffold-a-crate", "scaffold-a-crate-2"] {
+        let dir = out.path().join(name);
+        assert_eq!(std::fs::read_to_string(dir.join("prompt.txt")).unwrap(), "Scaffold a crate\n");
+        assert!(std::fs::read_to_string(dir.join("output.txt")).unwrap().contains("Here is the crate."));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("events.jsonl")).unwrap(),
+            std::fs::read_to_string("tests/fixtures/scaffold_answer.jsonl").unwrap()
+        );
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("report.json")).unwrap()).unwrap();
+        assert_eq!(report["exit_code"], 0);
+        assert!(dir.join("files/src/main.rs").is_file());
+    }
+}
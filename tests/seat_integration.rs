@@ -131,6 +131,11 @@ fn ok_attempt() -> AttemptResult {
         exit_code: 0,
         status_success: true,
         child_exit: 0,
+        timed_out: None,
+        cancelled: false,
+        stopped_early: false,
+        output_limited: None,
+        timings: Default::default(),
     }
 }
 
@@ -147,6 +152,11 @@ fn rate_limit_attempt() -> AttemptResult {
         exit_code: 1,
         status_success: false,
         child_exit: 1,
+        timed_out: None,
+        cancelled: false,
+        stopped_early: false,
+        output_limited: None,
+        timings: Default::default(),
     }
 }
 
@@ -164,6 +174,11 @@ fn auth_error_attempt() -> AttemptResult {
         exit_code: 1,
         status_success: false,
         child_exit: 1,
+        timed_out: None,
+        cancelled: false,
+        stopped_early: false,
+        output_limited: None,
+        timings: Default::default(),
     }
 }
 